@@ -0,0 +1,180 @@
+/*!
+
+An owned copy of a parsed specification for tools outside of Lesk.
+
+The parser's own `Item`s borrow from the source text and change with the parser. The types here
+own their text, record where it came from as plain byte offsets, and implement
+`serde::Serialize`, so a linter or build system can read a specification as JSON (or any other
+serde format) without linking against the parser's internals. The items of an `%include`d file
+are nested in an `Item::Include`, and their locations are relative to that file.
+
+*/
+
+use serde::Serialize;
+
+use crate::options::OptionField;
+use crate::section_items::{Action as ParsedAction, Item as ParsedItem};
+use crate::Span;
+use crate::parser::ToSpan;
+
+
+/// A parsed specification.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Ast {
+  /// Definitions, options, start conditions, and code
+  pub section_one: Vec<Item>,
+  /// Rules and the code local to the scanner routine
+  pub section_two: Vec<Item>,
+  /// The user code after the second `%%`, verbatim
+  pub user_code: String,
+}
+
+/// Where a piece of text was found in its source file. `line` and `column` count from 1.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Location {
+  pub start: usize,
+  pub end: usize,
+  pub line: u32,
+  pub column: usize,
+}
+
+/// Text from the specification together with its location.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Text {
+  pub text: String,
+  pub location: Location,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Item {
+  // Section One
+  User { code: Text },
+  Top { code: Text },
+  Class { code: Text },
+  Init { code: Text },
+  Unknown { code: Text },
+  Option { option: OptionField, text: Text },
+  Include { file: Text, items: Vec<Item> },
+  State { is_exclusive: bool, name: Text },
+  Definition { name: Text, params: Vec<Text>, regex: Text },
+  Comment { text: Text },
+  Token { name: Text, payload: Option<Text> },
+
+  // Section Two
+  ScannerTop { code: Text },
+  Rule {
+    conditions: Vec<Text>,
+    pattern: Text,
+    context: Option<Text>,
+    /// Pattern options set by the start condition scopes around the rule
+    modifiers: Vec<OptionField>,
+    action: Action,
+  },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+  Code { code: Text },
+  FallThrough,
+  Empty,
+}
+
+
+impl Ast {
+  pub fn new(section_one: &[ParsedItem], section_two: &[ParsedItem], user_code: &str) -> Self {
+    Self {
+      section_one: section_one.iter().map(Item::from).collect(),
+      section_two: section_two.iter().map(Item::from).collect(),
+      user_code: user_code.to_string(),
+    }
+  }
+
+  /// Every item of both sections, with the items of included files in place of each `Include`.
+  pub fn items(&self) -> Vec<&Item> {
+    fn flatten<'a>(items: &'a [Item], flattened: &mut Vec<&'a Item>) {
+      for item in items {
+        match item {
+          Item::Include { items, .. } => flatten(items, flattened),
+          item => flattened.push(item),
+        }
+      }
+    }
+
+    let mut flattened = vec![];
+    flatten(&self.section_one, &mut flattened);
+    flatten(&self.section_two, &mut flattened);
+    flattened
+  }
+}
+
+impl<'s> From<&Span<'s>> for Text {
+  fn from(span: &Span<'s>) -> Self {
+    Text {
+      text: span.fragment().to_string(),
+      location: Location {
+        start: usize::from(span.to_span().start()),
+        end: usize::from(span.to_span().end()),
+        line: span.location_line(),
+        column: span.get_column(),
+      },
+    }
+  }
+}
+
+impl<'s> From<&ParsedAction<'s>> for Action {
+  fn from(action: &ParsedAction<'s>) -> Self {
+    match action {
+      ParsedAction::Code(code)      => Action::Code { code: code.into() },
+      ParsedAction::FallThrough(_)  => Action::FallThrough,
+      ParsedAction::Empty           => Action::Empty,
+    }
+  }
+}
+
+impl<'s> From<&ParsedItem<'s>> for Item {
+  fn from(item: &ParsedItem<'s>) -> Self {
+    match item {
+      // Section One
+      ParsedItem::User(code)    => Item::User { code: code.into() },
+      ParsedItem::Top(code)     => Item::Top { code: code.into() },
+      ParsedItem::Class(code)   => Item::Class { code: code.into() },
+      ParsedItem::Init(code)    => Item::Init { code: code.into() },
+      ParsedItem::Unknown(code) => Item::Unknown { code: code.into() },
+      ParsedItem::Comment(text) => Item::Comment { text: text.into() },
+
+      ParsedItem::Option(option, text) => {
+        Item::Option { option: option.clone(), text: text.into() }
+      }
+      ParsedItem::Include { file, contents } => {
+        Item::Include { file: file.into(), items: contents.iter().map(Item::from).collect() }
+      }
+      ParsedItem::State { is_exclusive, name } => {
+        Item::State { is_exclusive: *is_exclusive, name: name.into() }
+      }
+      ParsedItem::Definition { name, params, code } => {
+        Item::Definition {
+          name: name.into(),
+          params: params.iter().map(Text::from).collect(),
+          regex: code.into()
+        }
+      }
+      ParsedItem::Token { name, payload } => {
+        Item::Token { name: name.into(), payload: payload.as_ref().map(Text::from) }
+      }
+
+      // Section Two
+      ParsedItem::ScannerTop(code) => Item::ScannerTop { code: code.into() },
+      ParsedItem::Rule { conditions, pattern, context, modifiers, action } => {
+        Item::Rule {
+          conditions: conditions.iter().map(Text::from).collect(),
+          pattern: pattern.into(),
+          context: context.as_ref().map(Text::from),
+          modifiers: modifiers.clone(),
+          action: action.into(),
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,361 @@
+/*!
+
+Named regex definitions from section one and the expansion of `{NAME}` references to them.
+
+A reference is replaced by the parenthesized expansion of the definition it names, so that
+`{DIGIT}+` means one or more digits rather than the definition's last character repeated.
+References inside quotes and bracket expressions are not references and are left alone.
+
+A definition may take parameters, `DELIM(l,r)  {l}[^{r}]*{r}`, and is then referenced with
+arguments, `{DELIM(<,>)}`. Each `{l}` in the body is replaced by the text of its argument before
+the body is expanded, inside bracket expressions too, and without parentheses.
+
+*/
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use nom::Slice;
+
+use crate::error::{Error, Errors, RecursiveDefinitionError, UndefinedNameError};
+use crate::parser::ToSpan;
+use crate::section_items::Item;
+use crate::Span;
+
+
+struct Definition<'s> {
+  params: Vec<&'s str>,
+  regex: Span<'s>,
+}
+
+#[derive(Default)]
+pub struct Definitions<'s> {
+  definitions: HashMap<&'s str, Definition<'s>>,
+}
+
+impl<'s> Definitions<'s> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Collects the `Item::Definition`s among `items`. A later definition replaces an earlier one.
+  pub fn from_items(items: &[Item<'s>]) -> Self {
+    let mut definitions = Self::new();
+
+    for item in items {
+      if let Item::Definition { name, params, code } = item {
+        definitions.insert(*name, params, *code);
+      }
+    }
+
+    definitions
+  }
+
+  pub fn insert(&mut self, name: Span<'s>, params: &[Span<'s>], regex: Span<'s>) {
+    let params = params.iter().map(|p| *p.fragment()).collect();
+    self.definitions.insert(name.fragment(), Definition { params, regex });
+  }
+
+  /// The number of distinct names defined.
+  pub fn len(&self) -> usize {
+    self.definitions.len()
+  }
+
+  #[allow(dead_code)]
+  pub fn is_empty(&self) -> bool {
+    self.definitions.is_empty()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Span<'s>> {
+    self.definitions.get(name).map(|d| &d.regex)
+  }
+
+  /// Expands every `{NAME}` reference in `pattern`, recursively.
+  pub fn expand(&self, pattern: Span<'s>) -> Result<String, Errors> {
+    let mut chain: Vec<(&str, Span<'s>)> = vec![];
+    self.expand_inner(pattern.fragment(), pattern, false, &mut chain)
+  }
+
+  /**
+  Expands only references to definitions with parameters, which lex and flex lack, leaving
+  `{NAME}` references to plain definitions as they are.
+  */
+  pub fn expand_parameterized(&self, pattern: Span<'s>) -> Result<String, Errors> {
+    let mut chain: Vec<(&str, Span<'s>)> = vec![];
+    self.expand_inner(pattern.fragment(), pattern, true, &mut chain)
+  }
+
+  /**
+  `text` is either the text of `origin` or, for the body of a parameterized definition, that body
+  with its arguments substituted, in which case errors are reported at the whole of `origin`.
+
+  `chain` holds the names currently being expanded, together with the span of the reference
+  that started each expansion, so that a cycle can be reported along its whole length.
+  */
+  fn expand_inner<'a>(
+    &'a self,
+    text: &str,
+    origin: Span<'s>,
+    only_parameterized: bool,
+    chain: &mut Vec<(&'a str, Span<'s>)>
+  ) -> Result<String, Errors>
+  {
+    let exact           = std::ptr::eq(text, *origin.fragment());
+    let mut expanded    = String::with_capacity(text.len());
+    let mut in_quotes   = false;
+    let mut in_brackets = false;
+    let mut escaped     = false;
+    let mut chars       = text.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+      if escaped {
+        escaped = false;
+        expanded.push(c);
+        continue;
+      }
+
+      match c {
+        '\\'                 => escaped = true,
+        '"' if !in_brackets  => in_quotes = !in_quotes,
+        '[' if !in_quotes    => in_brackets = true,
+        ']' if in_brackets   => in_brackets = false,
+
+        // `{NAME}` or `{NAME(args)}` as opposed to a repeat `{n,m}`
+        '{' if !in_quotes && !in_brackets
+            && chars.peek().is_some_and(|(_, c)| c.is_alphabetic() || *c == '_') =>
+        {
+          let (name, args, close) = match parse_reference(&text[idx..]) {
+            Some((name, args, length)) => (name, args, idx + length - 1),
+            None => {
+              // Not a reference. Leave it for the regex parser to complain about.
+              expanded.push(c);
+              continue;
+            }
+          };
+          let reference = if exact { origin.slice(idx..close + 1) } else { origin };
+
+          let (name, definition) = match self.definitions.get_key_value(name) {
+            Some((name, definition)) => (*name, definition),
+            None => {
+              return Err(Errors::from(
+                Error::from(UndefinedNameError::new("definition", name, reference))
+              ));
+            }
+          };
+
+          if args.len() != definition.params.len() {
+            return Err(Errors::from(Error::Message(
+              reference.to_span(),
+              Cow::Owned(format!(
+                "`{}` takes {} argument(s) but {} were given",
+                name,
+                definition.params.len(),
+                args.len()
+              ))
+            )));
+          }
+
+          if only_parameterized && definition.params.is_empty() {
+            expanded.push_str(&text[idx..close + 1]);
+            for (i, _) in chars.by_ref() {
+              if i == close {
+                break;
+              }
+            }
+            continue;
+          }
+
+          if chain.iter().any(|(n, _)| *n == name) {
+            let mut spans: Vec<Span> = chain.iter().map(|(_, s)| *s).collect();
+            spans.push(reference);
+            return Err(Errors::from(
+              Error::from(RecursiveDefinitionError::new(name, spans))
+            ));
+          }
+
+          chain.push((name, reference));
+          let inner =
+              if definition.params.is_empty() {
+                self.expand_inner(
+                  definition.regex.fragment(),
+                  definition.regex,
+                  only_parameterized,
+                  chain
+                )?
+              } else {
+                let body = substitute(definition.regex.fragment(), &definition.params, &args);
+                self.expand_inner(&body, reference, only_parameterized, chain)?
+              };
+          chain.pop();
+
+          expanded.push('(');
+          expanded.push_str(&inner);
+          expanded.push(')');
+
+          // Skip past the closing brace.
+          for (i, _) in chars.by_ref() {
+            if i == close {
+              break;
+            }
+          }
+          continue;
+        }
+
+        _ => { /* pass */ }
+      }
+
+      expanded.push(c);
+    }
+
+    Ok(expanded)
+  }
+}
+
+/**
+Parses the reference at the start of `text`, which begins with `{`, returning the name, the
+arguments, and the length of the reference. Arguments are separated by commas outside of
+parentheses, and a `\` escapes the character after it.
+*/
+fn parse_reference(text: &str) -> Option<(&str, Vec<&str>, usize)> {
+  let name_end = text[1..]
+      .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+      .map(|offset| offset + 1)?;
+  let name = &text[1..name_end];
+
+  match text[name_end..].chars().next()? {
+    '}' => Some((name, vec![], name_end + 1)),
+
+    '(' => {
+      let mut args    = vec![];
+      // The start of the current argument
+      let mut start   = name_end + 1;
+      let mut depth   = 0usize;
+      let mut escaped = false;
+
+      for (offset, c) in text[name_end + 1..].char_indices() {
+        let idx = name_end + 1 + offset;
+        if escaped {
+          escaped = false;
+          continue;
+        }
+
+        match c {
+          '\\'              => escaped = true,
+          '('               => depth += 1,
+          ')' if depth > 0  => depth -= 1,
+          ',' if depth == 0 => {
+            args.push(&text[start..idx]);
+            start = idx + 1;
+          }
+          ')' => {
+            args.push(&text[start..idx]);
+            return match text[idx + 1..].chars().next() {
+              Some('}') => Some((name, args, idx + 2)),
+              _ => None
+            };
+          }
+          _ => { /* pass */ }
+        }
+      }
+
+      None
+    }
+
+    _ => None
+  }
+}
+
+/// Replaces each `{param}` in `body` with the corresponding argument, wherever it appears.
+fn substitute(body: &str, params: &[&str], args: &[&str]) -> String {
+  let mut result = body.to_string();
+
+  for (param, arg) in params.iter().zip(args) {
+    result = result.replace(&format!("{{{}}}", param), arg);
+  }
+
+  result
+}
+
+/// The names referenced by `{NAME}` or `{NAME(args)}` in `pattern`, with their byte offsets.
+pub fn references(pattern: &str) -> Vec<(usize, &str)> {
+  let mut found       = vec![];
+  let mut in_quotes   = false;
+  let mut in_brackets = false;
+  let mut escaped     = false;
+  let mut chars       = pattern.char_indices().peekable();
+
+  while let Some((idx, c)) = chars.next() {
+    if escaped {
+      escaped = false;
+      continue;
+    }
+
+    match c {
+      '\\'                 => escaped = true,
+      '"' if !in_brackets  => in_quotes = !in_quotes,
+      '[' if !in_quotes    => in_brackets = true,
+      ']' if in_brackets   => in_brackets = false,
+      '{' if !in_quotes && !in_brackets
+          && chars.peek().is_some_and(|(_, c)| c.is_alphabetic() || *c == '_') =>
+      {
+        if let Some((name, _, _)) = parse_reference(&pattern[idx..]) {
+          found.push((idx, name));
+        }
+      }
+      _ => { /* pass */ }
+    }
+  }
+
+  found
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::LSpan;
+
+  /// The definitions `DELIM(l,r)  {l}[^{r}]*{r}`, `PAIR(x)  {x}{x}`, and `DIGIT  [0-9]`.
+  fn definitions() -> Definitions<'static> {
+    let mut definitions = Definitions::new();
+    definitions.insert(
+      LSpan::new("DELIM"),
+      &[LSpan::new("l"), LSpan::new("r")],
+      LSpan::new("{l}[^{r}]*{r}")
+    );
+    definitions.insert(LSpan::new("PAIR"), &[LSpan::new("x")], LSpan::new("{x}{x}"));
+    definitions.insert(LSpan::new("DIGIT"), &[], LSpan::new("[0-9]"));
+    definitions
+  }
+
+  fn expand(pattern: &'static str) -> Result<String, Vec<&'static str>> {
+    definitions().expand(LSpan::new(pattern)).map_err(|errors| {
+      errors.iter().map(Error::code).collect()
+    })
+  }
+
+  #[test]
+  fn arguments_replace_parameters_inside_brackets_too() {
+    assert_eq!(expand("{DELIM(<,>)}"), Ok("(<[^>]*>)".to_string()));
+    assert_eq!(expand("a{DELIM(\\(,\\))}"), Ok("a(\\([^\\)]*\\))".to_string()));
+  }
+
+  #[test]
+  fn arguments_are_expanded_after_substitution() {
+    assert_eq!(expand("{PAIR({DIGIT})}"), Ok("(([0-9])([0-9]))".to_string()));
+    assert_eq!(expand("{PAIR((a|b))}"), Ok("((a|b)(a|b))".to_string()));
+  }
+
+  #[test]
+  fn only_parameterized_references_are_expanded_for_flex() {
+    let expanded = definitions().expand_parameterized(LSpan::new("{PAIR(x)}{DIGIT}")).unwrap();
+    assert_eq!(expanded, "(xx){DIGIT}");
+  }
+
+  #[test]
+  fn wrong_number_of_arguments_is_an_error() {
+    assert_eq!(expand("{DELIM(<)}"), Err(vec!["L0014"]));
+    assert_eq!(expand("{DIGIT(1)}"), Err(vec!["L0014"]));
+    assert_eq!(expand("{PAIR}"), Err(vec!["L0014"]));
+  }
+}
@@ -0,0 +1,586 @@
+/*!
+
+The regex engines a scanner can be generated for, selected with `%option matcher=NAME` or
+`--matcher NAME`. An engine compiles the patterns of the start conditions into the tables or code
+of the scanner, and supplies the `Matcher` and `Pattern` types the generated lexer scans with.
+
+*/
+
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use regex_automata::meta::Regex;
+use regex_automata::MatchKind;
+
+use crate::error::{Error, Errors};
+use crate::mode::ConditionPattern;
+use crate::parser::ToSpan;
+
+
+/// The engine used when none is selected.
+pub const DEFAULT_ENGINE: &str = "regex";
+
+/// The names of the engines `%option matcher` can select.
+pub const ENGINE_NAMES: [&str; 2] = ["regex", "relesk"];
+
+/**
+A regex engine the scanner is generated for. The lexer that `Specification::write` generates
+brings the engine's `Matcher` and `Pattern` into the scanner as a module, and names them through
+it, so that two scanners with different prefixes can share a Rust module. It creates a `Matcher`
+over its input with `Matcher::new(input)`, of a `&str` or a `String`, holds one `Pattern` per
+start condition, built by the expression `patterns` gives, and calls `matcher.scan(&pattern)`,
+which returns `None` at the end of the input, `Some(0)` when no rule matches, having consumed one
+character, and `Some(n)` when the accept index `n` matches. It reads the text of the match with
+`matcher.text()` and tests for the end with `matcher.at_end()`. A lexer scanning several inputs,
+as `push_input` has it do, has a `Matcher` for each. It gives back all but the first `n` bytes of
+the match with `matcher.less(n)`, and puts text back in the input with `matcher.unput(text)`.
+*/
+pub trait RegexEngine {
+  /// The name `%option matcher` selects the engine by.
+  fn name(&self) -> &'static str;
+
+  /// The items that bring the engine's `Matcher` and `Pattern` into the scanner as the module
+  /// `module`, which is `matcher` or, after the prefix, `calc_matcher`.
+  fn prelude(&self, module: &str) -> String;
+
+  /**
+  Compiles the patterns of the start conditions, in `ModeID` order, into the tables or code of
+  the scanner. Besides whatever `patterns` reads, they must define `ACCEPTS`, which maps the
+  accept index `n` of each start condition to its rule, `ACCEPTS[condition][n - 1]`. The name of
+  every item they define starts with `prefix`, the `CALC_` of `%option prefix="calc"`. A rule
+  whose regex the engine rejects is reported at its pattern, so that the generated lexer does not
+  fail when it is created.
+  */
+  fn compile(&self, patterns: &[ConditionPattern], prefix: &str) -> Result<String, Errors>;
+
+  /// The expression that creates the `Vec<Pattern>` of the start conditions, in `ModeID` order,
+  /// from the tables `compile` wrote with `prefix` when the lexer is created. The runtime is
+  /// `module`, as `prelude` brought it in.
+  fn patterns(&self, prefix: &str, module: &str) -> String;
+
+  /// Checks that the engine can compile a rule, the regex of its text and that of its trailing
+  /// context, if it has one, returning the engine's message for a rule it rejects.
+  fn check(&self, text: &str, context: Option<&str>) -> Result<(), String>;
+}
+
+/// The engine named `name`, if there is one.
+pub fn regex_engine(name: &str) -> Option<Box<dyn RegexEngine>> {
+  match name {
+    "regex"  => Some(Box::new(RegexAutomataEngine)),
+    "relesk" => Some(Box::new(ReleskEngine)),
+    _        => None,
+  }
+}
+
+/**
+The default engine, the meta regex of the `regex-automata` crate, which is the engine of the
+`regex` crate and reads its syntax. The scanner depends on `regex-automata` 0.4, and the engine
+writes the rest of its runtime, a `Matcher` and `Pattern` of a hundred lines, into the scanner.
+The runtime names only `core` and `alloc`, so that a `no_std` scanner can depend on
+`regex-automata` without its `std` feature.
+
+Each rule is compiled to a regex of its own when the lexer is created, and the matcher tries all
+of the rules of the start condition at its position, taking the longest match and, of those as
+long, the earliest rule, as lex does. A rule matches the longest text it can, not the first its
+alternatives give. Trailing context is matched after the text of the rule in a regex of its own
+group, and given back. `^` and `$` match at the start and end of a line.
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RegexAutomataEngine;
+
+impl RegexEngine for RegexAutomataEngine {
+  fn name(&self) -> &'static str {
+    "regex"
+  }
+
+  fn prelude(&self, module: &str) -> String {
+    format!("\nmod {} {{\n{}}}\n\n", module, REGEX_AUTOMATA_RUNTIME)
+  }
+
+  fn compile(&self, patterns: &[ConditionPattern], prefix: &str) -> Result<String, Errors> {
+    check_patterns(patterns, |text, context| self.check(text, context))?;
+
+    let mut tables = String::new();
+
+    for pattern in patterns {
+      let rules: Vec<String> =
+          pattern.contexts.iter().map(|rule| format!("({:?}, {:?})", rule.0, rule.1)).collect();
+      let rule_ids: Vec<String> = pattern.rule_ids.iter().map(usize::to_string).collect();
+      tables.push_str(&format!(
+        "static {prefix}RULES_{name}: [(&str, Option<&str>); {count}] = [{rules}];\n\
+        static {prefix}ACCEPT_{name}: [usize; {count}] = [{rule_ids}];\n\n",
+        prefix = prefix,
+        name = pattern.condition,
+        count = rules.len(),
+        rules = rules.join(", "),
+        rule_ids = rule_ids.join(", ")
+      ));
+    }
+
+    let names: Vec<&str> = patterns.iter().map(|pattern| pattern.condition.as_str()).collect();
+    let count = names.len();
+    tables.push_str(&format!(
+      "static {prefix}RULES: [(&str, &[(&str, Option<&str>)]); {}] = [{}];\n\
+      static {prefix}ACCEPTS: [&[usize]; {}] = [{}];\n\n",
+      count,
+      names.iter()
+           .map(|name| format!("({:?}, &{}RULES_{})", name, prefix, name))
+           .collect::<Vec<_>>()
+           .join(", "),
+      count,
+      names.iter().map(|name| format!("&{}ACCEPT_{}", prefix, name)).collect::<Vec<_>>().join(", "),
+      prefix = prefix
+    ));
+
+    Ok(tables)
+  }
+
+  fn patterns(&self, prefix: &str, module: &str) -> String {
+    format!(
+      "{}RULES.iter().map(|(condition, rules)| {{\n              \
+        {}::Pattern::new(rules).unwrap_or_else(|e| {{\n                \
+          panic!(\"the rules of {{}} do not compile: {{}}\", condition, e)\n              \
+        }})\n            \
+      }}).collect()",
+      prefix,
+      module
+    )
+  }
+
+  fn check(&self, text: &str, context: Option<&str>) -> Result<(), String> {
+    Regex::builder()
+        .configure(Regex::config().match_kind(MatchKind::All))
+        .build(&rule_regex(text, context))
+        .map(|_| ())
+        .map_err(|e| match e.syntax_error() {
+          // Without the regex and the caret below it, which the diagnostic shows at the rule
+          Some(regex_syntax::Error::Parse(e))     => e.kind().to_string(),
+          Some(regex_syntax::Error::Translate(e)) => e.kind().to_string(),
+          _                                       => e.to_string(),
+        })
+  }
+}
+
+/**
+The regex the runtime of `RegexAutomataEngine` compiles for a rule, as `Pattern::new` writes it:
+with `^` and `$` matching at the ends of lines, and the text of a rule with trailing context in
+the group the matcher reads the end of the text from.
+*/
+fn rule_regex(text: &str, context: Option<&str>) -> String {
+  match context {
+    Some(context) => format!("(?m:({}))(?m:{})", text, context),
+    None          => format!("(?m:{})", text),
+  }
+}
+
+/// The `Matcher` and `Pattern` of `RegexAutomataEngine`, which its prelude writes into the
+/// scanner. `Pattern::new` compiles each rule with `rule_regex`.
+const REGEX_AUTOMATA_RUNTIME: &str = r#"    //! The runtime of the scanner, over `regex-automata`.
+    //! It needs `alloc`, but not `std`.
+    extern crate alloc;
+
+    use alloc::borrow::Cow;
+    use alloc::format;
+    use alloc::vec::Vec;
+    use core::ops::Range;
+
+    use regex_automata::meta::{BuildError, Regex};
+    use regex_automata::{Anchored, Input, MatchKind};
+
+    /// The rules of a start condition, each compiled on its own.
+    pub struct Pattern {
+        /// Each rule's regex, and whether it has trailing context
+        rules: Vec<(Regex, bool)>,
+    }
+
+    impl Pattern {
+        /// Compiles `rules`, for each rule the regex of its text and that of its trailing
+        /// context, if it has one.
+        pub fn new(rules: &[(&str, Option<&str>)]) -> Result<Pattern, BuildError> {
+            let mut builder = Regex::builder();
+            builder.configure(Regex::config().match_kind(MatchKind::All));
+
+            let mut compiled = Vec::with_capacity(rules.len());
+            for (text, context) in rules {
+                let regex = match context {
+                    Some(context) => format!("(?m:({}))(?m:{})", text, context),
+                    None => format!("(?m:{})", text),
+                };
+                compiled.push((builder.build(&regex)?, context.is_some()));
+            }
+            Ok(Pattern { rules: compiled })
+        }
+    }
+
+    /// Scans its input with the `Pattern` of the current start condition.
+    pub struct Matcher<'i> {
+        /// The input, borrowed from the caller or, as a file read while scanning, owned
+        input: Cow<'i, str>,
+        position: usize,
+        /// The span of the last match in the input
+        text: Range<usize>,
+    }
+
+    impl<'i> Matcher<'i> {
+        pub fn new(input: impl Into<Cow<'i, str>>) -> Self {
+            Matcher { input: input.into(), position: 0, text: 0..0 }
+        }
+
+        /// Matches the longest text a rule of `pattern` matches at the current position, the
+        /// earliest rule winning a tie. Returns `None` at the end of the input, `Some(0)` when
+        /// no rule matches, having consumed one character, and `Some(n)` when the `n`th rule
+        /// matches.
+        pub fn scan(&mut self, pattern: &Pattern) -> Option<usize> {
+            if self.at_end() {
+                self.text = self.position..self.position;
+                return None;
+            }
+
+            let input = Input::new(self.input.as_ref());
+            let input = input.range(self.position..).anchored(Anchored::Yes);
+            let mut accept = 0;
+            let mut end = self.position;
+            for (index, (regex, context)) in pattern.rules.iter().enumerate() {
+                let found = if *context {
+                    // The slots of the whole match, then of the group around the text
+                    let mut slots = [None; 4];
+                    regex.search_slots(&input, &mut slots);
+                    slots[3].map(|end| end.get())
+                } else {
+                    regex.search(&input).map(|found| found.end())
+                };
+                if let Some(found) = found.filter(|found| *found > end) {
+                    accept = index + 1;
+                    end = found;
+                }
+            }
+
+            if accept == 0 {
+                end += self.input[self.position..].chars().next().map_or(0, char::len_utf8);
+            }
+            self.text = self.position..end;
+            self.position = end;
+            Some(accept)
+        }
+
+        /// The text of the last match.
+        pub fn text(&self) -> &str {
+            &self.input[self.text.clone()]
+        }
+
+        /// Keeps the first `n` bytes of the last match, giving the rest back to be scanned again.
+        pub fn less(&mut self, n: usize) {
+            assert!(n <= self.text.len(), "less({}) of a match of {} bytes", n, self.text.len());
+            self.position = self.text.start + n;
+            self.text.end = self.position;
+        }
+
+        /// Puts `text` back in the input, to be scanned next.
+        pub fn unput(&mut self, text: &str) {
+            self.input.to_mut().insert_str(self.position, text);
+        }
+
+        /// Whether the whole input has been scanned.
+        pub fn at_end(&self) -> bool {
+            self.position >= self.input.len()
+        }
+    }
+"#;
+
+/**
+The runtime matcher of `relesk`. The regex of each start condition is written to the scanner as a
+string, and compiled when the lexer is created. Compiling the DFAs ahead of time needs that
+crate's DFA compiler (see Notes.md), when the tables of `--fast` and `--full` will differ.
+
+`relesk` is not published, so a scanner generated for it builds only where the crate is at hand,
+and `check` is `approximate_syntax_check` rather than that crate's parser.
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReleskEngine;
+
+impl RegexEngine for ReleskEngine {
+  fn name(&self) -> &'static str {
+    "relesk"
+  }
+
+  fn prelude(&self, module: &str) -> String {
+    format!("\nuse relesk::matcher as {};\n\n", module)
+  }
+
+  fn compile(&self, patterns: &[ConditionPattern], prefix: &str) -> Result<String, Errors> {
+    check_patterns(patterns, |text, context| self.check(text, context))?;
+
+    let mut tables = String::new();
+
+    for pattern in patterns {
+      tables.push_str(&write_regex(prefix, &pattern.condition, &pattern.regex, &pattern.rule_ids));
+    }
+
+    let names: Vec<&str> = patterns.iter().map(|pattern| pattern.condition.as_str()).collect();
+    let count = names.len();
+    tables.push_str(&format!(
+      "static {prefix}REGEXES: [(&str, &str); {}] = [{}];\n\
+      static {prefix}ACCEPTS: [&[usize]; {}] = [{}];\n\n",
+      count,
+      names.iter()
+           .map(|name| format!("({:?}, {}REGEX_{})", name, prefix, name))
+           .collect::<Vec<_>>()
+           .join(", "),
+      count,
+      names.iter().map(|name| format!("&{}ACCEPT_{}", prefix, name)).collect::<Vec<_>>().join(", "),
+      prefix = prefix
+    ));
+
+    Ok(tables)
+  }
+
+  fn patterns(&self, prefix: &str, module: &str) -> String {
+    format!(
+      "{}REGEXES.iter().map(|(condition, regex)| {{\n              \
+        {}::Pattern::new(regex).unwrap_or_else(|e| {{\n                \
+          panic!(\"the regex of start condition {{}} does not compile: {{:?}}\", condition, e)\n              \
+        }})\n            \
+      }}).collect()",
+      prefix,
+      module
+    )
+  }
+
+  fn check(&self, text: &str, context: Option<&str>) -> Result<(), String> {
+    approximate_syntax_check(text)?;
+    context.map_or(Ok(()), approximate_syntax_check)
+  }
+}
+
+/**
+Checks every rule of `patterns` with `check`, given the regex of its text and that of its
+trailing context, which returns the regex engine's message for a rule it rejects, reporting each
+failure at the rule's pattern. A rule active in several start conditions is checked once.
+*/
+pub fn check_patterns<F>(patterns: &[ConditionPattern], check: F) -> Result<(), Errors>
+  where F: Fn(&str, Option<&str>) -> Result<(), String>
+{
+  let mut errors  = Errors::new();
+  let mut checked = HashSet::new();
+
+  for pattern in patterns {
+    let rules = pattern.contexts.iter().zip(&pattern.rules).zip(&pattern.rule_ids);
+    for (((text, context), rule), rule_id) in rules {
+      if !checked.insert(*rule_id) {
+        continue;
+      }
+      if let Err(message) = check(text, context.as_deref()) {
+        errors.push(Error::Message(
+          rule.to_span(),
+          format!("the regex of this rule does not compile: {}", message).into()
+        ));
+      }
+      if errors.is_full() {
+        return Err(errors);
+      }
+    }
+  }
+
+  if errors.has_errors() {
+    Err(errors)
+  } else {
+    Ok(())
+  }
+}
+
+/**
+An approximate syntax check of `regex` against the syntax `relesk` reads, which stands in for
+that crate's parser, returning why it does not compile: a group or bracket expression that is not
+closed, a `)` that closes no group, a repetition of nothing, a bound `{n,m}` whose `n` is greater
+than its `m`, or a `\` that ends the regex. Nothing else is checked, so a regex that passes may
+still be one `relesk` rejects. The constructs `relesk` has and other engines lack, such as the
+`--` and `&&` of bracket expressions, pass.
+*/
+fn approximate_syntax_check(regex: &str) -> Result<(), String> {
+  let mut chars      = regex.chars().peekable();
+  let mut groups     = 0usize;
+  // Whether there is something before a quantifier for it to repeat
+  let mut repeatable = false;
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        if chars.next().is_none() {
+          return Err("the regex ends with `\\`".to_string());
+        }
+        repeatable = true;
+      }
+
+      '[' => {
+        check_bracket(&mut chars)?;
+        repeatable = true;
+      }
+
+      '(' => {
+        groups += 1;
+        // `(?:`, `(?i:`, `(?=`, and the like
+        if chars.peek() == Some(&'?') {
+          chars.next();
+        }
+        repeatable = false;
+      }
+
+      ')' => {
+        if groups == 0 {
+          return Err("a `)` closes no group".to_string());
+        }
+        groups -= 1;
+        repeatable = true;
+      }
+
+      '|' => repeatable = false,
+
+      '*' | '+' | '?' if !repeatable => {
+        return Err(format!("the `{}` repeats nothing", c));
+      }
+
+      '{' if chars.peek().is_some_and(char::is_ascii_digit) => {
+        if !repeatable {
+          return Err("a bound `{n,m}` repeats nothing".to_string());
+        }
+        check_bound(&mut chars)?;
+      }
+
+      _ => repeatable = true,
+    }
+  }
+
+  if groups > 0 {
+    Err("a `(` is not closed".to_string())
+  } else {
+    Ok(())
+  }
+}
+
+/// Reads a bracket expression after its `[` up to its `]`, including the `[:alpha:]` and the
+/// like within it, failing if the regex ends first.
+fn check_bracket(chars: &mut Peekable<Chars>) -> Result<(), String> {
+  let unclosed = || "a bracket expression `[` is not closed".to_string();
+
+  if chars.peek() == Some(&'^') {
+    chars.next();
+  }
+  // A `]` first is a member of the set.
+  if chars.peek() == Some(&']') {
+    chars.next();
+  }
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        chars.next().ok_or_else(unclosed)?;
+      }
+      '[' if matches!(chars.peek(), Some(':') | Some('.') | Some('=')) => {
+        let kind = chars.next().ok_or_else(unclosed)?;
+        loop {
+          match chars.next() {
+            Some(c) if c == kind && chars.peek() == Some(&']') => {
+              chars.next();
+              break;
+            }
+            Some(_) => { /* pass */ }
+            None    => return Err(unclosed()),
+          }
+        }
+      }
+      ']' => return Ok(()),
+      _   => { /* pass */ }
+    }
+  }
+
+  Err(unclosed())
+}
+
+/// Reads a bound `{n}`, `{n,}`, or `{n,m}` after its `{`, failing if it is not closed or if `n`
+/// is greater than `m`.
+fn check_bound(chars: &mut Peekable<Chars>) -> Result<(), String> {
+  let mut text = String::new();
+  loop {
+    match chars.next() {
+      Some('}') => break,
+      Some(c) if c.is_ascii_digit() || c == ',' => text.push(c),
+      _ => return Err("a bound `{n,m}` is not closed".to_string()),
+    }
+  }
+
+  let mut bounds = text.splitn(2, ',');
+  let min        = bounds.next().and_then(|n| n.parse::<u32>().ok());
+  let max        = bounds.next().filter(|m| !m.is_empty()).map(str::parse::<u32>);
+  match (min, max) {
+    (Some(min), Some(Ok(max))) if min > max => {
+      Err(format!("the bound `{{{}}}` repeats at least {} times but at most {}", text, min, max))
+    }
+    (Some(_), None) | (Some(_), Some(Ok(_))) => Ok(()),
+    _ => Err(format!("the bound `{{{}}}` is not a number of repetitions", text)),
+  }
+}
+
+/**
+`REGEX_<NAME>` and `ACCEPT_<NAME>`, after `prefix`, for the start condition `condition`. `regex` is
+written as a string literal, which `{:?}` escapes.
+*/
+fn write_regex(prefix: &str, condition: &str, regex: &str, rule_ids: &[usize]) -> String {
+  let rule_ids: Vec<String> = rule_ids.iter().map(usize::to_string).collect();
+
+  format!(
+    "static {prefix}REGEX_{name}: &str = {regex:?};\n\
+    static {prefix}ACCEPT_{name}: [usize; {count}] = [{rules}];\n\n",
+    prefix = prefix,
+    name = condition,
+    regex = regex,
+    count = rule_ids.len(),
+    rules = rule_ids.join(", ")
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn regex_checks_rules_with_its_parser() {
+    let engine = RegexAutomataEngine;
+    for regex in &["(?i:select)", "[[:alpha:]_][[:alnum:]_]*", "[a-z--[aeiou]]", "^a$", "x*?"] {
+      assert_eq!(engine.check(regex, None), Ok(()), "{}", regex);
+    }
+    assert_eq!(engine.check("ab|cd", Some("x")), Ok(()));
+
+    for regex in &["(ab", "ab)", "*a", "[a-z", "a{3,2}", "a\\", "a(?=b)"] {
+      assert!(engine.check(regex, None).is_err(), "{}", regex);
+    }
+    assert!(engine.check("a", Some("(b")).is_err());
+  }
+
+  #[test]
+  fn regex_runtime_compiles_the_rules_as_checked() {
+    assert_eq!(rule_regex("a|b", None), "(?m:a|b)");
+    assert!(REGEX_AUTOMATA_RUNTIME.contains("None => format!(\"(?m:{})\", text),"));
+    assert_eq!(rule_regex("a|b", Some("c")), "(?m:(a|b))(?m:c)");
+    assert!(REGEX_AUTOMATA_RUNTIME.contains(
+      "Some(context) => format!(\"(?m:({}))(?m:{})\", text, context),"
+    ));
+  }
+
+  #[test]
+  fn relesk_accepts_its_own_syntax() {
+    for regex in &[
+      "(?i:select)", "[]a-z]+", "[^]]", "[[:alpha:]_][[:alnum:]_]*", "a{2,}", "x*?", "\\(",
+      "[a-z--[aeiou]]", "(a|b)/c",
+    ] {
+      assert_eq!(approximate_syntax_check(regex), Ok(()), "{}", regex);
+    }
+  }
+
+  #[test]
+  fn relesk_rejects_malformed_regexes() {
+    for regex in &["(ab", "ab)", "*a", "a|+", "[a-z", "[[:alpha:]", "a{3,2}", "a{3", "a\\"] {
+      assert!(approximate_syntax_check(regex).is_err(), "{}", regex);
+    }
+  }
+}
@@ -0,0 +1,218 @@
+//! Stable diagnostic codes and their extended explanations, as printed by `lesk explain`.
+
+/// The extended explanation of a diagnostic code.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Explanation {
+    pub code: &'static str,
+    /// What the diagnostic is about, in a few words.
+    pub summary: &'static str,
+    /// The cause of the diagnostic and how to address it, with examples.
+    pub text: &'static str,
+}
+
+/// Returns the explanation of `code`, which may be given in lower case.
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS.iter().find(|explanation| explanation.code.eq_ignore_ascii_case(code))
+}
+
+/// Every diagnostic code, in order. `L0001` to `L0099` are errors in the specification and `L0101`
+/// to `L0199` warnings about it; `L0201` and up are left for the errors of the regex compiler.
+/// Codes are never reused, and a retired code keeps its entry.
+pub static EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: "L0001",
+        summary: "expected one thing, found another",
+        text: "\
+The parser expected a particular item at this point of the specification and found something
+else. The message names both.
+
+    %option tabs=four   // `tabs` takes a number",
+    },
+    Explanation {
+        code: "L0002",
+        summary: "incorrect closing delimiter",
+        text: "\
+A block was closed with a delimiter that does not match the one that opened it.
+
+    %{
+      use std::fmt;
+    }               // `%{` is closed by `%}`",
+    },
+    Explanation {
+        code: "L0003",
+        summary: "invalid code block label",
+        text: "\
+A code block in section one is labeled with a name Lesk does not know. The labeled blocks are
+`%top{`, `%class{`, and `%init{`.
+
+    %header{ … }    // write `%top{ … }` for module-level items",
+    },
+    Explanation {
+        code: "L0004",
+        summary: "unclosed delimiters",
+        text: "\
+The file ended while a block, a quoted string, or a bracket expression was still open. The
+diagnostic points at the opening delimiter.
+
+    %{
+      use std::fmt;
+    %%              // the `%{` block was never closed with `%}`",
+    },
+    Explanation {
+        code: "L0005",
+        summary: "unexpected token",
+        text: "\
+Something appears where the grammar of the section does not allow it, such as an option Lesk
+does not know, or a value given to an option that takes none.
+
+    %option nosuch      // not an option
+    %option main=1      // `main` is turned on by naming it",
+    },
+    Explanation {
+        code: "L0006",
+        summary: "missing item",
+        text: "\
+A required part of an item is missing, such as the value of an option that takes one.
+
+    %option outfile     // write `%option outfile=\"lex.rs\"`",
+    },
+    Explanation {
+        code: "L0007",
+        summary: "section ended inside a block",
+        text: "\
+A section separator `%%` was found while a code block or a start condition scope was still open.
+Close the block before the `%%`.
+
+    <STRING>{
+      \\\"    BEGIN(INITIAL);
+    %%              // the `<STRING>{` scope needs its closing `}`",
+    },
+    Explanation {
+        code: "L0008",
+        summary: "undefined name",
+        text: "\
+A pattern refers to a definition with `{NAME}`, or a rule to a start condition with `<NAME>`,
+that is never declared.
+
+    %%
+    {DIGIT}+        // add `DIGIT [0-9]` to section one
+    <COMMENT>.      // add `%x COMMENT` to section one",
+    },
+    Explanation {
+        code: "L0009",
+        summary: "recursive definition",
+        text: "\
+A definition refers to itself, directly or through other definitions, so it cannot be expanded.
+Regular expressions cannot describe nested structure; match the parts with separate rules and
+track the nesting in the actions, with start conditions or a counter.
+
+    LIST    \"(\"({LIST}|[a-z]+)*\")\"",
+    },
+    Explanation {
+        code: "L0010",
+        summary: "conflicting options",
+        text: "\
+Two options were set that cannot be used together, in the specification or on the command line.
+The diagnostic points at each `%option` involved; remove one of them.",
+    },
+    Explanation {
+        code: "L0011",
+        summary: "cannot include file",
+        text: "\
+An `%include`d file could not be found in the directory of the including file or on the include
+path, or could not be read. Add its directory with `--include-path`, or in `Lesk.toml`.",
+    },
+    Explanation {
+        code: "L0012",
+        summary: "include cycle",
+        text: "\
+A file includes itself, directly or through other files. The diagnostic lists the files of the
+cycle. Move what the files share into a file that includes neither.",
+    },
+    Explanation {
+        code: "L0013",
+        summary: "unsupported flex feature",
+        text: "\
+The specification uses a flex feature that Lesk scanners cannot provide. `REJECT` makes a flex
+scanner fall back to the next best match, which needs a backtracking matcher; Lesk scanners
+always take the longest match. Write a rule for each match `REJECT` would fall back to, or match
+the text once and examine it in the action. The help of the diagnostic says how to do without
+the others.
+
+    %option header-file=\"lex.h\"  // a Lesk scanner is a Rust module, which needs no header",
+    },
+    Explanation {
+        code: "L0014",
+        summary: "invalid specification",
+        text: "\
+An error without a more specific code. The message describes the problem.",
+    },
+    Explanation {
+        code: "L0015",
+        summary: "internal parser error",
+        text: "\
+The parser failed in a way it does not report properly. This is a bug in Lesk; please report it
+with the specification that caused it.",
+    },
+    Explanation {
+        code: "L0101",
+        summary: "unused definition (warning `unused-definition`)",
+        text: "\
+A definition that no rule or other definition refers to. Remove it, or refer to it as `{NAME}`.",
+    },
+    Explanation {
+        code: "L0102",
+        summary: "exclusive start condition without rules (warning `unused-condition`)",
+        text: "\
+No rule is active in an exclusive start condition, so a scanner in it can match nothing. Rules
+without a condition list are not active in exclusive start conditions; give the rules meant for
+it `<NAME>`, or declare it with `%s` instead of `%x`.",
+    },
+    Explanation {
+        code: "L0103",
+        summary: "pattern matches the empty string (warning `empty-match`)",
+        text: "\
+A rule whose pattern can match the empty string. The scanner takes the longest match, so the rule
+matches nothing at all wherever another rule matches, and elsewhere matches without consuming
+input.
+
+    [a-z]*          // write [a-z]+",
+    },
+    Explanation {
+        code: "L0104",
+        summary: "overridden option (warning `overridden-option`)",
+        text: "\
+An option is set more than once with different values, so only the last setting has effect.
+Remove the earlier one.",
+    },
+    Explanation {
+        code: "L0105",
+        summary: "ignored option (warning `ignored-option`)",
+        text: "\
+A flex option or directive that concerns the C scanner flex generates, and that Lesk accepts for
+compatibility but ignores.
+
+    %option 8bit        // Lesk scanners always read 8-bit input",
+    },
+    Explanation {
+        code: "L0106",
+        summary: "code between rules (warning `moved-code`)",
+        text: "\
+Indented code or a `%{ … %}` block between the rules of section two. It belongs to no rule, and
+is moved to the top of the scanner routine, where it runs each time `lex` is called. Put it
+before the first rule, or in the action of the rule it belongs to.
+
+    [0-9]+      { return Token::Number; }
+        count += 1;     // runs before every scan, not after numbers",
+    },
+    Explanation {
+        code: "L0107",
+        summary: "variable-length trailing context (warning `variable-context`)",
+        text: "\
+A rule `r/s` whose text `r` and trailing context `s` can both match strings of different lengths.
+Where the text ends and the context begins is then ambiguous, and the scanner may return a match
+of the wrong length, as a flex scanner does. Make one of the two fixed-length.
+
+    [a-z]+/[0-9]*   // write [a-z]+/[0-9] or [a-z]/[0-9]*",
+    },
+];
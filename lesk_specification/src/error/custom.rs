@@ -0,0 +1,93 @@
+//! Diagnostics of kinds defined outside this crate.
+
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::Diagnostic;
+
+use super::SourceID;
+
+/// A kind of diagnostic defined by a crate built on this one, such as a regex compiler reporting
+/// errors in the rules, which can then travel in an `Errors` stack with those of the parser.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[derive(Debug)]
+/// struct EmptyClass(Span);
+///
+/// impl Display for EmptyClass {
+///     fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+///         write!(fmt, "empty character class")
+///     }
+/// }
+///
+/// impl CustomDiagnostic for EmptyClass {
+///     fn code(&self) -> &'static str { "L0201" }
+///     fn span(&self) -> Option<Span> { Some(self.0) }
+///     fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+///         let label = Label::primary(file, self.0).with_message("matches nothing");
+///         Diagnostic::error().with_message(self.to_string()).with_labels(vec![label])
+///     }
+/// }
+///
+/// errors.push(Error::custom(EmptyClass(span)));
+/// ```
+pub trait CustomDiagnostic: Debug + Display + Send + Sync {
+    /// The stable code of the diagnostic. The codes from `L0201` are left for other crates.
+    fn code(&self) -> &'static str;
+
+    /// The span the diagnostic is reported at.
+    fn span(&self) -> Option<Span>;
+
+    /// The spans of the other places the diagnostic involves.
+    fn related_spans(&self) -> Vec<Span> {
+        Vec::new()
+    }
+
+    /// Whether the diagnostic is a warning, which does not stop the scanner from being generated.
+    fn is_warning(&self) -> bool {
+        false
+    }
+
+    /// Converts the diagnostic to a `Diagnostic` in `file`, without its code, which is added.
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID>;
+}
+
+/// A `CustomDiagnostic` held by an `Error`. Two are equal when they have the same code, span, and
+/// message.
+#[derive(Clone)]
+pub struct CustomError(pub Arc<dyn CustomDiagnostic>);
+
+impl CustomError {
+    /// Constructs a new `CustomError`.
+    pub fn new<D>(diagnostic: D) -> Self
+    where
+        D: CustomDiagnostic + 'static,
+    {
+        CustomError(Arc::new(diagnostic))
+    }
+}
+
+impl Debug for CustomError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        Debug::fmt(&*self.0, fmt)
+    }
+}
+
+impl Display for CustomError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        Display::fmt(&*self.0, fmt)
+    }
+}
+
+impl PartialEq for CustomError {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.code() == other.0.code()
+            && self.0.span() == other.0.span()
+            && self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Eq for CustomError {}
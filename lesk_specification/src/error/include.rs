@@ -0,0 +1,60 @@
+//! Include error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when an `%include`d file cannot be found or read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IncludeError {
+    /// The file name as written in the `%include`.
+    pub file: String,
+    /// Span of the file name.
+    pub span: Span,
+    /// Why the file could not be included.
+    pub reason: String,
+    /// Paths that were tried, in order.
+    pub searched: Vec<String>,
+}
+
+impl IncludeError {
+    /// Constructs a new `IncludeError`.
+    pub fn new<S>(file: &str, span: S, reason: String, searched: Vec<String>) -> Self
+    where
+        S: ToSpan,
+    {
+        IncludeError {
+            file: file.to_string(),
+            span: span.to_span(),
+            reason,
+            searched,
+        }
+    }
+}
+
+impl Display for IncludeError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "cannot include `{}`: {}", self.file, self.reason)
+    }
+}
+
+impl Error for IncludeError {}
+
+impl ToDiagnostic for IncludeError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let label = Label::primary(file, self.span).with_message(self.reason.clone());
+        let notes = self.searched
+                        .iter()
+                        .map(|path| format!("note: searched {}", path))
+                        .collect();
+
+        Diagnostic::error().with_message(self.to_string())
+                           .with_labels(vec![label])
+                           .with_notes(notes)
+    }
+}
@@ -0,0 +1,67 @@
+//! Include cycle error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when a file includes itself, directly or through other files.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IncludeCycleError {
+    /// The file name as written in the `%include` that closes the cycle.
+    pub file: String,
+    /// Span of the file name that closes the cycle.
+    pub span: Span,
+    /// The files in the cycle, each with the span of the `%include` that included it. The first
+    /// file of the cycle may be the main file, in which case it has no span.
+    pub chain: Vec<(PathBuf, Option<Span>)>,
+}
+
+impl IncludeCycleError {
+    /// Constructs a new `IncludeCycleError`.
+    pub fn new<S>(file: &str, span: S, chain: Vec<(PathBuf, Option<Span>)>) -> Self
+    where
+        S: ToSpan,
+    {
+        IncludeCycleError {
+            file: file.to_string(),
+            span: span.to_span(),
+            chain,
+        }
+    }
+}
+
+impl Display for IncludeCycleError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "`{}` includes itself", self.file)
+    }
+}
+
+impl Error for IncludeCycleError {}
+
+impl ToDiagnostic for IncludeCycleError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let mut labels =
+            vec![Label::primary(file, self.span).with_message("this include closes the cycle")];
+        let mut notes = vec![];
+
+        for (path, included_at) in &self.chain {
+            if let Some(span) = included_at {
+                labels.push(
+                    Label::secondary(file, *span)
+                        .with_message(format!("includes {}", path.display()))
+                );
+            }
+            notes.push(format!("note: {}", path.display()));
+        }
+
+        Diagnostic::error().with_message(self.to_string())
+                           .with_labels(labels)
+                           .with_notes(notes)
+    }
+}
@@ -0,0 +1,80 @@
+//! The error returned by the public entry points of the crate.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io;
+
+use super::Errors;
+
+/// Error that ends the processing of a specification.
+#[derive(Debug)]
+pub enum LeskError {
+    /// A file could not be read or written.
+    Io {
+        /// The file being read or written.
+        path: String,
+        error: io::Error,
+    },
+    /// The specification has errors. Render them with `Specification::emit_diagnostics`, which
+    /// knows the source files the spans refer to.
+    Diagnostics(Errors),
+    /// A configuration, such as one given to `Options::from_toml`, is not valid.
+    Config {
+        /// What is wrong with it.
+        message: String,
+    },
+    /// With `--deterministic`, generating an artifact twice gave different output.
+    Nondeterministic {
+        /// The artifact, such as `code` or `tables`.
+        artifact: String,
+    },
+}
+
+impl LeskError {
+    /// Constructs a new `LeskError::Io`.
+    pub fn io<P: Into<String>>(path: P, error: io::Error) -> Self {
+        LeskError::Io {
+            path: path.into(),
+            error,
+        }
+    }
+
+    /// Constructs a new `LeskError::Config`.
+    pub fn config<M: Into<String>>(message: M) -> Self {
+        LeskError::Config {
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for LeskError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            LeskError::Io { path, error } => write!(fmt, "{}: {}", path, error),
+            LeskError::Diagnostics(errors) => write!(fmt, "{}", errors),
+            LeskError::Config { message } => write!(fmt, "{}", message),
+            LeskError::Nondeterministic { artifact } => write!(
+                fmt,
+                "the generated {} differs from one generation to the next, so it is not \
+                 reproducible",
+                artifact
+            ),
+        }
+    }
+}
+
+impl Error for LeskError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LeskError::Io { error, .. } => Some(error),
+            LeskError::Diagnostics(errors) => Some(errors),
+            LeskError::Config { .. } | LeskError::Nondeterministic { .. } => None,
+        }
+    }
+}
+
+impl From<Errors> for LeskError {
+    fn from(errors: Errors) -> Self {
+        LeskError::Diagnostics(errors)
+    }
+}
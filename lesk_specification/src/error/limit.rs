@@ -0,0 +1,20 @@
+//! The most errors an `Errors` stack holds, set from `--error-limit` and `--fail-fast`.
+//!
+//! A stack that holds as many errors as the limit drops any further ones, counting them instead,
+//! so that a badly broken specification does not bury its first error under thousands of others
+//! that follow from it. Warnings are not limited.
+
+use std::cell::Cell;
+
+thread_local! {
+    static ERROR_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Sets the most errors a stack holds, or `None` for no limit, the default.
+pub fn set_error_limit(limit: Option<usize>) {
+    ERROR_LIMIT.with(|l| l.set(limit));
+}
+
+pub fn error_limit() -> Option<usize> {
+    ERROR_LIMIT.with(|l| l.get())
+}
@@ -0,0 +1,63 @@
+//! Conflicting options error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when options that cannot be used together are both set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionConflictError {
+    /// Explanation of the conflict.
+    pub message: &'static str,
+    /// Spans of the `%option` items involved. Options given on the command line have no span.
+    pub spans: Vec<Span>,
+    /// Where the options without a span were set.
+    pub notes: Vec<String>,
+}
+
+impl OptionConflictError {
+    /// Constructs a new `OptionConflictError`.
+    pub fn new<S>(message: &'static str, spans: Vec<S>) -> Self
+    where
+        S: ToSpan,
+    {
+        OptionConflictError {
+            message,
+            spans: spans.iter().map(|s| s.to_span()).collect(),
+            notes: vec![],
+        }
+    }
+
+    /// Attaches notes, such as where the options involved were set.
+    pub fn with_notes(mut self, notes: Vec<String>) -> Self {
+        self.notes = notes;
+        self
+    }
+}
+
+impl Display for OptionConflictError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "conflicting options: {}", self.message)
+    }
+}
+
+impl Error for OptionConflictError {}
+
+impl ToDiagnostic for OptionConflictError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let labels = self.spans
+                         .iter()
+                         .map(|span| Label::primary(file, *span).with_message("set here"))
+                         .collect();
+
+        Diagnostic::error()
+            .with_message(self.to_string())
+            .with_labels(labels)
+            .with_notes(self.notes.clone())
+    }
+}
@@ -0,0 +1,100 @@
+//! The level at which each kind of warning is reported.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::WarningKind;
+
+/// How a warning is reported.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Level {
+    /// The warning is not reported.
+    Allow,
+    /// The warning is reported, and the scanner is generated regardless. The default.
+    Warn,
+    /// The warning is reported as an error, so that no scanner is generated.
+    Deny,
+}
+
+impl Level {
+    /// The name of the level, which is also that of its command line flag and `%option`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn  => "warn",
+            Level::Deny  => "deny",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}", self.name())
+    }
+}
+
+/// The group of all warnings, as in `--deny warnings`.
+pub const ALL_WARNINGS: &str = "warnings";
+
+/**
+The level of each kind of warning, keyed by diagnostic code. A warning is named by its code,
+`L0105`, or its name, `ignored-option`; `warnings` names them all, and a level given for a
+particular warning takes precedence over that of `warnings`. Codes from `L0201` on name the
+warnings of other crates, which are not known here.
+*/
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WarningPolicy {
+    levels: HashMap<String, Level>,
+}
+
+impl WarningPolicy {
+    /// Constructs a policy that reports every warning as a warning.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the level of the warning or warnings `name`, returning an error message if `name` does
+    /// not name any.
+    pub fn set(&mut self, name: &str, level: Level) -> Result<(), String> {
+        let code = warning_code(name).ok_or_else(|| format!("no warning named `{}`", name))?;
+        self.levels.insert(code, level);
+        Ok(())
+    }
+
+    /// Sets the level of each warning in the comma separated list `names`.
+    pub fn set_all(&mut self, names: &str, level: Level) -> Result<(), String> {
+        names.split(',')
+             .map(str::trim)
+             .filter(|name| !name.is_empty())
+             .try_for_each(|name| self.set(name, level))
+    }
+
+    /// The level of the warning with the code `code`.
+    pub fn level(&self, code: &str) -> Level {
+        self.levels
+            .get(code)
+            .or_else(|| self.levels.get(ALL_WARNINGS))
+            .copied()
+            .unwrap_or(Level::Warn)
+    }
+}
+
+/// The code of the warning `name`, normalizing a name to its code, or `ALL_WARNINGS` for itself.
+pub fn warning_code(name: &str) -> Option<String> {
+    if name.eq_ignore_ascii_case(ALL_WARNINGS) {
+        return Some(ALL_WARNINGS.to_string());
+    }
+    if let Some(kind) = WarningKind::ALL
+        .iter()
+        .find(|kind| kind.name() == name || kind.code().eq_ignore_ascii_case(name))
+    {
+        return Some(kind.code().to_string());
+    }
+
+    // The codes of other crates' diagnostics.
+    let digits = name.strip_prefix('L').or_else(|| name.strip_prefix('l'))?;
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) || digits < "0201" {
+        return None;
+    }
+    Some(format!("L{}", digits))
+}
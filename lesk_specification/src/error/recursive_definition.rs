@@ -0,0 +1,56 @@
+//! Recursive definition error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when a definition refers to itself, directly or through other definitions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecursiveDefinitionError {
+    /// The name of the definition that refers to itself.
+    pub name: String,
+    /// Spans of the `{NAME}` references forming the cycle, in the order they were expanded.
+    pub chain: Vec<Span>,
+}
+
+impl RecursiveDefinitionError {
+    /// Constructs a new `RecursiveDefinitionError`.
+    pub fn new<S>(name: &str, chain: Vec<S>) -> Self
+    where
+        S: ToSpan,
+    {
+        RecursiveDefinitionError {
+            name: name.to_string(),
+            chain: chain.iter().map(|s| s.to_span()).collect(),
+        }
+    }
+}
+
+impl Display for RecursiveDefinitionError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "definition `{}` refers to itself", self.name)
+    }
+}
+
+impl Error for RecursiveDefinitionError {}
+
+impl ToDiagnostic for RecursiveDefinitionError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let mut spans = self.chain.iter().rev();
+        let mut labels = vec![];
+
+        if let Some(last) = spans.next() {
+            labels.push(Label::primary(file, *last).with_message("recursive reference"));
+        }
+        for span in spans {
+            labels.push(Label::secondary(file, *span).with_message("expanded from here"));
+        }
+
+        Diagnostic::error().with_message(self.to_string()).with_labels(labels)
+    }
+}
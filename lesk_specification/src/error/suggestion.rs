@@ -0,0 +1,111 @@
+//! Machine-applicable suggestions attached to diagnostics.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+
+/// An edit to the source that fixes a diagnostic: `replacement` takes the place of the text at
+/// `span`, which is empty for an insertion. `lesk --fix` applies them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Suggestion {
+    /// Printable description of the edit, e.g. "insert `%}`".
+    pub message: String,
+    /// Span of the text that is replaced.
+    pub span: Span,
+    /// The text that replaces it.
+    pub replacement: String,
+}
+
+impl Suggestion {
+    /// Constructs a suggestion to replace the text at `span` with `replacement`.
+    pub fn replace(span: Span, replacement: &str) -> Self {
+        Suggestion {
+            message: format!("replace with `{}`", replacement.trim_end()),
+            span,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    /// Constructs a suggestion to insert `text` at `offset`.
+    pub fn insert(offset: usize, text: &str) -> Self {
+        let offset = offset as u32;
+        Suggestion {
+            message: format!("insert `{}`", text.trim_end()),
+            span: Span::new(offset, offset),
+            replacement: text.to_string(),
+        }
+    }
+}
+
+impl Display for Suggestion {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}", self.message)
+    }
+}
+
+/// Applies `suggestions` to `source`, returning the edited source and how many were applied. A
+/// suggestion that overlaps one before it, or falls outside `source`, is left out.
+pub fn apply_suggestions(source: &str, suggestions: &[Suggestion]) -> (String, usize) {
+    let mut sorted: Vec<&Suggestion> = suggestions.iter().collect();
+    sorted.sort_by_key(|s| (s.span.start(), s.span.end()));
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut copied = 0;
+    let mut applied = 0;
+
+    for suggestion in sorted {
+        let start = usize::from(suggestion.span.start());
+        let end = usize::from(suggestion.span.end());
+
+        if start < copied
+            || end > source.len()
+            || !source.is_char_boundary(start)
+            || !source.is_char_boundary(end)
+        {
+            continue;
+        }
+
+        fixed.push_str(&source[copied..start]);
+        fixed.push_str(&suggestion.replacement);
+        copied = end;
+        applied += 1;
+    }
+    fixed.push_str(&source[copied..]);
+
+    (fixed, applied)
+}
+
+/// The candidate nearest to `name`, ignoring case, if any is close enough to be a likely typo.
+pub fn closest<'c, I>(name: &str, candidates: I) -> Option<&'c str>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    let threshold = std::cmp::max(1, name.len() / 3);
+    let name = name.to_lowercase();
+
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(&name, &c.to_lowercase()), c))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
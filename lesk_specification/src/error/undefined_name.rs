@@ -0,0 +1,96 @@
+//! Undefined name error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{closest, Suggestion, ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when a name is referenced but never defined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UndefinedNameError {
+    /// Printable description of what kind of name was referenced, e.g. "definition".
+    pub kind: &'static str,
+    /// The referenced name.
+    pub name: String,
+    /// Span of the reference.
+    pub span: Span,
+    /// The names that are defined, if it is helpful to list them.
+    pub candidates: Vec<String>,
+}
+
+impl UndefinedNameError {
+    /// Constructs a new `UndefinedNameError`.
+    pub fn new<S>(kind: &'static str, name: &str, span: S) -> Self
+    where
+        S: ToSpan,
+    {
+        UndefinedNameError {
+            kind,
+            name: name.to_string(),
+            span: span.to_span(),
+            candidates: vec![],
+        }
+    }
+
+    /// Lists the names that are defined, so the diagnostic can list them and suggest the
+    /// nearest one.
+    pub fn with_candidates<I, T>(mut self, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        self.candidates = candidates.into_iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// The candidate nearest to the undefined name, if any is close enough to be a likely typo.
+    pub fn suggestion(&self) -> Option<&str> {
+        closest(&self.name, self.candidates.iter().map(String::as_str))
+    }
+}
+
+impl Display for UndefinedNameError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "undefined {} `{}`", self.kind, self.name)
+    }
+}
+
+impl Error for UndefinedNameError {}
+
+impl ToDiagnostic for UndefinedNameError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let label = Label::primary(file, self.span)
+                        .with_message(format!("no {} named `{}`", self.kind, self.name));
+        let mut notes = vec![];
+
+        if let Some(suggestion) = self.suggestion() {
+            notes.push(format!("help: did you mean `{}`?", suggestion));
+        }
+        if !self.candidates.is_empty() {
+            notes.push(format!("note: declared: {}", self.candidates.join(", ")));
+        }
+
+        Diagnostic::error().with_message(self.to_string())
+                           .with_labels(vec![label])
+                           .with_notes(notes)
+    }
+
+    fn suggestions(&self) -> Vec<Suggestion> {
+        // The span of a reference can include more than the name, as the `-` of `<-NAME>` does.
+        let end = usize::from(self.span.end());
+        let start = std::cmp::max(
+            usize::from(self.span.start()),
+            end.saturating_sub(self.name.len()),
+        );
+        let span = Span::new(start as u32, end as u32);
+
+        self.suggestion()
+            .map(|suggestion| Suggestion::replace(span, suggestion))
+            .into_iter()
+            .collect()
+    }
+}
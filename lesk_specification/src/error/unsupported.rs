@@ -0,0 +1,53 @@
+//! Unsupported feature error data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// Error that occurs when a specification uses a flex feature Lesk cannot generate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnsupportedError {
+    /// The feature, as written in the specification.
+    pub feature: &'static str,
+    /// Span of the use of the feature.
+    pub span: Span,
+    /// How to do without the feature.
+    pub help: &'static str,
+}
+
+impl UnsupportedError {
+    /// Constructs a new `UnsupportedError`.
+    pub fn new<S>(feature: &'static str, span: S, help: &'static str) -> Self
+    where
+        S: ToSpan,
+    {
+        UnsupportedError {
+            feature,
+            span: span.to_span(),
+            help,
+        }
+    }
+}
+
+impl Display for UnsupportedError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "`{}` is not supported", self.feature)
+    }
+}
+
+impl Error for UnsupportedError {}
+
+impl ToDiagnostic for UnsupportedError {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let label = Label::primary(file, self.span).with_message("used here");
+
+        Diagnostic::error().with_message(self.to_string())
+                           .with_labels(vec![label])
+                           .with_notes(vec![format!("help: {}", self.help)])
+    }
+}
@@ -0,0 +1,129 @@
+//! Warning data structure.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use codespan::Span;
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+use super::{ToDiagnostic, SourceID};
+use crate::parser::ToSpan;
+
+/// The kinds of warnings. Each has a stable name used to refer to it on the command line.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WarningKind {
+    /// A definition that no rule or other definition refers to.
+    UnusedDefinition,
+    /// An exclusive start condition in which no rule is active.
+    UnusedCondition,
+    /// A rule whose pattern matches the empty string.
+    EmptyMatch,
+    /// An option set more than once, so that the earlier setting has no effect.
+    OverriddenOption,
+    /// A flex option or directive that Lesk accepts but ignores.
+    IgnoredOption,
+    /// Code between rules, which is moved to the top of the scanner routine.
+    MovedCode,
+    /// A rule whose text and trailing context are both of variable length.
+    VariableContext,
+}
+
+impl WarningKind {
+    /// Every kind of warning, in order of code.
+    pub const ALL: [WarningKind; 7] = [
+        WarningKind::UnusedDefinition,
+        WarningKind::UnusedCondition,
+        WarningKind::EmptyMatch,
+        WarningKind::OverriddenOption,
+        WarningKind::IgnoredOption,
+        WarningKind::MovedCode,
+        WarningKind::VariableContext,
+    ];
+
+    /// The stable name of the warning.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedDefinition => "unused-definition",
+            WarningKind::UnusedCondition  => "unused-condition",
+            WarningKind::EmptyMatch       => "empty-match",
+            WarningKind::OverriddenOption => "overridden-option",
+            WarningKind::IgnoredOption    => "ignored-option",
+            WarningKind::MovedCode        => "moved-code",
+            WarningKind::VariableContext  => "variable-context",
+        }
+    }
+
+    /// The stable code of the warning. See `codes`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedDefinition => "L0101",
+            WarningKind::UnusedCondition  => "L0102",
+            WarningKind::EmptyMatch       => "L0103",
+            WarningKind::OverriddenOption => "L0104",
+            WarningKind::IgnoredOption    => "L0105",
+            WarningKind::MovedCode        => "L0106",
+            WarningKind::VariableContext  => "L0107",
+        }
+    }
+}
+
+/// A problem that does not prevent generating a scanner but probably indicates a mistake.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// Span of the offending item.
+    pub span: Span,
+    /// Span of a second item involved, with a description of its role.
+    pub related: Option<(Span, &'static str)>,
+}
+
+impl Warning {
+    /// Constructs a new `Warning`.
+    pub fn new<S>(kind: WarningKind, message: String, span: S) -> Self
+    where
+        S: ToSpan,
+    {
+        Warning {
+            kind,
+            message,
+            span: span.to_span(),
+            related: None,
+        }
+    }
+
+    /// Attaches a second span to the warning.
+    pub fn with_related<S>(mut self, span: S, message: &'static str) -> Self
+    where
+        S: ToSpan,
+    {
+        self.related = Some((span.to_span(), message));
+        self
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}", self.message)
+    }
+}
+
+impl Error for Warning {}
+
+impl ToDiagnostic for Warning {
+    fn to_diagnostic(&self, file: SourceID) -> Diagnostic<SourceID> {
+        let mut labels = vec![Label::primary(file, self.span)];
+
+        if let Some((span, message)) = self.related {
+            labels.push(Label::secondary(file, span).with_message(message));
+        }
+
+        Diagnostic::warning().with_message(self.to_string())
+                             .with_labels(labels)
+                             .with_notes(vec![format!(
+                                 "note: `{}` is on by default; `--allow {}` turns it off",
+                                 self.kind.name(),
+                                 self.kind.code()
+                             )])
+    }
+}
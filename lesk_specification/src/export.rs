@@ -0,0 +1,197 @@
+/*!
+
+Writes a parsed specification as a flex specification, for `lesk --export-flex FILE`.
+
+Constructs that flex lacks are lowered to ones it has:
+
+  * a reference to a definition with parameters is replaced by its expansion, and the
+    definition itself is left out,
+  * a start condition list with exclusions, `<-COMMENT>`, is replaced by the list of conditions
+    it denotes,
+  * pattern options set inside a start condition scope are written as a modifier group, `(?i:…)`,
+    around each of the scope's rules,
+  * the items of an `%include`d file are written in place of the `%include`, and
+  * only the branch of an `%if` selected by the `--define`d features is written, as the parser
+    has already chosen it.
+
+Options flex does not know, `%token` declarations, and `%class` and `%init` blocks have no flex
+equivalent and are replaced by a comment saying so.
+
+*/
+
+use crate::definitions::Definitions;
+use crate::error::Errors;
+use crate::format::{action_text, push_code};
+use crate::pattern::with_modifiers;
+use crate::section_items::{flatten_includes, Item, ItemType};
+use crate::Span;
+
+
+/// The options flex understands, without a `no` prefix or a value.
+static FLEX_OPTIONS: &[&str] = &[
+  "7bit", "8bit", "align", "always-interactive", "array", "backup", "batch", "bison-bridge",
+  "bison-locations", "c++", "case-insensitive", "case-sensitive", "caseful", "caseless", "debug",
+  "default", "ecs", "extra-type", "fast", "full", "header-file", "input", "interactive",
+  "lex-compat", "line", "main", "meta-ecs", "never-interactive", "outfile", "perf-report",
+  "pointer", "posix-compat", "prefix", "read", "reentrant", "reject", "stack", "stdinit",
+  "stdout", "tables-file", "tables-verify", "unistd", "unput", "verbose", "warn", "yyclass",
+  "yylineno", "yymore", "yywrap",
+];
+
+pub fn to_flex(section_one: &[Item], section_two: &[Item], user_code: &str)
+    -> Result<String, Errors>
+{
+  let section_one = flatten_includes(section_one.iter().cloned());
+  let section_two = flatten_includes(section_two.iter().cloned());
+  let definitions = Definitions::from_items(&section_one);
+
+  let mut conditions = vec!["INITIAL"];
+  conditions.extend(section_one.iter().filter_map(|item| match item {
+    Item::State { name, .. } => Some(*name.fragment()),
+    _ => None
+  }));
+
+  let mut out    = String::new();
+  let mut errors = Errors::new();
+
+  for item in &section_one {
+    match item {
+      Item::Definition { params, .. } if !params.is_empty() => { /* Expanded at each use. */ }
+
+      Item::Definition { name, code, .. } => {
+        match definitions.expand_parameterized(*code) {
+          Ok(regex) => out.push_str(&format!("{}  {}\n", name.fragment(), regex)),
+          Err(e)    => errors.merge(e),
+        }
+      }
+
+      Item::State { is_exclusive, name } => {
+        out.push_str(&format!("%{} {}\n", if *is_exclusive { "x" } else { "s" }, name.fragment()));
+      }
+
+      Item::Option(_, text) if text.fragment().starts_with('%') => {
+        out.push_str(&format!("{}\n", text.fragment()));
+      }
+
+      Item::Option(_, text) => {
+        if FLEX_OPTIONS.contains(&option_name(text.fragment())) {
+          out.push_str(&format!("%option {}\n", text.fragment()));
+        } else {
+          out.push_str(&format!("/* lesk: %option {} has no flex equivalent */\n", text.fragment()));
+        }
+      }
+
+      Item::Token { name, .. } => {
+        out.push_str(&format!("/* lesk: %token {} has no flex equivalent */\n", name.fragment()));
+      }
+
+      Item::Class(_) | Item::Init(_) => {
+        out.push_str(&format!(
+          "/* lesk: a {} block has no flex equivalent */\n",
+          item.open_delimiter().trim_end_matches('{')
+        ));
+      }
+
+      Item::Comment(text) => {
+        out.push_str(text.fragment());
+        out.push('\n');
+      }
+
+      Item::Top(code) | Item::User(code) => push_code(&mut out, item.item_type(), code),
+
+      Item::Unknown(code) => {
+        out.push_str(code.fragment().trim_end());
+        out.push('\n');
+      }
+
+      | Item::Include { .. }
+      | Item::ScannerTop(_)
+      | Item::Rule { .. } => { /* Not in section one after flattening */ }
+    }
+  }
+
+  out.push_str("%%\n");
+
+  for item in &section_two {
+    match item {
+      Item::Rule { conditions: rule_conditions, pattern, context, modifiers, action } => {
+        let mut rule = String::new();
+
+        let rule_conditions = lower_conditions(rule_conditions, &conditions);
+        if !rule_conditions.is_empty() {
+          rule.push_str(&format!("<{}>", rule_conditions.join(",")));
+        }
+
+        match definitions.expand_parameterized(*pattern) {
+          Ok(regex) => rule.push_str(&with_modifiers(&regex, modifiers)),
+          Err(e)    => errors.merge(e),
+        }
+        if let Some(context) = context {
+          match definitions.expand_parameterized(*context) {
+            Ok(regex) => rule.push_str(&format!("/{}", with_modifiers(&regex, modifiers))),
+            Err(e)    => errors.merge(e),
+          }
+        }
+
+        match action_text(action) {
+          Some(action) => out.push_str(&format!("{}  {}\n", rule, action)),
+          None         => out.push_str(&format!("{}\n", rule)),
+        }
+      }
+
+      Item::ScannerTop(code) => push_code(&mut out, ItemType::ScannerTop, code),
+
+      Item::Comment(text) => {
+        out.push_str("  ");
+        out.push_str(text.fragment());
+        out.push('\n');
+      }
+
+      _ => { /* Only rules, code, and comments appear in section two. */ }
+    }
+  }
+
+  if !user_code.trim().is_empty() {
+    out.push_str("%%\n");
+    out.push_str(user_code);
+  }
+
+  if errors.has_errors() {
+    Err(errors)
+  } else {
+    Ok(out)
+  }
+}
+
+/// `nodefault` → `default`, `outfile="lex.c"` → `outfile`
+fn option_name(text: &str) -> &str {
+  let name = text.split('=').next().unwrap_or("").trim();
+
+  if !FLEX_OPTIONS.contains(&name) && name.starts_with("no") {
+    &name[2..]
+  } else {
+    name
+  }
+}
+
+/**
+Flex has `<*>` but no exclusions, so a list containing `-NAME` is replaced by the conditions it
+denotes. A list of exclusions alone excludes from every condition.
+*/
+fn lower_conditions<'s>(conditions: &[Span<'s>], all: &[&'s str]) -> Vec<&'s str> {
+  let names: Vec<&str> = conditions.iter().map(|c| *c.fragment()).collect();
+
+  if !names.iter().any(|name| name.starts_with('-')) {
+    return names;
+  }
+
+  let excluded: Vec<&str> = names.iter().filter_map(|name| name.strip_prefix('-')).collect();
+  let included: Vec<&str> =
+      if names.iter().all(|name| name.starts_with('-')) || names.contains(&"*") {
+        all.to_vec()
+      } else {
+        names.iter().filter(|name| !name.starts_with('-')).copied().collect()
+      };
+
+  included.into_iter().filter(|name| !excluded.contains(name)).collect()
+}
@@ -0,0 +1,250 @@
+/*!
+
+Prints a parsed specification back out in a canonical layout for `lesk --fmt`.
+
+The printer works from the items the parser produces, so anything the parser does not keep, such
+as blank lines, is regenerated: a blank line separates runs of different kinds of items, and a
+comment stays attached to the item below it. Definitions and rule actions are aligned in columns,
+each option gets its own `%option` line, and consecutive `%state` or `%xstate` declarations are
+merged into one sorted list. Rules written inside a start condition scope are printed one by one
+with the scope's conditions spelled out and its pattern options written as a modifier group,
+`(?i:…)`, and of an `%if` section only the branch selected by the `--define`d features is printed.
+
+*/
+
+use crate::options::OptionField;
+use crate::pattern::with_modifiers;
+use crate::section_items::{Action, Item, ItemType};
+use crate::Span;
+
+
+/// A pattern longer than this does not push the action column of every other rule to the right.
+const MAX_ACTION_COLUMN: usize = 32;
+/// Comments in section two must be indented, as otherwise they would be read as patterns.
+const INDENT: &str = "  ";
+
+
+/// Formats the items of sections one and two followed by the verbatim user code of section three.
+pub fn format(section_one: &[Item], section_two: &[Item], user_code: &str) -> String {
+  let mut out = String::new();
+
+  format_items(&mut out, section_one, "");
+
+  if !out.is_empty() {
+    out.push('\n');
+  }
+  out.push_str("%%\n");
+
+  if !section_two.is_empty() {
+    out.push('\n');
+    format_items(&mut out, section_two, INDENT);
+  }
+
+  if !user_code.trim().is_empty() {
+    out.push_str("\n%%\n");
+    out.push_str(user_code);
+  }
+
+  out
+}
+
+fn format_items(out: &mut String, items: &[Item], comment_indent: &str) {
+  let name_width = items.iter().filter_map(|item| match item {
+    Item::Definition { name, params, .. } => Some(definition_head(name, params).len()),
+    _ => None
+  }).max().unwrap_or(0);
+
+  let pattern_width = items.iter().filter_map(|item| match item {
+    Item::Rule { conditions, pattern, context, modifiers, .. } => {
+      Some(rule_pattern(conditions, pattern, context, modifiers).len())
+    }
+    _ => None
+  }).max().unwrap_or(0).min(MAX_ACTION_COLUMN);
+
+  let mut previous: Option<ItemType> = None;
+  let mut idx = 0;
+
+  while let Some(item) = items.get(idx) {
+    separate(out, &mut previous, item.item_type());
+    // The number of items printed by this iteration
+    let mut consumed = 1;
+
+    match item {
+      Item::Comment(text) => {
+        out.push_str(comment_indent);
+        out.push_str(text.fragment());
+        out.push('\n');
+      }
+
+      Item::Definition { name, params, code } => {
+        out.push_str(&format!(
+          "{:width$}  {}\n",
+          definition_head(name, params),
+          code.fragment().trim_end(),
+          width = name_width
+        ));
+      }
+
+      // A flex directive, `%array`, rather than an option
+      Item::Option(_, text) if text.fragment().starts_with('%') => {
+        out.push_str(&format!("{}\n", text.fragment()));
+      }
+      Item::Option(_, text) => {
+        out.push_str(&format!("%option {}\n", text.fragment()));
+      }
+
+      Item::State { is_exclusive, .. } => {
+        let mut names: Vec<&str> = items[idx..]
+            .iter()
+            .take_while(|next| match next {
+              Item::State { is_exclusive: e, .. } => e == is_exclusive,
+              _ => false
+            })
+            .map(|next| *next.code_span().fragment())
+            .collect();
+        consumed = names.len();
+
+        names.sort();
+        names.dedup();
+        let keyword = if *is_exclusive { "%xstate" } else { "%state" };
+        out.push_str(&format!("{} {}\n", keyword, names.join(" ")));
+      }
+
+      Item::Token { .. } => {
+        let tokens: Vec<String> = items[idx..]
+            .iter()
+            .take_while(|next| next.item_type() == ItemType::Token)
+            .filter_map(|next| match next {
+              Item::Token { name, payload: Some(payload) } => {
+                Some(format!("{}({})", name.fragment(), payload.fragment()))
+              }
+              Item::Token { name, payload: None } => Some(name.fragment().to_string()),
+              _ => None
+            })
+            .collect();
+        consumed = tokens.len();
+
+        out.push_str(&format!("%token {}\n", tokens.join(" ")));
+      }
+
+      Item::Include { file, .. } => {
+        out.push_str(&format!("%include {}\n", file.fragment()));
+      }
+
+      | Item::User(code)
+      | Item::ScannerTop(code)
+      | Item::Top(code)
+      | Item::Class(code)
+      | Item::Init(code) => {
+        push_code(out, item.item_type(), code);
+      }
+
+      Item::Unknown(code) => {
+        out.push_str(code.fragment().trim_end());
+        out.push('\n');
+      }
+
+      Item::Rule { conditions, pattern, context, modifiers, action } => {
+        let pattern = rule_pattern(conditions, pattern, context, modifiers);
+
+        match action_text(action) {
+          Some(action) => {
+            out.push_str(&format!("{:width$}  {}\n", pattern, action, width = pattern_width));
+          }
+          None => {
+            out.push_str(&pattern);
+            out.push('\n');
+          }
+        }
+      }
+    }
+
+    idx += consumed;
+  }
+}
+
+/// Starts a new paragraph when the kind of item changes. A comment belongs to the item after it.
+fn separate(out: &mut String, previous: &mut Option<ItemType>, next: ItemType) {
+  if let Some(previous) = *previous {
+    if previous != next && previous != ItemType::Comment {
+      out.push('\n');
+    }
+  }
+  *previous = Some(next);
+}
+
+/**
+Code spans do not include their delimiters, so they are put back. Indented code has no delimiters
+and is printed as it was written.
+*/
+pub fn push_code(out: &mut String, item_type: ItemType, code: &Span) {
+  let text = code.fragment();
+  let indented = text.starts_with([' ', '\t'])
+      && (item_type == ItemType::User || item_type == ItemType::ScannerTop);
+
+  if !indented {
+    out.push_str(item_type.open_delimiter());
+  }
+  out.push_str(text);
+  if !text.ends_with('\n') {
+    out.push('\n');
+  }
+  if !indented {
+    out.push_str(item_type.close_delimiter());
+    out.push('\n');
+  }
+}
+
+/// `NAME` or `NAME(a,b)`
+fn definition_head(name: &Span, params: &[Span]) -> String {
+  if params.is_empty() {
+    return name.fragment().to_string();
+  }
+
+  let params: Vec<&str> = params.iter().map(|p| *p.fragment()).collect();
+  format!("{}({})", name.fragment(), params.join(","))
+}
+
+/// `<COND1,COND2>pattern/context`, or `<COND>(?i:pattern)/(?i:context)` for a rule with scoped options
+fn rule_pattern(
+  conditions: &[Span],
+  pattern: &Span,
+  context: &Option<Span>,
+  modifiers: &[OptionField]
+) -> String {
+  let mut text = String::new();
+
+  if !conditions.is_empty() {
+    let names: Vec<&str> = conditions.iter().map(|c| *c.fragment()).collect();
+    text.push('<');
+    text.push_str(&names.join(","));
+    text.push('>');
+  }
+
+  text.push_str(&with_modifiers(pattern.fragment(), modifiers));
+
+  if let Some(context) = context {
+    text.push('/');
+    text.push_str(&with_modifiers(context.fragment(), modifiers));
+  }
+
+  text
+}
+
+/// The action as it should be printed after the pattern, or `None` for a rule with no action.
+pub fn action_text(action: &Action) -> Option<String> {
+  match action {
+    Action::Empty => None,
+    Action::FallThrough(_) => Some("|".to_string()),
+    Action::Code(code) => {
+      let text = code.fragment().trim_end();
+
+      // A `{ … }` action keeps its braces, but a `%{ … %}` action loses its delimiters.
+      if text.contains('\n') && !text.starts_with('{') {
+        Some(format!("%{{{}\n%}}", text))
+      } else {
+        Some(text.to_string())
+      }
+    }
+  }
+}
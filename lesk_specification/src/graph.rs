@@ -0,0 +1,80 @@
+/*!
+
+The start condition graph of a specification in Graphviz DOT format: a node for each start
+condition, and an edge for each rule whose action switches start condition, labeled with the
+rule's pattern.
+
+The switches are found by looking for `BEGIN`, `begin`, `push_state`, and `yy_push_state` applied
+to a declared start condition in the action code. Switches computed at runtime and returns by
+`pop_state` cannot be known from the specification and are not shown.
+
+*/
+
+use std::fmt::Write;
+
+use crate::mode::{ModeID, Modes};
+use crate::specification::identifier_offsets;
+
+
+const SWITCHES: [&str; 4] = ["BEGIN", "begin", "push_state", "yy_push_state"];
+
+/// The start condition graph of `modes` in DOT format.
+pub fn condition_graph(modes: &Modes) -> String {
+  let mut dot = String::from("digraph conditions {\n");
+
+  for mode in &modes.modes {
+    let shape = if mode.exclusive { "box" } else { "ellipse" };
+    writeln!(dot, "  \"{}\" [shape={}];", mode.name(), shape).unwrap();
+  }
+
+  for (mode_id, mode) in modes.modes.iter().enumerate() {
+    let rule_ids = modes.mode_rules.get(&(mode_id as ModeID)).cloned().unwrap_or_default();
+
+    for rule_id in rule_ids {
+      let rule   = &modes.rules[rule_id];
+      let action = match rule.action() {
+        Some(action_id) => modes.actions[action_id].fragment(),
+        None            => continue,
+      };
+
+      for target in switch_targets(action) {
+        if modes.mode_id(target).is_some() {
+          writeln!(
+            dot,
+            "  \"{}\" -> \"{}\" [label=\"{}\"];",
+            mode.name(),
+            target,
+            escape(rule.span().fragment())
+          ).unwrap();
+        }
+      }
+    }
+  }
+
+  dot.push_str("}\n");
+  dot
+}
+
+/// The names the start condition switches in `code` switch to, in order.
+fn switch_targets(code: &str) -> Vec<&str> {
+  let mut targets = vec![];
+
+  for switch in SWITCHES.iter() {
+    for offset in identifier_offsets(code, switch) {
+      // Both `BEGIN(NAME)` and flex's `BEGIN NAME`.
+      let rest = code[offset + switch.len()..].trim_start();
+      let rest = rest.strip_prefix('(').unwrap_or(rest).trim_start();
+      let end  = rest.find(|c: char| !(c == '_' || c.is_ascii_alphanumeric())).unwrap_or(rest.len());
+      if end > 0 {
+        targets.push((offset, &rest[..end]));
+      }
+    }
+  }
+
+  targets.sort();
+  targets.into_iter().map(|(_, target)| target).collect()
+}
+
+fn escape(text: &str) -> String {
+  text.replace('\\', "\\\\").replace('"', "\\\"")
+}
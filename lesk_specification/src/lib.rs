@@ -1,5 +1,3 @@
-#![feature(iterator_fold_self)]
-#![feature(drain_filter)]
 /*!
 
 Utilities to parse a lexer specification file and create an abstract representation of the files
@@ -16,33 +14,53 @@ mod parser;
 mod section_items;
 mod mergable;
 mod mode;
+mod engine;
+mod definitions;
+mod pattern;
+mod lint;
+mod format;
+mod export;
+mod stats;
+mod graph;
+mod timings;
+pub mod ast;
+pub mod sink;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 
 use std::collections::{HashMap, HashSet};
 
-pub use options::Options;
-pub use specification::Specification;
+pub use error::{
+  explain, CustomDiagnostic, CustomError, Error, Errors, Explanation, LeskError, Level, Suggestion,
+  ToDiagnostic, WarningPolicy
+};
+pub use options::{
+  ColorWhen, Emit, MessageFormat, Options, OptionField, OptionSource, OptionsBuilder, Provenance,
+  Subsystem, TimingsFormat
+};
+pub use specification::{Specification, SpecificationBuilder};
+pub use mode::ConditionPattern;
+pub use engine::{
+  regex_engine, RegexAutomataEngine, RegexEngine, ReleskEngine, DEFAULT_ENGINE, ENGINE_NAMES
+};
+pub use stats::{ConditionStatistics, Statistics};
+pub use timings::Timings;
 pub use crate::parser::source::Span;
+pub use codespan_reporting::term::termcolor;
 use crate::parser::source::{SourceFiles, SourceID};
 
 
 type Code<'s>       = Vec<Span<'s>>;             //< Collection of ordered lines of code
 type CodeMap<'s>    = HashMap<Start, Code<'s>>;  //< Map of start conditions to lines of code
-type Dictionary<'s> = HashMap<String, &'s str>;  //< Dictionary (const char*)
-type Rules<'a>      = Vec<Rule<'a>>;             //< Collection of ordered rules
 type RulesMap<'a>   = HashMap<Start, Rule<'a>>;  //< Map of start conditions to rules
 type Start          = usize;                     //< Start condition state type
 type Starts         = HashSet<Start>;            //< Set of start conditions
-type StrMap<'s>     = HashMap<&'s str, &'s str>; //< Dictionary (std::string)
 type StrVec<'s>     = Vec<&'s str>;              //< Collection of ordered strings
 
 
 
-// todo: Write the RegexEngine struct
-#[derive(Default)]
-struct RegexEngine;
-
-
 /// A regex pattern and action pair that forms a rule
+#[allow(dead_code)]
 struct Rule<'a> {
   pattern : Span<'a>, //< the pattern
   // regex   : String,   //< the pattern-converted regex for the selected regex engine
@@ -0,0 +1,329 @@
+/*!
+
+Checks over the parsed items of a specification that produce warnings: things that are allowed
+but are probably mistakes.
+
+*/
+
+use std::collections::HashSet;
+use std::mem::discriminant;
+
+use crate::definitions::references;
+use crate::error::{Errors, Warning, WarningKind};
+use crate::options::OptionField;
+use crate::section_items::Item;
+
+
+/// Runs every check over `items`, returning the warnings found.
+pub fn lint(items: &[Item]) -> Errors {
+  let mut warnings = Errors::new();
+
+  unused_definitions(items, &mut warnings);
+  unused_conditions(items, &mut warnings);
+  empty_matches(items, &mut warnings);
+  overridden_options(items, &mut warnings);
+  ignored_options(items, &mut warnings);
+  moved_code(items, &mut warnings);
+  variable_contexts(items, &mut warnings);
+
+  warnings
+}
+
+fn unused_definitions(items: &[Item], warnings: &mut Errors) {
+  let mut used: HashSet<&str> = HashSet::new();
+
+  for item in items {
+    match item {
+      Item::Rule { pattern, context, .. } => {
+        used.extend(references(pattern.fragment()).into_iter().map(|(_, name)| name));
+        if let Some(context) = context {
+          used.extend(references(context.fragment()).into_iter().map(|(_, name)| name));
+        }
+      }
+      Item::Definition { code, .. } => {
+        used.extend(references(code.fragment()).into_iter().map(|(_, name)| name));
+      }
+      _ => { /* pass */ }
+    }
+  }
+
+  for item in items {
+    if let Item::Definition { name, .. } = item {
+      if !used.contains(name.fragment()) {
+        warnings.push(Warning::new(
+          WarningKind::UnusedDefinition,
+          format!("definition `{}` is never used", name.fragment()),
+          *name
+        ));
+      }
+    }
+  }
+}
+
+/// An inclusive condition is never unused, as every rule without a condition list is active in
+/// it. An exclusive condition without rules can never match anything.
+fn unused_conditions(items: &[Item], warnings: &mut Errors) {
+  let mut used: HashSet<&str> = HashSet::new();
+
+  for item in items {
+    if let Item::Rule { conditions, .. } = item {
+      for condition in conditions {
+        let name = condition.fragment();
+        if *name == "*" {
+          // Every condition is used.
+          return;
+        }
+        if !name.starts_with('-') {
+          used.insert(name);
+        }
+      }
+    }
+  }
+
+  for item in items {
+    if let Item::State { is_exclusive: true, name } = item {
+      if !used.contains(name.fragment()) {
+        warnings.push(Warning::new(
+          WarningKind::UnusedCondition,
+          format!("exclusive start condition `{}` has no rules", name.fragment()),
+          *name
+        ));
+      }
+    }
+  }
+}
+
+fn empty_matches(items: &[Item], warnings: &mut Errors) {
+  for item in items {
+    if let Item::Rule { pattern, .. } = item {
+      if matches_empty(pattern.fragment()) {
+        warnings.push(Warning::new(
+          WarningKind::EmptyMatch,
+          format!("pattern `{}` matches the empty string", pattern.fragment()),
+          *pattern
+        ));
+      }
+    }
+  }
+}
+
+fn overridden_options(items: &[Item], warnings: &mut Errors) {
+  let options: Vec<_> = items.iter().filter_map(|item| match item {
+    // Ignored options cannot override each other, and the levels of warnings accumulate.
+    | Item::Option(OptionField::Legacy(_), _)
+    | Item::Option(OptionField::Allow(_), _)
+    | Item::Option(OptionField::Warnings(_), _)
+    | Item::Option(OptionField::Deny(_), _) => None,
+    Item::Option(field, span) => Some((field, span)),
+    _ => None
+  }).collect();
+
+  for (n, (field, span)) in options.iter().enumerate() {
+    let later = options[n + 1..]
+        .iter()
+        .find(|(other, _)| discriminant(*other) == discriminant(*field));
+
+    if let Some((other, other_span)) = later {
+      if other != field {
+        warnings.push(
+          Warning::new(
+            WarningKind::OverriddenOption,
+            format!("`{}` is overridden by a later `%option`", span.fragment().trim()),
+            **span
+          ).with_related(**other_span, "overridden here")
+        );
+      }
+    }
+  }
+}
+
+fn ignored_options(items: &[Item], warnings: &mut Errors) {
+  for item in items {
+    if let Item::Option(OptionField::Legacy(_), span) = item {
+      warnings.push(Warning::new(
+        WarningKind::IgnoredOption,
+        format!("`{}` has no effect in Lesk and is ignored", span.fragment().trim()),
+        *span
+      ));
+    }
+  }
+}
+
+/// Code after the first rule, which the scanner runs with the code before the first rule.
+fn moved_code(items: &[Item], warnings: &mut Errors) {
+  let first_rule = items.iter().position(|item| matches!(item, Item::Rule { .. }));
+
+  for item in items.iter().skip(first_rule.map_or(items.len(), |rule| rule + 1)) {
+    if let Item::ScannerTop(code) = item {
+      warnings.push(Warning::new(
+        WarningKind::MovedCode,
+        "code between rules is moved to the top of the scanner routine".to_string(),
+        *code
+      ));
+    }
+  }
+}
+
+/// A rule whose text and trailing context both vary in length, so that where one ends is ambiguous.
+fn variable_contexts(items: &[Item], warnings: &mut Errors) {
+  for item in items {
+    if let Item::Rule { pattern, context: Some(context), .. } = item {
+      if is_variable_length(pattern.fragment()) && is_variable_length(context.fragment()) {
+        warnings.push(Warning::new(
+          WarningKind::VariableContext,
+          format!(
+            "rule `{}/{}` has variable-length text and trailing context, which may match \
+            incorrectly",
+            pattern.fragment(),
+            context.fragment()
+          ),
+          *pattern
+        ).with_related(*context, "trailing context"));
+      }
+    }
+  }
+}
+
+/**
+Conservatively determines whether `pattern` matches the empty string: some top-level alternative
+consists only of atoms that are optional. `{NAME}` references are assumed to be nonempty.
+*/
+fn matches_empty(pattern: &str) -> bool {
+  split_top_level(pattern, '|').iter().any(|alternative| {
+    let atoms = atoms(alternative);
+    !atoms.is_empty() && atoms.iter().all(|(atom, quantifier)| {
+      match quantifier.chars().next() {
+        Some('*') | Some('?') => true,
+        Some('{') => quantifier[1..].starts_with('0') || quantifier[1..].starts_with(','),
+        _ => atom.starts_with('(') && matches_empty(&atom[1..atom.len() - 1]),
+      }
+    })
+  })
+}
+
+/// Splits `pattern` at each occurrence of `separator` outside of quotes, brackets, and parentheses.
+fn split_top_level(pattern: &str, separator: char) -> Vec<&str> {
+  let mut parts       = vec![];
+  let mut start       = 0;
+  let mut depth       = 0usize;
+  let mut in_quotes   = false;
+  let mut in_brackets = false;
+  let mut escaped     = false;
+
+  for (idx, c) in pattern.char_indices() {
+    if escaped {
+      escaped = false;
+      continue;
+    }
+
+    match c {
+      '\\'                => escaped = true,
+      '"' if !in_brackets => in_quotes = !in_quotes,
+      '[' if !in_quotes   => in_brackets = true,
+      ']' if in_brackets  => in_brackets = false,
+      '(' if !in_quotes && !in_brackets => depth += 1,
+      ')' if !in_quotes && !in_brackets => depth = depth.saturating_sub(1),
+      c if c == separator && !in_quotes && !in_brackets && depth == 0 => {
+        parts.push(&pattern[start..idx]);
+        start = idx + c.len_utf8();
+      }
+      _ => { /* pass */ }
+    }
+  }
+
+  parts.push(&pattern[start..]);
+  parts
+}
+
+/// Splits a concatenation into its atoms, each paired with the quantifier that follows it, if any.
+fn atoms(pattern: &str) -> Vec<(&str, &str)> {
+  let bytes     = pattern.as_bytes();
+  let mut atoms = vec![];
+  let mut idx   = 0;
+
+  // Finds the index just past the delimiter that closes the one at `start`.
+  let closing = |start: usize, open: u8, close: u8| -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < bytes.len() {
+      match bytes[i] {
+        b'\\' => i += 1,
+        b if b == close && (depth == 1 || open == close) && i > start => return i + 1,
+        b if b == open => depth += 1,
+        b if b == close => depth -= 1,
+        _ => { /* pass */ }
+      }
+      i += 1;
+    }
+    bytes.len()
+  };
+
+  while idx < bytes.len() {
+    let start = idx;
+    idx = match bytes[idx] {
+      b'\\' => (idx + 2).min(bytes.len()),
+      b'"'  => closing(idx, b'"', b'"'),
+      b'['  => closing(idx, b'[', b']'),
+      b'('  => closing(idx, b'(', b')'),
+      b'{'  => closing(idx, b'{', b'}'),
+      // Anchors match the empty string but do not make a pattern match it by themselves.
+      b'^' | b'$' => { idx += 1; continue; }
+      _ => idx + pattern[idx..].chars().next().map_or(1, |c| c.len_utf8()),
+    };
+    let atom = &pattern[start..idx];
+
+    let quantifier_start = idx;
+    if idx < bytes.len() {
+      idx = match bytes[idx] {
+        b'*' | b'+' | b'?' => idx + 1,
+        b'{' if bytes.get(idx + 1).is_some_and(|b| b.is_ascii_digit() || *b == b',') => {
+          closing(idx, b'{', b'}')
+        }
+        _ => idx,
+      };
+      // A lazy quantifier
+      if idx > quantifier_start && bytes.get(idx) == Some(&b'?') {
+        idx += 1;
+      }
+    }
+
+    atoms.push((atom, &pattern[quantifier_start..idx]));
+  }
+
+  atoms
+}
+
+/**
+Conservatively determines whether a pattern can match strings of different lengths. `{NAME}`
+references are assumed to be fixed-length, so a pattern is only reported as variable-length
+when it certainly is.
+*/
+fn is_variable_length(pattern: &str) -> bool {
+  let mut in_quotes   = false;
+  let mut in_brackets = false;
+  let mut escaped     = false;
+  let mut chars       = pattern.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    if escaped {
+      escaped = false;
+      continue;
+    }
+
+    match c {
+      '\\'                       => escaped = true,
+      '"' if !in_brackets        => in_quotes = !in_quotes,
+      '[' if !in_quotes          => in_brackets = true,
+      ']' if in_brackets         => in_brackets = false,
+      '*' | '+' | '?' | '|' if !in_quotes && !in_brackets => return true,
+      // A repeat `{n,m}` rather than a definition reference `{NAME}`
+      '{' if !in_quotes && !in_brackets
+        && chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+          return true;
+        }
+      _                          => { /* pass */ }
+    }
+  }
+
+  false
+}
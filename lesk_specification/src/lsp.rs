@@ -0,0 +1,519 @@
+/*!
+
+A language server for specification files, run with `lesk lsp` and spoken to over stdin and stdout.
+
+Every request parses the document afresh, which is fast enough for files of the size people write
+by hand. The server provides
+
+  * diagnostics: the parse errors and warnings `lesk` itself would print, published whenever a
+    document is opened or changed,
+  * go to definition for `{NAME}` references and for start conditions in `<…>` lists,
+  * hover over a `{NAME}` reference, showing the fully expanded regex, and
+  * document symbols for definitions, start conditions, and rules.
+
+Positions are counted in characters rather than the UTF-16 code units of the protocol, which only
+differ for text outside the Basic Multilingual Plane.
+
+*/
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+
+use codespan_reporting::diagnostic::{LabelStyle, Severity};
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+  notification::{
+    DidChangeTextDocument,
+    DidCloseTextDocument,
+    DidOpenTextDocument,
+    Notification as _,
+    PublishDiagnostics,
+  },
+  request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _},
+  Diagnostic,
+  DiagnosticSeverity,
+  DocumentSymbol,
+  DocumentSymbolResponse,
+  GotoDefinitionResponse,
+  Hover,
+  HoverContents,
+  Location,
+  MarkedString,
+  Position,
+  PublishDiagnosticsParams,
+  Range,
+  ServerCapabilities,
+  SymbolKind,
+  TextDocumentSyncCapability,
+  TextDocumentSyncKind,
+  Url,
+};
+
+use crate::definitions::{references, Definitions};
+use crate::error::{Errors, ToDiagnostic};
+use crate::lint::lint;
+use crate::options::Options;
+use crate::parser::{
+  self,
+  include::{self, IncludedSources},
+  parser::{expand_includes, section_one, section_two},
+  InputType,
+  ToSpan,
+};
+use crate::section_items::{flatten_includes, Item};
+use crate::Span;
+
+
+type LspResult<T> = Result<T, Box<dyn StdError + Sync + Send>>;
+
+
+/**
+Serves requests until the client asks the server to shut down. Documents are parsed with
+`options`, those of the project's `Lesk.toml`, so that `%include`s, `%if`s, and flex
+compatibility are read as `lesk` reads them.
+*/
+pub fn run(options: Options) -> LspResult<()> {
+  parser::configure(&options);
+  let (connection, io_threads) = Connection::stdio();
+
+  let capabilities = ServerCapabilities {
+    text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+    definition_provider: Some(true),
+    hover_provider: Some(true.into()),
+    document_symbol_provider: Some(true),
+    ..ServerCapabilities::default()
+  };
+  connection.initialize(serde_json::to_value(capabilities)?)?;
+
+  let mut documents: HashMap<Url, String> = HashMap::new();
+
+  for message in &connection.receiver {
+    match message {
+      Message::Request(request) => {
+        if connection.handle_shutdown(&request)? {
+          break;
+        }
+        let response = respond(&documents, request)?;
+        connection.sender.send(Message::Response(response))?;
+      }
+
+      Message::Notification(notification) => {
+        if let Some(uri) = update_documents(&mut documents, notification)? {
+          let text = documents.get(&uri).map(String::as_str).unwrap_or("");
+          let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics(&uri, text), None);
+          connection.sender.send(Message::Notification(
+            Notification::new(PublishDiagnostics::METHOD.to_string(), params)
+          ))?;
+        }
+      }
+
+      Message::Response(_) => { /* The server sends no requests. */ }
+    }
+  }
+
+  io_threads.join()?;
+  Ok(())
+}
+
+/// Applies an open, change, or close notification, returning the document that changed.
+fn update_documents(documents: &mut HashMap<Url, String>, notification: Notification)
+    -> LspResult<Option<Url>>
+{
+  match notification.method.as_str() {
+    DidOpenTextDocument::METHOD => {
+      let params: <DidOpenTextDocument as lsp_types::notification::Notification>::Params =
+          serde_json::from_value(notification.params)?;
+      let uri = params.text_document.uri;
+      documents.insert(uri.clone(), params.text_document.text);
+      Ok(Some(uri))
+    }
+
+    DidChangeTextDocument::METHOD => {
+      let params: <DidChangeTextDocument as lsp_types::notification::Notification>::Params =
+          serde_json::from_value(notification.params)?;
+      let uri = params.text_document.uri;
+      // Full synchronization: the last change holds the whole document.
+      if let Some(change) = params.content_changes.into_iter().last() {
+        documents.insert(uri.clone(), change.text);
+      }
+      Ok(Some(uri))
+    }
+
+    DidCloseTextDocument::METHOD => {
+      let params: <DidCloseTextDocument as lsp_types::notification::Notification>::Params =
+          serde_json::from_value(notification.params)?;
+      documents.remove(&params.text_document.uri);
+      Ok(None)
+    }
+
+    _ => Ok(None)
+  }
+}
+
+fn respond(documents: &HashMap<Url, String>, request: Request) -> LspResult<Response> {
+  let request = match request.extract::<lsp_types::GotoDefinitionParams>(GotoDefinition::METHOD) {
+    Ok((id, params)) => {
+      let position = params.text_document_position_params;
+      let uri      = position.text_document.uri;
+      let text     = documents.get(&uri).map(String::as_str).unwrap_or("");
+      let result   = definition(&uri, text, position.position).map(|range| {
+        GotoDefinitionResponse::Scalar(Location::new(uri.clone(), range))
+      });
+      return ok(id, result);
+    }
+    Err(request) => request,
+  };
+
+  let request = match request.extract::<lsp_types::HoverParams>(HoverRequest::METHOD) {
+    Ok((id, params)) => {
+      let position = params.text_document_position_params;
+      let uri      = position.text_document.uri;
+      let text     = documents.get(&uri).map(String::as_str).unwrap_or("");
+      return ok(id, hover(&uri, text, position.position));
+    }
+    Err(request) => request,
+  };
+
+  let request = match request.extract::<lsp_types::DocumentSymbolParams>(DocumentSymbolRequest::METHOD) {
+    Ok((id, params)) => {
+      let uri  = params.text_document.uri;
+      let text = documents.get(&uri).map(String::as_str).unwrap_or("");
+      return ok(id, Some(DocumentSymbolResponse::Nested(symbols(&uri, text))));
+    }
+    Err(request) => request,
+  };
+
+  Ok(Response::new_err(
+    request.id,
+    lsp_server::ErrorCode::MethodNotFound as i32,
+    format!("unsupported request: {}", request.method)
+  ))
+}
+
+fn ok<T: serde::Serialize>(id: RequestId, result: T) -> LspResult<Response> {
+  Ok(Response::new_ok(id, serde_json::to_value(result)?))
+}
+
+// region Analysis
+
+/**
+Parses `text` and passes the items, with `%include`s left nested, to `f` along with any errors.
+Items after an error are not available.
+*/
+fn with_items<R>(uri: &Url, text: &str, f: impl FnOnce(&[Item], Errors) -> R) -> R {
+  // The document's own directory is searched first for `%include`d files.
+  let path = uri.to_file_path().unwrap_or_default();
+  include::enter_file(path, None);
+
+  // The text of the included files, which the items borrow until `f` returns.
+  let sources    = IncludedSources::default();
+  let mut items  = vec![];
+  let mut errors = Errors::new();
+  let mut rest   = InputType::new(text);
+
+  for parser in [section_one, section_two].iter() {
+    let result = parser(rest).and_then(|(new_rest, new_items)| {
+      Ok((new_rest, expand_includes(new_items, &sources)?))
+    });
+    match result {
+      Ok((new_rest, new_items)) => {
+        rest = new_rest;
+        items.extend(new_items);
+      }
+      | Err(nom::Err::Error(e))
+      | Err(nom::Err::Failure(e)) => {
+        errors.extend(e);
+        break;
+      }
+      Err(nom::Err::Incomplete(_)) => break,
+    }
+  }
+
+  include::exit_file();
+
+  f(&items, errors)
+}
+
+fn diagnostics(uri: &Url, text: &str) -> Vec<Diagnostic> {
+  let index = LineIndex::new(text);
+
+  with_items(uri, text, |items, mut errors| {
+    errors.extend(lint(&flatten_includes(items.iter().cloned())));
+
+    errors
+        .iter()
+        // Errors within included files belong to those files.
+        .filter(|error| error.source_name().is_none())
+        .filter_map(|error| {
+          let diagnostic = error.to_diagnostic(0);
+          let label = diagnostic.labels.iter().find(|l| l.style == LabelStyle::Primary)?;
+          let severity = match diagnostic.severity {
+            Severity::Bug | Severity::Error => DiagnosticSeverity::Error,
+            Severity::Warning               => DiagnosticSeverity::Warning,
+            Severity::Note                  => DiagnosticSeverity::Information,
+            Severity::Help                  => DiagnosticSeverity::Hint,
+          };
+
+          let mut message = diagnostic.message.clone();
+          for note in &diagnostic.notes {
+            message.push('\n');
+            message.push_str(note);
+          }
+
+          Some(Diagnostic {
+            range: index.range(label.range.start, label.range.end),
+            severity: Some(severity),
+            source: Some("lesk".to_string()),
+            message,
+            ..Diagnostic::default()
+          })
+        })
+        .collect()
+  })
+}
+
+/// The `{NAME}` reference or start condition at `offset`, if any.
+enum Reference<'a> {
+  /// The name and the offset of its opening brace
+  Definition(&'a str, usize),
+  Condition(&'a str),
+}
+
+fn reference_at<'a>(items: &'a [Item], offset: usize) -> Option<Reference<'a>> {
+  for item in items {
+    let patterns: Vec<&Span> = match item {
+      Item::Rule { pattern, context, conditions, .. } => {
+        for condition in conditions {
+          if contains(condition, offset) {
+            return Some(Reference::Condition(condition.fragment().trim_start_matches('-')));
+          }
+        }
+        std::iter::once(pattern).chain(context.iter()).collect()
+      }
+      Item::Definition { code, .. } => vec![code],
+      _ => continue,
+    };
+
+    for pattern in patterns {
+      let start = usize::from(pattern.to_span().start());
+      for (idx, name) in references(pattern.fragment()) {
+        // `{` + name + `}`
+        if start + idx <= offset && offset < start + idx + name.len() + 2 {
+          return Some(Reference::Definition(name, start + idx));
+        }
+      }
+    }
+  }
+
+  None
+}
+
+fn definition(uri: &Url, text: &str, position: Position) -> Option<Range> {
+  let index  = LineIndex::new(text);
+  let offset = index.offset(position);
+
+  with_items(uri, text, |items, _errors| {
+    let target = match reference_at(items, offset)? {
+      Reference::Definition(name, _) => items.iter().find_map(|item| match item {
+        Item::Definition { name: n, .. } if *n.fragment() == name => Some(*n),
+        _ => None
+      }),
+      Reference::Condition(name) => items.iter().find_map(|item| match item {
+        Item::State { name: n, .. } if *n.fragment() == name => Some(*n),
+        _ => None
+      }),
+    }?;
+
+    let target = target.to_span();
+    Some(index.range(usize::from(target.start()), usize::from(target.end())))
+  })
+}
+
+fn hover(uri: &Url, text: &str, position: Position) -> Option<Hover> {
+  let index  = LineIndex::new(text);
+  let offset = index.offset(position);
+
+  with_items(uri, text, |items, _errors| {
+    let (name, start) = match reference_at(items, offset)? {
+      Reference::Definition(name, start) => (name, start),
+      Reference::Condition(_) => return None,
+    };
+
+    let items       = flatten_includes(items.iter().cloned());
+    let definitions = Definitions::from_items(&items);
+    let regex       = definitions.get(name)?;
+    let expanded    = definitions.expand(*regex).unwrap_or_else(|e| e.to_string());
+
+    Some(Hover {
+      contents: HoverContents::Scalar(MarkedString::String(format!("```\n{}\n```", expanded))),
+      range: Some(index.range(start, start + name.len() + 2)),
+    })
+  })
+}
+
+#[allow(deprecated)]
+fn symbols(uri: &Url, text: &str) -> Vec<DocumentSymbol> {
+  let index = LineIndex::new(text);
+
+  let symbol = |name: String, kind: SymbolKind, span: &Span| {
+    let span  = span.to_span();
+    let range = index.range(usize::from(span.start()), usize::from(span.end()));
+    DocumentSymbol {
+      name,
+      detail: None,
+      kind,
+      deprecated: None,
+      range,
+      selection_range: range,
+      children: None,
+    }
+  };
+
+  with_items(uri, text, |items, _errors| {
+    items.iter().filter_map(|item| match item {
+      Item::Definition { name, .. } => {
+        Some(symbol(name.fragment().to_string(), SymbolKind::Constant, name))
+      }
+      Item::State { name, .. } => {
+        Some(symbol(name.fragment().to_string(), SymbolKind::EnumMember, name))
+      }
+      Item::Rule { conditions, pattern, .. } => {
+        let mut name = String::new();
+        if !conditions.is_empty() {
+          let conditions: Vec<&str> = conditions.iter().map(|c| *c.fragment()).collect();
+          name.push_str(&format!("<{}>", conditions.join(",")));
+        }
+        name.push_str(pattern.fragment());
+        Some(symbol(name, SymbolKind::Function, pattern))
+      }
+      _ => None
+    }).collect()
+  })
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+  let span = span.to_span();
+  usize::from(span.start()) <= offset && offset < usize::from(span.end())
+}
+
+// endregion
+
+/// Converts between byte offsets and line/character positions.
+struct LineIndex<'t> {
+  text: &'t str,
+  /// The byte offset at which each line starts
+  starts: Vec<usize>,
+}
+
+impl<'t> LineIndex<'t> {
+  fn new(text: &'t str) -> Self {
+    let starts = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect();
+
+    LineIndex { text, starts }
+  }
+
+  fn position(&self, offset: usize) -> Position {
+    let line = match self.starts.binary_search(&offset) {
+      Ok(line)  => line,
+      Err(next) => next - 1,
+    };
+    let start     = self.starts[line];
+    let character = self.text[start..offset.min(self.text.len())].chars().count();
+
+    Position::new(line as u64, character as u64)
+  }
+
+  fn range(&self, start: usize, end: usize) -> Range {
+    Range::new(self.position(start), self.position(end))
+  }
+
+  fn offset(&self, position: Position) -> usize {
+    let start = match self.starts.get(position.line as usize) {
+      Some(start) => *start,
+      None => return self.text.len(),
+    };
+
+    self.text[start..]
+        .char_indices()
+        .nth(position.character as usize)
+        .map_or(self.text.len(), |(idx, _)| start + idx)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  const TEXT: &str = "DIGIT [0-9]\n%x comment\n%%\n<comment>{DIGIT}+   f();\n[a-z]*   g();\n";
+
+  /// The result of the request `method` with `params` on the document `TEXT`.
+  fn request(method: &str, params: serde_json::Value) -> serde_json::Value {
+    let uri       = Url::parse("file:///spec.l").unwrap();
+    let documents = std::iter::once((uri, TEXT.to_string())).collect();
+    let response  = respond(&documents, Request::new(RequestId::from(1), method.into(), params))
+        .unwrap();
+    assert!(response.error.is_none(), "{:?}", response.error);
+    response.result.unwrap()
+  }
+
+  /// The parameters of a request at the position `line`, `character` of the document.
+  fn at(line: u64, character: u64) -> serde_json::Value {
+    json!({
+      "textDocument": { "uri": "file:///spec.l" },
+      "position": { "line": line, "character": character }
+    })
+  }
+
+  #[test]
+  fn diagnostics_include_warnings() {
+    let uri         = Url::parse("file:///spec.l").unwrap();
+    let diagnostics = diagnostics(&uri, TEXT);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::Warning));
+    assert_eq!(diagnostics[0].range, Range::new(Position::new(4, 0), Position::new(4, 6)));
+  }
+
+  #[test]
+  fn definition_of_a_reference_and_a_condition() {
+    let range = json!({
+      "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 5 }
+    });
+    assert_eq!(request(GotoDefinition::METHOD, at(3, 11))["range"], range);
+
+    let range = json!({
+      "start": { "line": 1, "character": 3 }, "end": { "line": 1, "character": 10 }
+    });
+    assert_eq!(request(GotoDefinition::METHOD, at(3, 2))["range"], range);
+    assert_eq!(request(GotoDefinition::METHOD, at(4, 2)), serde_json::Value::Null);
+  }
+
+  #[test]
+  fn hover_expands_a_reference() {
+    let hover = request(HoverRequest::METHOD, at(3, 11));
+    assert_eq!(hover["contents"], "```\n[0-9]\n```");
+    assert_eq!(request(HoverRequest::METHOD, at(4, 2)), serde_json::Value::Null);
+  }
+
+  #[test]
+  fn symbols_of_definitions_conditions_and_rules() {
+    let params  = json!({ "textDocument": { "uri": "file:///spec.l" } });
+    let symbols = request(DocumentSymbolRequest::METHOD, params);
+    let names: Vec<&str> = symbols.as_array().unwrap().iter()
+        .map(|symbol| symbol["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["DIGIT", "comment", "<comment>{DIGIT}+", "[a-z]*"]);
+  }
+
+  #[test]
+  fn unsupported_requests_are_errors() {
+    let uri       = Url::parse("file:///spec.l").unwrap();
+    let documents = std::iter::once((uri, TEXT.to_string())).collect();
+    let request   = Request::new(RequestId::from(1), "textDocument/rename".into(), json!({}));
+    let response  = respond(&documents, request).unwrap();
+    assert_eq!(response.error.unwrap().code, lsp_server::ErrorCode::MethodNotFound as i32);
+  }
+}
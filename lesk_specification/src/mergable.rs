@@ -4,13 +4,15 @@ A trait for structs that can determine if they can be merged with each other.
 
 */
 
+#![allow(dead_code)]
 
-
-
-use std::cmp::{min, max};
+use std::cmp::max;
 use std::fmt::{Display, Debug};
+use codespan::Span;
 use smallvec::SmallVec;
 
+use crate::parser::{LSpan, ToSpan};
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Merged<T, U> {
   Yes(T),
@@ -79,7 +81,7 @@ impl Mergable for Span {
   }
 
   fn merged<'a>(&'a mut self, other: &'a mut Self) -> Merged<&'a mut Self, &'a mut Self> {
-    if self.mergable(&other){
+    if self.mergable(other){
       // The tablecloth trick: we pull `self` right out from beneath us.
       let mut merged_span = self.merge(*other);
       std::mem::swap(&mut merged_span, self);
@@ -91,41 +93,58 @@ impl Mergable for Span {
 
 }
 
-/*
-impl Mergable for LocatedSpan<T>{
-
+/**
+Two `LSpan`s are mergable if their byte ranges are, as for `Span`, and they are slices of the same
+text. The latter is checked by the addresses of their fragments, which differ by exactly the
+difference of their offsets only when both point into one string.
+*/
+impl<'s> Mergable for LSpan<'s> {
 
   fn mergable(&self, other: &Self) -> bool {
-    // See `merged()` for `Span` for an explaination of why this works.
-    let (first, second) = if self.location_offset() < other.location_offset() {
+    let (first, second) = if self.location_offset() <= other.location_offset() {
       (self, other)
     } else {
       (other, self)
     };
+    let distance = second.location_offset() - first.location_offset();
 
-    second.start() <= first.location_offset() + first.len()
+    first.to_span().mergable(&second.to_span())
+        && first.fragment().as_ptr().wrapping_add(distance) == second.fragment().as_ptr()
   }
 
   fn merged<'a>(&'a mut self, other: &'a mut Self) -> Merged<&'a mut Self, &'a mut Self> {
-    // See `merged()` for `Span` for an explaination of why this works.
-    if self.mergable(&other){
-      // The tablecloth trick: we pull `self` right out from beneath us.
-      self.fragment().spl
-
-
-      let mut merged_span = self.merge(other);
-      std::mem::swap(&mut merged_span, self);
-      Merged::Yes(self)
-    } else{
-      Merged::No(self, other)
+    if !self.mergable(other) {
+      return Merged::No(self, other);
     }
 
+    let (first, second) = if self.location_offset() <= other.location_offset() {
+      (*self, *other)
+    } else {
+      (*other, *self)
+    };
+    let start = first.location_offset();
+    let end = max(
+      start + first.fragment().len(),
+      second.location_offset() + second.fragment().len()
+    );
+    // Safety: `mergable` checked that both fragments are slices of one string, and they overlap
+    // or touch, so `start..end` is a slice of that string that begins and ends on the character
+    // boundaries of `first` and `second`.
+    let merged_span = unsafe {
+      let bytes = std::slice::from_raw_parts(first.fragment().as_ptr(), end - start);
+      LSpan::new_from_raw_offset(
+        start,
+        first.location_line(),
+        std::str::from_utf8_unchecked(bytes),
+        ()
+      )
+    };
+    *self = merged_span;
+    Merged::Yes(self)
   }
 
 }
 
-*/
-
 pub fn merge_or_push_item<T, A>(items: &mut SmallVec<A>, mut item: T) -> &mut SmallVec<A>
   where T: Mergable + Display,
         A: smallvec::Array<Item=T>,
@@ -139,7 +158,7 @@ pub fn merge_or_push_item<T, A>(items: &mut SmallVec<A>, mut item: T) -> &mut Sm
 
   // Unwrap always succeeds because of preceding `if`.
   let mut last_item = items.pop().unwrap();
-  let mut result = last_item.merged(&mut item);
+  let result = last_item.merged(&mut item);
 
   match result {
     Merged::Yes(_) => {
@@ -165,13 +184,12 @@ pub fn merge_or_append_items<'a, T>(lhs: &'a mut Vec<T>, rhs: &'a mut Vec<T>) ->
     std::mem::swap(lhs, rhs);
     return lhs;
   } else if rhs.is_empty() {
-    std::mem::swap(lhs, rhs);
     return lhs;
   }
 
   // Unwraps always succeed because of preceding `if` block.
   let mut lhs_last_item  = lhs.pop().unwrap();
-  let mut rhs_first_item = rhs.first_mut().unwrap();
+  let rhs_first_item = rhs.first_mut().unwrap();
   match lhs_last_item.merged(rhs_first_item) {
 
     Merged::No(_, _) => {
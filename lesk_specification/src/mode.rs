@@ -1,31 +1,26 @@
 use std::collections::HashMap;
-use std::num::NonZeroU32;
 
 use byte_set::ByteSet;
 
 use crate::parser::LSpan;
-use crate::{Span, SourceID};
+use crate::Span;
+use crate::section_items::{Action, Item};
+use crate::error::{Error, Errors, UndefinedNameError};
+use crate::parser::ToSpan;
+use crate::options::OptionField;
+use crate::definitions::Definitions;
+use crate::pattern::{translate_text, with_modifiers};
 
-type RuleID = usize;
-type ModeID = u8;
+pub type RuleID   = usize;
+pub type ActionID = usize;
+pub type ModeID   = u8;
 
+/// The most start conditions a scanner can have, `INITIAL` included, as a `ModeID` is a byte.
+pub const MAX_MODES: usize = ModeID::MAX as usize + 1;
 
-// Every scanner has an initial mode.
-const INITIAL_MODE: Mode =
-  Mode{
-    name: Span{
-      source_id   : SourceID(NonZeroU32(1)),
-      located_span: LSpan::new("INITIAL")
-    },
-    exclusive: false,
-    // mode_id  : 0 // The only mode with id zero.
-  };
 
-
-
-
-#[derive(Copy, Clone, Debug, Hash)]
-struct Mode<'s> {
+#[derive(Copy, Clone, Debug)]
+pub struct Mode<'s> {
   name          : Span<'s>,
   pub exclusive : bool,
   // mode_id       : u16
@@ -42,52 +37,177 @@ impl<'a> Mode<'a> {
   }
 
   pub fn name(&self) -> &str {
-    self.name().fragment()
+    self.name.fragment()
   }
 
-  pub fn initial_mode() -> Mode {
-    INITIAL_MODE
+  /// Every scanner has an initial mode, the only mode with id zero.
+  pub fn initial_mode() -> Mode<'static> {
+    Mode::new(LSpan::new("INITIAL"), false)
   }
 
 }
 
-impl Eq for Mode{}
+impl Eq for Mode<'_> {}
 
-impl PartialEq for Mode{
+impl PartialEq for Mode<'_> {
   fn eq(&self, other: &Self) -> bool {
     self.name.fragment() == other.name.fragment()
   }
 }
 
-#[derive(Copy, Clone, Debug, Hash)]
-struct Rule<'a> {
+#[derive(Clone, Debug)]
+pub struct Rule<'a> {
   // todo: Should this be a mutable string?
-  regex: Span<'a>,
-  code : Span<'a>,
+  regex : Span<'a>,
+  /// Trailing context, compiled as a lookahead following `regex`
+  context: Option<Span<'a>>,
+  /// Pattern options set by the start condition scopes around the rule
+  modifiers: Vec<OptionField>,
+  /// Index into `Modes::actions`, or `None` if the rule has no action. Rules joined by `|`
+  /// share one action, so the action code is emitted once for the whole group.
+  action: Option<ActionID>,
+}
+
+impl<'a> Rule<'a> {
+  /**
+  The regex handed to the regex engine, with `{NAME}` references expanded, quoted strings
+  escaped, any trailing context written as a lookahead, and the rule's scoped pattern options
+  lowered to a modifier group. `case_insensitive` is the specification-wide setting, which a
+  scoped option overrides.
+  */
+  pub fn to_regex(&self, definitions: &Definitions, case_insensitive: bool)
+      -> Result<String, Errors>
+  {
+    let regex = match self.translate(definitions, case_insensitive)? {
+      // Grouped, so that the lookahead follows the whole of an alternation such as `ab|cd`.
+      (regex, Some(context)) => format!("(?:{})(?={})", regex, context),
+      (regex, None)          => regex,
+    };
+
+    Ok(with_modifiers(&regex, &self.modifiers))
+  }
+
+  /**
+  The regex of the rule's text and that of its trailing context, if it has one, each translated
+  as by `to_regex`, for a regex engine without lookahead, which matches the two in turn and gives
+  the context back.
+  */
+  pub fn to_regexes(&self, definitions: &Definitions, case_insensitive: bool)
+      -> Result<(String, Option<String>), Errors>
+  {
+    let (regex, context) = self.translate(definitions, case_insensitive)?;
+
+    Ok((
+      with_modifiers(&regex, &self.modifiers),
+      context.map(|context| with_modifiers(&context, &self.modifiers))
+    ))
+  }
+
+  /// The rule's text and trailing context with `{NAME}` references expanded and quoted strings
+  /// escaped.
+  fn translate(&self, definitions: &Definitions, case_insensitive: bool)
+      -> Result<(String, Option<String>), Errors>
+  {
+    let case_insensitive = self.modifiers
+        .iter()
+        .filter_map(OptionField::modifier)
+        .find(|(flag, _)| *flag == 'i')
+        .map_or(case_insensitive, |(_, value)| value);
+
+    let expanded = definitions.expand(self.regex)?;
+    let regex    = translate_text(&expanded, self.regex, case_insensitive)?.regex;
+    let context  =
+        match self.context {
+          Some(context) => {
+            let expanded = definitions.expand(context)?;
+            Some(translate_text(&expanded, context, case_insensitive)?.regex)
+          }
+          None => None,
+        };
+
+    Ok((regex, context))
+  }
+
+  /// The action run when the rule matches, or `None` for a rule with an empty action.
+  pub fn action(&self) -> Option<ActionID> {
+    self.action
+  }
+
+  pub fn span(&self) -> Span<'a> {
+    self.regex
+  }
+}
+
+/**
+The rules active in one start condition lowered to the single regex handed to the regex engine,
+which is what the DFA of that start condition is compiled from.
+*/
+#[derive(Clone, Debug)]
+pub struct ConditionPattern<'s> {
+  pub condition: String,
+  pub exclusive: bool,
+  /// An alternation with one group per rule, in the order of the rules, so that the accept index
+  /// `n` of the regex engine is the `n`th rule
+  pub regex: String,
+  /// For each rule, its regex on its own, with the specification's pattern options applied, for
+  /// matching the rules one at a time
+  pub alternatives: Vec<String>,
+  /// For each rule, the regex of its text and that of its trailing context, if it has one, with
+  /// the specification's pattern options applied, for engines without lookahead
+  pub contexts: Vec<(String, Option<String>)>,
+  /// For each rule, its number among the rules of all start conditions, so that the automata of
+  /// all start conditions share one numbering of rules and actions
+  pub rule_ids: Vec<RuleID>,
+  /// For each rule, its pattern in the specification
+  pub rules: Vec<Span<'s>>,
+}
+
+impl<'s> ConditionPattern<'s> {
+  /// The global rule number of the accept index `accept` of this start condition's automaton.
+  /// Accept indices count from 1, and 0 means no rule matched.
+  pub fn rule_id(&self, accept: usize) -> Option<RuleID> {
+    accept.checked_sub(1).and_then(|idx| self.rule_ids.get(idx)).copied()
+  }
 }
 
 /**
 A `Mode` is a "start condition" or "state" in the language of lex/flex.
+
+A rule without start conditions is active in every inclusive mode (`%s`), `INITIAL` among them,
+but in no exclusive mode (`%x`). In the flex manual's example,
+
+  %s example
+  %%
+  <example>foo   do_something();
+  bar            something_else();
+
+`bar` is active in both `INITIAL` and `example`, whereas with `%x example` it would be active in
+`INITIAL` only, as if it had been written `<INITIAL>bar`. Within each mode, rules keep the order
+they have in the specification, which is their priority.
 */
-struct Modes<'a>{
+pub struct Modes<'a>{
   /// A ModeID is just an index into `Modes`. It is used as a proxy for the mode at that index.
   pub modes: Vec<Mode<'a>>,
   pub rules: Vec<Rule<'a>>,
+  /// The distinct action code blocks, indexed by `ActionID`.
+  pub actions: Vec<Span<'a>>,
   /// A mapping from a mode to the set of rules active within that mode.
-  pub mode_rules: HashMap<ModeID, RuleID>,
+  pub mode_rules: HashMap<ModeID, Vec<RuleID>>,
   /// A stack of "active" modes used in parsing modes and rules. When a rule is encountered, it
   // is added to all active modes.
+  #[allow(dead_code)]
   pub stack: Vec<ByteSet>,
 }
 
 impl<'a> Default for Modes<'a> {
   fn default() -> Self {
-    let mut modes: Vec<Mode<'a>> = vec![Mode::initial_mode()];
+    let modes: Vec<Mode<'a>> = vec![Mode::initial_mode()];
 
     Modes{
       modes,
       rules: vec![],
-      mode_rules: hash_map![],
+      actions: vec![],
+      mode_rules: HashMap::new(),
       stack: vec![],
     }
   }
@@ -95,12 +215,49 @@ impl<'a> Default for Modes<'a> {
 }
 
 impl<'a> Modes<'a>{
-  fn new() -> Self{
+  pub fn new() -> Self{
     Self::default()
   }
 
-  fn add_mode(&mut self, name: Span, exclusive: bool) -> ModeID {
-    let mode_id = self.modes.len() as u8;
+  /**
+  The modes declared by the `%state` and `%xstate` items among `items` and the rules among them.
+  A start condition may be declared more than once, but not as both inclusive and exclusive, as
+  that changes which rules are active in it. `INITIAL` is always inclusive.
+  */
+  pub fn from_items(items: &[Item<'a>]) -> Result<Self, Errors> {
+    let mut modes  = Self::new();
+    let mut errors = Errors::new();
+
+    for item in items {
+      if let Item::State { is_exclusive, name } = item {
+        match modes.mode_id(name.fragment()) {
+          Some(mode_id) if modes.modes[mode_id as usize].exclusive != *is_exclusive => {
+            errors.push(Error::Message(
+              name.to_span(),
+              format!(
+                "`{}` is declared both inclusive (`%s`) and exclusive (`%x`)",
+                name.fragment()
+              ).into()
+            ));
+          }
+          _ => {
+            if let Err(error) = modes.add_mode(*name, *is_exclusive) {
+              errors.push(error);
+            }
+          }
+        }
+      }
+    }
+    if !errors.is_empty() {
+      return Err(errors);
+    }
+    modes.add_rules(items)?;
+
+    Ok(modes)
+  }
+
+  /// The `ModeID` of the mode `name`, which is added if it is new. There can be `MAX_MODES`.
+  pub fn add_mode(&mut self, name: Span<'a>, exclusive: bool) -> Result<ModeID, Error> {
     let mode = Mode{
       name,
       exclusive,
@@ -111,18 +268,222 @@ impl<'a> Modes<'a>{
     match self
         .modes
         .iter()
-        .position(|x| x.name() == &mode.name())
+        .position(|x| x.name() == mode.name())
     {
 
       Some(index) => {
-        index as u8
+        Ok(index as ModeID)
+      }
+
+      None if self.modes.len() == MAX_MODES => {
+        Err(Error::Message(
+          name.to_span(),
+          format!("a scanner can have at most {} start conditions, `INITIAL` included", MAX_MODES)
+              .into()
+        ))
       }
 
       None => {
         self.modes.push(mode);
-        mode_id
+        Ok((self.modes.len() - 1) as ModeID)
       }
     }
   }
 
+  /**
+  Lowers the `Item::Rule`s of section two into `Rule`s, recording each rule in every mode in which
+  it is active. A run of rules with the `|` action shares the action of the rule that ends the
+  run.
+  */
+  pub fn add_rules(&mut self, items: &[Item<'a>]) -> Result<(), Errors> {
+    // Rules waiting for the action of a later rule.
+    let mut pending: Vec<RuleID> = vec![];
+    let mut last_bar = None;
+
+    for item in items {
+      if let Item::Rule { conditions, pattern, context, modifiers, action } = item {
+        let rule_id = self.rules.len();
+        let active  = self.resolve_conditions(conditions)?;
+
+        self.rules.push(Rule {
+          regex: *pattern,
+          context: *context,
+          modifiers: modifiers.clone(),
+          action: None
+        });
+        for mode_id in (0..self.modes.len()).map(|mode_id| mode_id as ModeID) {
+          if active.contains(mode_id) {
+            self.mode_rules.entry(mode_id).or_default().push(rule_id);
+          }
+        }
+
+        match action {
+          Action::FallThrough(bar) => {
+            pending.push(rule_id);
+            last_bar = Some(*bar);
+          }
+
+          Action::Code(code) => {
+            let action_id = self.actions.len();
+            self.actions.push(*code);
+            pending.push(rule_id);
+            for rule_id in pending.drain(..) {
+              self.rules[rule_id].action = Some(action_id);
+            }
+          }
+
+          Action::Empty => {
+            // Rules falling through to an empty action have no action either.
+            pending.clear();
+          }
+        }
+      }
+    }
+
+    match last_bar {
+      Some(bar) if !pending.is_empty() => {
+        Err(Errors::from(Error::unexpected(
+          "`|` action",
+          bar,
+          Some("The last rule has no following rule whose action it could share.")
+        )))
+      }
+      _ => Ok(())
+    }
+  }
+
+  /**
+  Lowers the rules of each mode into a `ConditionPattern`, in the order of the `ModeID`s.
+  `modifiers` are the pattern options of the whole specification, which apply to every rule not
+  overriding them in a start condition scope.
+  */
+  pub fn patterns(&self, definitions: &Definitions, modifiers: &[OptionField])
+      -> Result<Vec<ConditionPattern<'a>>, Errors>
+  {
+    let case_insensitive = modifiers.contains(&OptionField::CaseInsensitive(true));
+    let mut errors   = Errors::new();
+    let mut patterns = vec![];
+
+    for (mode_id, mode) in self.modes.iter().enumerate() {
+      let rule_ids         = self.mode_rules.get(&(mode_id as ModeID)).cloned().unwrap_or_default();
+      let mut alternatives = vec![];
+      let mut contexts     = vec![];
+
+      for rule_id in &rule_ids {
+        let rule = &self.rules[*rule_id];
+        match rule.to_regex(definitions, case_insensitive) {
+          Ok(regex) => alternatives.push(regex),
+          Err(e)    => errors.merge(e),
+        }
+        if let Ok((regex, context)) = rule.to_regexes(definitions, case_insensitive) {
+          contexts.push((
+            with_modifiers(&regex, modifiers),
+            context.map(|context| with_modifiers(&context, modifiers))
+          ));
+        }
+      }
+
+      patterns.push(ConditionPattern {
+        condition: mode.name().to_string(),
+        exclusive: mode.exclusive,
+        regex: with_modifiers(
+          &alternatives.iter().map(|regex| format!("({})", regex)).collect::<Vec<_>>().join("|"),
+          modifiers
+        ),
+        alternatives: alternatives.iter().map(|regex| with_modifiers(regex, modifiers)).collect(),
+        contexts,
+        rules: rule_ids.iter().map(|rule_id| self.rules[*rule_id].span()).collect(),
+        rule_ids,
+      });
+    }
+
+    if errors.has_errors() {
+      Err(errors)
+    } else {
+      Ok(patterns)
+    }
+  }
+
+  /// For each rule, by global rule number, the index of its action, or `None` for an empty action.
+  pub fn rule_actions(&self) -> Vec<Option<ActionID>> {
+    self.rules.iter().map(Rule::action).collect()
+  }
+
+  /// Looks up a mode by name.
+  pub fn mode_id(&self, name: &str) -> Option<ModeID> {
+    self.modes
+        .iter()
+        .position(|x| x.name() == name)
+        .map(|index| index as ModeID)
+  }
+
+  /**
+  Resolves the start condition list of a rule into the set of modes in which the rule is active.
+
+    * An empty list means every inclusive mode, which always includes `INITIAL`.
+    * `*` means every mode, inclusive or exclusive.
+    * `-NAME` removes `NAME`. A list made only of exclusions starts from every mode.
+
+  Every name must have been declared with `%state` or `%xstate`.
+  */
+  pub fn resolve_conditions(&self, conditions: &[Span]) -> Result<ByteSet, Errors> {
+    let mut active = ByteSet::new();
+
+    if conditions.is_empty() {
+      for (mode_id, mode) in self.modes.iter().enumerate() {
+        if !mode.exclusive {
+          active.insert(mode_id as ModeID);
+        }
+      }
+      return Ok(active);
+    }
+
+    let undeclared: Errors =
+      conditions
+          .iter()
+          .filter(|c| *c.fragment() != "*")
+          .filter(|c| self.mode_id(c.fragment().trim_start_matches('-')).is_none())
+          .map(|c| {
+            UndefinedNameError::new("start condition", c.fragment().trim_start_matches('-'), *c)
+                .with_candidates(self.modes.iter().map(|m| m.name()))
+                .into()
+          })
+          .collect();
+    if !undeclared.is_empty() {
+      return Err(undeclared);
+    }
+
+    let only_exclusions = conditions.iter().all(|c| c.fragment().starts_with('-'));
+    if only_exclusions {
+      for mode_id in 0..self.modes.len() {
+        active.insert(mode_id as ModeID);
+      }
+    }
+
+    // Exclusions are applied last so that their position in the list does not matter.
+    for condition in conditions {
+      let name = condition.fragment();
+
+      if *name == "*" {
+        for mode_id in 0..self.modes.len() {
+          active.insert(mode_id as ModeID);
+        }
+      } else if !name.starts_with('-') {
+        if let Some(mode_id) = self.mode_id(name) {
+          active.insert(mode_id);
+        }
+      }
+    }
+
+    for condition in conditions {
+      if let Some(excluded) = condition.fragment().strip_prefix('-') {
+        if let Some(mode_id) = self.mode_id(excluded) {
+          active.remove(mode_id);
+        }
+      }
+    }
+
+    Ok(active)
+  }
+
 }
@@ -1,537 +1,1691 @@
-#[macro_use]
-use phf::{map, Map};
-use structopt::StructOpt;
-
-use crate::parser::InputType;
+use clap::Parser;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::default::Default; // Not the `OptionField::Default` variant
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use clap::ArgMatches;
+use toml::value::{Table, Value};
+
+use crate::engine::{DEFAULT_ENGINE, ENGINE_NAMES};
+use crate::error::{
+  warning_code, Error, Errors, Level, LeskError, OptionConflictError, WarningPolicy
+};
+use crate::parser::ToSpan;
+use crate::section_items::Item;
+use crate::Span;
 use OptionField::*;
 use smallvec::SmallVec; // Enum defined below
 
 
-const DEFAULT_TAB_WIDTH: u8 = 2u8;
+const HEADER_FILE_HELP: &str =
+  "A Lesk scanner is a Rust module, which needs no header. The lexer, its start conditions, and \
+  the `Token` enum are public items of the module.";
 
 pub type OptionSet = SmallVec<[OptionField;1]>;
 
-pub enum OptionValue<'a> {
-  String(InputType<'a>),
-  Bool(bool),
-  Number(u8)
-}
-
 pub enum OptionKind {
   String(fn(String) -> OptionField),
   Bool(fn(bool) -> OptionField),
   NegatedBool(fn(bool) -> OptionField),
   Number(fn(u8) -> OptionField),
+  /// An option turned on by naming it, or given a value, as `%option warn` and
+  /// `%option warn=empty-match` are.
+  BoolOrString(fn(bool) -> OptionField, fn(String) -> OptionField),
   Legacy,
+  /// A flex option that a Lesk scanner cannot provide, with how to do without it
+  Unsupported(&'static str),
+  #[allow(dead_code)]
   Unimplemented,
 }
 
 
-// todo: Not all of these options are used. Replace the unused options with Unimplemented/Legacy
-//       OptionKind.
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
-pub enum OptionField {
-  // Scanner
-  InFile(String),
-
-  // Cannot be updated
-  Batch(bool),
-  CaseInsensitive(bool),
-  Dotall(bool),
-  Fast(bool),
-  Find(bool),
-  Flex(bool),
-  Freespace(bool),
-  Full(bool),
-  GraphsFile(String),
-  Include(String),
-  Interactive(bool),
-  OutFile(String),
-  Pattern(String),
-  RegexpFile(String),
-  Stdout(bool),
-  TablesFile(String),
-  Tabs(u8),
-  Unicode(bool),
-
-  // Generator
-  Bison(bool),
-  BisonBridge(bool),
-  BisonCc(bool),
-  BisonCcNamespace(String),
-  BisonCcParser(String),
-  BisonComplete(bool),
-  BisonLocations(bool),
-  Class(String),
-  Exception(String),
-  Lex(String),
-  Lexer(String),
-  Line(bool),
-  Main(bool),
-  Namespace(String),
-  Prefix(String),
-  Reentrant(bool),
-  Stdinit(bool),
-  TokenType(String),
-  Yy(bool),
-  Yyclass(String),
-  Yywrap(bool),
-
-  // Debugging and Benchmarking
-  Debug_(bool),
-  Default(bool),
-  Warn(bool),
-  PerfReport(bool),
-  Verbose(bool),
-  Reject(bool),
-
-  // Obsolete / Unsettable
-  // Included for completeness
-  Cpp(bool),
-  LexCompat(bool),
-  Unistd(bool),
-  PosixCompat(bool),
-  Stack(bool),
-  Yylineno(bool),
-  Yymore(bool),
-
-  Legacy(&'static str),
-}
-
-
-// todo: switch to argh (https://docs.rs/argh/0.1.3/argh/)
-#[derive(Debug, StructOpt)]
-#[structopt(name = "Lesk", about = "A lexer generator.")]
-pub struct Options {
-  // region Scanner
-
-  /// Generate scanner for batch input by buffering the entire input
-  #[structopt(short = "B", long)]
-  pub batch: bool,
-
-  #[structopt(short = "i", long)]
-  /// Ignore case in patterns
-  pub case_insensitive: bool,
-
-  #[structopt(short = "a", long)]
-  /// Dot in patterns match newline
-  pub dotall: bool,
-
-  #[structopt(short = "F", long)]
-  /// Generate fast scanner with FSM code
-  pub fast: bool,
-
-  #[structopt(short = "S", long)]
-  /// generate search engine to find matches, ignores unmatched input
-  pub find: bool,
-
-  #[structopt(short = "+", long, required_if("noyywrap", "true"))]
-  /// Generate Flex-compatible C++ scanner
-  pub flex: bool,
-
-  #[structopt(short = "x", long)]
-  /// ignore space in patterns
-  pub freespace: bool,
-
-  #[structopt(short, long)]
-  /// Generate full scanner with FSM opcode tables
-  pub full: bool,
-
-  #[structopt(name = "FILE", default_value = "STDIN")]
-  /// The scanner specification file
-  pub in_file: String,
-
-  #[structopt(long)]
-  /// include header FILE.h for custom matcher option -m
-  pub include: Option<String>,
-
-  // todo: option alias
-  #[structopt(short = "I", long)]
+/// The part of Lesk that reads an option.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+pub enum Subsystem {
+  /// Reading the specification and the patterns in it
+  Parser,
+  /// The automaton built from the patterns and how it matches
+  Scanner,
+  /// The code of the generated scanner
+  Generator,
+  /// The files written
+  Output,
+  /// The warnings reported and the logging
+  Diagnostics,
+  /// Nothing: the option is accepted for compatibility with flex and otherwise has no effect
+  Obsolete,
+}
+
+/// Where the value of an option was set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OptionSource {
+  /// An `%option` of the specification, with its line and its text
+  Specification { line: u32, text: String },
+  /// The project configuration file, `Lesk.toml`, at the path
+  Config(String),
+  /// The command line
+  CommandLine,
+  /// `OptionsBuilder`, for use as a library
+  Builder,
+}
+
+impl OptionSource {
+  /// The precedence of the source. A setting does not override one from a source of higher
+  /// precedence, so that the command line overrides `Lesk.toml`, which overrides the
+  /// specification.
+  pub fn precedence(&self) -> u8 {
+    match self {
+      OptionSource::Specification { .. } => 0,
+      OptionSource::Config(_)            => 1,
+      OptionSource::CommandLine          => 2,
+      OptionSource::Builder              => 2,
+    }
+  }
+}
+
+impl Display for OptionSource {
+  fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+    match self {
+      OptionSource::Specification { line, text } => {
+        write!(fmt, "`%option {}` on line {}", text, line)
+      }
+      OptionSource::Config(path) => write!(fmt, "{}", path),
+      OptionSource::CommandLine  => write!(fmt, "the command line"),
+      OptionSource::Builder      => write!(fmt, "`OptionsBuilder`"),
+    }
+  }
+}
+
+/// A setting of an option, and whether it took effect when it was made.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Setting {
+  pub source : OptionSource,
+  pub applied: bool,
+}
+
+/// Every setting of each option, in the order they were made, keyed by the option's long flag.
+/// An option without settings has its default value.
+#[derive(Clone, Debug, Default)]
+pub struct Provenance {
+  settings: BTreeMap<&'static str, Vec<Setting>>,
+}
+
+impl Provenance {
+  /// Records that `source` set the option of `flag`, returning whether the setting takes effect,
+  /// which it does unless a setting from a source of higher precedence already has.
+  pub fn record(&mut self, flag: &'static str, source: OptionSource) -> bool {
+    let settings = self.settings.entry(flag).or_default();
+    let applied  = settings.iter()
+                           .filter(|setting| setting.applied)
+                           .all(|setting| setting.source.precedence() <= source.precedence());
+    settings.push(Setting { source, applied });
+    applied
+  }
+
+  /// The settings of the option of `flag`, in the order they were made.
+  pub fn settings(&self, flag: &str) -> &[Setting] {
+    self.settings.get(flag).map_or(&[], |settings| settings.as_slice())
+  }
+
+  /// Where the value of the option of `flag` came from, or `None` for its default.
+  pub fn source(&self, flag: &str) -> Option<&OptionSource> {
+    self.settings(flag).iter().rev().find(|setting| setting.applied).map(|setting| &setting.source)
+  }
+}
+
+impl OptionField {
   /**
-  Generate interactive scanner. Related options:
-    always-interactive
-    never_interactive
+  The inline modifier flag of an option that changes how patterns are read, with whether it is
+  turned on, or `None` for every other option. `%option case-insensitive` is `('i', true)`, which
+  the regex engine reads as `(?i:…)`.
   */
-  pub interactive: bool, //
+  pub fn modifier(&self) -> Option<(char, bool)> {
+    match self {
+      OptionField::CaseInsensitive(v) => Some(('i', *v)),
+      OptionField::Dotall(v)          => Some(('s', *v)),
+      OptionField::Freespace(v)       => Some(('x', *v)),
+      OptionField::Unicode(v)         => Some(('u', *v)),
+      _ => None
+    }
+  }
+}
+
+
+/// An artifact `--emit` can select. The code of the scanner is written to the output file, and
+/// each of the others to a file of its own; see `Options::artifact_path`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum Emit {
+  /// The generated scanner
+  Code,
+  /// The start condition graph in Graphviz DOT format
+  Graph,
+  /// The tables the scanner is driven by
+  Tables,
+  /// The parsed specification as JSON, in the form of `ast::Ast`
+  Json,
+  /// The regex of each start condition, one per line
+  Regexp,
+  /// The `extern` block of a LALRPOP grammar that takes the lexer, declaring its tokens
+  Lalrpop,
+}
+
+impl Emit {
+  pub const ALL: [Emit; 6] =
+    [Emit::Code, Emit::Graph, Emit::Tables, Emit::Json, Emit::Regexp, Emit::Lalrpop];
+
+  /// The name of the artifact, as `--emit` is given it.
+  pub fn name(self) -> &'static str {
+    match self {
+      Emit::Code    => "code",
+      Emit::Graph   => "graph",
+      Emit::Tables  => "tables",
+      Emit::Json    => "json",
+      Emit::Regexp  => "regexp",
+      Emit::Lalrpop => "lalrpop",
+    }
+  }
+}
+
+impl FromStr for Emit {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "code"    => Ok(Emit::Code),
+      "graph"   => Ok(Emit::Graph),
+      "tables"  => Ok(Emit::Tables),
+      "json"    => Ok(Emit::Json),
+      "regexp"  => Ok(Emit::Regexp),
+      "lalrpop" => Ok(Emit::Lalrpop),
+      _ => {
+        Err(format!(
+          "unknown artifact `{}`, expected code, graph, tables, json, regexp, or lalrpop",
+          text
+        ))
+      }
+    }
+  }
+}
+
+
+/// How diagnostics are printed, chosen by `--message-format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum MessageFormat {
+  /// Rendered for a person at a terminal, with the source lines they refer to
+  Human,
+  /// One JSON object per line, for editors and CI to consume
+  Json,
+}
+
+impl FromStr for MessageFormat {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "human" => Ok(MessageFormat::Human),
+      "json"  => Ok(MessageFormat::Json),
+      _       => Err(format!("unknown message format `{}`, expected human or json", text)),
+    }
+  }
+}
+
+
+/// When diagnostics are colored, chosen by `--color`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum ColorWhen {
+  /// When standard error is a terminal and the `NO_COLOR` environment variable is not set
+  Auto,
+  Always,
+  Never,
+}
+
+impl FromStr for ColorWhen {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "auto"   => Ok(ColorWhen::Auto),
+      "always" => Ok(ColorWhen::Always),
+      "never"  => Ok(ColorWhen::Never),
+      _        => Err(format!("unknown color choice `{}`, expected auto, always, or never", text)),
+    }
+  }
+}
+
+
+/// How `--timings` reports the time each phase took.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum TimingsFormat {
+  /// A table for a person to read
+  Table,
+  /// A JSON object mapping each phase to milliseconds
+  Json,
+}
+
+impl FromStr for TimingsFormat {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "table" => Ok(TimingsFormat::Table),
+      "json"  => Ok(TimingsFormat::Json),
+      _       => Err(format!("unknown timings format `{}`, expected table or json", text)),
+    }
+  }
+}
+
+
+/// The type of the value an `OptionField` of each kind of `declare_options!` carries.
+macro_rules! option_payload {
+  (bool)     => { bool };
+  (inverted) => { bool };
+  (count)    => { bool };
+  (number)   => { u8 };
+  (string)   => { String };
+  (file)     => { String };
+  (list)     => { String };
+}
+
+/**
+Sets the field of `Options` at `$place` from the value of its `OptionField`: an `inverted` field
+holds the negation of its `%option`, a `count` is turned on at its lowest count, a `file` is set to
+the file named, and a `list` is added to.
+*/
+macro_rules! update_option {
+  ($place:expr, bool, $value:ident)     => { $place = $value };
+  ($place:expr, inverted, $value:ident) => { $place = !$value };
+  ($place:expr, count, $value:ident)    => { $place = if $value { $place.max(1) } else { 0 } };
+  ($place:expr, number, $value:ident)   => { $place = $value };
+  ($place:expr, string, $value:ident)   => { $place = Some($value) };
+  ($place:expr, file, $value:ident)     => { $place = Some(Some($value)) };
+  ($place:expr, list, $value:ident)     => { $place.push($value) };
+}
+
+/// The `OptionKind` by which an `%option` sets a field of each kind.
+macro_rules! option_kind {
+  (bool, $variant:ident)     => { OptionKind::Bool(OptionField::$variant) };
+  (inverted, $variant:ident) => { OptionKind::Bool(OptionField::$variant) };
+  (count, $variant:ident)    => { OptionKind::Bool(OptionField::$variant) };
+  (number, $variant:ident)   => { OptionKind::Number(OptionField::$variant) };
+  (string, $variant:ident)   => { OptionKind::String(OptionField::$variant) };
+  (file, $variant:ident)     => { OptionKind::String(OptionField::$variant) };
+  (list, $variant:ident)     => { OptionKind::String(OptionField::$variant) };
+}
+
+/// The values of a field of `Options` of each kind that has values to validate, as strings.
+macro_rules! option_values {
+  ($place:expr, number) => { vec![$place.to_string()] };
+  ($place:expr, string) => { $place.iter().cloned().collect::<Vec<String>>() };
+  ($place:expr, file)   => { $place.iter().flatten().cloned().collect::<Vec<String>>() };
+  ($place:expr, list)   => { $place.clone() };
+}
+
+/// The value of a field of `Options` of each kind, as `--explain-option` prints it.
+macro_rules! option_display {
+  ($place:expr, bool)     => { $place.to_string() };
+  ($place:expr, inverted) => { $place.to_string() };
+  ($place:expr, count)    => { $place.to_string() };
+  ($place:expr, number)   => { $place.to_string() };
+  ($place:expr, string)   => { $place.as_deref().unwrap_or("none").to_string() };
+  ($place:expr, file)     => {
+    match &$place {
+      Some(Some(file)) => file.clone(),
+      Some(None)       => "on, named after the output file".to_string(),
+      None             => "off".to_string(),
+    }
+  };
+  ($place:expr, list)     => { format!("[{}]", $place.join(", ")) };
+}
+
+/// The value of a field of `Options` of each kind in a `Lesk.toml`, or `None` if it is not set.
+macro_rules! option_toml {
+  ($place:expr, bool)     => { Some(Value::Boolean($place)) };
+  ($place:expr, inverted) => { Some(Value::Boolean($place)) };
+  ($place:expr, count)    => { Some(Value::Integer($place.into())) };
+  ($place:expr, number)   => { Some(Value::Integer($place.into())) };
+  ($place:expr, string)   => { $place.clone().map(Value::String) };
+  ($place:expr, file)     => {
+    match &$place {
+      Some(Some(file)) => Some(Value::String(file.clone())),
+      Some(None)       => Some(Value::Boolean(true)),
+      None             => None,
+    }
+  };
+  ($place:expr, list)     => {
+    Some(Value::Array($place.iter().cloned().map(Value::String).collect()))
+  };
+}
+
+/// Whether a field of each kind is set by how many times its flag is given.
+macro_rules! counted {
+  (count)       => { true };
+  ($kind:ident) => { false };
+}
+
+/// Whether a flag is marked `negates` in the table of `declare_options!`.
+macro_rules! negates {
+  ()        => { false };
+  (negates) => { true };
+}
+
+/// Generates the setter of the builder for a field of `Options` of each kind. A `list` has none;
+/// those that are wanted add to the list instead and are written out.
+macro_rules! setter {
+  (bool, $field:ident $(, $flag:tt)?) => {
+    pub fn $field(mut self, value: bool) -> Self {
+      $( self.options.provenance.record($flag, OptionSource::Builder); )?
+      self.options.$field = value;
+      self
+    }
+  };
+  (inverted, $field:ident $(, $flag:tt)?) => { setter!(bool, $field $(, $flag)?); };
+  (number, $field:ident $(, $flag:tt)?) => {
+    pub fn $field(mut self, value: u8) -> Self {
+      $( self.options.provenance.record($flag, OptionSource::Builder); )?
+      self.options.$field = value;
+      self
+    }
+  };
+  (count, $field:ident $(, $flag:tt)?) => { setter!(number, $field $(, $flag)?); };
+  (string, $field:ident $(, $flag:tt)?) => {
+    pub fn $field<S: Into<String>>(mut self, value: S) -> Self {
+      $( self.options.provenance.record($flag, OptionSource::Builder); )?
+      self.options.$field = Some(value.into());
+      self
+    }
+  };
+  (file, $field:ident $(, $flag:tt)?) => {
+    /// `None` derives the file name from the output file.
+    pub fn $field(mut self, value: Option<String>) -> Self {
+      $( self.options.provenance.record($flag, OptionSource::Builder); )?
+      self.options.$field = Some(value);
+      self
+    }
+  };
+  (list, $field:ident $(, $flag:tt)?) => {};
+}
+
+/// Generates setters of the builder for fields of `Options` that are not in the table of
+/// `declare_options!`, by kind.
+macro_rules! setters {
+  ($($kind:ident: $($field:ident),*;)*) => {
+    $($( setter!($kind, $field); )*)*
+  };
+}
+
+/**
+Declares every option in one table, from which the `Options` struct the command line is parsed
+into, `OptionField`, `Options::update`, `OptionField::flag` and `OptionField::subsystem`, the
+setters of `OptionsBuilder`, and the `%option` names of `OPTIONS` are all generated, so that they
+cannot disagree. A row of `options` reads
+
+```text
+/// specify output FILE instead of lex.yy.rs
+out_file: [Option<String>] => OutFile(string) in Output,
+  flag "out-file" (short), %option ["outfile"];
+```
+
+giving the field with its type and an optional `= "default"`, the `OptionField` variant with the
+kind of its value, the subsystem that reads it, the long flag, marked `negates` if it turns the
+option off, with any further arguments to clap, the function that checks its value, written
+`valid check`, and the `%option` names, followed by
+`not [...]` for names that turn it off. The kinds are `bool`, `inverted` for a field that holds the
+negation of its `%option`, `count`, `number`, `string`, `file` for an artifact whose file name is
+optional, and `list`. The `cli` section holds the fields with no `%option`, as they are written
+for clap, `legacy` the flex options Lesk accepts and ignores, `unsupported` those it rejects,
+with how to do without them, and `shared` the `%option`s that set more than one field.
+*/
+macro_rules! declare_options {
+  (
+    cli {
+      $(
+        $(#[$cli_attr:meta])*
+        $cli_field:ident: [$($cli_ty:tt)+],
+      )*
+    }
+    options {
+      $(
+        $(#[doc = $doc:tt])*
+        $field:ident: [$($ty:tt)+] $(= $default:tt)?
+          => $variant:ident($kind:ident) in $subsystem:ident,
+          flag $flag:tt $($negates:ident)? $(($($arg:tt)*))? $(, valid $validator:ident)?
+          $(, %option [$($name:tt),*] $(not [$($negated:tt),*])?)?;
+      )*
+    }
+    legacy [$($legacy:tt),* $(,)?]
+    unsupported {
+      $($unsupported:tt => $help:tt,)*
+    }
+    shared {
+      $($shared:tt => $shared_kind:ident($($shared_field:ident),*),)*
+    }
+  ) => {
+    // The command line is parsed by clap's derive. Each argument is named after its field, which
+    // `record_command_line` looks it up by. `option_table` lists the `%option` equivalent of each
+    // flag in `--help`.
+    #[derive(Clone, Debug, Parser, Serialize)]
+    #[clap(name = "Lesk", about = "A lexer generator.")]
+    pub struct Options {
+      $(
+        $(#[$cli_attr])*
+        pub $cli_field: $($cli_ty)+,
+      )*
+      $(
+        $(#[doc = $doc])*
+        #[clap(
+          name = stringify!($field), long = $flag $(, default_value = $default)?
+          $(, validator = $validator)? $(, $($arg)*)?
+        )]
+        pub $field: $($ty)+,
+      )*
+    }
+
+    #[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize)]
+    pub enum OptionField {
+      /// The specification file, which an `%option` cannot change
+      InFile(String),
+      $(
+        $(#[doc = $doc])*
+        $variant(option_payload!($kind)),
+      )*
+      /// A flex option that Lesk accepts and ignores
+      Legacy(&'static str),
+    }
+
+    impl OptionField {
+      /**
+      The long command line flag that sets the same option, with whether the flag turns the option
+      off, as `--nowarn` does `%option warn`, or `None` for the options the command line lacks.
+      */
+      pub fn flag(&self) -> Option<(&'static str, bool)> {
+        match self {
+          OptionField::InFile(_) | OptionField::Legacy(_) => None,
+          $( OptionField::$variant(_) => Some(($flag, negates!($($negates)?))), )*
+        }
+      }
+
+      /// The part of Lesk that reads the option, or `None` for those that no part reads.
+      pub fn subsystem(&self) -> Option<Subsystem> {
+        match self {
+          OptionField::InFile(_) | OptionField::Legacy(_) => None,
+          $( OptionField::$variant(_) => Some(Subsystem::$subsystem), )*
+        }
+      }
+
+      /// Checks the value of the option, returning why it is not valid if it is not.
+      pub fn validate(&self) -> Result<(), String> {
+        match self {
+          $($( OptionField::$variant(v) => $validator(&v.to_string()), )?)*
+          _ => Ok(()),
+        }
+      }
+    }
+
+    impl Options {
+      /**
+      Update the values of self with those of other, which `source` set, recording where each
+      came from. A value is not changed by a source of lower precedence than the one that set it.
+      The `OptionSet` `other` is consumed.
+      */
+      pub fn update(&mut self, other: OptionSet, source: OptionSource) {
+        for field in other {
+          if let Some((flag, _)) = field.flag() {
+            if !self.provenance.record(flag, source.clone()) {
+              continue;
+            }
+          }
+          match field {
+            OptionField::InFile(_) => { /* in_file cannot change. */ }
+            OptionField::Legacy(_) => { /* pass */ }
+            $( OptionField::$variant(v) => { update_option!(self.$field, $kind, v); } )*
+          }
+        }
+      }
+
+      /// The value of the option of the long flag `flag`, as `--explain-option` prints it.
+      pub fn value_of(&self, flag: &str) -> Option<String> {
+        match flag {
+          $( $flag => Some(option_display!(self.$field, $kind)), )*
+          _ => None,
+        }
+      }
+
+      /// The value of each option of the table that is set, keyed by its flag, for `to_toml`.
+      fn toml_table(&self) -> Table {
+        let mut table = Table::new();
+        $(
+          if let Some(value) = option_toml!(self.$field, $kind) {
+            table.insert($flag.to_string(), value);
+          }
+        )*
+        table
+      }
+
+      /// Whether the flag `flag` is given as many times as the count its option is set to.
+      fn is_counted(flag: &str) -> bool {
+        match flag {
+          $( $flag => counted!($kind), )*
+          _ => false,
+        }
+      }
+
+      /// Why each value of `self` that is not valid is not, naming its flag. The command line
+      /// checks its own values, so these are set through the builder.
+      fn invalid_settings(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+        $($(
+          for value in option_values!(self.$field, $kind) {
+            if let Err(message) = $validator(&value) {
+              messages.push(format!("`--{}`: {}", $flag, message));
+            }
+          }
+        )?)*
+        messages
+      }
+    }
+
+    impl OptionsBuilder {
+      $( setter!($kind, $field, $flag); )*
+    }
+
+    /// The field of each option in the table, which names its argument for clap, with its flag.
+    static OPTION_ARGUMENTS: &[(&str, &str)] = &[ $( (stringify!($field), $flag), )* ];
+
+    pub static OPTIONS: phf::Map<&'static str, OptionKind> = phf_map! {
+      $(
+        $($( $name => option_kind!($kind, $variant), )*)?
+        $($($( $negated => OptionKind::NegatedBool(OptionField::$variant), )*)?)?
+      )*
+      $( $legacy => OptionKind::Legacy, )*
+      $( $unsupported => OptionKind::Unsupported($help), )*
+      $( $shared => OptionKind::$shared_kind($(OptionField::$shared_field),*), )*
+    };
+  };
+}
+
+declare_options! {
+  cli {
+    #[clap(long)]
+    /// accept flex specifications, ignoring flex directives that have no effect with a warning
+    flex_compat: [bool],
+
+    #[clap(name = "FILE", default_value = "-")]
+    /// The scanner specification file, or - for standard input
+    in_file: [String],
+
+    #[clap(short = 'D', long = "define", number_of_values = 1)]
+    /// define FEATURE for `%if FEATURE` sections of the specification, may be repeated
+    define: [Vec<String>],
+
+    #[clap(long, value_delimiter = ',')]
+    /// the artifacts to write: code, graph, tables, json, regexp, lalrpop. The default is code
+    emit: [Vec<Emit>],
+
+    #[clap(long)]
+    /// print the specification in canonical form instead of generating a scanner
+    fmt: [bool],
+
+    #[clap(long)]
+    /// check the specification for errors and warnings without writing any output
+    check: [bool],
+
+    #[clap(long)]
+    /// apply the fixes suggested by diagnostics to the specification file, then report what remains
+    fix: [bool],
+
+    #[clap(long)]
+    /// guarantee reproducible output: name the specification without its directory in the banner,
+    /// and fail if generating an artifact twice gives different output
+    deterministic: [bool],
+
+    #[clap(long, value_name = "N")]
+    /// stop collecting errors after N of them, reporting how many more there were; 0, the
+    /// default, is no limit
+    error_limit: [Option<usize>],
+
+    #[clap(long)]
+    /// stop at the first error, as `--error-limit 1` does, and with several specifications, do not
+    /// build those after the first that fails
+    fail_fast: [bool],
+
+    #[clap(long, default_value = "human")]
+    /// print diagnostics rendered for the terminal, human, or as one JSON object per line, json
+    message_format: [MessageFormat],
+
+    #[clap(long, default_value = "auto")]
+    /// color diagnostics: auto, when standard error is a terminal and NO_COLOR is not set, always,
+    /// or never
+    color: [ColorWhen],
+
+    #[clap(long)]
+    /// report the time each phase took to stderr, as a table or, with --timings=json, as JSON
+    timings: [Option<Option<TimingsFormat>>],
+
+    #[clap(long)]
+    /// write the specification as a flex specification to FILE instead of generating a scanner
+    export_flex: [Option<String>],
+
+    #[clap(long, value_name = "NAME")]
+    /// print the value of the option NAME, a flag or an `%option` name, and where it was set,
+    /// among the specification, Lesk.toml, and the command line, instead of generating a scanner
+    explain_option: [Option<String>],
+
+    #[clap(long)]
+    /// print the options in effect, with the specification's `%option`s, as a Lesk.toml instead
+    /// of generating a scanner
+    print_config: [bool],
+
+    #[clap(skip)]
+    #[serde(skip)]
+    /// Where each option was set
+    provenance: [Provenance],
+  }
+
+  options {
+    // region Scanner
+
+    /// Generate scanner for batch input by buffering the entire input
+    batch: [bool] => Batch(bool) in Scanner,
+      flag "batch" (short = 'B'), %option ["batch"];
+
+    /// Ignore case in patterns
+    case_insensitive: [bool] => CaseInsensitive(bool) in Parser,
+      flag "case-insensitive" (short = 'i'),
+      %option ["caseless", "case-insensitive"] not ["caseful", "case-sensitive"];
+
+    /// Dot in patterns match newline
+    dotall: [bool] => Dotall(bool) in Parser,
+      flag "dotall" (short = 'a'), %option ["dotall"];
+
+    /// Generate fast scanner with FSM code
+    fast: [bool] => Fast(bool) in Scanner,
+      flag "fast" (short = 'F'), %option ["fast"];
+
+    /// generate search engine to find matches, ignores unmatched input
+    find: [bool] => Find(bool) in Scanner,
+      flag "find" (short = 'S'), %option ["find"];
+
+    /// Generate Flex-compatible C++ scanner
+    flex: [bool] => Flex(bool) in Generator,
+      flag "flex" (short = '+'), %option ["flex"];
+
+    /// ignore space in patterns
+    freespace: [bool] => Freespace(bool) in Parser,
+      flag "freespace" (short = 'x'), %option ["freespace"];
+
+    /// Generate full scanner with FSM opcode tables
+    full: [bool] => Full(bool) in Scanner,
+      flag "full" (short), %option ["full"];
+
+    /// include header FILE.h for custom matcher option -m
+    include: [Option<String>] => Include(string) in Generator,
+      flag "include", %option ["include"];
 
-  // todo: selectable regex engine
-  //#[structopt(short, long)]
-  //#[parse(type [= path::to::parser::fn])]
-  // /// Which regex backend to use
-  //matcher: RegexEngine,
+    /// search DIR for files named in %include, may be repeated
+    include_path: [Vec<String>] => IncludePath(list) in Parser,
+      flag "include-path" (number_of_values = 1), %option ["include-path"];
 
-  #[structopt(long)]
-  /// use custom pattern class NAME for custom matcher option -m
-  pub pattern: Option<String>,
+    // todo: It's not clear what to do about these *interactive synonyms.
+    /// Generate interactive scanner, as `%option always-interactive` also does
+    interactive: [bool] => Interactive(bool) in Scanner,
+      flag "interactive" (short = 'I'),
+      %option ["always-interactive", "interactive"] not ["never-interactive"];
 
-  #[structopt(short = "T", long, default_value = "2")]
-  /// set default tab size to N (2,4,8) for indent/dedent matching
-  pub tabs: u8,
+    /// use the regex engine NAME to match the patterns, regex if not given
+    matcher: [Option<String>] => Matcher(string) in Scanner,
+      flag "matcher" (short = 'm', value_name = "NAME"), valid valid_matcher,
+      %option ["matcher"];
 
-  #[structopt(short = "u", long)]
-  /// match Unicode . (dot), \\p, \\s, \\w, etc and group UTF-8 bytes
-  pub unicode: bool,
+    /// use custom pattern class NAME for custom matcher option -m
+    pattern: [Option<String>] => Pattern(string) in Generator,
+      flag "pattern", %option ["pattern"];
 
-  // endregion
+    /// set default tab size to N (2,4,8) for indent/dedent matching
+    tabs: [u8] = "2" => Tabs(number) in Parser,
+      flag "tabs" (short = 'T'), valid valid_tabs, %option ["tabs"];
 
-  //region Generated Files
+    /// match Unicode . (dot), \\p, \\s, \\w, etc and group UTF-8 bytes
+    unicode: [bool] => Unicode(bool) in Parser,
+      flag "unicode" (short = 'u'), %option ["unicode"];
 
-  #[structopt(long)]
-  /// write the scanner's DFA in Graphviz format to FILE.gv
-  pub graphs_file: Option<Option<String>>,
+    // endregion
 
-  //#[structopt(short, long, parse(from_os_str))]
-  ///// write a C++ header FILE.h in addition to the scanner
-  //header_file: Option<Option<String>>,
+    // region Generated Files
 
-  #[structopt(short, long)]
-  /// specify output FILE instead of lex.yy.cpp
-  pub out_file: Option<String>,
+    /// write the graph of `--emit graph` to FILE instead of one named after the output file
+    graphs_file: [Option<Option<String>>] => GraphsFile(file) in Output,
+      flag "graphs-file", valid valid_graph_file, %option ["graphs_file"];
 
-  #[structopt(long)]
-  /// write the scanner's regular expression patterns to FILE.txt
-  pub regexp_file: Option<Option<String>>,
+    /// specify output FILE instead of lex.yy.rs, or - for standard output, or with several
+    /// specifications the directory to write their scanners to
+    out_file: [Option<String>] => OutFile(string) in Output,
+      flag "out-file" (short), valid valid_out_file, %option ["outfile"];
 
-  #[structopt(long)]
-  /// override Lesk's decision as to whether you use the options, either by setting them (e.g.,
-  /// %option reject) to indicate the feature is indeed used
-  pub reject: bool,
+    /// write the regexes of `--emit regexp` to FILE instead of one named after the output file
+    regexp_file: [Option<Option<String>>] => RegexpFile(file) in Output,
+      flag "regexp-file", %option ["regexp_file"];
 
-  #[structopt(short = "t", long)]
-  /// write scanner on stdout instead of lex.yy.cpp
-  pub stdout: bool,
+    /// override Lesk's decision as to whether you use the options, either by setting them (e.g.,
+    /// %option reject) to indicate the feature is indeed used
+    reject: [bool] => Reject(bool) in Generator,
+      flag "reject", %option ["reject"];
 
-  #[structopt(long)]
-  /// write the scanner's FSM opcode tables or FSM code to FILE.cpp
-  pub tables_file: Option<Option<String>>,
-  // endregion
+    /// write scanner on stdout instead of lex.yy.rs
+    stdout: [bool] => Stdout(bool) in Output,
+      flag "stdout" (short = 't'), %option ["stdout"];
 
-  // region Generated Code
+    /// write the tables of `--emit tables` to FILE instead of one named after the output file
+    tables_file: [Option<Option<String>>] => TablesFile(file) in Output,
+      flag "tables-file", valid valid_rust_file, %option ["tables-file"];
 
-  #[structopt(long)]
-  /// use C++ namespace NAME for the generated scanner class, with multiple
-  /// namespaces specified as NAME1.NAME2.NAME3 ...
-  pub namespace: Option<String>,
+    // endregion
 
-  #[structopt(long)]
-  /// use lexer class NAME instead of Lexer or yyFlexLexer
-  pub lexer: Option<String>,
+    // region Generated Code
 
-  #[structopt(long)]
-  /// use lex function NAME instead of lex or yylex
-  pub lex: Option<String>,
+    /// use C++ namespace NAME for the generated scanner class, with multiple
+    /// namespaces specified as NAME1.NAME2.NAME3 ...
+    namespace: [Option<String>] => Namespace(string) in Generator,
+      flag "namespace", valid valid_namespace, %option ["namespace"];
 
-  #[structopt(long)]
-  /// declare a user-defined scanner class NAME
-  pub class: Option<String>,
+    /// use lexer class NAME instead of Lexer or yyFlexLexer
+    lexer: [Option<String>] => Lexer(string) in Generator,
+      flag "lexer", valid valid_identifier, %option ["lexer"];
 
-  #[structopt(long)]
-  /// generate Flex-compatible scanner with user-defined class NAME
-  pub yyclass: Option<String>,
+    /// use lex function NAME instead of lex or yylex
+    lex: [Option<String>] => Lex(string) in Generator,
+      flag "lex", valid valid_identifier, %option ["lex"];
 
-  #[structopt(long)]
-  /// generate main() to invoke lex() or yylex() once
-  pub main: bool,
+    /// declare a user-defined scanner class NAME
+    class: [Option<String>] => Class(string) in Generator,
+      flag "class", valid valid_identifier, %option ["class"];
 
-  #[structopt(short = "L", long="noline")]
-  /// suppress #line directives in scanner
-  pub line: bool,
+    /// generate Flex-compatible scanner with user-defined class NAME
+    yyclass: [Option<String>] => Yyclass(string) in Generator,
+      flag "yyclass", valid valid_identifier, %option ["yyclass"];
 
-  #[structopt(short = "P", long)]
-  /// use NAME as prefix of the FlexLexer class name and its members
-  pub prefix: Option<String>,
+    /// generate main() to invoke lex() or yylex() once
+    main: [bool] => Main(bool) in Generator,
+      flag "main", %option ["main"];
 
-  #[structopt(long="nostdinit")]
-  /// initialize input to std::cin instead of stdin
-  pub stdinit: bool,
+    /// suppress #line directives in scanner
+    line: [bool] => Line(inverted) in Generator,
+      flag "noline" negates (short = 'L'), %option ["line"];
 
-  #[structopt(long)]
-  /// generate global yylex() scanner, yytext, yyleng, yylineno
-  pub bison: bool,
+    /// use NAME as prefix of the scanner's names: NameLexer, NameToken, NAME_INITIAL
+    prefix: [Option<String>] => Prefix(string) in Generator,
+      flag "prefix" (short = 'P'), valid valid_identifier, %option ["prefix"];
 
-  #[structopt(long)]
-  /// generate reentrant yylex() scanner for bison pure parser
-  pub bison_bridge: bool,
+    /// initialize input to std::cin instead of stdin
+    stdinit: [bool] => Stdinit(inverted) in Generator,
+      flag "nostdinit" negates, %option ["stdinit"];
 
-  #[structopt(long)]
-  /// generate bison C++ interface code for bison lalr1.cc skeleton
-  pub bison_cc: bool,
+    /// generate global yylex() scanner, yytext, yyleng, yylineno
+    bison: [bool] => Bison(bool) in Generator,
+      flag "bison", %option ["bison"];
+
+    /// generate reentrant yylex() scanner for bison pure parser
+    bison_bridge: [bool] => BisonBridge(bool) in Generator,
+      flag "bison-bridge", %option ["bison_bridge"];
+
+    /// generate bison C++ interface code for bison lalr1.cc skeleton
+    bison_cc: [bool] => BisonCc(bool) in Generator,
+      flag "bison-cc", %option ["bison_cc"];
 
-  #[structopt(long)]
-  /// use namespace NAME with bison lalr1.cc skeleton
-  pub bison_cc_namespace: Option<String>,
+    /// use namespace NAME with bison lalr1.cc skeleton
+    bison_cc_namespace: [Option<String>] => BisonCcNamespace(string) in Generator,
+      flag "bison-cc-namespace", valid valid_namespace, %option ["bison_cc_namespace"];
 
-  #[structopt(long)]
-  /// use parser class NAME with bison lalr1.cc skeleton
-  pub bison_cc_parser: Option<String>,
+    /// use parser class NAME with bison lalr1.cc skeleton
+    bison_cc_parser: [Option<String>] => BisonCcParser(string) in Generator,
+      flag "bison-cc-parser", valid valid_identifier, %option ["bison_cc_parser"];
 
-  #[structopt(long)]
-  /// use bison complete-symbols feature, implies bison-cc
-  pub bison_complete: bool,
+    /// use bison complete-symbols feature, implies bison-cc
+    bison_complete: [bool] => BisonComplete(bool) in Generator,
+      flag "bison-complete", %option ["bison_complete"];
 
-  #[structopt(long)]
-  /// include bison yylloc support
-  pub bison_locations: bool,
+    /// include bison yylloc support
+    bison_locations: [bool] => BisonLocations(bool) in Generator,
+      flag "bison-locations", %option ["bison_locations"];
 
-  #[structopt(short = "R", long)]
-  /// generate Flex-compatible yylex() reentrant scanner functions
-  pub reentrant: bool,
+    /// make the lexer an iterator of the `Result<(usize, Token, usize), _>` items a LALRPOP
+    /// parser takes from an external lexer; `--emit lalrpop` writes the grammar's `extern` block
+    lalrpop: [bool] => Lalrpop(bool) in Generator,
+      flag "lalrpop", %option ["lalrpop"];
 
-  #[structopt(short, long)]
-  /// same as --flex and --bison, also generate global yyin, yyout
-  pub yy: bool,
+    /// generate a scanner for a `no_std` crate with `alloc`, which neither reads nor writes
+    no_std: [bool] => Std(inverted) in Generator,
+      flag "no-std" negates, %option ["std"];
 
-  #[structopt(long="noyywrap")]
-  /// do not call global yywrap() on EOF, requires option --flex
-  pub yywrap: bool,
+    /// generate Flex-compatible yylex() reentrant scanner functions
+    reentrant: [bool] => Reentrant(bool) in Generator,
+      flag "reentrant" (short = 'R'), %option ["reentrant"];
 
-  #[structopt(long)]
-  /// use exception VALUE to throw in the default rule of the scanner
-  pub exception: Option<String>,
+    /// same as --flex and --bison, also generate global yyin, yyout
+    yy: [bool] => Yy(bool) in Generator,
+      flag "yy" (short), %option ["yy"];
 
-  #[structopt(long)]
-  /// use NAME as the return type of lex() and yylex() instead of int
-  pub token_type: Option<String>,
-  // endregion
+    /// do not call global yywrap() on EOF, requires option --flex
+    yywrap: [bool] => Yywrap(inverted) in Generator,
+      flag "noyywrap" negates, %option ["yywrap"];
 
-  // region Debugging
+    /// use exception VALUE as the token the default rule returns for unmatched text
+    exception: [Option<String>] => Exception(string) in Generator,
+      flag "exception", %option ["exception"];
 
-  #[structopt(short, long)]
-  /// enable debug mode in scanner
-  pub debug: bool,
+    /// use NAME as the return type of lex() and yylex() instead of int; NAME must implement
+    /// Default, whose value lex() returns at the end of the input
+    token_type: [Option<String>] => TokenType(string) in Generator,
+      flag "token-type", %option ["token_type"];
 
-  #[structopt(short, long)]
-  /// scanner reports detailed performance statistics to stderr
-  pub perf_report: bool,
+    /// generate a start condition stack with `push_state`, `pop_state` and `top_state`
+    stack: [bool] => Stack(bool) in Generator,
+      flag "stack", %option ["stack"];
 
-  #[structopt(short = "s", long="nodefault")]
-  /// disable the default rule in scanner that echoes unmatched text
-  pub default: bool,
+    // endregion
+
+    // region Debugging
 
-  #[structopt(short, long)]
-  /// report summary of scanner statistics to stdout
-  pub verbose: bool,
+    /// enable debug mode in scanner
+    debug: [bool] => Debug_(bool) in Generator,
+      flag "debug" (short), %option ["debug"];
 
-  #[structopt(short = "w", long="nowarn")]
-  /// do not generate warnings
-  pub warn: bool,
-  // endregion
+    /// scanner reports detailed performance statistics to stderr
+    perf_report: [bool] => PerfReport(bool) in Generator,
+      flag "perf-report" (short), %option ["perf-report"];
 
-  // region Obsolete or Unsettable
+    /// disable the default rule in scanner that echoes unmatched text
+    nodefault: [bool] => Default(inverted) in Generator,
+      flag "nodefault" negates (short = 's'), %option ["default"];
 
-  #[structopt(long)]
-  /// n/a
-  pub cpp: bool,
+    /// report summary of scanner statistics to stderr; `-vv` also logs debugging output, and `-vvv`
+    /// traces every parsed item. `RUST_LOG` takes precedence for the logging
+    verbose: [u8] => Verbose(count) in Diagnostics,
+      flag "verbose" (short, parse(from_occurrences)), %option ["verbose"];
 
-  #[structopt(long)]
-  /// n/a
-  pub lex_compat: bool,
+    /// do not generate warnings
+    warn: [bool] => Warn(inverted) in Diagnostics,
+      flag "nowarn" negates (short = 'w');
 
-  // todo: It's not clear what to do about these *interactive synonyms.
-  // #[structopt(skip)]
-  // default
-  // pub never_interactive: bool,
+    /// do not report WARNING, a code such as `L0105` or a name such as `ignored-option`, or
+    /// `warnings` for all of them; may be repeated
+    allow: [Vec<String>] => Allow(list) in Diagnostics,
+      flag "allow" (value_name = "WARNING", number_of_values = 1, value_delimiter = ',',
+                    parse(try_from_str = parse_warning)),
+      %option ["allow"];
 
-  #[structopt(long="nounistd")]
-  /// n/a
-  pub unistd: bool,
+    /// report WARNING as a warning, overriding `--allow warnings`; may be repeated
+    warnings: [Vec<String>] => Warnings(list) in Diagnostics,
+      flag "warn" (value_name = "WARNING", number_of_values = 1, value_delimiter = ',',
+                   parse(try_from_str = parse_warning));
 
-  #[structopt(long)]
-  /// n/a
-  pub posix_compat: bool,
+    /// report WARNING as an error, so that no scanner is generated; may be repeated. Of `--allow`,
+    /// `--warn`, and `--deny` of the same warning, `--deny` wins, and any of them overrides the
+    /// `%option` of the same name
+    deny: [Vec<String>] => Deny(list) in Diagnostics,
+      flag "deny" (value_name = "WARNING", number_of_values = 1, value_delimiter = ',',
+                   parse(try_from_str = parse_warning)),
+      %option ["deny"];
 
-  #[structopt(long)]
-  /// n/a
-  pub stack: bool,
+    // endregion
+
+    // region Obsolete or Unsettable
 
-  #[structopt(long)]
-  /// Compute the line number while parsing - default
-  pub yylineno: bool,
+    /// n/a
+    cpp: [bool] => Cpp(bool) in Obsolete,
+      flag "cpp", %option ["c++"];
 
-  #[structopt(long)]
-  /// default
-  pub yymore: bool,
+    /// n/a
+    lex_compat: [bool] => LexCompat(bool) in Obsolete,
+      flag "lex-compat", %option ["lex-compat"];
 
-  // endregion
+    /// n/a
+    unistd: [bool] => Unistd(inverted) in Obsolete,
+      flag "nounistd" negates, %option ["unistd"];
+
+    /// n/a
+    posix_compat: [bool] => PosixCompat(bool) in Obsolete,
+      flag "posix-compat";
+
+    /// Compute the line number while parsing - default
+    yylineno: [bool] => Yylineno(bool) in Obsolete,
+      flag "yylineno", %option ["yylineno"];
+
+    /// default
+    yymore: [bool] => Yymore(bool) in Obsolete,
+      flag "yymore", %option ["yymore"];
+
+    // endregion
+  }
+
+  legacy [
+    "7bit", "8bit", "align", "array", "backup", "ctorarg", "ecs", "extra-type", "indent", "input",
+    "meta-ecs", "params", "permissive", "pointer", "posix-compat", "read", "tables-verify",
+    "tablesext", "token_eof", "unput", "yy_pop_state", "yy_push_state", "yy_scan_buffer",
+    "yy_scan_bytes", "yy_scan_string", "yy_top_state", "yyalloc", "yyfree", "yyget_column",
+    "yyget_debug", "yyget_extra", "yyget_in", "yyget_leng", "yyget_lineno", "yyget_lloc",
+    "yyget_lval", "yyget_out", "yyget_text", "yyltype", "yyrealloc", "yyset_column", "yyset_debug",
+    "yyset_extra", "yyset_in", "yyset_lineno", "yyset_lloc", "yyset_lval", "yyset_out", "yystype",
+  ]
+
+  unsupported {
+    "header-file" => HEADER_FILE_HELP,
+    "header_file" => HEADER_FILE_HELP,
+  }
+
+  shared {
+    "warn" => BoolOrString(Warn, Warnings),
+  }
 }
 
+/// The options of a command line naming no options and no file, for use as a library.
+impl Default for Options {
+  fn default() -> Self {
+    Options::parse_from(["lesk"])
+  }
+}
+
+/// A combination of options that cannot be used together.
+struct Conflict {
+  /// Whether the conflict is present in the effective options.
+  applies: fn(&Options) -> bool,
+  /// The options involved, used to find the spans of the `%option`s that set them.
+  fields : &'static [OptionField],
+  message: &'static str,
+}
+
+static CONFLICTS: &[Conflict] = &[
+  Conflict {
+    applies: |o| o.full && o.fast,
+    fields : &[Full(true), Fast(true)],
+    message: "`full` and `fast` select different table formats",
+  },
+  Conflict {
+    applies: |o| o.yywrap && !o.flex,
+    fields : &[Yywrap(false), Flex(true)],
+    message: "`noyywrap` requires `flex`",
+  },
+  Conflict {
+    applies: |o| o.batch && o.interactive,
+    fields : &[Batch(true), Interactive(true)],
+    message: "a scanner cannot be both `batch` and `interactive`",
+  },
+  Conflict {
+    applies: |o| o.no_std && o.debug,
+    fields : &[Std(false), Debug_(true)],
+    message: "a `nostd` scanner has no standard error to report `debug` to",
+  },
+  Conflict {
+    applies: |o| o.no_std && o.perf_report,
+    fields : &[Std(false), PerfReport(true)],
+    message: "a `nostd` scanner has no clock or standard error for `perf-report`",
+  },
+  Conflict {
+    applies: |o| o.no_std && o.main,
+    fields : &[Std(false), Main(true)],
+    message: "a `nostd` scanner has no standard input for `main` to scan",
+  },
+];
 
 impl Options {
-  /// Update the values of self with those of other. The `OptionSet` `other` is consumed.
-  pub fn update(&mut self, other: OptionSet) {
-    for field in other {
-      match field {
-        // Scanner
-        InFile(_) => { /* in_file cannot change. */ }
-        Legacy(_) => { /* pass */ }
-
-        Batch(v) => { self.batch = v; }
-        Bison(v) => { self.bison = v; }
-        BisonBridge(v) => { self.bison_bridge = v; }
-        BisonCc(v) => { self.bison_cc = v; }
-        BisonCcNamespace(v) => { self.bison_cc_namespace = Some(v); }
-        BisonCcParser(v) => { self.bison_cc_parser = Some(v); }
-        BisonComplete(v) => { self.bison_complete = v; }
-        BisonLocations(v) => { self.bison_locations = v; }
-        CaseInsensitive(v) => { self.case_insensitive = v; }
-        Class(v) => { self.class = Some(v); }
-        Cpp(v) => { self.cpp = v; }
-        Debug_(v) => { self.debug = v; }
-        Default(v) => { self.default = v; }
-        Dotall(v) => { self.dotall = v; }
-        Exception(v) => { self.exception = Some(v); }
-        Fast(v) => { self.fast = v; }
-        Find(v) => { self.find = v; }
-        Flex(v) => { self.flex = v; }
-        Freespace(v) => { self.freespace = v; }
-        Full(v) => { self.full = v; }
-        GraphsFile(v) => { self.graphs_file = Some(Some(v)); }
-        Include(v) => { self.include = Some(v); }
-        Interactive(v) => { self.interactive = v; }
-        Lex(v) => { self.lex = Some(v); }
-        LexCompat(v) => { self.lex_compat = v; }
-        Lexer(v) => { self.lexer = Some(v); }
-        Line(v) => { self.line = v }
-        Main(v) => { self.main = v; }
-        Namespace(v) => { self.namespace = Some(v); }
-        OutFile(v) => { self.out_file = Some(v); }
-        Pattern(v) => { self.pattern = Some(v); }
-        PerfReport(v) => { self.perf_report = v; }
-        PosixCompat(v) => { self.posix_compat = v; }
-        Prefix(v) => { self.prefix = Some(v); }
-        Reentrant(v) => { self.reentrant = v; }
-        RegexpFile(v) => { self.regexp_file = Some(Some(v)); }
-        Reject(v) => { self.reject = v }
-        Stack(v) => { self.stack = v; }
-        Stdinit(v) => { self.stdinit = v; }
-        Stdout(v) => { self.stdout = v; }
-        TablesFile(v) => { self.tables_file = Some(Some(v)); }
-        Tabs(v) => { self.tabs = v; }
-        TokenType(v) => { self.token_type = Some(v); }
-        Unicode(v) => { self.unicode = v; }
-        Unistd(v) => { self.unistd = v; }
-        Verbose(v) => { self.verbose = v; }
-        Warn(v) => { self.warn = v; }
-        Yy(v) => { self.yy = v; }
-        Yyclass(v) => { self.yyclass = Some(v); }
-        Yylineno(v) => { self.yylineno = v; }
-        Yymore(v) => { self.yymore = v; }
-        Yywrap(v) => { self.yywrap = v; }
-      } // end match
-    } // end for
-  }
-}
-
-// todo: Is this the right representation? Struct better?
-// todo: figure out which will be implemented, which are unsettable, and which are legacy.
-pub static OPTIONS: phf::Map<&'static str, OptionKind> = phf_map! {
-
-  "caseless"           => OptionKind::Bool(CaseInsensitive),
-  "case-insensitive"   => OptionKind::Bool(CaseInsensitive),
-  "caseful"            => OptionKind::NegatedBool(CaseInsensitive),
-  "case-sensitive"     => OptionKind::NegatedBool(CaseInsensitive),
-  "7bit"               => OptionKind::Legacy,
-  "8bit"               => OptionKind::Legacy,
-  "align"              => OptionKind::Legacy,
-  "always-interactive" => OptionKind::Bool(Interactive),
-  "array"              => OptionKind::Legacy,
-  "backup"             => OptionKind::Legacy,
-  "batch"              => OptionKind::Bool(Batch),
-  "bison"              => OptionKind::Bool(Bison),
-  "bison_bridge"       => OptionKind::Bool(BisonBridge),
-  "bison_cc"           => OptionKind::Bool(BisonCc),
-  "bison_cc_namespace" => OptionKind::String(BisonCcNamespace),
-  "bison_cc_parser"    => OptionKind::String(BisonCcParser),
-  "bison_complete"     => OptionKind::Bool(BisonComplete),
-  "bison_locations"    => OptionKind::Bool(BisonLocations),
-  "c++"                => OptionKind::Bool(Cpp),
-  "class"              => OptionKind::String(Class),
-  "ctorarg"            => OptionKind::Legacy,
-  "debug"              => OptionKind::Bool(Debug_),
-  "default"            => OptionKind::Bool(Default),
-  "dotall"             => OptionKind::Bool(Dotall),
-  "ecs"                => OptionKind::Legacy,
-  "exception"          => OptionKind::String(Exception),
-  "extra-type"         => OptionKind::Legacy,
-  "fast"               => OptionKind::Bool(Fast),
-  "find"               => OptionKind::Bool(Find),
-  "flex"               => OptionKind::Bool(Flex),
-  "freespace"          => OptionKind::Bool(Freespace),
-  "full"               => OptionKind::Bool(Full),
-  "graphs_file"        => OptionKind::String(GraphsFile),
-  "header_file"        => OptionKind::Legacy,
-  "include"            => OptionKind::String(Include),
-  "indent"             => OptionKind::Legacy,
-  "input"              => OptionKind::Legacy,
-  "interactive"        => OptionKind::Bool(Interactive),
-  "lex"                => OptionKind::String(Lex),
-  "lex-compat"         => OptionKind::Bool(LexCompat),
-  "lexer"              => OptionKind::String(Lexer),
-  "line"               => OptionKind::Bool(Line),
-  "main"               => OptionKind::Bool(Main),
-  "matcher"            => OptionKind::Legacy,
-  "meta-ecs"           => OptionKind::Legacy,
-  "namespace"          => OptionKind::String(Namespace),
-  "never-interactive"  => OptionKind::NegatedBool(Interactive),
-  "outfile"            => OptionKind::String(OutFile),
-  "params"             => OptionKind::Legacy,
-  "pattern"            => OptionKind::String(Pattern),
-  "perf-report"        => OptionKind::Bool(PerfReport),
-  "permissive"         => OptionKind::Legacy,
-  "pointer"            => OptionKind::Legacy,
-  "posix-compat"       => OptionKind::Legacy,
-  "prefix"             => OptionKind::String(Prefix),
-  "read"               => OptionKind::Legacy,
-  "reentrant"          => OptionKind::Bool(Reentrant),
-  "regexp_file"        => OptionKind::String(RegexpFile),
-  "reject"             => OptionKind::Bool(Reject),
-  "stack"              => OptionKind::Bool(Stack),
-  "stdinit"            => OptionKind::Bool(Stdinit),
-  "stdout"             => OptionKind::Bool(Stdout),
-  "tables-file"        => OptionKind::String(TablesFile),
-  "tables-verify"      => OptionKind::Legacy,
-  "tablesext"          => OptionKind::Legacy,
-  "tabs"               => OptionKind::Number(Tabs),
-  "token_eof"          => OptionKind::Legacy,
-  "token_type"         => OptionKind::String(TokenType),
-  "unicode"            => OptionKind::Bool(Unicode),
-  "unistd"             => OptionKind::Bool(Unistd),
-  "unput"              => OptionKind::Legacy,
-  "verbose"            => OptionKind::Bool(Verbose),
-  "warn"               => OptionKind::Bool(Warn),
-  "yy"                 => OptionKind::Bool(Yy),
-  "yy_pop_state"       => OptionKind::Legacy,
-  "yy_push_state"      => OptionKind::Legacy,
-  "yy_scan_buffer"     => OptionKind::Legacy,
-  "yy_scan_bytes"      => OptionKind::Legacy,
-  "yy_scan_string"     => OptionKind::Legacy,
-  "yy_top_state"       => OptionKind::Legacy,
-  "yyalloc"            => OptionKind::Legacy,
-  "yyclass"            => OptionKind::String(Yyclass),
-  "yyfree"             => OptionKind::Legacy,
-  "yyget_column"       => OptionKind::Legacy,
-  "yyget_debug"        => OptionKind::Legacy,
-  "yyget_extra"        => OptionKind::Legacy,
-  "yyget_in"           => OptionKind::Legacy,
-  "yyget_leng"         => OptionKind::Legacy,
-  "yyget_lineno"       => OptionKind::Legacy,
-  "yyget_lloc"         => OptionKind::Legacy,
-  "yyget_lval"         => OptionKind::Legacy,
-  "yyget_out"          => OptionKind::Legacy,
-  "yyget_text"         => OptionKind::Legacy,
-  "yylineno"           => OptionKind::Bool(Yylineno),
-  "yyltype"            => OptionKind::Legacy,
-  "yymore"             => OptionKind::Bool(Yymore),
-  "yyrealloc"          => OptionKind::Legacy,
-  "yyset_column"       => OptionKind::Legacy,
-  "yyset_debug"        => OptionKind::Legacy,
-  "yyset_extra"        => OptionKind::Legacy,
-  "yyset_in"           => OptionKind::Legacy,
-  "yyset_lineno"       => OptionKind::Legacy,
-  "yyset_lloc"         => OptionKind::Legacy,
-  "yyset_lval"         => OptionKind::Legacy,
-  "yyset_out"          => OptionKind::Legacy,
-  "yystype"            => OptionKind::Legacy,
-  "yywrap"             => OptionKind::Bool(Yywrap),
-};
+  /**
+  Reports combinations of options that contradict each other. The spans of the `%option` items in
+  `items` that set the offending options are attached to each conflict.
+  */
+  pub fn conflicts(&self, items: &[Item]) -> Errors {
+    let mut errors = Errors::new();
+
+    for conflict in CONFLICTS.iter().filter(|c| (c.applies)(self)) {
+      errors.push(
+        OptionConflictError::new(conflict.message, option_spans(items, conflict.fields))
+            .with_notes(self.source_notes(conflict.fields))
+      );
+    }
+
+    // Every rule is anchored, so a search scanner can only ever match at the start of a line.
+    let mut rules = items.iter().filter_map(|item| match item {
+      Item::Rule { pattern, .. } => Some(pattern),
+      _ => None
+    }).peekable();
+    if self.find && rules.peek().is_some() && rules.all(|p| p.fragment().starts_with('^')) {
+      errors.push(OptionConflictError::new(
+        "`find` with only anchored (`^`) patterns",
+        option_spans(items, &[Find(true)])
+      ).with_notes(self.source_notes(&[Find(true)])));
+    }
+
+    // `token_type` replaces the `Token` enum that `%token` declares the variants of.
+    if let Some(token_type) = &self.token_type {
+      let token = items.iter().find_map(|item| match item {
+        Item::Token { name, .. } => Some(*name),
+        _ => None
+      });
+      if let Some(token) = token {
+        let fields    = [TokenType(token_type.clone())];
+        let mut spans = option_spans(items, &fields);
+        spans.push(token);
+        errors.push(OptionConflictError::new(
+          "`token_type` with `%token` declarations, whose `Token` enum it replaces",
+          spans
+        ).with_notes(self.source_notes(&fields)));
+      }
+    }
+
+    errors
+  }
+
+  /**
+  Notes of where each of `fields` was set, for those set outside the specification, whose
+  `%option`s are labeled instead.
+  */
+  fn source_notes(&self, fields: &[OptionField]) -> Vec<String> {
+    fields.iter()
+          .filter_map(|field| field.flag())
+          .filter_map(|(flag, _)| match self.provenance.source(flag) {
+            None | Some(OptionSource::Specification { .. }) => None,
+            Some(source) => Some(format!("note: `--{}` is set by {}", flag, source)),
+          })
+          .collect()
+  }
+
+  /// Records the options the arguments that the configuration file at `path` supplies set.
+  pub fn record_config(&mut self, path: &str, arguments: &[String]) {
+    for (_, flag) in OPTION_ARGUMENTS.iter() {
+      if arguments.iter().any(|argument| argument.strip_prefix("--") == Some(*flag)) {
+        self.provenance.record(flag, OptionSource::Config(path.to_string()));
+      }
+    }
+  }
+
+  /// Records the options the command line of `matches` set, which should hold only the
+  /// arguments given, not those a configuration file supplies.
+  pub fn record_command_line(&mut self, matches: &ArgMatches) {
+    for (field, flag) in OPTION_ARGUMENTS.iter() {
+      if matches.occurrences_of(*field) > 0 {
+        self.provenance.record(flag, OptionSource::CommandLine);
+      }
+    }
+  }
+
+  /// Where each option was set.
+  pub fn provenance(&self) -> &Provenance {
+    &self.provenance
+  }
+
+  /**
+  Explains the value of the option `name`, a long flag such as `case-insensitive` or an `%option`
+  name such as `caseless`, for `--explain-option`: its value, and each setting of it, the one that
+  took effect and those it overrides, or that it is the default. `None` if `name` names no option.
+
+  ```text
+  --tabs = 8
+    set by the command line
+    overriding `%option tabs=4` on line 3
+  ```
+  */
+  pub fn explain(&self, name: &str) -> Option<String> {
+    let name = name.trim_start_matches('-');
+    let flag =
+        match OPTION_ARGUMENTS.iter().find(|(_, flag)| *flag == name) {
+          Some((_, flag)) => *flag,
+          None => {
+            let field =
+                match OPTIONS.get(name.to_lowercase().as_str())? {
+                  | OptionKind::Bool(field)
+                  | OptionKind::NegatedBool(field)
+                  | OptionKind::BoolOrString(field, _) => field(true),
+                  OptionKind::String(field) => field(String::new()),
+                  OptionKind::Number(field) => field(0),
+                  | OptionKind::Legacy
+                  | OptionKind::Unsupported(_)
+                  | OptionKind::Unimplemented => return None,
+                };
+            field.flag()?.0
+          }
+        };
+
+    let mut text   = format!("--{} = {}\n", flag, self.value_of(flag)?);
+    let settings   = self.provenance.settings(flag);
+    let effective  = settings.iter().rposition(|setting| setting.applied);
+    match effective {
+      Some(n) => text.push_str(&format!("  set by {}\n", settings[n].source)),
+      None    => text.push_str("  the default\n"),
+    }
+    for (_, setting) in settings.iter().enumerate().filter(|(n, _)| Some(*n) != effective) {
+      text.push_str(&format!("  overriding {}\n", setting.source));
+    }
+
+    Some(text)
+  }
+
+  /**
+  The options as a `Lesk.toml`, for `--print-config`: each option of the table that is set, by
+  its long flag, in the `[options]` table, which `from_toml` and the binary both read back. A flag
+  that is off is written `false`, and an option that is not set, such as `--prefix`, is left out.
+
+  ```toml
+  # lesk 0.1.0
+  [options]
+  case-insensitive = true
+  tabs = 8
+  ```
+  */
+  pub fn to_toml(&self) -> String {
+    let mut config = Table::new();
+    config.insert("options".to_string(), Value::Table(self.toml_table()));
+
+    format!("# lesk {}\n{}", env!("CARGO_PKG_VERSION"), Value::Table(config))
+  }
+
+  /**
+  The options of a `Lesk.toml`, such as `to_toml` writes, with the defaults for those it does not
+  set. The values are checked as they are on the command line, and paths are taken as they are
+  written rather than relative to the file.
+  */
+  pub fn from_toml(text: &str) -> Result<Options, LeskError> {
+    let config  = text.parse::<Value>().map_err(|e| LeskError::config(e.to_string()))?;
+    let options =
+        match config.get("options") {
+          Some(Value::Table(options)) => options.clone(),
+          Some(_) => return Err(LeskError::config("`options` must be a table")),
+          None    => Table::new(),
+        };
+
+    let mut arguments = vec!["lesk".to_string()];
+    for (name, value) in options {
+      let values =
+          match value {
+            Value::Array(values) => values,
+            value                => vec![value],
+          };
+
+      for value in values {
+        let value =
+            match value {
+              Value::Boolean(true)  => {
+                arguments.push(format!("--{}", name));
+                continue;
+              }
+              Value::Boolean(false) => continue,
+              Value::Integer(n) if Options::is_counted(&name) => {
+                arguments.extend((0..n).map(|_| format!("--{}", name)));
+                continue;
+              }
+              Value::Integer(n)     => n.to_string(),
+              Value::String(s)      => s,
+              _ => return Err(LeskError::config(format!("unsupported value for `{}`", name))),
+            };
+        arguments.push(format!("--{}", name));
+        arguments.push(value);
+      }
+    }
+
+    Options::try_parse_from(arguments).map_err(|e| LeskError::config(e.to_string()))
+  }
+
+  /**
+  Reports the option values that are not valid, such as `%option tabs=3`: those of the `%option`
+  items in `items` at their spans, and those of `self` that the builder set.
+  */
+  pub fn invalid_values(&self, items: &[Item]) -> Errors {
+    let mut errors = Errors::new();
+
+    for item in items {
+      if let Item::Option(field, span) = item {
+        if let Err(message) = field.validate() {
+          errors.push(Error::Message(span.to_span(), message.into()));
+        }
+      }
+    }
+    for message in self.invalid_settings() {
+      errors.push(Error::Message(codespan::Span::initial(), message.into()));
+    }
+
+    errors
+  }
+
+  /**
+  The level at which each warning is reported. The `%option allow`, `warn`, and `deny` items in
+  `items` apply in the order they appear, then `--allow`, `--warn`, and `--deny` in that order, so
+  that the command line overrides the specification and the strictest flag wins. An `%option`
+  naming no warning is an error at its span.
+  */
+  pub fn warning_policy(&self, items: &[Item]) -> Result<WarningPolicy, Errors> {
+    let mut policy = WarningPolicy::new();
+    let mut errors = Errors::new();
+
+    for item in items {
+      let (names, level, span) =
+          match item {
+            Item::Option(Allow(names), span)    => (names, Level::Allow, span),
+            Item::Option(Warnings(names), span) => (names, Level::Warn, span),
+            Item::Option(Deny(names), span)     => (names, Level::Deny, span),
+            _ => continue,
+          };
+      if let Err(message) = policy.set_all(names, level) {
+        errors.push(Error::Message(span.to_span(), message.into()));
+      }
+    }
+
+    let flags = [
+      (&self.allow, Level::Allow),
+      (&self.warnings, Level::Warn),
+      (&self.deny, Level::Deny),
+    ];
+    for (names, level) in flags.iter() {
+      for name in names.iter() {
+        if let Err(message) = policy.set_all(name, *level) {
+          errors.push(Error::Message(codespan::Span::initial(), message.into()));
+        }
+      }
+    }
+
+    if errors.is_empty() { Ok(policy) } else { Err(errors) }
+  }
+}
+
+/// Checks that the value of `--allow`, `--warn`, or `--deny` names a warning.
+fn parse_warning(name: &str) -> Result<String, String> {
+  warning_code(name).map(|_| name.to_string()).ok_or_else(|| format!("no warning named `{}`", name))
+}
+
+/// Checks that a tab size is one that indent and dedent matching supports.
+fn valid_tabs(value: &str) -> Result<(), String> {
+  match value.parse::<u8>() {
+    Ok(2) | Ok(4) | Ok(8) => Ok(()),
+    _ => Err(format!("the tab size must be 2, 4, or 8, not `{}`", value)),
+  }
+}
+
+/// Checks that a regex engine is named `value`.
+fn valid_matcher(value: &str) -> Result<(), String> {
+  if ENGINE_NAMES.contains(&value) {
+    Ok(())
+  } else {
+    Err(format!("`{}` is not a regex engine; the engines are {}", value, ENGINE_NAMES.join(", ")))
+  }
+}
+
+/// Whether `name` is an identifier, as the names of the generated classes and functions must be.
+fn is_identifier(name: &str) -> bool {
+  let mut chars = name.chars();
+  matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+      && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn valid_identifier(value: &str) -> Result<(), String> {
+  if is_identifier(value) {
+    Ok(())
+  } else {
+    Err(format!("`{}` is not an identifier", value))
+  }
+}
+
+/// Checks a namespace, identifiers separated by `.` or `::`, as in `NAME1.NAME2.NAME3`.
+fn valid_namespace(value: &str) -> Result<(), String> {
+  if value.split("::").flat_map(|part| part.split('.')).all(is_identifier) {
+    Ok(())
+  } else {
+    Err(format!("`{}` is not a namespace, which is identifiers separated by `.` or `::`", value))
+  }
+}
+
+/// Checks that the file `value` has one of `extensions`. Standard output, `-`, has them all.
+fn valid_extension(value: &str, extensions: &[&str]) -> Result<(), String> {
+  let extension = std::path::Path::new(value).extension().and_then(|e| e.to_str());
+  if value == "-" || extension.is_some_and(|e| extensions.contains(&e)) {
+    Ok(())
+  } else {
+    Err(format!("`{}` does not end in .{}", value, extensions.join(" or .")))
+  }
+}
+
+fn valid_rust_file(value: &str) -> Result<(), String> {
+  valid_extension(value, &["rs"])
+}
+
+/// Checks the output file, which may also be the directory the scanners of several
+/// specifications are written to.
+fn valid_out_file(value: &str) -> Result<(), String> {
+  if std::path::Path::new(value).is_dir() {
+    Ok(())
+  } else {
+    valid_rust_file(value)
+  }
+}
+
+fn valid_graph_file(value: &str) -> Result<(), String> {
+  valid_extension(value, &["gv", "dot"])
+}
+
+/**
+The spans of the `%option` items among `items` that set any of `fields` to the same value, so
+that `%option yywrap` is not blamed for what `noyywrap`, `Yywrap(false)`, does.
+*/
+fn option_spans<'s>(items: &[Item<'s>], fields: &[OptionField]) -> Vec<Span<'s>> {
+  items.iter().filter_map(|item| match item {
+    Item::Option(field, span) if fields.contains(field) => Some(*span),
+    _ => None
+  }).collect()
+}
+
+/**
+Builds `Options` for programmatic use, without a command line or `%option` lines:
+
+```ignore
+let options = Options::builder().case_insensitive(true).out_file("lexer.rs").build()?;
+```
+
+Each setter sets the field of `Options` of the same name, and the options start out as they are
+for a command line naming no options. `build` reports combinations of options that cannot be used
+together and values that are not valid, such as a tab size of 3, just as `%option`s are checked.
+*/
+#[derive(Debug, Default)]
+pub struct OptionsBuilder {
+  options: Options,
+}
+
+impl OptionsBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  setters! {
+    bool: flex_compat, fmt, check, fix, deterministic, fail_fast;
+    string: export_flex;
+  }
+
+  /// The name the specification is known by in diagnostics.
+  pub fn in_file<S: Into<String>>(mut self, value: S) -> Self {
+    self.options.in_file = value.into();
+    self
+  }
+
+  /// Defines a feature for `%if` sections. May be called repeatedly.
+  pub fn define<S: Into<String>>(mut self, feature: S) -> Self {
+    self.options.define.push(feature.into());
+    self
+  }
+
+  /// Stops collecting errors after `limit` of them, unless it is 0, which is no limit.
+  pub fn error_limit(mut self, limit: usize) -> Self {
+    self.options.error_limit = Some(limit);
+    self
+  }
+
+  /// Adds a directory to search for `%include`d files. May be called repeatedly.
+  pub fn include_path<S: Into<String>>(mut self, directory: S) -> Self {
+    self.options.include_path.push(directory.into());
+    self
+  }
+
+  /// Sets an option as an `%option` line would.
+  pub fn option(mut self, field: OptionField) -> Self {
+    self.options.update(SmallVec::from_elem(field, 1), OptionSource::Builder);
+    self
+  }
+
+  pub fn build(self) -> Result<Options, LeskError> {
+    let mut errors = self.options.conflicts(&[]);
+    errors.merge(self.options.invalid_values(&[]));
+
+    if errors.has_errors() {
+      Err(errors.into())
+    } else {
+      Ok(self.options)
+    }
+  }
+}
+
+impl Options {
+  /**
+  The `%option`s that set the same option as each command line flag, as a table for the end of
+  `--help`. It is generated from `OPTIONS`, so it lists every name a specification may use:
+
+  ```text
+      --case-insensitive     %option case-insensitive, caseless
+      --nowarn               %option nowarn
+  ```
+
+  An `%option` that turns off what its flag turns on, such as `caseful`, is left out, as is its
+  negation, `nocaseful`, which is the flag's own option under another name.
+  */
+  pub fn option_table() -> String {
+    let mut names: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+
+    for (key, kind) in OPTIONS.entries() {
+      let fields =
+          match kind {
+            OptionKind::Bool(field)        => vec![(field(true), false, "")],
+            OptionKind::NegatedBool(field) => vec![(field(true), true, "")],
+            OptionKind::String(field)      => vec![(field(String::new()), false, "=VALUE")],
+            OptionKind::Number(field)      => vec![(field(0), false, "=N")],
+            OptionKind::BoolOrString(flag, field) => {
+              vec![(flag(true), false, ""), (field(String::new()), false, "=VALUE")]
+            }
+            OptionKind::Legacy | OptionKind::Unsupported(_) | OptionKind::Unimplemented => continue,
+          };
+
+      for (field, negated, value) in fields {
+        let (flag, flag_negated) =
+            match field.flag() {
+              Some(flag) => flag,
+              None       => continue,
+            };
+
+        let name =
+            match (negated, flag_negated) {
+              (false, false) | (true, true) => format!("{}{}", key, value),
+              (false, true)                 => format!("no{}", key),
+              (true, false)                 => continue,
+            };
+        names.entry(flag).or_default().push(name);
+      }
+    }
+
+    let mut table = String::from("SPECIFICATION OPTIONS:\n");
+    for (flag, mut names) in names {
+      names.sort();
+      table.push_str(&format!("    --{:<22} %option {}\n", flag, names.join(", ")));
+    }
+    table
+  }
 
+  pub fn builder() -> OptionsBuilder {
+    OptionsBuilder::new()
+  }
+
+  /// Whether the specification is read from standard input, `-`, or `STDIN` as it once was.
+  pub fn reads_stdin(&self) -> bool {
+    self.in_file == "-" || self.in_file == "STDIN"
+  }
+
+  /// The most errors collected, as `--error-limit` and `--fail-fast` set it; `None` is no limit,
+  /// as is a limit of 0.
+  pub fn max_errors(&self) -> Option<usize> {
+    if self.fail_fast { Some(1) } else { self.error_limit.filter(|&limit| limit > 0) }
+  }
+
+  /// The name of the regex engine `--matcher` selects, or of the default engine.
+  pub fn engine_name(&self) -> &str {
+    self.matcher.as_deref().unwrap_or(DEFAULT_ENGINE)
+  }
+
+  /// The format `--timings` asks for, if it is given.
+  pub fn timings_format(&self) -> Option<TimingsFormat> {
+    self.timings.map(|format| format.unwrap_or(TimingsFormat::Table))
+  }
+
+  /// Whether `--emit` selects `artifact`. Without `--emit`, only the code is written.
+  pub fn emits(&self, artifact: Emit) -> bool {
+    if self.emit.is_empty() {
+      artifact == Emit::Code
+    } else {
+      self.emit.contains(&artifact)
+    }
+  }
+
+  /**
+  The file an artifact other than the code is written to: the file given for it by
+  `--graphs_file`, `--tables_file`, or `--regexp_file`, or else the output file with its extension
+  replaced, `lex.yy.gv` for the graph of `lex.yy.rs`. The code written to standard output counts
+  as `lex.yy.rs`.
+  */
+  pub fn artifact_path(&self, artifact: Emit) -> String {
+    let (file, extension) =
+        match artifact {
+          Emit::Graph   => (&self.graphs_file, "gv"),
+          Emit::Tables  => (&self.tables_file, "tables.rs"),
+          Emit::Regexp  => (&self.regexp_file, "txt"),
+          Emit::Json    => (&None, "json"),
+          Emit::Lalrpop => (&None, "extern"),
+          Emit::Code    => (&None, "rs"),
+        };
+
+    if let Some(Some(path)) = file {
+      return path.clone();
+    }
+
+    let out_file = self.out_file.as_deref().filter(|path| *path != "-").unwrap_or("lex.yy.rs");
+    let stem     = out_file.strip_suffix(".rs").unwrap_or(out_file);
+    format!("{}.{}", stem, extension)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::specification::Specification;
+
+  /// The codes of the errors that parsing the specification `text` with `options` reports.
+  fn error_codes(text: &str, options: Options) -> Vec<&'static str> {
+    match Specification::from_source("test.l", text, options).parse() {
+      Err(LeskError::Diagnostics(errors)) => errors.iter().map(Error::code).collect(),
+      _ => vec![],
+    }
+  }
+
+  #[test]
+  fn noyywrap_requires_flex() {
+    let text = "%option noyywrap\n%%\n";
+    assert!(error_codes(text, Options::default()).contains(&"L0010"));
+    assert!(!error_codes(text, Options::parse_from(["lesk", "--flex"])).contains(&"L0010"));
+  }
+
+  #[test]
+  fn yywrap_does_not_require_flex() {
+    assert!(!error_codes("%option yywrap\n%%\n", Options::default()).contains(&"L0010"));
+  }
+
+  #[test]
+  fn token_type_conflicts_with_token_declarations() {
+    let text = "%option token_type=\"u32\"\n%token Number\n%%\n";
+    assert!(error_codes(text, Options::default()).contains(&"L0010"));
+    let text = "%option token_type=\"u32\"\n%%\n";
+    assert!(!error_codes(text, Options::default()).contains(&"L0010"));
+  }
+
+  #[test]
+  fn nostd_conflicts_with_what_needs_std() {
+    for option in ["debug", "perf-report", "main"] {
+      let text = format!("%option nostd {}\n%%\n", option);
+      assert!(error_codes(&text, Options::default()).contains(&"L0010"), "{}", option);
+    }
+    assert!(!error_codes("%option nostd stack\n%%\n", Options::default()).contains(&"L0010"));
+  }
+
+  #[test]
+  fn option_table_lists_every_name_of_a_flag() {
+    let table = Options::option_table();
+    let line  = table.lines()
+                     .find(|line| line.trim_start().starts_with("--case-insensitive "))
+                     .expect("no row for --case-insensitive");
+    assert!(line.contains("caseless"));
+    assert!(line.contains("case-insensitive"));
+    assert!(!line.contains("caseful"));
+    assert!(table.contains("tabs=N"));
+  }
+
+  #[test]
+  fn invalid_values_are_errors() {
+    assert!(error_codes("%option tabs=3\n%%\n", Options::default()).contains(&"L0014"));
+    assert!(!error_codes("%option tabs=4\n%%\n", Options::default()).contains(&"L0014"));
+    let text = "%option prefix=\"9lives\"\n%%\n";
+    assert!(error_codes(text, Options::default()).contains(&"L0014"));
+    assert!(Options::builder().option(Tabs(3)).build().is_err());
+    assert!(Options::builder().option(Tabs(8)).build().is_ok());
+  }
+
+  #[test]
+  fn command_line_overrides_the_specification() {
+    let mut options = Options::parse_from(["lesk", "--tabs", "8"]);
+    options.provenance.record("tabs", OptionSource::CommandLine);
+    let source = OptionSource::Specification { line: 3, text: "tabs=4".to_string() };
+    options.update(SmallVec::from_elem(Tabs(4), 1), source);
+
+    assert_eq!(options.tabs, 8);
+    assert_eq!(options.provenance().source("tabs"), Some(&OptionSource::CommandLine));
+    assert_eq!(
+      options.explain("--tabs").unwrap(),
+      "--tabs = 8\n  set by the command line\n  overriding `%option tabs=4` on line 3\n"
+    );
+    assert_eq!(options.explain("dotall").unwrap(), "--dotall = false\n  the default\n");
+  }
+
+  #[test]
+  fn builder_starts_from_the_defaults() {
+    let built = Options::builder().build().unwrap();
+    assert_eq!(built.to_toml(), Options::default().to_toml());
+  }
+
+  #[test]
+  fn builder_sets_options_and_records_where() {
+    let options = Options::builder()
+        .case_insensitive(true)
+        .out_file("lexer.rs")
+        .define("UNICODE")
+        .error_limit(3)
+        .build()
+        .unwrap();
+
+    assert!(options.case_insensitive);
+    assert_eq!(options.out_file.as_deref(), Some("lexer.rs"));
+    assert_eq!(options.define, vec!["UNICODE".to_string()]);
+    assert_eq!(options.max_errors(), Some(3));
+    assert_eq!(options.provenance().source("case-insensitive"), Some(&OptionSource::Builder));
+  }
+
+  #[test]
+  fn error_limit_of_zero_is_no_limit() {
+    assert_eq!(Options::builder().error_limit(0).build().unwrap().max_errors(), None);
+    let options = Options::builder().error_limit(0).fail_fast(true).build().unwrap();
+    assert_eq!(options.max_errors(), Some(1));
+  }
+
+  #[test]
+  fn builder_reports_conflicts() {
+    match Options::builder().full(true).fast(true).build() {
+      Err(LeskError::Diagnostics(errors)) => {
+        assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0010"]);
+      }
+      _ => panic!("`full` and `fast` are accepted together"),
+    }
+  }
+
+  #[test]
+  fn toml_round_trips() {
+    let options = Options::builder()
+        .option(Tabs(4))
+        .option(CaseInsensitive(true))
+        .option(Prefix("calc".to_string()))
+        .build()
+        .unwrap();
+    let read = Options::from_toml(&options.to_toml()).unwrap();
+
+    assert_eq!(read.tabs, 4);
+    assert!(read.case_insensitive);
+    assert_eq!(read.prefix.as_deref(), Some("calc"));
+    assert!(!read.dotall);
+  }
+}
@@ -0,0 +1,24 @@
+/*!
+
+Whether the specification is read as a flex specification, set from `--flex-compat`.
+
+In compatibility mode the parser accepts flex directives that have no meaning for Lesk, such as
+`%array` and the table size hints `%p 3000`, and turns them into ignored options, which are
+reported as warnings. Without it they are errors, so that a stray directive is not silently
+dropped from a specification written for Lesk.
+
+*/
+
+use std::cell::Cell;
+
+thread_local! {
+  static FLEX_COMPAT: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_flex_compat(flex_compat: bool) {
+  FLEX_COMPAT.with(|f| f.set(flex_compat));
+}
+
+pub fn flex_compat() -> bool {
+  FLEX_COMPAT.with(|f| f.get())
+}
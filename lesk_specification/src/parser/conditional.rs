@@ -0,0 +1,27 @@
+/*!
+
+The features that `%if NAME … %else … %endif` sections of a specification are tested against.
+
+Like the include search path, the set of features is kept per thread, because the parsers are
+plain functions of their input. The `Specification` sets it from `--define` before parsing.
+
+*/
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+thread_local! {
+  static FEATURES: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Sets the features that are defined, replacing any set before.
+pub fn set_features<I, S>(features: I)
+  where I: IntoIterator<Item = S>,
+        S: Into<String>
+{
+  FEATURES.with(|f| *f.borrow_mut() = features.into_iter().map(|x| x.into()).collect());
+}
+
+pub fn is_defined(feature: &str) -> bool {
+  FEATURES.with(|f| f.borrow().contains(feature))
+}
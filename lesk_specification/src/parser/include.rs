@@ -0,0 +1,124 @@
+/*!
+
+Resolution of `%include`d files.
+
+The parsers are plain functions of their input, so the state needed to resolve an include, the
+search path and the stack of files currently being parsed, is kept per thread. The
+`Specification` sets it up before parsing and the include parser consults it. The text of the
+included files is kept in an `IncludedSources` that whoever parses owns.
+
+*/
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use codespan::Span;
+use elsa::FrozenVec;
+
+/// A file being parsed, with the span of the `%include` that caused it to be parsed.
+type IncludeFrame = (PathBuf, Option<Span>);
+
+thread_local! {
+  static INCLUDE_PATHS: RefCell<Vec<PathBuf>>     = const { RefCell::new(vec![]) };
+  static FILE_STACK   : RefCell<Vec<IncludeFrame>> = const { RefCell::new(vec![]) };
+}
+
+/**
+The name and text of each file `%include`d during a parse. The items parsed from a file borrow its
+text, so the sources must live as long as the items, and they are dropped with them. Files are
+only ever added, which is what lets `add` hand out a borrow of the text while taking `&self`.
+*/
+#[derive(Default)]
+pub struct IncludedSources {
+  files: FrozenVec<Box<(String, String)>>,
+}
+
+impl IncludedSources {
+  /// Keeps the text of the included file `name`, returning the text for the parser to read.
+  pub fn add(&self, name: String, text: String) -> &str {
+    &self.files.push_get(Box::new((name, text))).1
+  }
+
+  /// The name and text of each included file, in the order they were added.
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    (0..self.files.len())
+        .filter_map(move |index| self.files.get(index))
+        .map(|(name, text)| (name.as_str(), text.as_str()))
+  }
+}
+
+/// Sets the directories searched for included files, in order.
+pub fn set_include_paths<I, P>(paths: I)
+  where I: IntoIterator<Item = P>,
+        P: Into<PathBuf>
+{
+  INCLUDE_PATHS.with(|p| *p.borrow_mut() = paths.into_iter().map(|x| x.into()).collect());
+}
+
+/**
+Records that parsing of `path` has begun, because of the `%include` at `included_at` if it is
+not the main file. Every call must be paired with `exit_file`.
+*/
+pub fn enter_file<P: Into<PathBuf>>(path: P, included_at: Option<Span>) {
+  FILE_STACK.with(|s| s.borrow_mut().push((path.into(), included_at)));
+}
+
+/// Records that parsing of the innermost file has finished.
+pub fn exit_file() {
+  FILE_STACK.with(|s| { s.borrow_mut().pop(); });
+}
+
+/// The file currently being parsed, if known.
+pub fn current_file() -> Option<PathBuf> {
+  FILE_STACK.with(|s| s.borrow().last().map(|(path, _)| path.clone()))
+}
+
+/**
+If `path` is already being parsed, including it again would recurse forever. Returns the chain
+of includes from the first inclusion of `path` to the innermost file being parsed.
+*/
+pub fn cycle(path: &Path) -> Option<Vec<IncludeFrame>> {
+  let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+  let path = canonical(path);
+
+  FILE_STACK.with(|s| {
+    let stack = s.borrow();
+    stack.iter()
+         .position(|(p, _)| canonical(p) == path)
+         .map(|start| stack[start..].to_vec())
+  })
+}
+
+/**
+Finds the file named in an `%include`. An absolute name is used as is. A relative name is looked
+up first in the directory of the including file, then in each include path in order, and
+finally in the working directory.
+
+On failure, returns the list of places that were searched.
+*/
+pub fn resolve(name: &str) -> Result<PathBuf, Vec<PathBuf>> {
+  let name = Path::new(name);
+
+  if name.is_absolute() {
+    return if name.is_file() {
+      Ok(name.to_path_buf())
+    } else {
+      Err(vec![name.to_path_buf()])
+    };
+  }
+
+  let mut candidates = vec![];
+
+  if let Some(dir) = current_file().as_ref().and_then(|f| f.parent()) {
+    candidates.push(dir.join(name));
+  }
+  INCLUDE_PATHS.with(|paths| {
+    candidates.extend(paths.borrow().iter().map(|dir| dir.join(name)));
+  });
+  candidates.push(name.to_path_buf());
+
+  match candidates.iter().find(|c| c.is_file()) {
+    Some(found) => Ok(found.clone()),
+    None => Err(candidates),
+  }
+}
@@ -1,6 +1,28 @@
 mod whitespace;
+pub mod source;
+pub mod include;
+pub mod conditional;
+pub mod compat;
+#[allow(clippy::module_inception)]
 pub mod parser;
 
-pub use super::options::{OptionSet, OptionField};
+pub use super::options::OptionField;
 
-pub use saucepan::{Span, SourceID, Source, Sources};
+pub use source::{LSpan, Span, ToSpan};
+
+/// What the parsers read.
+pub type InputType<'s> = LSpan<'s>;
+
+use super::options::Options;
+
+/**
+Sets up what the parsers read from the options, which is kept per thread: the directories searched
+for `%include`d files, the features `%if` tests, flex compatibility, and the error limit. Whatever
+parses a specification, `Specification::parse` or the language server, calls this first.
+*/
+pub fn configure(options: &Options) {
+  include::set_include_paths(&options.include_path);
+  conditional::set_features(options.define.iter().cloned());
+  compat::set_flex_compat(options.flex_compat);
+  crate::error::set_error_limit(options.max_errors());
+}
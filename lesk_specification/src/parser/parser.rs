@@ -2,7 +2,6 @@
 
 use std::io::Read;
 use std::fs::File;
-use std::collections::HashMap;
 
 #[allow(unused_imports)]
 // region Use Nom
@@ -38,7 +37,6 @@ use nom::{
     cond,
     cut,
     map,
-    map_optc,
     map_parser,
     map_res,
     not,
@@ -64,7 +62,6 @@ use nom::{
     delimited,
     pair,
     preceded,
-    precededc,
     separated_pair,
     terminated,
     tuple,
@@ -75,7 +72,7 @@ use nom::{
   InputTakeAtPosition,
   combinator::{flat_map, peek},
   multi::many_till,
-  character::complete::{alpha1, space1},
+  character::complete::{alpha1, digit1, space1},
   multi::fold_many0
 };
 // endregion
@@ -83,53 +80,37 @@ use nom::{
 use smallvec::SmallVec;
 
 use whitespace::{
-  skip0,
   skip1,
   skip_no_nl0,
-  skip_no_nl1
+  eol_comment,
+  inline_comment,
 };
-use source::*;
 use super::*;
+use super::include::IncludedSources;
 use crate::{
-  error::Error::{
-    Unexpected,
-    Missing
-  },
   options::{
     OptionKind,
     OPTIONS
   },
   error::{
+    closest,
     Error,
     Errors,
-    ExpectedFoundError,
-    IncorrectDelimError,
-    InvalidLabelError,
-    UnclosedDelimError,
-    UnexpectedError,
-    UnexpectedSectionEndError,
+    IncludeError,
+    IncludeCycleError,
+    Suggestion,
+    UnsupportedError,
   },
   section_items::*,
   mergable::{
     Merged,
     Mergable,
-    merge_or_append_items,
     merge_or_push_item,
   },
-  error::Error::{
-    ExpectedFound,
-    IncorrectDelim,
-    InvalidLabel,
-    UnexpectedSectionEnd,
-    UnclosedDelim
-  },
-  error::MissingError
 };
 
 // todo: make typedef for Errors
 
-type InputType<'s> = Span<'s, &'s str>;
-
 // trait Parser<'a>: NomParser<InputType<'a>, InputType<'a>, Errors> {}
 
 pub type Result<'a>  = NomResult<InputType<'a>, InputType<'a>, Errors>;
@@ -145,17 +126,7 @@ pub fn section_one(i: InputType) -> SResult {
   // Each alternative returns a `SectionOneItemSet`, which are folded into each other.
   terminated(
     fold_many0(
-      alt((
-        parse_code_block,
-        parse_include,
-        parse_option,
-        parse_state,
-        parse_definition,
-
-        // Separating the skip_no_nl1 and newline ensures that `parse_code_block` has an
-        // opportunity to see the whitespace introducing indented code.
-        terminated(value(SectionItemSet::default(), skip_no_nl1), line_ending)
-      )),
+      section_one_item,
 
       SectionItemSet::default(),
 
@@ -170,22 +141,120 @@ pub fn section_one(i: InputType) -> SResult {
   )(i)
 }
 
+fn section_one_item(i: InputType) -> SResult {
+  alt((
+    parse_conditional(section_one_item),
+    // Before `parse_option`, which would take `%o 3000` for an abbreviated `%option`.
+    parse_flex_directive,
+    parse_code_block,
+    parse_include,
+    parse_option,
+    parse_token,
+    parse_state,
+    parse_definition,
+    parse_comment_line,
+
+    // The end of the line of an item, or a blank line. Separating the skip_no_nl0 and newline
+    // ensures that `parse_code_block` has an opportunity to see the whitespace introducing
+    // indented code.
+    terminated(value(SectionItemSet::default(), skip_no_nl0), line_ending)
+  ))(i)
+}
+
+/**
+A section included only when a feature is, or with `!`, is not defined with `--define`:
+
+  %if UNICODE
+  letter  \p{L}
+  %else
+  letter  [A-Za-z]
+  %endif
+
+Conditional sections may nest. Items of the branch not taken are parsed, so errors in them are
+still reported, but are then dropped.
+*/
+fn parse_conditional<'a>(item: fn(InputType<'a>) -> SResult<'a>)
+    -> impl Fn(InputType<'a>) -> SResult<'a>
+{
+  move |i: InputType<'a>| {
+    let (rest, (if_span, (negated, feature))) = terminated(
+      pair(
+        terminated(tag("%if"), space1),
+        pair(map(opt(char1('!')), |bang| bang.is_some()), parse_identifier)
+      ),
+      pair(space0, line_ending)
+    )(i)?;
+
+    // The items of a branch stop at the `%else` or `%endif` that ends it.
+    let branch = |input| fold_many0(
+      preceded(not(alt((tag("%else"), tag("%endif")))), item),
+      SectionItemSet::default(),
+      |mut acc: SectionItemSet<'a>, mut next: SectionItemSet<'a>| {
+        acc.extend(next.drain(..));
+        acc
+      }
+    )(input);
+
+    let (rest, then_items) = branch(rest)?;
+    let (rest, else_items) = opt(
+      preceded(terminated(tag("%else"), pair(space0, line_ending)), branch)
+    )(rest)?;
+    let (rest, _) = terminated(tag("%endif"), pair(space0, opt(line_ending)))(rest)
+        .map_err(|_: NomErr<Errors>| NomErr::Failure(Errors::from(
+          Error::unclosed_delim(if_span, "%endif", rest)
+        )))?;
+
+    if conditional::is_defined(feature.fragment()) != negated {
+      Ok((rest, then_items))
+    } else {
+      Ok((rest, else_items.unwrap_or_default()))
+    }
+  }
+}
+
+/**
+A line holding nothing but one or more comments, which becomes an `Item::Comment`:
+  /* Identifiers */
+  // Numbers
+*/
+fn parse_comment_line(i: InputType) -> SResult {
+  let (rest, comment) = terminated(
+    recognize(pair(
+      alt((inline_comment, eol_comment)),
+      many0(preceded(space0, alt((inline_comment, eol_comment))))
+    )),
+    pair(space0, line_ending)
+  )(i)?;
+
+  Ok((rest, SmallVec::from_elem(Item::Comment(comment), 1)))
+}
+
 /**
 A named definition of a regex:
   INTEGER  [0-9]+|0x[0-9a-fA-F]+
   ID       [a-z][a-z0-9]*
+  DELIM(l,r)  {l}[^{r}]*{r}
 */
 fn parse_definition(i: InputType) -> SResult {
-  let (rest, (name, _sep, regex)) = tuple((
+  let (rest, (name, params, _sep, regex)) = tuple((
     parse_identifier,
+    map(
+      opt(delimited(
+        char1('('),
+        separated_list1(delimited(space0, char1(','), space0), parse_identifier),
+        char1(')')
+      )),
+      |params| params.unwrap_or_default()
+    ),
     space1,
     not_line_ending
   ))(i)?;
 
   let result = SmallVec::from_elem(
     Item::Definition {
-        name: name.into(),
-        code: regex.into()
+        name,
+        params,
+        code: regex
       },
     1);
 
@@ -195,30 +264,73 @@ fn parse_definition(i: InputType) -> SResult {
 /**
 Parses a state definition of the form:
   %state CODE
-  %xstate COMMENT
+  %xstate COMMENT STRING
 */
 fn parse_state(i: InputType) -> SResult {
 
-  let (rest, (exclusive, name) )=
+  let (rest, (exclusive, names) )=
   pair(
     alt((
       map(parse_keyword("state"), |_| false),
       map(parse_keyword("xstate"), |_| true),
     )),
-    parse_identifier
+    separated_list1(space1, parse_identifier)
   )(i)?;
 
-  let result = SmallVec::from_elem(
-    Item::State {
-      is_exclusive: exclusive,
-      name: name.into()
-    }
-    , 1);
+  let result = names
+      .into_iter()
+      .map(|name| Item::State {
+        is_exclusive: exclusive,
+        name
+      })
+      .collect();
 
   Ok((rest, result))
 
 }
 
+/// The variants the generated scanner adds to the `Token` enum, which cannot be declared again.
+const RESERVED_TOKENS: [&str; 2] = ["Unmatched", "EndOfInput"];
+
+/**
+Declares variants of the `Token` enum synthesized for the generated scanner. An optional
+parenthesized payload type follows the variant name:
+  %token Plus Minus
+  %token Number(i64) Identifier(String)
+
+The names in `RESERVED_TOKENS` are errors.
+*/
+fn parse_token(i: InputType) -> SResult {
+  let (rest, tokens) = preceded(
+    parse_keyword("token"),
+    cut(separated_list1(
+      space1,
+      pair(
+        parse_identifier,
+        opt(delimited(char1('('), is_not("()\n"), char1(')')))
+      )
+    ))
+  )(i)?;
+
+  let reserved = tokens.iter().find(|(name, _)| RESERVED_TOKENS.contains(name.fragment()));
+  if let Some((name, _)) = reserved {
+    return Err(NomErr::Failure(Errors::from(Error::Message(
+      name.to_span(),
+      format!("`{}` is a token the scanner declares itself. Rename this token.", name).into()
+    ))));
+  }
+
+  let result = tokens
+      .into_iter()
+      .map(|(name, payload)| Item::Token {
+        name,
+        payload
+      })
+      .collect();
+
+  Ok((rest, result))
+}
+
 /**
 Expression on a new line of the form:
 
@@ -231,21 +343,87 @@ fn parse_option(i: InputType) -> SResult {
 
   map(
   many1(
-    alt((
+    spanned(alt((
       // tabs=4 namespace="ChickenScanner"
       parse_option_with_value,
       // Boolean option (line debug) or negated option (noline nodebug)
       parse_option_boolean,
-    ))
+    )))
   ),
-    | mut options | {
-      options.drain_filter(| x | x.is_some())
-             .map(| x | Item::Option(x.unwrap()))
+    | options | {
+      options.into_iter()
+             .filter_map(| (x, span) | x.map(| x | Item::Option(x, span)))
              .collect()
     }
   )(input)
 }
 
+/// The name of an option as it appears in `OPTIONS`, which lives for the whole program.
+fn static_name(key: &InputType) -> &'static str {
+  OPTIONS
+      .get_entry(key.fragment().to_lowercase().as_str())
+      .map_or("", |(name, _)| *name)
+}
+
+/// Suggests the option nearest to the unknown option `key` as its replacement, with the `no` of
+/// a `negated` option kept in the help, which names the option as it will read.
+fn suggest_option(error: Error, key: InputType, negated: bool) -> Error {
+  let known = OPTIONS
+      .entries()
+      .filter(|(_, kind)| {
+        !matches!(kind, OptionKind::Legacy | OptionKind::Unsupported(_) | OptionKind::Unimplemented)
+      })
+      .map(|(name, _)| *name);
+
+  match closest(key.fragment(), known) {
+    Some(name) => error.with_suggestion(Suggestion {
+      message: format!("did you mean `{}{}`?", if negated { "no" } else { "" }, name),
+      span: key.to_span(),
+      replacement: name.to_string(),
+    }),
+    None => error,
+  }
+}
+
+/**
+Flex directives that have no effect in Lesk:
+
+  %array
+  %pointer
+  %p 3000
+
+The last are the table size hints `%p`, `%n`, `%e`, `%k`, `%a`, and `%o` of AT&T lex. With
+`--flex-compat` they become ignored options, and without it they are errors.
+*/
+fn parse_flex_directive(i: InputType) -> SResult {
+  let (rest, directive) = terminated(
+    recognize(preceded(
+      char1('%'),
+      alt((
+        tag("array"),
+        tag("pointer"),
+        recognize(pair(one_of("pneako"), preceded(space1, digit1))),
+      ))
+    )),
+    pair(space0, line_ending)
+  )(i)?;
+
+  if !compat::flex_compat() {
+    return Err(NomErr::Failure(Errors::from(Error::Message(
+      directive.to_span(),
+      format!("`{}` is a flex directive. Use `--flex-compat` to ignore it.", directive).into()
+    ))));
+  }
+
+  let name = match directive.fragment().as_bytes()[1] {
+    b'a' if directive.fragment().starts_with("%array")   => "array",
+    b'p' if directive.fragment().starts_with("%pointer") => "pointer",
+    _ => "table size",
+  };
+
+  Ok((rest, SmallVec::from_elem(Item::Option(OptionField::Legacy(name), directive), 1)))
+}
+
 fn parse_option_boolean(i: InputType) -> NomResult<InputType, Option<OptionField>, Errors> {
   let (rest, (negated, key)) =
       terminated(pair(opt(tag("no")), is_not(" \t=\n")), space0)(i)?;
@@ -256,24 +434,28 @@ fn parse_option_boolean(i: InputType) -> NomResult<InputType, Option<OptionField
     | Some(OptionKind::Number(_)) => {
       let span_start = key.fragment().len();
       Err(NomErr::Failure(Errors::from(
-        Missing(MissingError::new(
+        Error::missing(
           "value assignment",
           i.slice(span_start..span_start),
           Some("This option requires a value.")
-        ))
+        )
       )))
     }
 
-    Some(OptionKind::NegatedBool(field)) => Ok((rest, Some(field(false)) )),
-    Some(OptionKind::Bool(field)) => Ok((rest, Some(field(true))  )),
+    // `noX` turns off what `X` turns on.
+    Some(OptionKind::NegatedBool(field)) => Ok((rest, Some(field(negated.is_some())) )),
+    Some(OptionKind::Bool(field)) => Ok((rest, Some(field(negated.is_none()))  )),
+    Some(OptionKind::BoolOrString(field, _)) => Ok((rest, Some(field(negated.is_none())) )),
 
-    Some(OptionKind::Legacy) => {
-      println!("The option {} is a legacy option. Ignoring.", key);
-      Ok((rest, None))
-    }
+    // Kept so that it can be reported as ignored.
+    Some(OptionKind::Legacy) => Ok((rest, Some(OptionField::Legacy(static_name(&key))))),
+
+    Some(OptionKind::Unsupported(help)) => Err(NomErr::Failure(Errors::from(Error::from(
+      UnsupportedError::new(static_name(&key), key, help)
+    )))),
 
     Some(OptionKind::Unimplemented) => {
-      println!("The option {} is not implemented. Ignoring.", key);
+      tracing::warn!(option = %key, "option is not implemented, ignoring");
       Ok((rest, None))
     }
 
@@ -284,7 +466,11 @@ fn parse_option_boolean(i: InputType) -> NomResult<InputType, Option<OptionField
         0
       };
       Err(NomErr::Failure(Errors::from(
-        Unexpected(UnexpectedError::new("unknown option", i.slice(0..span_end), None))
+        suggest_option(
+          Error::unexpected("unknown option", i.slice(0..span_end), None),
+          key,
+          negated.is_some(),
+        )
       )))
     }
   }
@@ -302,19 +488,18 @@ fn parse_option_with_value(input: InputType) -> NomResult<InputType, Option<Opti
   )(input)?;
 
   match OPTIONS.get(key.fragment().to_lowercase().as_str()) {
-    Some(OptionKind::String(field)) => {
+    | Some(OptionKind::String(field))
+    | Some(OptionKind::BoolOrString(_, field)) => {
       let (_, v) = parse_quoted(value)?;
       Ok((rest, Some(field(v.to_string())) ))
     }
 
     Some(OptionKind::Number(field)) => {
-      let result = value.fragment().parse::<u8>();
-      if result.is_err() {
-        Err(NomErr::Failure(Errors::from(
-          ExpectedFound(ExpectedFoundError::new("number", "cannot parse as a number", value))
-        )))
-      } else {
-        Ok((rest, Some(field(result.unwrap())) ))
+      match value.fragment().parse::<u8>() {
+        Ok(number) => Ok((rest, Some(field(number)))),
+        Err(_) => Err(NomErr::Failure(Errors::from(
+          Error::expected_found("number", "cannot parse as a number", value)
+        ))),
       }
     }
 
@@ -323,27 +508,28 @@ fn parse_option_with_value(input: InputType) -> NomResult<InputType, Option<Opti
       let span_start = key.fragment().len();
       let span_end = span_start + sep.fragment().len() + value.fragment().len();
       Err(NomErr::Failure(Errors::from(
-        Unexpected(UnexpectedError::new(
+        Error::unexpected(
           "assignment",
           input.slice(span_start..span_end),
           Some("This is a binary option and thus takes no value.")
-        ))
+        )
       )))
     }
 
-    Some(OptionKind::Legacy) => {
-      println!("The option {} is a legacy option. Ignoring.", key);
-      Ok((rest, None))
-    }
+    Some(OptionKind::Legacy) => Ok((rest, Some(OptionField::Legacy(static_name(&key))))),
+
+    Some(OptionKind::Unsupported(help)) => Err(NomErr::Failure(Errors::from(Error::from(
+      UnsupportedError::new(static_name(&key), key, help)
+    )))),
 
     Some(OptionKind::Unimplemented) => {
-      println!("The option {} is not implemented. Ignoring.", key);
+      tracing::warn!(option = %key, "option is not implemented, ignoring");
       Ok((rest, None))
     }
 
     None => {
       Err(NomErr::Failure(Errors::from(
-        Unexpected(UnexpectedError::new("unknown option", key, None))
+        suggest_option(Error::unexpected("unknown option", key, None), key, false)
       )))
     }
   }
@@ -354,7 +540,7 @@ fn parse_option_with_value(input: InputType) -> NomResult<InputType, Option<Opti
 Constructs the parser that parses code for `ItemType` `item_type`.
 
 There are five `ItemType`s this applies to:
-```rust
+```text
 ItemType::Top
 ItemType::Class
 ItemType::Init
@@ -372,21 +558,23 @@ fn parse_code_type(item_type: ItemType) -> impl Fn(InputType) -> Result {
                 .map_err(
                   |mut result| {
                     if let NomErr::Failure(errors) = &mut result {
-                      errors.push(
-                        UnclosedDelim(
-                          UnclosedDelimError::new(delim_span, input.slice(delim_span.input_len()..))
-                        )
-                      );
+                      errors.push(Error::unclosed_delim(
+                        delim_span,
+                        item_type.close_delimiter(),
+                        input.slice(delim_span.input_len()..)
+                      ));
                     }
                     result
                   } // end closure mapped onto nested_code(..) error result
                 )
-                .and_then(|(rest, inner_span)| {
+                .map(|(rest, mut inner_span)| {
                   // Put the delim_span back on the inner_span if item_type is ItemType::Unknown.
                   if item_type == ItemType::Unknown {
-                    Ok((rest, delim_span.into().merge(inner_span)))
+                    let mut code_span = delim_span;
+                    code_span.merged(&mut inner_span);
+                    (rest, code_span)
                   } else {
-                    Ok((rest, inner_span))
+                    (rest, inner_span)
                   }
                 }
                 )
@@ -414,8 +602,8 @@ fn parse_code_block(i: InputType) -> SResult {
       fold_many1(
         recognize(preceded(is_a("\t "), pair(not_line_ending, line_ending))),
         SectionItemSet::default(),
-        |mut acc, mut next: InputType| {
-          merge_or_push_item(&mut acc, Item::User(next.into()));
+        |mut acc, next: InputType| {
+          merge_or_push_item(&mut acc, Item::User(next));
           acc
         }
       ),
@@ -472,7 +660,7 @@ fn parse_code_block(i: InputType) -> SResult {
           let rest = Some(i.slice((l_span.fragment().len() + 2)..));
           Err(
             Errors::from(
-              InvalidLabel(InvalidLabelError::new(name, name, rest))
+              Error::invalid_label(name, name, rest)
             )
           )
         },
@@ -509,26 +697,26 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
         // A string: "This, }, is a closing brace but does not close a block."
         map(parse_string, |l_span| {
           report(l_span, item_type);
-          l_span.into()
+          l_span
         }),
 
         // A character: '}'
         map(parse_character, |l_span| {
           report(l_span, item_type);
-          l_span.into()
+          l_span
         }),
 
         // Whitespace and comments
         map(recognize(skip1), |l_span| {
           report(l_span, item_type);
-          l_span.into()
+          l_span
         }),
 
         // Match "safe" characters. This is an optimization so we don't parse a single character at
         // a time with the next parser below.
         map(is_not(r#"/\"'%{}"#), |l_span: InputType| {
           report(l_span, item_type);
-          l_span.into()
+          l_span
         }),
 
         // Any character not matched above. We use more or less the code for anychar but in a way
@@ -544,7 +732,7 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
               Some((idx, _)) => {
                 let (rest, l_span) = input.take_split(idx);
                 report(l_span, item_type);
-                Ok((rest, l_span.to_span()))
+                Ok((rest, l_span))
               }
 
               // Just one character remaining.
@@ -552,7 +740,7 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
                 report(input, item_type);
                 Ok((
                   input.slice(input.input_len()..),
-                  input.into()
+                  input
                 ))
               }
             },
@@ -570,7 +758,7 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
                   if item_type.close_delimiter() != "%}" {
                     // If the wrong closing tag is found, it is always an error.
                     Err(NomErr::Failure(Errors::from(
-                      ExpectedFound(ExpectedFoundError::new(item_type.close_delimiter(), "%}", input))
+                      Error::expected_found(item_type.close_delimiter(), "%}", input)
                     )))
                   } else {
                     // The ending `%}` is thrown away.
@@ -585,7 +773,7 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
                   if item_type.close_delimiter() != "}" {
                     // Always an error.
                     Err(NomErr::Failure(Errors::from(
-                      ExpectedFound(ExpectedFoundError::new(item_type.close_delimiter(), "}", input))
+                      Error::expected_found(item_type.close_delimiter(), "}", input)
                     )))
                   } else if item_type == ItemType::Unknown {
                     // Do not throw away the `}`
@@ -611,7 +799,7 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
         //   |input: InputType| {
         //     // Always an error.
         //     Err(NomErr::Error(Errors::from(
-        //       UnexpectedSectionEnd(UnexpectedSectionEndError::new(Vec::<LSpan>::default(), input))
+        //       Error::unexpected_section_end(Vec::<LSpan>::default(), input)
         //     )))
         //   }
         // ),
@@ -620,8 +808,8 @@ pub fn parse_nested_code<'a>(i: InputType<'a>, item_type: ItemType) -> Result<'a
       // endregion
     ), // end many_till
 
-    |(mut code, (rest, mut close_delim_item))| {
-      consolidate_code(code, close_delim_item)
+    |(code, (_rest, close_delim_item))| {
+      consolidate_code(code, close_delim_item, item_type)
     }
   )(i)
 }
@@ -633,7 +821,8 @@ Expression on a new line of the form:
 
   %include file1 "file2" "file3"
 
-The phrase `%include` following by one or more optionally quoted file names.
+The phrase `%include` following by one or more optionally quoted file names. Each becomes an
+`Item::Include` without contents, which `expand_includes` reads the file into.
 */
 fn parse_include(i: InputType) -> SResult {
   let (rest, files) = preceded(
@@ -641,46 +830,93 @@ fn parse_include(i: InputType) -> SResult {
     cut(separated_list1(space1, parse_filename))
   )(i)?;
 
-  for f in &files{
-    println!("file: {}", f.fragment());
-  }
+  let included_items = files.into_iter().map(|in_file| Item::Include {
+    file: in_file,
+    contents: vec![]
+  }).collect();
 
-  let included_items = files.iter().map(|in_file| {
-    // retrieve and parse the contents of the file
-    let mut filename = String::default();
+  Ok((rest, included_items))
+}
 
-    std::fs::File::open(in_file.fragment())
-        .expect(
-          // todo: Make a proper diagnostic for this
-          format!("Could not read from file: {}", &in_file).as_str()
-        )
-        .read_to_string(&mut filename)
-        .unwrap_or_else(
-          // todo: Make a proper diagnostic for this
-          |x| { panic!("Could not read from included file: {:?}", x.into_inner()); }
-        );
+/**
+Parses the file each `Item::Include` among `items` names into its `contents`, recursively. The
+text of every file is kept in `sources`, which the items parsed from it borrow, so `sources` lives
+as long as the items do. The parsers of the sections are followed by this one:
+
+```ignore
+let sources = IncludedSources::default();
+let (rest, items) = section_one(input).and_then(|(rest, items)| {
+  Ok((rest, expand_includes(items, &sources)?))
+})?;
+```
+*/
+pub fn expand_includes<'a>(items: SectionItemSet<'a>, sources: &'a IncludedSources)
+  -> std::result::Result<SectionItemSet<'a>, NomErr<Errors>>
+{
+  let mut expanded = SectionItemSet::default();
+
+  for item in items {
+    match item {
+      Item::Include { file, .. } => expanded.push(include_file(file, sources)?),
+      item => expanded.push(item),
+    }
+  }
 
+  Ok(expanded)
+}
 
-    // todo: Figure out how to implement SourceFile, give it to codespan_reporting.
-    let new_source = Source::new(in_file.fragment().to_string(), new_source.as_str());
+/// Reads and parses the file `%include` names in `in_file`, with the files it includes in turn.
+fn include_file<'a>(in_file: Span<'a>, sources: &'a IncludedSources)
+  -> std::result::Result<Item<'a>, NomErr<Errors>>
+{
+  // A quoted file name keeps its quotes in `in_file`.
+  let name = in_file.fragment().trim_matches('"');
+
+  let path = include::resolve(name).map_err(|searched| {
+    NomErr::Failure(Errors::from(Error::from(
+      IncludeError::new(
+        name,
+        in_file,
+        "file not found".to_string(),
+        searched.iter().map(|p| p.display().to_string()).collect()
+      )
+    )))
+  })?;
 
-    match section_one(InputType::new(
-      0,
-      new_source.len(),
-      &new_source,
-    )) {
-      Ok((_rest, section_items)) => section_items,
-      Err(errors) => {
-        panic!("{}", errors);
-      }
-    }
+  // A file already being parsed is not read again, as including it would recurse forever.
+  if let Some(chain) = include::cycle(&path) {
+    return Err(NomErr::Failure(Errors::from(Error::from(
+      IncludeCycleError::new(name, in_file, chain)
+    ))));
   }
-  ).fold(Vec::new(), |mut acc, mut next| {
-    acc.extend(&mut next.drain(..));
-    acc
-  });
 
-  Ok((rest, SmallVec::from(included_items)))
+  let mut contents = String::default();
+  File::open(&path)
+      .and_then(|mut f| f.read_to_string(&mut contents))
+      .map_err(|e| {
+        NomErr::Failure(Errors::from(Error::from(
+          IncludeError::new(name, in_file, e.to_string(), vec![])
+        )))
+      })?;
+
+  let source_name = path.display().to_string();
+  let contents    = sources.add(source_name.clone(), contents);
+
+  include::enter_file(&path, Some(in_file.to_span()));
+  let result = section_one(InputType::new(contents))
+      .and_then(|(_rest, items)| expand_includes(items, sources));
+  include::exit_file();
+
+  let items = result.map_err(|e| match e {
+    NomErr::Error(errors)   => NomErr::Error(errors.in_file(&source_name)),
+    NomErr::Failure(errors) => NomErr::Failure(errors.in_file(&source_name)),
+    incomplete              => incomplete,
+  })?;
+
+  Ok(Item::Include {
+    file: in_file,
+    contents: items.into_vec()
+  })
 }
 
 // endregion
@@ -689,14 +925,12 @@ fn parse_include(i: InputType) -> SResult {
 
 pub fn section_two(i: InputType) -> SResult {
 
-  let mut items = SectionItemSet::default();
-
   // region Scanner Top Code
 
   // Section Two starts with an optional code section for code local to the scanner routine. The
   // code is either surrounded by `%{ ... %}` or is indented. We store such code in
   // `Item::ScannerTop`.
-  let (_rest, mut scanner_top_code) =
+  let (rest, mut items) =
     fold_many0(
       alt((
 
@@ -704,13 +938,13 @@ pub fn section_two(i: InputType) -> SResult {
         fold_many1(
           recognize(preceded(is_a("\t "), pair(not_line_ending, line_ending))),
           SectionItemSet::default(),
-          |mut acc, mut next: InputType| {
-            merge_or_push_item(&mut acc, Item::ScannerTop(next.into()));
+          |mut acc, next: InputType| {
+            merge_or_push_item(&mut acc, Item::ScannerTop(next));
             acc
           }
         ),
 
-        // Scanner Top Code within `{ }`
+        // Scanner Top Code within `%{ ... %}`
         map(
           parse_code_type(ItemType::ScannerTop),
           |span| {
@@ -718,14 +952,10 @@ pub fn section_two(i: InputType) -> SResult {
           },
         ),
 
-        // Scanner Top Code within `%{ ... %}`
-
-
-
-
+        // Blank lines
+        value(SectionItemSet::default(), line_ending),
 
         // Note that comments MUST be indented, as otherwise they are interpreted as regexes.
-
       )),
 
       SectionItemSet::default(),
@@ -736,26 +966,341 @@ pub fn section_two(i: InputType) -> SResult {
       }
   )(i)?;
 
-  // Since non-scanner_top_code comments from above are empty, this vector might be empty.
-  if !scanner_top_code.is_empty(){
-    if let Some(span) = scanner_top_code {
-      items.push(Item::scanner_top_code(span));
+  // endregion
+
+  // region Rules
+
+  let (rest, rules) =
+    terminated(
+      fold_many0(
+        section_two_item,
+
+        SectionItemSet::default(),
+
+        | mut acc, mut next | {
+          acc.extend(next.drain(..));
+          acc
+        }
+      ),
+
+      opt(terminated(tag("%%"), opt(line_ending)))
+    )(rest)?;
+
+  items.extend(rules);
+
+  // endregion
+
+  Ok((rest, items))
+}
+
+/**
+A rule, a start condition scope, or what lies between rules. Code between rules, indented or
+within `%{ … %}`, belongs to no rule. Flex copies it into the scanner routine where it stands, to
+undefined effect; Lesk keeps it with the code at the top of the scanner routine, and `lint` warns
+that it has moved.
+*/
+fn section_two_item(i: InputType) -> SResult {
+  alt((
+    parse_conditional(section_two_item),
+    parse_condition_scope,
+    // Before `parse_rule`, which would take `%{` for a pattern.
+    map(
+      parse_code_type(ItemType::ScannerTop),
+      |span| SmallVec::from_elem(Item::ScannerTop(span), 1)
+    ),
+    parse_rule,
+
+    preceded(is_a("\t "), parse_comment_line),
+    map(
+      recognize(preceded(is_a("\t "), pair(not_line_ending, line_ending))),
+      |code: InputType| SmallVec::from_elem(Item::ScannerTop(code), 1)
+    ),
+    value(SectionItemSet::default(), line_ending),
+  ))(i)
+}
+
+/**
+A single rule of the form
+
+  <COND1,COND2>pattern   action
+
+The start condition list is optional. The action is one of
+
+  * the rest of the line,
+  * a `{ … }` or `%{ … %}` block, which may span several lines,
+  * `|`, meaning "the same action as the next rule," or
+  * nothing at all.
+*/
+fn parse_rule(i: InputType) -> SResult {
+  // The section separator is not a pattern.
+  let (rest, (conditions, pattern)) = preceded(
+    not(tag("%%")),
+    pair(
+      map(opt(parse_start_conditions), |c| c.unwrap_or_default()),
+      parse_pattern
+    )
+  )(i)?;
+
+  let (rest, action) = preceded(space0, parse_action)(rest)?;
+  let (pattern, context) = split_trailing_context(pattern);
+
+  let result = SmallVec::from_elem(
+    Item::Rule {
+      conditions,
+      pattern,
+      context,
+      modifiers: vec![],
+      action
+    },
+    1);
+
+  Ok((rest, result))
+}
+
+/**
+A group of rules sharing a start condition prefix. Scopes may nest, and a rule inside a scope is
+active in the union of its own start conditions and those of every enclosing scope.
+
+  <STRING,CHAR>{
+    \n        { error("newline in literal"); }
+    <CHAR>'    { BEGIN(INITIAL); }
+  }
+
+A scope may also set the pattern options `case-insensitive`, `dotall`, `freespace`, and `unicode`
+(and their negations) for every rule in it, including the rules of nested scopes. An inner scope's
+setting takes precedence over an outer one.
+
+  <SQL>{
+    %option case-insensitive
+    "select"   { return keyword(); }
+  }
+*/
+fn parse_condition_scope(i: InputType) -> SResult {
+  let (rest, (conditions, open_brace)) = pair(
+    parse_start_conditions,
+    terminated(tag("{"), pair(space0, line_ending))
+  )(i)?;
+
+  // Rules in a scope are usually indented, so a line starting with `/*` or `//` is read as a
+  // comment, as flex reads it, rather than as a pattern.
+  let (rest, (rules, _)) = many_till(
+    preceded(
+      space0,
+      alt((
+        parse_comment_line,
+        parse_condition_scope,
+        parse_scoped_option,
+        parse_rule,
+        value(SectionItemSet::default(), line_ending),
+      ))
+    ),
+    tuple((space0, char1('}'), space0, opt(line_ending)))
+  )(rest)
+      .map_err(
+        |result| {
+          let mut errors = match result {
+            | NomErr::Error(errors)
+            | NomErr::Failure(errors) => errors,
+            incomplete => return incomplete,
+          };
+          errors.push(
+            Error::unclosed_delim(open_brace, "}", rest)
+          );
+          NomErr::Failure(errors)
+        }
+      )?;
+
+  let (options, items): (Vec<Item>, Vec<Item>) =
+      rules.into_iter().flatten().partition(|item| item.item_type() == ItemType::Option);
+  let options: Vec<OptionField> = options.into_iter().filter_map(|item| match item {
+    Item::Option(option, _) => Some(option),
+    _ => None
+  }).collect();
+
+  let mut result = SectionItemSet::default();
+  for mut item in items {
+    if let Item::Rule { conditions: rule_conditions, modifiers, .. } = &mut item {
+      for condition in &conditions {
+        if !rule_conditions.iter().any(|c| c.fragment() == condition.fragment()) {
+          rule_conditions.push(*condition);
+        }
+      }
+      // Options of nested scopes come first and so take precedence.
+      modifiers.extend(options.iter().cloned());
     }
+    result.push(item);
   }
 
-  // endregion
+  Ok((rest, result))
+}
 
-  // Recursively parse modes (start conditions).
-  let modes = Modes::default();
+/// An `%option` line in a start condition scope, which may only set pattern options.
+fn parse_scoped_option(i: InputType) -> SResult {
+  let (rest, options) = terminated(parse_option, pair(space0, line_ending))(i)?;
+
+  for option in &options {
+    if let Item::Option(field, text) = option {
+      if field.modifier().is_none() {
+        return Err(NomErr::Failure(Errors::from(Error::Message(
+          text.to_span(),
+          "Only the pattern options `case-insensitive`, `dotall`, `freespace`, and `unicode` \
+          can be set inside a start condition scope.".into()
+        ))));
+      }
+    }
+  }
 
-  // Start Conditions:
-  //    <start1,start2,...>
-  //    < -start1,-start2,...>
+  Ok((rest, options))
+}
 
-  SResult
+/**
+Parses a start condition list. Besides plain names, the list may contain the wildcard `*`,
+meaning every start condition, and exclusions written `-NAME`:
+
+  <COND1,COND2>
+  <*>
+  < -COND1,-COND2>
 
+The `-` is kept in the returned span. Conditions are resolved to modes by `Modes`. Flex's
+end-of-file pattern, `<<EOF>>`, is not a list, and is left for `parse_pattern`.
+*/
+fn parse_start_conditions(i: InputType) -> NomResult<InputType, Vec<InputType>, Errors> {
+  delimited(
+    pair(not(tag("<<EOF>>")), char1('<')),
+    cut(separated_list1(
+      delimited(space0, char1(','), space0),
+      preceded(
+        space0,
+        alt((
+          tag("*"),
+          recognize(pair(char1('-'), parse_identifier)),
+          parse_identifier,
+        ))
+      )
+    )),
+    cut(preceded(space0, char1('>')))
+  )(i)
 }
 
+/**
+A pattern extends to the first whitespace character that is not escaped, quoted, or inside a
+bracket expression. A backslash at the end of a line continues the pattern on the next line,
+where leading whitespace is skipped, so a long pattern can be wrapped:
+
+  "BEGIN"|"END"|"IF"|"THEN"|"ELSE"|\
+      "WHILE"|"DO"     { return keyword(); }
+
+The span of such a pattern covers every line of it.
+*/
+fn parse_pattern(i: InputType) -> Result {
+  let mut in_quotes    = false;
+  let mut in_brackets  = false;
+  let mut escaped      = false;
+  let mut continuation = false;
+  let mut after_cr     = false;
+  let mut end          = i.input_len();
+
+  for (idx, c) in i.fragment().char_indices() {
+    if escaped {
+      escaped = false;
+      continuation = c == '\r' || c == '\n';
+      after_cr = c == '\r';
+      continue;
+    }
+
+    if continuation {
+      match c {
+        '\n' if after_cr => { after_cr = false; continue; }
+        ' ' | '\t'      => continue,
+        _               => continuation = false,
+      }
+    }
+
+    match c {
+      '\\'                       => escaped = true,
+      '"' if !in_brackets        => in_quotes = !in_quotes,
+      '[' if !in_quotes          => in_brackets = true,
+      ']' if in_brackets         => in_brackets = false,
+      '\n' | '\r'                => { end = idx; break; }
+      ' ' | '\t' if !in_quotes && !in_brackets => { end = idx; break; }
+      _                          => { /* pass */ }
+    }
+  }
+
+  if end == 0 {
+    return Err(NomErr::Error(Errors::from_error_kind(i, ErrorKind::TakeWhile1)));
+  }
+
+  Ok(i.take_split(end))
+}
+
+/**
+Splits `r/s` into the pattern `r` and its trailing context `s` at the first `/` that is not
+escaped, quoted, bracketed, or parenthesized.
+*/
+fn split_trailing_context(pattern: InputType) -> (InputType, Option<InputType>) {
+  let mut in_quotes   = false;
+  let mut in_brackets = false;
+  let mut escaped     = false;
+  let mut depth       = 0usize;
+
+  for (idx, c) in pattern.fragment().char_indices() {
+    if escaped {
+      escaped = false;
+      continue;
+    }
+
+    match c {
+      '\\'                       => escaped = true,
+      '"' if !in_brackets        => in_quotes = !in_quotes,
+      '[' if !in_quotes          => in_brackets = true,
+      ']' if in_brackets         => in_brackets = false,
+      '(' if !in_quotes && !in_brackets => depth += 1,
+      ')' if !in_quotes && !in_brackets => depth = depth.saturating_sub(1),
+      '/' if !in_quotes && !in_brackets && depth == 0 => {
+        return (pattern.slice(..idx), Some(pattern.slice(idx + 1..)));
+      }
+      _                          => { /* pass */ }
+    }
+  }
+
+  (pattern, None)
+}
+
+/// Parses the action of a rule, including the line ending that terminates it.
+fn parse_action(i: InputType) -> NomResult<InputType, Action, Errors> {
+  alt((
+    // No action
+    map(line_ending, |_| Action::Empty),
+
+    // Fall through to the next rule's action
+    map(
+      terminated(tag("|"), pair(space0, line_ending)),
+      |bar: InputType| Action::FallThrough(bar)
+    ),
+
+    // `{ … }`, keeping the braces and whatever follows them on the line
+    map(
+      terminated(
+        recognize(pair(parse_code_type(ItemType::Unknown), not_line_ending)),
+        opt(line_ending)
+      ),
+      |code: InputType| Action::Code(code)
+    ),
+
+    // `%{ … %}`
+    map(
+      terminated(parse_code_type(ItemType::User), opt(line_ending)),
+      Action::Code
+    ),
+
+    // The rest of the line
+    map(
+      terminated(not_line_ending, opt(line_ending)),
+      |code: InputType| Action::Code(code)
+    ),
+  ))(i)
+}
 
 // endregion
 
@@ -847,6 +1392,18 @@ fn parse_keyword(keyword: &'static str) -> impl Fn(InputType) -> Result {
 }
 
 
+/// Pairs the output of `parser` with the span of the input it consumed.
+fn spanned<'a, O, F>(parser: F) -> impl Fn(InputType<'a>) -> NomResult<InputType<'a>, (O, InputType<'a>), Errors>
+  where F: Fn(InputType<'a>) -> NomResult<InputType<'a>, O, Errors>
+{
+  move |input| {
+    let (rest, output) = parser(input)?;
+    let consumed = input.slice(..input.input_len() - rest.input_len());
+    Ok((rest, (output, consumed)))
+  }
+}
+
+
 /// Parses a quoted string with escapes and returns the entire string, including the surrounding
 /// double quotes. No escapes are transformed.
 fn parse_string(i: InputType) -> Result {
@@ -884,27 +1441,23 @@ fn parse_quoted(i: InputType) -> Result {
 
 
 
-fn consolidate_code(mut code: Vec<InputType>, item_type: InputType) -> InputType {
-  // Consolidate parsed value
-  if code.is_empty() {
-    return close_delim_item;
-  }
-
-  let mut first_span = code.first().unwrap().into();
+/// Joins the pieces of a block of code and its closing delimiter, if it is kept, into one span.
+fn consolidate_code<'a>(
+  code: Vec<InputType<'a>>,
+  close_delim_item: InputType<'a>,
+  item_type: ItemType
+) -> InputType<'a> {
+  let mut pieces = code.into_iter();
+  let mut code_span = match pieces.next() {
+    Some(first) => first,
+    None => return close_delim_item,
+  };
 
-  let code_span = code[1..].iter_mut().fold(
-    first_span,
-    |mut acc: InputType, mut next| {
-      match acc.merged(&mut next) {
-        Merged::Yes(_s) => { /* pass */ }
-        Merged::No(_s, _) => {
-          println!("Non contiguous {}: {} <--> {}", item_type, acc, next.to_span());
-        }
-      };
-      acc
+  for mut next in pieces.chain(std::iter::once(close_delim_item)) {
+    if let Merged::No(acc, next) = code_span.merged(&mut next) {
+      tracing::debug!("non-contiguous {}: {} <--> {}", item_type, acc, next);
     }
-  );
-  code_span.merged(&mut close_delim_item.to_span());
+  }
   code_span
 }
 
@@ -920,3 +1473,228 @@ fn report(l_span: InputType, item_type: ItemType) {
   //          l_span.into()
   // );
 }
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The fields the `%option` lines of section one `text` set, in order.
+  fn option_fields(text: &str) -> Vec<OptionField> {
+    let (_, items) = section_one(InputType::new(text)).unwrap();
+    items.into_iter().filter_map(|item| match item {
+      Item::Option(field, _) => Some(field),
+      _ => None
+    }).collect()
+  }
+
+  #[test]
+  fn plain_option_and_its_negation() {
+    assert_eq!(option_fields("%option yymore\n"), vec![OptionField::Yymore(true)]);
+    assert_eq!(option_fields("%option noyymore\n"), vec![OptionField::Yymore(false)]);
+  }
+
+  #[test]
+  fn inverted_option_and_its_negation() {
+    assert_eq!(option_fields("%option default\n"), vec![OptionField::Default(true)]);
+    assert_eq!(option_fields("%option nodefault\n"), vec![OptionField::Default(false)]);
+    assert_eq!(option_fields("%option noyywrap\n"), vec![OptionField::Yywrap(false)]);
+  }
+
+  #[test]
+  fn negated_name_of_an_option() {
+    assert_eq!(option_fields("%option caseful\n"), vec![OptionField::CaseInsensitive(false)]);
+    assert_eq!(option_fields("%option nocaseful\n"), vec![OptionField::CaseInsensitive(true)]);
+  }
+
+  #[test]
+  fn header_file_is_unsupported() {
+    let errors = match section_one(InputType::new("%option header-file=\"lex.h\"\n")) {
+      Err(NomErr::Failure(errors)) => errors,
+      _ => panic!("`%option header-file` is accepted"),
+    };
+    assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0013"]);
+  }
+
+  #[test]
+  fn reserved_tokens_are_errors() {
+    let declarations = [
+      ("%token Plus Unmatched\n", "Unmatched"),
+      ("%token EndOfInput(u8)\n", "EndOfInput"),
+    ];
+    for (text, name) in declarations {
+      let errors = match section_one(InputType::new(text)) {
+        Err(NomErr::Failure(errors)) => errors,
+        _ => panic!("`%token {}` is accepted", name),
+      };
+      // The error is reported at the declaration of the name.
+      let start = text.find(name).unwrap() as u32;
+      let span  = codespan::Span::new(start, start + name.len() as u32);
+      assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0014"]);
+      assert_eq!(errors.iter().next().unwrap().span(), Some(span));
+    }
+  }
+
+  /// The names of the definitions section one `text` makes with the features `features` defined.
+  fn defined_names(text: &str, features: &[&str]) -> Vec<String> {
+    conditional::set_features(features.iter().copied());
+    let (_, items) = section_one(InputType::new(text)).unwrap();
+    items.into_iter().filter_map(|item| match item {
+      Item::Definition { name, .. } => Some(name.fragment().to_string()),
+      _ => None
+    }).collect()
+  }
+
+  #[test]
+  fn conditional_sections_select_a_branch() {
+    let text = "%if UNICODE\nletter  \\p{L}\n%else\nascii  [A-Za-z]\n%endif\ndigit  [0-9]\n";
+    assert_eq!(defined_names(text, &["UNICODE"]), vec!["letter", "digit"]);
+    assert_eq!(defined_names(text, &[]), vec!["ascii", "digit"]);
+
+    let text = "%if !UNICODE\nascii  [A-Za-z]\n%endif\n";
+    assert_eq!(defined_names(text, &["UNICODE"]), Vec::<String>::new());
+    assert_eq!(defined_names(text, &[]), vec!["ascii"]);
+  }
+
+  #[test]
+  fn conditional_sections_nest() {
+    let text = "%if A\na  a\n%if B\nab  ab\n%endif\n%else\nnone  x\n%endif\n";
+    assert_eq!(defined_names(text, &["A", "B"]), vec!["a", "ab"]);
+    assert_eq!(defined_names(text, &["A"]), vec!["a"]);
+    assert_eq!(defined_names(text, &["B"]), vec!["none"]);
+  }
+
+  #[test]
+  fn conditional_sections_in_section_two() {
+    conditional::set_features(["DEBUG"]);
+    let patterns = |text| section_two_items(text).into_iter().filter_map(|item| match item {
+      Item::Rule { pattern, .. } => Some(pattern.fragment().to_string()),
+      _ => None
+    }).collect::<Vec<_>>();
+    let text = "%if DEBUG\na   f();\n%else\nb   g();\n%endif\nc   h();\n";
+    assert_eq!(patterns(text), vec!["a", "c"]);
+  }
+
+  #[test]
+  fn unclosed_conditional_section_is_an_error() {
+    let errors = match section_one(InputType::new("%if A\nletter  [a-z]\n")) {
+      Err(NomErr::Failure(errors)) => errors,
+      _ => panic!("an `%if` without `%endif` is accepted"),
+    };
+    assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0004"]);
+  }
+
+  /// The items section two `text` parses into.
+  fn section_two_items(text: &str) -> Vec<Item<'_>> {
+    let (_, items) = section_two(InputType::new(text)).unwrap();
+    items.into_vec()
+  }
+
+  #[test]
+  fn braced_action_keeps_the_rest_of_its_line() {
+    let items = section_two_items("a   { x(); } // then y\nb   y();\n");
+    match &items[0] {
+      Item::Rule { action: Action::Code(code), .. } => {
+        assert_eq!(*code.fragment(), "{ x(); } // then y");
+      }
+      item => panic!("expected a rule, found {:?}", item),
+    }
+  }
+
+  #[test]
+  fn code_between_rules_is_kept() {
+    let items = section_two_items("a   x();\n    count += 1;\n%{\nreset();\n%}\nb   y();\n");
+    let code: Vec<&str> = items.iter().filter_map(|item| match item {
+      Item::ScannerTop(code) => Some(code.fragment().trim()),
+      _ => None
+    }).collect();
+    assert_eq!(code, vec!["count += 1;", "reset();"]);
+    assert_eq!(items.iter().filter(|item| matches!(item, Item::Rule { .. })).count(), 2);
+  }
+
+  #[test]
+  fn comments_in_a_condition_scope_are_not_rules() {
+    let text  = "<STR>{\n    /* the end of a string */\n\n    // escapes\n    \\\"   end();\n}\n";
+    let items = section_two_items(text);
+    assert_eq!(items.iter().filter(|item| matches!(item, Item::Comment(_))).count(), 2);
+    match &items[..] {
+      [.., Item::Rule { pattern, conditions, .. }] => {
+        assert_eq!(*pattern.fragment(), "\\\"");
+        assert_eq!(*conditions[0].fragment(), "STR");
+      }
+      items => panic!("expected a rule, found {:?}", items),
+    }
+  }
+
+  /// The rules of section two `text`, as their conditions, pattern, context, and action.
+  fn rules(text: &str) -> Vec<(Vec<String>, String, Option<String>, String)> {
+    section_two_items(text).into_iter().filter_map(|item| match item {
+      Item::Rule { conditions, pattern, context, action, .. } => {
+        let action = match action {
+          Action::Code(code)        => code.fragment().to_string(),
+          Action::FallThrough(_)    => "|".to_string(),
+          Action::Empty             => String::new(),
+        };
+        Some((
+          conditions.iter().map(|c| c.fragment().to_string()).collect(),
+          pattern.fragment().to_string(),
+          context.map(|c| c.fragment().to_string()),
+          action,
+        ))
+      }
+      _ => None
+    }).collect()
+  }
+
+  #[test]
+  fn pattern_continues_after_a_trailing_backslash() {
+    let items = section_two_items("abc\\\n    def   f();\nx   g();\n");
+    let patterns: Vec<&str> = items.iter().filter_map(|item| match item {
+      Item::Rule { pattern, .. } => Some(*pattern.fragment()),
+      _ => None
+    }).collect();
+    assert_eq!(patterns, vec!["abc\\\n    def", "x"]);
+  }
+
+  #[test]
+  fn forms_of_a_rule() {
+    let rules = rules("<A,B>x   y();\nz   |\nw\nv   {\n  multi();\n}\n");
+    assert_eq!(rules[0].0, vec!["A", "B"]);
+    assert_eq!((rules[0].1.as_str(), rules[0].3.as_str()), ("x", "y();"));
+    assert_eq!((rules[1].1.as_str(), rules[1].3.as_str()), ("z", "|"));
+    assert_eq!((rules[2].1.as_str(), rules[2].3.as_str()), ("w", ""));
+    assert_eq!((rules[3].1.as_str(), rules[3].3.as_str()), ("v", "{\n  multi();\n}"));
+    assert!(rules[1].0.is_empty());
+  }
+
+  #[test]
+  fn nested_scopes_join_their_conditions() {
+    let text  = "<A>{\n  a   x();\n  <B>{\n    <C>b   y();\n  }\n}\n";
+    let rules = rules(text);
+    assert_eq!(rules[0].0, vec!["A"]);
+    assert_eq!(rules[1].0, vec!["C", "B", "A"]);
+  }
+
+  #[test]
+  fn scoped_options_apply_to_nested_rules() {
+    let text  = "<A>{\n%option caseless\n<B>{\n%option nodotall\nb   y();\n}\n}\n";
+    match &section_two_items(text)[..] {
+      [Item::Rule { modifiers, .. }] => {
+        assert_eq!(
+          modifiers,
+          &vec![OptionField::Dotall(false), OptionField::CaseInsensitive(true)]
+        );
+      }
+      items => panic!("expected a rule, found {:?}", items),
+    }
+  }
+
+  #[test]
+  fn trailing_context_is_split_at_the_first_bare_slash() {
+    let rules = rules("a\"/\"b/c   x();\n[/]d/e   y();\n(f/g)   z();\n\\/h   w();\n");
+    assert_eq!((rules[0].1.as_str(), rules[0].2.as_deref()), ("a\"/\"b", Some("c")));
+    assert_eq!((rules[1].1.as_str(), rules[1].2.as_deref()), ("[/]d", Some("e")));
+    assert_eq!((rules[2].1.as_str(), rules[2].2.as_deref()), ("(f/g)", None));
+    assert_eq!((rules[3].1.as_str(), rules[3].2.as_deref()), ("\\/h", None));
+  }
+}
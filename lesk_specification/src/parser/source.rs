@@ -0,0 +1,48 @@
+/*!
+
+The text the parsers read and the files it comes from. The parsers read an `LSpan`, a slice of a
+source with its offset and line, and the items they produce keep theirs, so that diagnostics can
+point into the specification. `ToSpan` turns them into the byte ranges, `codespan::Span`, that
+diagnostics are labeled with, and the files themselves are kept in `SourceFiles`, keyed by
+`SourceID`.
+
+*/
+
+use codespan_reporting::files::SimpleFiles;
+use nom_locate::LocatedSpan;
+
+/// A slice of a source, with its byte offset and its line.
+pub type LSpan<'s> = LocatedSpan<&'s str>;
+
+/// The span an item of the specification is made from.
+pub type Span<'s> = LSpan<'s>;
+
+/// The files a specification is read from, its own and those it includes.
+pub type SourceFiles<Name, Source> = SimpleFiles<Name, Source>;
+
+/// The key of a file in `SourceFiles`.
+pub type SourceID = usize;
+
+/// Conversion to the byte range a diagnostic labels.
+pub trait ToSpan {
+  fn to_span(&self) -> codespan::Span;
+}
+
+impl ToSpan for codespan::Span {
+  fn to_span(&self) -> codespan::Span {
+    *self
+  }
+}
+
+impl<'s> ToSpan for LSpan<'s> {
+  fn to_span(&self) -> codespan::Span {
+    let start = self.location_offset();
+    codespan::Span::new(start as u32, (start + self.fragment().len()) as u32)
+  }
+}
+
+impl<T: ToSpan> ToSpan for &T {
+  fn to_span(&self) -> codespan::Span {
+    (*self).to_span()
+  }
+}
@@ -1,71 +1,32 @@
+#![allow(dead_code)]
 
 use nom::{
-  AsChar,
   branch::alt,
-  bytes::{
-    complete::{
-      escaped,
-      is_a,
-      take_while,
-      is_not,
-      take_until,
-      take_while1,
-      tag,
-    }
-  }, character::{
-    complete::{
-      alphanumeric0,
-      alphanumeric1,
-      char as char1,
-      multispace1,
-      newline,
-      none_of,
-      not_line_ending,
-      one_of,
-    },
-    is_alphanumeric,
+  bytes::complete::{
+    escaped,
+    is_not,
+    tag,
   },
-  combinator::{
-    cond,
-    cut,
-    map,
-    map_optc,
-    map_parser,
-    map_res,
-    not,
-    opt,
-    recognize,
-    value,
-  },
-  Compare,
-  Err as NomErr,
-  error::{
-    ErrorKind,
-    ParseError,
+  character::complete::{
+    multispace1,
+    not_line_ending,
   },
+  combinator::value,
+  error::ParseError,
   IResult as NomResult,
   multi::{
-    fold_many1,
     many0,
     many1,
-    separated_nonempty_list as separated_list1,
   },
   sequence::{
     delimited,
     pair,
-    preceded,
-    precededc,
-    separated_pair,
     terminated,
     tuple,
   },
 };
-use nom_locate::LocatedSpan;
 
-use crate::{
-  error::Errors,
-  section_items::*,
-};
+use crate::error::Errors;
 
 use super::*;
 use nom::character::complete::{space1, anychar};
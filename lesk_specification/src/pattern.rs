@@ -0,0 +1,254 @@
+/*!
+
+The translation of a rule's pattern into the regex handed to the regex engine.
+
+Lex patterns may quote literal text, `"a+b"`, which matches the characters between the quotes
+exactly. The regex engine knows nothing of quotes, so each quoted string is replaced by its text
+with every character the engine would treat specially escaped. Escape sequences inside the quotes
+keep their meaning, so `"\n"` still matches a newline and `"\""` matches a double quote. A
+backslash at the end of a line, which continues a pattern on the next line, is removed together
+with the line ending and the next line's indentation.
+
+The translated regex is longer than the pattern it came from, so `Translated` records, for each
+byte of the regex, the offset within the pattern of the byte that produced it. An error the regex
+engine reports at an offset of the regex can then be shown at the right place in the file.
+
+Pattern options written inside a start condition scope, such as `%option case-insensitive`, apply
+to the scope's rules only. They are lowered by `with_modifiers` into an inline modifier group
+around each rule's regex, `(?i:…)`, which the regex engine already understands.
+
+*/
+
+use nom::Slice;
+
+use crate::error::{Error, Errors};
+use crate::options::OptionField;
+use crate::Span;
+
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Translated {
+  pub regex: String,
+  /// For each byte of `regex`, the offset within the pattern of the byte it was translated from
+  pub origins: Vec<usize>,
+}
+
+impl Translated {
+  /// The offset within the pattern that the byte at `offset` in the regex came from.
+  #[allow(dead_code)]
+  pub fn origin(&self, offset: usize) -> usize {
+    self.origins
+        .get(offset)
+        .or_else(|| self.origins.last())
+        .copied()
+        .unwrap_or(0)
+  }
+
+  fn push(&mut self, text: &str, origin: usize) {
+    self.regex.push_str(text);
+    self.origins.extend(std::iter::repeat_n(origin, text.len()));
+  }
+
+  fn push_char(&mut self, c: char, origin: usize) {
+    let mut buffer = [0u8; 4];
+    self.push(c.encode_utf8(&mut buffer), origin);
+  }
+}
+
+/**
+Replaces each quoted string in `pattern` with an escaped regex. When `case_insensitive` is set
+the literal is wrapped in `(?i:…)`, so that it stays caseless wherever it appears in the pattern.
+*/
+#[allow(dead_code)]
+pub fn translate_quotes(pattern: Span, case_insensitive: bool) -> Result<Translated, Errors> {
+  translate_text(pattern.fragment(), pattern, case_insensitive)
+}
+
+/**
+Like `translate_quotes`, but for `text` derived from `origin`, typically the pattern with its
+`{NAME}` references expanded. Offsets in `Translated` are then offsets into `text`, and an
+unterminated quote is reported at the whole of `origin`.
+*/
+pub fn translate_text(text: &str, origin: Span, case_insensitive: bool)
+    -> Result<Translated, Errors>
+{
+  let exact           = std::ptr::eq(text, *origin.fragment());
+  let mut translated  = Translated::default();
+  let mut in_brackets = false;
+  // The offset of the backslash when the previous character was one
+  let mut escape: Option<usize>      = None;
+  // The offset of the opening quote when inside a quoted string
+  let mut quote_start: Option<usize> = None;
+  let mut continuation = false;
+  let mut after_cr     = false;
+
+  for (idx, c) in text.char_indices() {
+    if continuation {
+      match c {
+        '\n' if after_cr => { after_cr = false; continue; }
+        ' ' | '\t'      => continue,
+        _               => continuation = false,
+      }
+    }
+
+    if escape.is_some() && (c == '\r' || c == '\n') {
+      if quote_start.is_none() {
+        // Outside quotes the backslash has already been copied.
+        translated.regex.pop();
+        translated.origins.pop();
+      }
+      escape = None;
+      continuation = true;
+      after_cr = c == '\r';
+      continue;
+    }
+
+    match (quote_start, escape) {
+      // An escape inside quotes
+      (Some(_), Some(backslash)) => {
+        escape = None;
+        translated.push("\\", backslash);
+        translated.push_char(c, backslash);
+      }
+
+      (Some(_), None) => match c {
+        '\\' => escape = Some(idx),
+        '"' => {
+          quote_start = None;
+          if case_insensitive {
+            translated.push(")", idx);
+          }
+        }
+        c if c.is_ascii_punctuation() || c == ' ' => {
+          translated.push("\\", idx);
+          translated.push_char(c, idx);
+        }
+        c => translated.push_char(c, idx),
+      },
+
+      // An escape outside quotes is copied as it is.
+      (None, Some(_)) => {
+        escape = None;
+        translated.push_char(c, idx);
+      }
+
+      (None, None) => {
+        match c {
+          '\\'                => escape = Some(idx),
+          '[' if !in_brackets => in_brackets = true,
+          ']' if in_brackets  => in_brackets = false,
+          '"' if !in_brackets => {
+            quote_start = Some(idx);
+            if case_insensitive {
+              translated.push("(?i:", idx);
+            }
+            continue;
+          }
+          _ => { /* pass */ }
+        }
+        translated.push_char(c, idx);
+      }
+    }
+  }
+
+  if let Some(start) = quote_start {
+    let (open, end) =
+        if exact {
+          (origin.slice(start..start + 1), origin.slice(text.len()..))
+        } else {
+          (origin, origin.slice(origin.fragment().len()..))
+        };
+    return Err(Errors::from(Error::unclosed_delim(open, "\"", end)));
+  }
+
+  Ok(translated)
+}
+
+/**
+Wraps `regex` in an inline modifier group for the pattern options in `modifiers`, so that
+`[case-insensitive, nodotall]` gives `(?i-s:regex)`. An option given more than once counts the
+first time, and without options `regex` is returned as it is.
+*/
+pub fn with_modifiers(regex: &str, modifiers: &[OptionField]) -> String {
+  let mut on  = String::new();
+  let mut off = String::new();
+
+  for (flag, value) in modifiers.iter().filter_map(OptionField::modifier) {
+    if on.contains(flag) || off.contains(flag) {
+      continue;
+    }
+    if value { on.push(flag); } else { off.push(flag); }
+  }
+
+  match (on.is_empty(), off.is_empty()) {
+    (true, true)  => regex.to_string(),
+    (_, true)     => format!("(?{}:{})", on, regex),
+    _             => format!("(?{}-{}:{})", on, off, regex),
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::LSpan;
+
+  fn translate(pattern: &str, case_insensitive: bool) -> String {
+    translate_quotes(LSpan::new(pattern), case_insensitive).unwrap().regex
+  }
+
+  #[test]
+  fn quoted_text_is_escaped() {
+    assert_eq!(translate("\"a+b\"", false), "a\\+b");
+    assert_eq!(translate("x\"(.)\"*", false), "x\\(\\.\\)*");
+    assert_eq!(translate("\"a b\"", false), "a\\ b");
+  }
+
+  #[test]
+  fn escapes_in_quotes_keep_their_meaning() {
+    assert_eq!(translate("\"\\n\"", false), "\\n");
+    assert_eq!(translate("\"\\\"\"", false), "\\\"");
+  }
+
+  #[test]
+  fn quotes_in_brackets_and_escaped_quotes_are_literal() {
+    assert_eq!(translate("[\"a]+", false), "[\"a]+");
+    assert_eq!(translate("\\\"a+", false), "\\\"a+");
+  }
+
+  #[test]
+  fn caseless_literals_are_grouped() {
+    assert_eq!(translate("\"if\"x", true), "(?i:if)x");
+  }
+
+  #[test]
+  fn origins_point_into_the_pattern() {
+    let translated = translate_quotes(LSpan::new("a\"+\"b"), false).unwrap();
+    assert_eq!(translated.regex, "a\\+b");
+    assert_eq!(translated.origins, vec![0, 2, 2, 4]);
+  }
+
+  #[test]
+  fn continuation_lines_are_joined() {
+    assert_eq!(translate("abc\\\n    def", false), "abcdef");
+    assert_eq!(translate("abc\\\r\n\tdef", false), "abcdef");
+    assert_eq!(translate("\"ab\\\n  cd\"", false), "abcd");
+    // Only a backslash at the end of a line continues the pattern.
+    assert_eq!(translate("a\\ b", false), "a\\ b");
+  }
+
+  #[test]
+  fn continuation_keeps_the_origins_of_the_next_line() {
+    let translated = translate_quotes(LSpan::new("ab\\\n  c"), false).unwrap();
+    assert_eq!(translated.regex, "abc");
+    assert_eq!(translated.origins, vec![0, 1, 6]);
+  }
+
+  #[test]
+  fn unterminated_quote_is_an_error() {
+    let errors = translate_quotes(LSpan::new("ab\"cd"), false).unwrap_err();
+    assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0004"]);
+    // The error is at the end of the pattern, where the closing quote is missing.
+    assert_eq!(errors.iter().next().unwrap().span(), Some(codespan::Span::new(5, 5)));
+  }
+}
@@ -8,11 +8,10 @@ use std::fmt::Display;
 
 use nom::lib::std::fmt::Formatter;
 
-use crate::mergable::{Mergable, Merged, merge_or_append_items, merge_or_push_item};
+use crate::mergable::{Mergable, Merged};
 use crate::options::OptionField;
 use crate::parser::{Span, ToSpan};
 
-use super::{Code, SourceFile};
 use smallvec::SmallVec;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -27,9 +26,12 @@ pub enum ItemType {
   State,
   Definition,
   Option,
+  Token,
+  Comment,
 
   // Section Two
   ScannerTop,
+  Rule,
   // Start,    //< Start States
 }
 
@@ -47,9 +49,12 @@ impl Display for ItemType{
           ItemType::Option => "ItemType::Option",
           ItemType::State => "ItemType::State",
           ItemType::Definition => "ItemType::Definition",
+          ItemType::Token => "ItemType::Token",
+          ItemType::Comment => "ItemType::Comment",
 
           // Section Two
           ItemType::ScannerTop => "ItemType::ScannerTop",
+          ItemType::Rule => "ItemType::Rule",
         };
 
     write!(f, "{}", name)
@@ -57,27 +62,6 @@ impl Display for ItemType{
 }
 
 impl ItemType {
-  pub fn new<S>(&self, item: S) -> Item
-      where S: ToSpan
-  {
-    match self {
-      ItemType::Top => Item::top_code(item.to_span()),
-      ItemType::Class => Item::class_code(item.to_span()),
-      ItemType::Init => Item::init_code(item.to_span()),
-      ItemType::User => Item::user_code(item.to_span()),
-      ItemType::Unknown => Item::unknown_code(item.to_span()),
-      ItemType::ScannerTop => Item::scanner_top(item.to_span()),
-
-      _ => {
-        unreachable!("Cannot use ItemType::new to create an Options, Include, State, or Definition\
-        .");
-      }
-      // ItemType::Option => SectionItem::options_code(item),
-      // ItemType::Include => SectionItem::Include(item),
-      // ItemType::State => SectionItem::State(item)
-    }
-  }
-
   pub fn open_delimiter(&self) -> &'static str {
     match self {
       ItemType::Top => "%top{",
@@ -90,6 +74,8 @@ impl ItemType {
       ItemType::Unknown => "{",
       ItemType::Include => "%include",
       ItemType::Option => "%options",
+      ItemType::Token => "%token",
+      ItemType::Comment => "",
 
       ItemType::State => {
         // This method is never called on `SectionItem::State`
@@ -99,6 +85,10 @@ impl ItemType {
         // This method is never called on `SectionItem::Definition`
         panic! {"SectionItem::State has no opening delimiter."};
       }
+      ItemType::Rule => {
+        // This method is never called on `SectionItem::Rule`
+        panic! {"SectionItem::Rule has no opening delimiter."};
+      }
     }
   }
 
@@ -114,6 +104,9 @@ impl ItemType {
       | ItemType::Include
       | ItemType::Option
       | ItemType::Definition
+      | ItemType::Token
+      | ItemType::Comment
+      | ItemType::Rule
       | ItemType::State => false,
     }
   }
@@ -132,6 +125,9 @@ impl ItemType {
       | ItemType::Include
       | ItemType::Option
       | ItemType::Definition
+      | ItemType::Token
+      | ItemType::Comment
+      | ItemType::Rule
       | ItemType::State => ""
     }
   }
@@ -139,6 +135,27 @@ impl ItemType {
 
 pub type SectionItemSet<'s> = SmallVec<[Item<'s>;1]>;
 
+/// The action half of a rule in section two.
+#[derive(Clone, Debug)]
+pub enum Action<'s> {
+  /// Code to run on a match, either the rest of the line or a `{ … }` or `%{ … %}` block.
+  Code(Span<'s>),
+  /// The `|` action: use the same action as the next rule.
+  FallThrough(Span<'s>),
+  /// No action at all. The matched text is discarded.
+  Empty,
+}
+
+impl<'s> Display for Action<'s> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Action::Code(code)        => write!(f, "Code({:?})", code),
+      Action::FallThrough(_bar) => write!(f, "FallThrough"),
+      Action::Empty             => write!(f, "Empty"),
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub enum Item<'s> {
   // Section One
@@ -147,9 +164,10 @@ pub enum Item<'s> {
   Class(Span<'s>),
   Init(Span<'s>),
   Unknown(Span<'s>),
-  Option(OptionField),
+  Option(OptionField, Span<'s>),
+  /// The items of an `%include`d file. `file` is the name as written, including any quotes.
   Include {
-    file: SourceFile<String, String>,
+    file: Span<'s>,
     contents: Vec<Item<'s>>,
   },
   State {
@@ -158,14 +176,33 @@ pub enum Item<'s> {
   },
   Definition {
     name: Span<'s>,
+    /// The parameters of a definition written `NAME(a,b)`
+    params: Vec<Span<'s>>,
     code: Span<'s>,
   },
+  /// A comment on a line of its own, kept so that the specification can be printed back out.
+  Comment(Span<'s>),
+  /// A variant of the synthesized `Token` enum, `%token Number(i64)`.
+  Token {
+    name: Span<'s>,
+    payload: Option<Span<'s>>,
+  },
 
   // Section Two
   ScannerTop(Span<'s>),
+  /// A pattern and its action, optionally prefixed with start conditions: `<STR>\"  { … }`
+  Rule {
+    conditions: Vec<Span<'s>>,
+    pattern: Span<'s>,
+    /// The trailing context `s` of a rule written `r/s`
+    context: Option<Span<'s>>,
+    /// Pattern options, `%option case-insensitive`, of the start condition scopes around the rule
+    modifiers: Vec<OptionField>,
+    action: Action<'s>,
+  },
 }
 
-impl Display for Item {
+impl Display for Item<'_> {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     let text =
         match self {
@@ -175,13 +212,14 @@ impl Display for Item {
           Item::Class(code)   => format!("Class({:?})",   code),
           Item::Init(code)    => format!("Init({:?})",    code),
           Item::Unknown(code) => format!("Unknown({:?})", code),
+          Item::Comment(text) => format!("Comment({:?})", text),
 
 
           Item::Include {
             file,
             contents,
           } => {
-            format!("Include{{ file={:?} }}", file.name())
+            format!("Include{{ file={:?}, items={} }}", file, contents.len())
           }
           Item::State {
             is_exclusive,
@@ -191,16 +229,39 @@ impl Display for Item {
           }
           Item::Definition {
             name,
+            params,
             code,
           } => {
-            format!("Definition{{name: {:?}, regex={:?} }}", name, code)
+            format!("Definition{{name: {:?}, params={:?}, regex={:?} }}", name, params, code)
           }
-          Item::Option(option) => {
+          Item::Option(option, _) => {
             format!("Options: {:?}", *option)
           }
+          Item::Token {
+            name,
+            payload,
+          } => {
+            format!("Token{{name: {:?}, payload={:?} }}", name, payload)
+          }
 
           // Section Two
           Item::ScannerTop(code) => format!("ScannerTop({:?})", code),
+          Item::Rule {
+            conditions,
+            pattern,
+            context,
+            modifiers,
+            action,
+          } => {
+            format!(
+              "Rule{{conditions: {:?}, pattern={:?}, context={:?}, modifiers={:?}, action={} }}",
+              conditions,
+              pattern,
+              context,
+              modifiers,
+              action
+            )
+          }
 
         };
 
@@ -208,31 +269,7 @@ impl Display for Item {
   }
 }
 
-impl Item {
-  pub fn user_code<S: ToSpan>(span: S) -> Self {
-    Item::User(span.to_span())
-  }
-
-  pub fn top_code<S: ToSpan>(span: S) -> Self {
-    Item::Top(span.to_span())
-  }
-
-  pub fn class_code<S: ToSpan>(span: S) -> Self {
-    Item::Class(span.to_span())
-  }
-
-  pub fn init_code<S: ToSpan>(span: S) -> Self {
-    Item::Init(span.to_span())
-  }
-
-  pub fn unknown_code<S: ToSpan>(span: S) -> Self {
-    Item::Unknown(span.to_span())
-  }
-
-  pub fn scanner_top_code<S: ToSpan>(span: S) -> Self {
-    Item::ScannerTop(span.to_span())
-  }
-
+impl<'s> Item<'s> {
   // Supplies the `ItemType` variant associated to this `SectionItem`.
   pub fn item_type(&self) -> ItemType {
     match self {
@@ -243,12 +280,15 @@ impl Item {
       Item::User(_)           => ItemType::User,
       Item::Unknown(_)        => ItemType::Unknown,
       Item::Include { .. }    => ItemType::Include,
-      Item::Option(_)         => ItemType::Option,
+      Item::Option(..)        => ItemType::Option,
       Item::State { .. }      => ItemType::State,
       Item::Definition { .. } => ItemType::Definition,
+      Item::Token { .. }      => ItemType::Token,
+      Item::Comment(_)        => ItemType::Comment,
 
       // Section Two
       Item::ScannerTop(_) => ItemType::ScannerTop,
+      Item::Rule { .. }   => ItemType::Rule,
     }
   }
 
@@ -265,7 +305,7 @@ impl Item {
     self.item_type().close_delimiter()
   }
 
-  pub fn get_code(&mut self) -> Option<&mut Span> {
+  pub fn get_code(&mut self) -> Option<&mut Span<'s>> {
     match self {
       | Item::User(code)
       | Item::Top(code)
@@ -277,7 +317,10 @@ impl Item {
 
       | Item::Include { .. }
       | Item::Definition { .. }
-      | Item::Option(_) => {
+      | Item::Token { .. }
+      | Item::Comment(_)
+      | Item::Rule { .. }
+      | Item::Option(..) => {
         None
       }
     }
@@ -285,8 +328,33 @@ impl Item {
 }
 
 
-impl ToSpan for Item {
-  fn to_span(&self) -> Span {
+/// Replaces each `Item::Include` with the items of the file it includes, recursively.
+pub fn flatten_includes<'s, I>(items: I) -> Vec<Item<'s>>
+  where I: IntoIterator<Item = Item<'s>>
+{
+  let mut flattened = vec![];
+
+  for item in items {
+    match item {
+      Item::Include { contents, .. } => flattened.extend(flatten_includes(contents)),
+      item => flattened.push(item),
+    }
+  }
+
+  flattened
+}
+
+
+impl ToSpan for Item<'_> {
+  fn to_span(&self) -> codespan::Span {
+    self.code_span().to_span()
+  }
+}
+
+
+impl<'s> Item<'s> {
+  /// The code of a code item, or the name of a start condition. Panics for any other item.
+  pub fn code_span(&self) -> Span<'s> {
     match self {
       | Item::User(code)
       | Item::Top(code)
@@ -294,11 +362,14 @@ impl ToSpan for Item {
       | Item::Init(code)
       | Item::State { name: code, .. }
       | Item::ScannerTop(code)
+      | Item::Comment(code)
       | Item::Unknown(code) => *code,
 
       | Item::Include { .. }
       | Item::Definition { .. }
-      | Item::Option(_) => {
+      | Item::Token { .. }
+      | Item::Rule { .. }
+      | Item::Option(..) => {
         panic!("Tried to turn {} into code.", self);
       }
     }
@@ -306,10 +377,10 @@ impl ToSpan for Item {
 }
 
 
-impl Mergable for Item {
+impl Mergable for Item<'_> {
 
 
-  fn mergable(&self, other: &Item) -> bool {
+  fn mergable(&self, other: &Self) -> bool {
 
     if !self.is_code() || !other.is_code() {
       return false;
@@ -320,7 +391,7 @@ impl Mergable for Item {
       return false;
     }
 
-    self.to_span().mergable(&other.to_span())
+    self.code_span().mergable(&other.code_span())
 
   }
 
@@ -329,9 +400,7 @@ impl Mergable for Item {
   Attempts tp merge `self` with `other`. This method is asymmetric: it assumes `self` was parsed
   before `other`.
   */
-  fn merged<'a>(&'a mut self, other: &'a mut Item)
-      -> Merged<&'a mut Item, &'a mut Item>
-  {
+  fn merged<'a>(&'a mut self, other: &'a mut Self) -> Merged<&'a mut Self, &'a mut Self> {
     if self.item_type() == other.item_type() || self.item_type().is_code() && other.item_type()
         == ItemType::Unknown {
       match self {
@@ -353,8 +422,11 @@ impl Mergable for Item {
 
         | Item::State{..}
         | Item::Definition { .. }
+        | Item::Token { .. }
+        | Item::Comment(_)
+        | Item::Rule { .. }
         | Item::Include{..}
-        | Item::Option(_) => Merged::No(self, other)
+        | Item::Option(..) => Merged::No(self, other)
 
       } // end match self
 
@@ -0,0 +1,261 @@
+/*!
+
+Destinations for generated output.
+
+Everything Lesk writes, whether the generated scanner or a formatted specification, goes through
+an `OutputSink`. The binary writes to a file, standard output, or both, while a build script or
+a test can capture the output in a `BufferSink` and inspect it afterwards.
+
+*/
+
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::rc::Rc;
+
+
+pub trait OutputSink {
+  /// The destination as it is named in error messages, typically its path.
+  fn name(&self) -> String;
+
+  fn write_str(&mut self, text: &str) -> io::Result<()>;
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Writes to a file, which is either truncated or appended to.
+pub struct FileSink {
+  path: String,
+  /// `None` until the first write, for a sink that is `deferred`.
+  writer: Option<BufWriter<File>>,
+}
+
+impl FileSink {
+  /// Creates the file at `path`, replacing its contents if it exists.
+  pub fn create(path: &str) -> io::Result<Self> {
+    Ok(FileSink { path: path.to_string(), writer: Some(BufWriter::new(File::create(path)?)) })
+  }
+
+  /// Creates the file at `path` when something is first written to it, so that a build that fails
+  /// before writing leaves the file as it was.
+  pub fn deferred(path: &str) -> Self {
+    FileSink { path: path.to_string(), writer: None }
+  }
+
+  /// Opens the file at `path` for appending, creating it if it does not exist.
+  pub fn append(path: &str) -> io::Result<Self> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(FileSink { path: path.to_string(), writer: Some(BufWriter::new(file)) })
+  }
+}
+
+impl OutputSink for FileSink {
+  fn name(&self) -> String {
+    self.path.clone()
+  }
+
+  fn write_str(&mut self, text: &str) -> io::Result<()> {
+    let writer =
+        match &mut self.writer {
+          Some(writer) => writer,
+          None         => self.writer.insert(BufWriter::new(File::create(&self.path)?)),
+        };
+    writer.write_all(text.as_bytes())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match &mut self.writer {
+      Some(writer) => writer.flush(),
+      None         => Ok(()),
+    }
+  }
+}
+
+pub struct StdoutSink {
+  writer: BufWriter<Stdout>,
+}
+
+impl StdoutSink {
+  pub fn new() -> Self {
+    StdoutSink { writer: BufWriter::new(io::stdout()) }
+  }
+}
+
+impl Default for StdoutSink {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl OutputSink for StdoutSink {
+  fn name(&self) -> String {
+    "stdout".to_string()
+  }
+
+  fn write_str(&mut self, text: &str) -> io::Result<()> {
+    self.writer.write_all(text.as_bytes())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.writer.flush()
+  }
+}
+
+/**
+Collects the output in memory. Clones share the same buffer, so a clone kept by the caller sees
+what was written to the sink handed to a `Specification`:
+
+```ignore
+let buffer = BufferSink::new();
+specification.set_sink(Box::new(buffer.clone()));
+specification.parse()?;
+let generated = buffer.contents();
+```
+*/
+#[derive(Clone, Debug, Default)]
+pub struct BufferSink {
+  buffer: Rc<RefCell<String>>,
+}
+
+impl BufferSink {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Everything written so far.
+  pub fn contents(&self) -> String {
+    self.buffer.borrow().clone()
+  }
+}
+
+impl OutputSink for BufferSink {
+  fn name(&self) -> String {
+    "buffer".to_string()
+  }
+
+  fn write_str(&mut self, text: &str) -> io::Result<()> {
+    self.buffer.borrow_mut().push_str(text);
+    Ok(())
+  }
+}
+
+/// Discards the output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+  fn name(&self) -> String {
+    "null".to_string()
+  }
+
+  fn write_str(&mut self, _text: &str) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Writes the same output to each of several sinks, such as a file and standard output.
+pub struct TeeSink {
+  sinks: Vec<Box<dyn OutputSink>>,
+}
+
+impl TeeSink {
+  pub fn new(sinks: Vec<Box<dyn OutputSink>>) -> Self {
+    TeeSink { sinks }
+  }
+}
+
+impl OutputSink for TeeSink {
+  fn name(&self) -> String {
+    let names: Vec<String> = self.sinks.iter().map(|sink| sink.name()).collect();
+    names.join(", ")
+  }
+
+  fn write_str(&mut self, text: &str) -> io::Result<()> {
+    for sink in self.sinks.iter_mut() {
+      sink.write_str(text)?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    for sink in self.sinks.iter_mut() {
+      sink.flush()?;
+    }
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A path under the temporary directory for the test `test`.
+  fn temp_path(test: &str) -> String {
+    let path = std::env::temp_dir().join(format!("lesk-sink-{}-{}.rs", test, std::process::id()));
+    path.display().to_string()
+  }
+
+  #[test]
+  fn clones_of_a_buffer_share_it() {
+    let buffer   = BufferSink::new();
+    let mut sink = buffer.clone();
+    sink.write_str("fn lex() ").unwrap();
+    sink.write_str("{}").unwrap();
+    assert_eq!(buffer.contents(), "fn lex() {}");
+  }
+
+  #[test]
+  fn file_sink_creates_or_appends() {
+    let path = temp_path("file");
+
+    let mut sink = FileSink::create(&path).unwrap();
+    sink.write_str("first\n").unwrap();
+    sink.flush().unwrap();
+    drop(sink);
+    let mut sink = FileSink::create(&path).unwrap();
+    sink.write_str("second\n").unwrap();
+    sink.flush().unwrap();
+    drop(sink);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+
+    let mut sink = FileSink::append(&path).unwrap();
+    sink.write_str("third\n").unwrap();
+    sink.flush().unwrap();
+    assert_eq!(sink.name(), path);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\nthird\n");
+  }
+
+  #[test]
+  fn deferred_file_sink_creates_the_file_on_the_first_write() {
+    let path = temp_path("deferred");
+    std::fs::write(&path, "previous\n").unwrap();
+
+    let mut sink = FileSink::deferred(&path);
+    sink.flush().unwrap();
+    drop(sink);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "previous\n");
+
+    let mut sink = FileSink::deferred(&path);
+    sink.write_str("next\n").unwrap();
+    sink.flush().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "next\n");
+  }
+
+  #[test]
+  fn tee_writes_to_every_sink() {
+    let (first, second) = (BufferSink::new(), BufferSink::new());
+    let mut tee = TeeSink::new(vec![
+      Box::new(first.clone()),
+      Box::new(NullSink),
+      Box::new(second.clone()),
+    ]);
+    tee.write_str("text").unwrap();
+    tee.flush().unwrap();
+
+    assert_eq!((first.contents(), second.contents()), ("text".to_string(), "text".to_string()));
+    assert_eq!(tee.name(), "buffer, null, buffer");
+  }
+}
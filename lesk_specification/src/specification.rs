@@ -1,44 +1,67 @@
 #![allow(dead_code)]
 
-use std::io::{Write, Read, BufWriter};
-use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use structopt::StructOpt;
-use nom_locate::LocatedSpan;
-use codespan_reporting::term::{emit, termcolor, Config};
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle};
+use codespan_reporting::term::emit;
 use nom::Err as NomErr;
+use nom::Slice;
+use serde_json::json;
 
 use super::*;
 use parser::{
+  include::IncludedSources,
+  parser::expand_includes,
   parser::section_one as parse_section_one,
   parser::section_two as parse_section_two
 };
-use crate::section_items::{Item, SectionItemSet};
-use crate::error::Errors;
-use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
-use crate::parser::{LSpan, InputType};
-use crate::parser::parser::SResult;
-
-
-static DEFAULT_OUTPUT_PATH: &str = "lex.yy.cpp";
+use crate::section_items::{Action, Item, SectionItemSet, flatten_includes};
+use crate::error::{apply_suggestions, Error, Errors, LeskError, Suggestion, ToDiagnostic,
+                   UnsupportedError};
+use codespan_reporting::term::termcolor::{ColorChoice, WriteColor};
+use crate::parser::{InputType, include};
+use crate::parser::ToSpan;
+use crate::ast::Ast;
+use crate::definitions::Definitions;
+use crate::engine::{regex_engine, RegexAutomataEngine, RegexEngine};
+use crate::mode::{ConditionPattern, Modes};
+use crate::options::{ColorWhen, Emit, MessageFormat, OptionField, OptionSource};
+use crate::sink::{BufferSink, NullSink, OutputSink};
+use crate::stats::Statistics;
+use crate::timings::Timings;
 
 
 pub struct Specification<'s> {
   pub options: Options,
   //< maps option name (from the options_table) to its option value
-  color_term: bool,    //< terminal supports colors
 
-  writer: Box<dyn FnMut(&str)>,
+  sink: Box<dyn OutputSink>,
   //< output stream
-  source_files: SourceFiles<String, String>,
+  engine: Option<Box<dyn RegexEngine>>,
+  //< the regex engine given to `set_engine`, which overrides `%option matcher`
+  sink_error: Option<std::io::Error>,
+  //< the first error writing to `sink`
+  source_files: SourceFiles<String, &'s str>,
   //< Source code database
   // source       : String,               //< source text
   source_id: usize,
+  included_source_ids: HashMap<String, usize>,
+  //< source IDs of `%include`d files, by name
+  included_sources: &'s IncludedSources,
+  //< the text of each `%include`d file, which the spans of its items point into
+  ast: Option<Ast>,
+  //< owned copy of the parsed items, for tools
 
   conditions: StrVec<'s>,
   //< "INITIAL" start condition etc. defined with %x name
-  definitions: StrMap<'s>,
+  definitions: Definitions<'s>,
   //< map of {name} to regex
+  modes: Modes<'s>,
+  //< start conditions and the rules active in each
+  tokens: Vec<(Span<'s>, Option<Span<'s>>)>,
+  //< `%token` names and payload types, the variants of the `Token` enum
   inclusive: Starts,     //< inclusive start conditions
 
   //library      : Library,      //< the regex library selected
@@ -48,43 +71,64 @@ pub struct Specification<'s> {
   //< current line number at input
   patterns: StrVec<'s>,
   //< regex patterns for each start condition
-  rules: RulesMap,
+  rules: RulesMap<'s>,
   //< <Start_i>regex_j action for Start i Rule j
-  section_1: Code,
+  section_1: Code<'s>,
   //< %{ user code %} in section 1 container
-  section_2: CodeMap,
+  section_2: CodeMap<'s>,
   //< lexer user code in section 2 container
-  section_3: Code,
+  section_3: Code<'s>,
   //< main user code in section 3 container
-  section_init: Code,
+  section_init: Code<'s>,
   //< %init{ init code %} in section 1 container
-  section_struct: Code,
+  section_struct: Code<'s>,
   //< %class{ class code %} in section 1 container
-  section_top: Code,        //< %top{ user code %} in section 1 container
+  section_top: Code<'s>,        //< %top{ user code %} in section 1 container
+  user_action: Option<Span<'s>>,
+  //< flex's `#define YY_USER_ACTION`, run before every action
+  warnings: Errors,
+  //< warnings about the specification found by `parse`
+  timings: Timings,
+  //< how long each phase of `parse` took, for `--timings`
+  statistics: Option<Statistics>,
+  //< the statistics of the scanner `write` generated, with `%option verbose`
 }
 
-impl<'s> Default for Specification<'s> {
-  fn default() -> Self {
-    // This method:
-    //    1. Parses the command line arguments, and
-    //    2. Establishes the output stream.
-    //    3. Read the source file from the input stream into a codespan structure.
-
-
-    let mut new_spec = Self {
-      options: Options::from_args(), // Parses command line arguments
-      color_term: true,
-      // todo        : writer to be replaced with Akama
-      writer: Box::new(|_| {}),       // a dummy initial value
-      source_files: SourceFiles::new(),
-      // source         : String::default(),
-      // in_file        : String::default(),
-      source_id: 43usize,       // Arbitrary initial value will be overwritten
+impl<'s> Specification<'s> {
+  /**
+  A specification for the source `text`, named `name` in diagnostics and in the banner of the
+  generated scanner. Nothing is read from the command line, the environment, or the file system,
+  except for `%include`d files, and output is discarded until a sink is given to `set_sink`.
+
+  The items parsed from the text borrow it, and the specification keeps them, so the text and that
+  of the files it includes are kept for the rest of the program rather than dropped with the
+  specification.
+  */
+  pub fn from_source<N, T>(name: N, text: T, options: Options) -> Self
+    where N: Into<String>,
+          T: Into<String>
+  {
+    let text: &'static str = Box::leak(text.into().into_boxed_str());
+    let mut source_files = SourceFiles::new();
+    let source_id = source_files.add(name.into(), text);
+
+    Self {
+      options,
+      sink: Box::new(NullSink),
+      engine: None,
+      sink_error: None,
+      source_files,
+      source_id,
+      included_source_ids: HashMap::new(),
+      included_sources: Box::leak(Box::default()),
+      ast: None,
       conditions: StrVec::default(),
-      definitions: StrMap::default(),
+      definitions: Definitions::default(),
+      modes: Modes::default(),
+      tokens: vec![],
       inclusive: Starts::default(),
       //library      : Library::default(),
-      line: &"",
+      line: "",
       lineno: 0,
       patterns: StrVec::default(),
       rules: RulesMap::default(),
@@ -94,239 +138,2550 @@ impl<'s> Default for Specification<'s> {
       section_init: Code::default(),
       section_struct: Code::default(),
       section_top: Code::default(),
+      user_action: None,
+      warnings: Errors::new(),
+      timings: Timings::new(),
+      statistics: None,
+    }
+  }
 
-    };
+  /// A specification for the contents of the file at `path`.
+  pub fn from_file(path: &str, options: Options) -> Result<Self, LeskError> {
+    let source = std::fs::read_to_string(path).map_err(|e| LeskError::io(path, e))?;
+    Ok(Self::from_source(path, source, options))
+  }
+
+  pub fn builder<N, T>(name: N, source: T) -> SpecificationBuilder
+    where N: Into<String>,
+          T: Into<String>
+  {
+    SpecificationBuilder::new(name, source)
+  }
+
+  /// Sets where the generated scanner, or the formatted specification, is written.
+  pub fn set_sink(&mut self, sink: Box<dyn OutputSink>) {
+    self.sink = sink;
+  }
+
+  /// Sets the regex engine the scanner is generated for, in place of the one `%option matcher`
+  /// or `--matcher` selects.
+  pub fn set_engine(&mut self, engine: Box<dyn RegexEngine>) {
+    self.engine = Some(engine);
+  }
 
+  /// Calls `f` with the engine given to `set_engine`, or else the one the options select.
+  fn with_engine<R>(&self, f: impl FnOnce(&dyn RegexEngine) -> R) -> R {
+    match &self.engine {
+      Some(engine) => f(engine.as_ref()),
+      None => {
+        let engine = regex_engine(self.options.engine_name())
+            .unwrap_or_else(|| Box::new(RegexAutomataEngine));
+        f(engine.as_ref())
+      }
+    }
+  }
 
-    // Establish the output stream
+  /**
+  Parses the specification and writes the generated scanner, or the formatted or exported
+  specification, to the sink. Errors in the specification are returned as
+  `LeskError::Diagnostics`, and warnings are kept for `warnings`.
 
-    new_spec.writer = // the value of the if statement
-        if let Some(path) = &new_spec.options.out_file {
-          let f = File::create(&path)
-              .expect(format!("Unable to create file: {}", &path).as_str());
-          let mut buf_writer = BufWriter::new(f);
+  With `--check`, nothing is written. The definitions are expanded and the patterns of every
+  start condition compiled by the regex engine that would generate the scanner, as for its
+  tables, so that a specification that checks cleanly is one a scanner can be generated from.
+  */
+  pub fn parse(&mut self) -> Result<(), LeskError> {
+    let file = self.source_files.get(self.source_id).unwrap().name().to_string();
+    let span = tracing::debug_span!("parse", file = file.as_str());
+    let _entered = span.enter();
 
-          // Write to both file and stdout.
-          if new_spec.options.stdout {
-            let mut std_out = BufWriter::new(std::io::stdout());
+    self.conditions.push("INITIAL");
+    self.inclusive.insert(0);
+    self.lineno = 0;
 
-            Box::new(
-              move |buf: &str| {
-                let _ = std_out.write_all(buf.as_bytes());
-                let _ = buf_writer.write_all(buf.as_bytes());
-              }
-            )
-          }
-          // Only write to file
-          else {
-            Box::new(
-              move |buf: &str| {
-                let _ = buf_writer.write_all(buf.as_bytes());
-              }
-            )
-          }
+    // If there were a choice of libraries...
+    //set_library();
+
+    parser::configure(&self.options);
+    include::enter_file(self.source_files.get(self.source_id).unwrap().name(), None);
+
+    let start    = Instant::now();
+    let sections = [parse_section_one, parse_section_two].iter();
+    let text: &'s str = self.source_files.get(self.source_id).unwrap().source();
+    let mut rest = InputType::new(text);
+    let mut items: SectionItemSet;
+    let mut section_items: Vec<Vec<Item>> = vec![];
+    let mut all_items: Vec<Item> = vec![];
+
+    let included_sources = self.included_sources;
+    for parser in sections {
+      let result = parser(rest).and_then(|(new_rest, new_items)| {
+        Ok((new_rest, expand_includes(new_items, included_sources)?))
+      });
+      self.add_included_sources();
+
+      let (new_rest, new_items) = match result {
+        Ok(parsed) => parsed,
+        Err(e) => {
+          include::exit_file();
+          return Err(parse_errors(e, rest).into());
         }
-        // No filename supplied
-        else {
-          // Only write to STDOUT
-          if new_spec.options.stdout {
-            let mut std_out = BufWriter::new(std::io::stdout());
-
-            Box::new(
-              move |buf: &str| {
-                let _ = std_out.write(buf.as_bytes());
-              }
-            )
-          }
-          // Only write to default output file `lex.yy.rs`
-          else {
-            let f = File::create(DEFAULT_OUTPUT_PATH)
-                .expect(format!("Unable to create file: {}", DEFAULT_OUTPUT_PATH).as_str());
-            let mut buf_writer = BufWriter::new(f);
-
-            Box::new(
-              move |buf: &str| {
-                let _ = buf_writer.write_all(buf.as_bytes());
-              }
-            )
+      };
+      rest = new_rest;
+      items = new_items;
+
+      tracing::debug!("parsed {} items", items.len());
+      for item in items.iter() {
+        tracing::trace!("{}", item);
+      }
+      all_items.extend(flatten_includes(items.iter().cloned()));
+      section_items.push(items.into_vec());
+    }
+
+    include::exit_file();
+    self.timings.record("parse", start.elapsed());
+
+    self.ast = Some(Ast::new(&section_items[0], &section_items[1], rest.fragment()));
+    self.collect_code(&all_items);
+
+    // Checking a specification validates it as a scanner, whatever else is asked for.
+    if self.options.fmt && !self.options.check {
+      let formatted = self.timings.time("format", || {
+        crate::format::format(&section_items[0], &section_items[1], rest.fragment())
+      });
+      self.output(&formatted);
+      return self.finish_output();
+    }
+
+    if let Some(path) = self.options.export_flex.as_ref().filter(|_| !self.options.check) {
+      let flex = self.timings.time("export", || {
+        crate::export::to_flex(&section_items[0], &section_items[1], rest.fragment())
+      })?;
+      return std::fs::write(path, flex).map_err(|e| LeskError::io(path.as_str(), e));
+    }
+
+    let invalid = self.options.invalid_values(&all_items);
+    if invalid.has_errors() {
+      return Err(invalid.into());
+    }
+
+    // The `%option`s take effect where neither `Lesk.toml` nor the command line sets the option.
+    // The lists are read from the items where they are used.
+    for item in all_items.iter() {
+      match item {
+        | Item::Option(OptionField::Allow(_), _)
+        | Item::Option(OptionField::Warnings(_), _)
+        | Item::Option(OptionField::Deny(_), _)
+        | Item::Option(OptionField::IncludePath(_), _) => { /* pass */ }
+        Item::Option(field, span) => {
+          let source = OptionSource::Specification {
+            line: span.location_line(),
+            text: span.fragment().trim().to_string(),
+          };
+          self.options.update(smallvec::smallvec![field.clone()], source);
+        }
+        _ => { /* pass */ }
+      }
+    }
+    let conflicts = self.options.conflicts(&all_items);
+    if conflicts.has_errors() {
+      return Err(conflicts.into());
+    }
+
+    // Warnings denied by `--deny` or `%option deny` are errors, and those allowed are dropped.
+    let policy   = self.options.warning_policy(&all_items)?;
+    let warnings = self.timings.time("lint", || crate::lint::lint(&all_items)).with_policy(&policy);
+    let (warnings, denied): (Errors, Errors) = warnings.into_iter().partition(|e| e.is_warning());
+    self.warnings = warnings;
+    if !denied.is_empty() {
+      return Err(denied.into());
+    }
+
+    let unsupported = unsupported_features(&all_items);
+    if !unsupported.is_empty() {
+      return Err(unsupported.into());
+    }
+
+    let start  = Instant::now();
+    self.modes = Modes::from_items(&all_items)?;
+    self.definitions = Definitions::from_items(&all_items);
+    self.tokens = all_items.iter().filter_map(|item| match item {
+      Item::Token { name, payload } => Some((*name, *payload)),
+      _ => None
+    }).collect();
+    self.section_3.push(rest);
+    self.timings.record("lower", start.elapsed());
+
+    if self.options.check {
+      let start = Instant::now();
+      self.tables()?;
+      self.timings.record("compile", start.elapsed());
+      return Ok(());
+    }
+
+    let start  = Instant::now();
+    let result = self.write_artifacts();
+    self.timings.record("write", start.elapsed());
+    result
+  }
+
+  /**
+  Writes the artifacts `--emit` selects: the scanner to the sink, and each of the others to the
+  file `Options::artifact_path` names. With `--deterministic`, each artifact is generated twice,
+  and an artifact that differs between the two is an error rather than output.
+  */
+  fn write_artifacts(&mut self) -> Result<(), LeskError> {
+    if self.options.emits(Emit::Code) {
+      self.write()?;
+    }
+
+    for artifact in Emit::ALL.iter().copied().filter(|artifact| *artifact != Emit::Code) {
+      if !self.options.emits(artifact) {
+        continue;
+      }
+
+      let path = self.options.artifact_path(artifact);
+      let text = self.artifact(artifact, &path)?;
+      if self.options.deterministic && self.artifact(artifact, &path)? != text {
+        return Err(LeskError::Nondeterministic { artifact: artifact.name().to_string() });
+      }
+
+      tracing::debug!(artifact = ?artifact, path = path.as_str(), "writing artifact");
+      std::fs::write(&path, text).map_err(|e| LeskError::io(path.as_str(), e))?;
+    }
+
+    Ok(())
+  }
+
+  /// The text of an artifact other than the code, which is written to `path`.
+  fn artifact(&self, artifact: Emit, path: &str) -> Result<String, LeskError> {
+    let text =
+        match artifact {
+          Emit::Graph   => self.condition_graph(),
+          Emit::Tables  => self.tables()?,
+          Emit::Regexp  => self.regexps()?,
+          Emit::Lalrpop => self.lalrpop_extern()?,
+          Emit::Json    => {
+            serde_json::to_string_pretty(&self.ast).map_err(|e| LeskError::io(path, e.into()))?
           }
+          Emit::Code    => unreachable!(),
         };
 
-    new_spec.init_source_file();
+    Ok(text)
+  }
+
+  /**
+  For each start condition, the regex of the rules active in it, ready to be compiled into that
+  start condition's DFA. Empty until `parse` has succeeded.
+  */
+  pub fn patterns(&self) -> Result<Vec<ConditionPattern<'s>>, Errors> {
+    let modifiers: Vec<OptionField> = vec![
+      OptionField::CaseInsensitive(self.options.case_insensitive),
+      OptionField::Dotall(self.options.dotall),
+      OptionField::Freespace(self.options.freespace),
+      OptionField::Unicode(self.options.unicode),
+    ].into_iter().filter(|option| option.modifier().is_some_and(|(_, on)| on)).collect();
 
-    new_spec
+    self.modes.patterns(&self.definitions, &modifiers)
   }
-}
 
-impl<'s> Specification<'s> {
-  pub fn new() -> Self {
-    Self::default()
+  /// The statistics of the scanner, which `%option verbose` prints after generating it.
+  pub fn stats(&self) -> Result<Statistics, Errors> {
+    Ok(Statistics::new(
+      &self.patterns()?,
+      self.modes.rules.len(),
+      self.modes.actions.len(),
+      self.definitions.len()
+    ))
   }
 
-  pub fn set_in_file(&mut self, path: String) {
-    self.options.in_file = path;
-    self.init_source_file();
+  /**
+  The start condition graph of the specification in Graphviz DOT format, showing which rules
+  switch to which start conditions. Only `INITIAL` until `parse` has succeeded.
+  */
+  pub fn condition_graph(&self) -> String {
+    crate::graph::condition_graph(&self.modes)
   }
 
+  /// The files `%include`d by the specification, as found on the include path, in sorted order.
+  pub fn included_files(&self) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = self.included_source_ids.keys().map(PathBuf::from).collect();
+    files.sort();
+    files
+  }
 
-  fn init_source_file(&mut self) {
-    // Read the source file
+  /// How long each phase of `parse` took. The phases that did not run are absent.
+  pub fn timings(&self) -> &Timings {
+    &self.timings
+  }
 
-    // Read from STDIN
-    if self.options.in_file == "STDIN" {
-      // Both `new_source` and `new_file` will be moved.
-      let mut new_source = String::default();
-      let mut in_file = self.options.in_file.clone();
+  /// The statistics of the scanner generated by `parse` with `%option verbose`, for printing.
+  pub fn statistics(&self) -> Option<&Statistics> {
+    self.statistics.as_ref()
+  }
 
-      let _ = std::io::stdin().read_to_string(&mut new_source);
-      self.source_id = self.source_files.add(in_file, new_source);
-    }
-    // Read from a file
-    else {
-      // Both `new_source` and `new_file` will be consumed.
-      let mut new_source = String::default();
-      let mut in_file = String::default();
+  /// The warnings found by the last call to `parse`.
+  pub fn warnings(&self) -> &Errors {
+    &self.warnings
+  }
+
+  /// The parsed specification, once `parse` has succeeded.
+  pub fn ast(&self) -> Option<&Ast> {
+    self.ast.as_ref()
+  }
 
-      std::mem::swap(&mut self.options.in_file, &mut in_file);
-      std::fs::File::open(&in_file)
-          .expect(format!(
-            "Could not read from file: {}",
-            &in_file
-          ).as_str())
-          .read_to_string(&mut new_source)
-          .unwrap_or_else(
-            |x| { panic!("Could not read from file: {:?}", x.into_inner()); }
-          );
+  /**
+  Sorts the code blocks of the specification by where they go in the generated Rust module:
 
-      self.source_id = self.source_files.add(in_file, new_source);
+    * `%top{ … }` holds module-level items, typically `use` declarations, written before anything
+      else,
+    * `%{ … %}` and indented code in section one hold further module-level items, written after
+      the generated `Token` enum and before the `Lexer`,
+    * `%class{ … }` holds field declarations, written inside `struct Lexer` after its own fields,
+    * `%init{ … }` holds statements run at the end of `Lexer::new`, with the new lexer bound
+      mutably to `lexer`, and
+    * code at the top of section two holds statements run at the start of every call to
+      `Lexer::lex`.
+  */
+  fn collect_code(&mut self, items: &[Item<'s>]) {
+    for item in items {
+      match item {
+        Item::Top(code)        => self.section_top.push(*code),
+        Item::User(code)       => {
+          if self.options.flex_compat {
+            self.user_action = self.user_action.or_else(|| user_action(code));
+          }
+          self.section_1.push(*code)
+        }
+        Item::Class(code)      => self.section_struct.push(*code),
+        Item::Init(code)       => self.section_init.push(*code),
+        // Scanner top code is the same in every start condition.
+        Item::ScannerTop(code) => self.section_2.entry(0).or_default().push(*code),
+        _ => { /* pass */ }
+      }
     }
   }
 
-  pub fn parse(&mut self) {
-    if self.source_files.is_empty() {
-      eprintln!("Empty source file.");
-      return;
+  /**
+  Writes the generated scanner, a Rust module, in this order:
+
+    1. a banner naming the specification it was generated from,
+    2. the `%top{ … }` code,
+    3. the imports from the runtime,
+    4. a constant for each start condition and the `Token` enum of the `%token` declarations,
+    5. the code of section one,
+    6. for each start condition, the regex of its rules, and the action of every rule,
+    7. the lexer, with the `%class{ … }` fields, the `%init{ … }` code, and the actions, and its
+       `Iterator` implementation,
+    8. the user code of section three, and
+    9. with `%option main`, a `fn main()` that scans standard input.
+
+  With `%option verbose`, the statistics of the scanner are then kept for `statistics`.
+
+  The scanner is generated whole before anything is written to the sink, so an error writes
+  nothing. With `--deterministic`, it is generated twice, and if the two differ, nothing is
+  written and the error is `LeskError::Nondeterministic`.
+
+  The regexes are compiled when the lexer is created, by the runtime of the selected engine. The
+  FSM code of `--fast` and `--full` needs that crate's DFA compiler and code generator (see
+  Notes.md), so for now they produce the same tables.
+  */
+  pub fn write(&mut self) -> Result<(), LeskError> {
+    let span = tracing::debug_span!("write");
+    let _entered = span.enter();
+
+    // The whole scanner is generated before any of it is written, so that a specification with
+    // errors leaves nothing half written on standard output.
+    let scanner = self.render_scanner()?;
+    if self.options.deterministic && self.render_scanner()? != scanner {
+      return Err(LeskError::Nondeterministic { artifact: Emit::Code.name().to_string() });
     }
+    self.output(&scanner);
+    self.finish_output()?;
 
-    self.conditions.push("INITIAL");
-    self.inclusive.insert(0);
-    self.lineno = 0;
+    if self.options.verbose > 0 {
+      self.statistics = Some(self.stats()?);
+    }
+    Ok(())
+  }
 
-    // If there were a choice of libraries...
-    //set_library();
+  fn write_scanner(&mut self) -> Result<(), LeskError> {
+    self.write_banner();
+    self.write_section_top();
+    self.write_prelude();
+    self.write_defines();
+    self.write_section_1();
+    self.write_tables()?;
+    self.write_lexer();
+    self.write_section_3();
+    if self.options.main {
+      self.write_main();
+    }
+    Ok(())
+  }
 
-    let sections = [parse_section_one, parse_section_two].iter();
-    let mut rest = InputType::new(self.source_files.get(self.source_id).unwrap().source().as_str());
-    let mut items: SectionItemSet;
+  /// The scanner `write_scanner` writes, collected in memory instead of written to the sink.
+  fn render_scanner(&mut self) -> Result<String, LeskError> {
+    let buffer = BufferSink::new();
+    let sink   = std::mem::replace(&mut self.sink, Box::new(buffer.clone()));
+    let result = self.write_scanner();
+    self.sink  = sink;
 
-    for parser in sections {
-      let mut result = parser(rest);
+    result.map(|()| buffer.contents())
+  }
 
-      if result.is_err() {
-        self.handle_parse_errors(&result);
-        return;
+  /// Writes `text` to the sink. The first error is kept for `finish_output` to report.
+  fn output(&mut self, text: &str) {
+    if self.sink_error.is_none() {
+      if let Err(e) = self.sink.write_str(text) {
+        self.sink_error = Some(e);
       }
-      // Unwrap is safe because of preceding `if`.
-      let (new_rest, new_items) = result.unwrap();
-      rest = new_rest;
-      items = new_items;
+    }
+  }
 
-      println!("Parsed {} items.", items.len());
-      for item in items {
-        println!("{}", item);
-      }
+  /// Flushes the sink and reports the first error writing to it.
+  fn finish_output(&mut self) -> Result<(), LeskError> {
+    let result =
+        match self.sink_error.take() {
+          Some(e) => Err(e),
+          None    => self.sink.flush(),
+        };
+
+    result.map_err(|e| LeskError::io(self.sink.name(), e))
+  }
+
+  /**
+  The banner names the specification as it was given, unless the output is to be reproducible,
+  when its directory is left out, as it may differ from one checkout or build directory to the
+  next.
+  */
+  fn write_banner(&mut self) {
+    let name = self.display_name(self.source_files.get(self.source_id).unwrap().name());
+    let banner = format!(
+      "// Generated by lesk {} from {}. Do not edit.\n\n",
+      env!("CARGO_PKG_VERSION"),
+      name
+    );
+    self.output(&banner);
+  }
+
+  /// The name of a source file as the generated scanner gives it; see `write_banner`.
+  fn display_name(&self, name: &str) -> String {
+    if self.options.deterministic {
+      Path::new(name).file_name().map_or(name.to_string(), |file| file.to_string_lossy().into())
+    } else {
+      name.to_string()
     }
   }
 
-  fn handle_parse_errors(&self, result: &SResult) {
-    match result {
-      Err(NomErr::Error(e)) => {
-        let mut writer = StandardStream::stderr(ColorChoice::Always);
-        let config = codespan_reporting::term::Config::default();
+  /**
+  Unless `%option noline` is given, the code copied from the specification is preceded by a
+  comment naming the file and line it comes from, `// lesk:calc.l:12`, so that an error the Rust
+  compiler reports in an action can be traced back to the specification. `span` is the code, which
+  is in the specification or in one of the files it includes.
+  */
+  fn source_marker(&self, span: &Span<'s>) -> String {
+    if self.options.line || span.fragment().trim().is_empty() {
+      return String::new();
+    }
 
-        for d in e.to_diagnostics(self.source_id) {
-          emit(&mut writer, &config, &self.source_files, &d);
-        }
+    let main  = self.source_files.get(self.source_id).unwrap();
+    let start = span.fragment().as_ptr() as usize;
+    let contains = |text: &str| {
+      let text_start = text.as_ptr() as usize;
+      text_start <= start && start <= text_start + text.len()
+    };
+    let name =
+        self.included_sources
+            .iter()
+            .find(|(_, text)| contains(text))
+            .map_or(main.name().as_str(), |(name, _)| name);
 
-        return;
+    format!("// lesk:{}:{}\n", self.display_name(name), span.location_line())
+  }
+
+  /// The code blocks of `code`, each on lines of its own and preceded by its `source_marker`.
+  fn source_code(&self, code: &Code<'s>) -> String {
+    let mut text = String::new();
+
+    for block in code {
+      text.push_str(&self.source_marker(block));
+      text.push_str(block.fragment());
+      if !block.fragment().ends_with('\n') {
+        text.push('\n');
       }
+    }
 
-      Err(NomErr::Failure(e)) => {
-        let mut writer = StandardStream::stderr(ColorChoice::Always);
-        let config = codespan_reporting::term::Config::default();
+    text
+  }
 
-        for d in e.to_diagnostics(self.source_id) {
-          emit(&mut writer, &config, &self.source_files, &d);
-        }
+  /**
+  The runtime of the regex engine. With `%option nostd` the scanner also declares the `alloc`
+  crate, whose `Vec` holds the patterns of the lexer: the regexes are compiled when the lexer is
+  created, so a `no_std` scanner needs `alloc`, and depends on `regex-automata` with its default
+  features but `std`. Its tables are the regexes rather than `const` DFAs, which need the DFA
+  compiler of `relesk` (see Notes.md), and it scans a `str` rather than bytes, as its actions read
+  the text of a match as one.
+  */
+  fn write_prelude(&mut self) {
+    let module  = self.matcher_module();
+    let prelude = self.with_engine(|engine| engine.prelude(&module));
+    self.output(&prelude);
+    if self.options.no_std {
+      self.output("extern crate alloc;\n\nuse alloc::vec::Vec;\n\n");
+    }
+  }
 
-        return;
-      }
+  /// The start conditions, numbered as in the tables, and the `Token` enum, after the prefix.
+  fn write_defines(&mut self) {
+    let prefix      = self.item_prefix();
+    let token       = self.type_name("Token");
+    let mut defines = String::new();
+
+    for (mode_id, mode) in self.modes.modes.iter().enumerate() {
+      defines.push_str(&format!("pub const {}{}: usize = {};\n", prefix, mode.name(), mode_id));
+    }
 
-      Err(NomErr::Incomplete(_)) => {
-        panic!("Got a nom incomplete error.");
+    if !self.tokens.is_empty() {
+      // A LALRPOP parser clones the tokens it takes.
+      let derives = if self.options.lalrpop { "Clone, Debug" } else { "Debug" };
+      defines.push_str(&format!("\n#[derive({})]\npub enum {} {{\n", derives, token));
+      for (name, payload) in &self.tokens {
+        match payload {
+          Some(payload) => {
+            defines.push_str(&format!("    {}({}),\n", name.fragment(), payload.fragment()));
+          }
+          None => defines.push_str(&format!("    {},\n", name.fragment())),
+        }
       }
+      // `no_default_rule` returns `Unmatched`, and it can be set at runtime without nodefault.
+      defines.push_str(&format!(
+        "    Unmatched,\n    EndOfInput,\n}}\n\n\
+        impl Default for {token} {{\n    fn default() -> Self {{\n        {token}::EndOfInput\n    }}\n}}\n",
+        token = token
+      ));
+    }
+
+    defines.push('\n');
+    defines.push_str(&self.unprefixed_module());
+    self.output(&defines);
+  }
+
+  /**
+  The tables the lexer is driven by: `RULE_ACTIONS`, the action of each rule by global rule
+  number, and the tables the regex engine compiles the patterns into. Those of the default
+  engine, `regex`, are
+
+    * `RULES_<NAME>`, for each start condition, the regex of each rule active in it and of its
+      trailing context, to which the pattern options of the specification apply, and
+    * `ACCEPT_<NAME>`, for each start condition, the global rule number of each rule.
+
+  Those of `relesk` have `REGEX_<NAME>` in place of `RULES_<NAME>`, the rules as one alternation
+  with a group per rule.
+
+  Each start condition has its own automaton, and `BEGIN` only changes which one is used. The
+  accept indices of all of them map into the one numbering of rules, so every action is written
+  once however many start conditions its rule is active in.
+  */
+  fn write_tables(&mut self) -> Result<(), Errors> {
+    let tables = self.tables()?;
+    self.output(&tables);
+    Ok(())
+  }
+
+  /// The text `write_tables` writes, which is also the artifact of `--emit tables`.
+  fn tables(&self) -> Result<String, Errors> {
+    let patterns   = self.patterns()?;
+    let prefix     = self.item_prefix();
+    let mut tables = format!("const {}NO_ACTION: usize = usize::MAX;\n\n", prefix);
+
+    let rule_actions: Vec<String> =
+        self.modes
+            .rule_actions()
+            .iter()
+            .map(|action| action.map_or(format!("{}NO_ACTION", prefix), |a| a.to_string()))
+            .collect();
+    tables.push_str(&format!(
+      "static {}RULE_ACTIONS: [usize; {}] = [{}];\n\n",
+      prefix,
+      rule_actions.len(),
+      rule_actions.join(", ")
+    ));
+
+    tables.push_str(&self.with_engine(|engine| engine.compile(&patterns, &prefix))?);
+
+    if self.options.debug || self.options.perf_report {
+      let lines: Vec<String> =
+          self.modes.rules.iter().map(|rule| rule.span().location_line().to_string()).collect();
+      tables.push_str(&format!(
+        "static {}RULE_LINES: [u32; {}] = [{}];\n\n",
+        prefix,
+        lines.len(),
+        lines.join(", ")
+      ));
+    }
+    if self.options.debug {
+      let names: Vec<String> =
+          self.modes.modes.iter().map(|mode| format!("{:?}", mode.name())).collect();
+      tables.push_str(&format!(
+        "static {}CONDITION_NAMES: [&str; {}] = [{}];\n\n",
+        prefix,
+        names.len(),
+        names.join(", ")
+      ));
+    }
+
+    Ok(tables)
+  }
+
+  /// The artifact of `--emit regexp`: the regex of each start condition on a line of its own.
+  fn regexps(&self) -> Result<String, Errors> {
+    Ok(
+      self.patterns()?
+          .iter()
+          .map(|pattern| format!("{}\n", pattern.regex))
+          .collect()
+    )
+  }
+
+  /**
+  The artifact of `--emit lalrpop`: the `extern` block of a LALRPOP grammar whose parser takes the
+  lexer generated with `%option lalrpop`, declaring its location and error types and its `%token`s
+  as the grammar's terminals, each named as it is declared:
+
+  ```text
+  extern {
+      type Location = usize;
+      type Error = core::convert::Infallible;
 
-      _ => {
-        unreachable!();
+      enum Token {
+          "Number" => Token::Number(<i64>),
+          "Plus" => Token::Plus,
       }
+  }
+  ```
+
+  The grammar `use`s the token type from the scanner's module. Text no rule matches reaches the
+  parser as `Token::Unmatched`, which is not a terminal, so the parser reports it as an
+  unrecognized token.
+  */
+  fn lalrpop_extern(&self) -> Result<String, LeskError> {
+    if self.tokens.is_empty() || self.options.token_type.is_some() {
+      return Err(LeskError::config(
+        "`--emit lalrpop` declares the tokens of `%token`, and the specification has none or \
+        replaces them with `--token-type`",
+      ));
     }
+
+    let token = self.type_name("Token");
+    let terminals: String =
+        self.tokens
+            .iter()
+            .map(|(name, payload)| {
+              let name = name.fragment();
+              match payload {
+                Some(payload) => {
+                  format!("        {:?} => {}::{}(<{}>),\n", name, token, name, payload.fragment())
+                }
+                None => format!("        {:?} => {}::{},\n", name, token, name),
+              }
+            })
+            .collect();
+
+    Ok(format!(
+      "// The tokens of the scanner lesk generated from {}, for a LALRPOP grammar.\n\
+      extern {{\n    \
+        type Location = usize;\n    \
+        type Error = core::convert::Infallible;\n\n    \
+        enum {} {{\n\
+          {}    \
+        }}\n\
+      }}\n",
+      self.display_name(self.source_files.get(self.source_id).unwrap().name()),
+      token,
+      terminals
+    ))
   }
 
+  /**
+  The lexer struct and its `lex` method. The runtime's `Matcher::scan` returns `None` at the end
+  of the input, `Some(0)` when no rule matches, having consumed one character, and `Some(n)` when
+  the `n`th alternative of the pattern matches. Unmatched text goes to the default rule; see
+  `write_default_rule`. It tracks the line and column of each match; see `write_location`.
 
-  /*
-  void        parse_section_2();
-  void        parse_section_3();
-  void        include(const std::string& filename);
-  void        write();
-  // These will be replaced with a template library
-  void        write_banner(const char *title);
-  void        write_prelude();
-  void        write_defines();
-  void        write_class();
-  void        write_section_top();
-  void        write_section_class();
-  void        write_section_init();
-  void        write_perf_report();
-  void        write_section_1();
-  void        write_section_3();
-  void        write_code(const Codes& codes);
-  void        write_code(const Code& code);
-  void        write_lexer();
-  void        write_main();
-  void        write_regex(const std::string *condition, const std::string& regex);
-  void        write_namespace_open();
-  void        write_namespace_close();
-  void        write_namespace_scope();
+  The `%class{ … }` fields are initialized with `Default::default()` before the `%init{ … }` code
+  runs. With `%option stack` the lexer also keeps a start condition stack; see `write_state_stack`.
+  With `%option debug` it reports what it matches; see `write_debug`. With `%option perf-report`
+  it counts the matches of each rule; see `write_perf_report`. It scans the inputs its actions
+  push before going back to the one it was scanning; see `write_input_stack`. Its actions can give
+  back text, or put text back, to be scanned again; see `write_less_and_unput`.
+  */
+  fn write_lexer(&mut self) {
+    let lexer  = self.lexer_name();
+    let lex    = self.options.lex.clone().unwrap_or_else(|| "lex".to_string());
+    let prefix = self.item_prefix();
+    let module = self.matcher_module();
+    let token_type =
+        match &self.options.token_type {
+          Some(token_type)                => token_type.clone(),
+          None if self.tokens.is_empty()  => "i32".to_string(),
+          None                            => self.type_name("Token"),
+        };
 
-  void        undot_namespace(std::string& s);
-  void        stats();
-  bool        get_line();
-  bool        skip_comment(size_t& pos);
-  bool        is(const char *s);
-  bool        br(size_t pos, const char *s = NULL);
-  bool        as(size_t& pos, const char *s);
-  bool        ws(size_t& pos);
-  bool        eq(size_t& pos);
-  bool        nl(size_t& pos);
-  bool        is_code();
-  bool        is_topcode();
-  bool        is_classcode();
-  bool        is_initcode();
-  std::string get_name(size_t& pos);
-  std::string get_option(size_t& pos);
-  std::string get_start(size_t& pos);
-  std::string get_string(size_t& pos);
-  bool        get_pattern(size_t& pos, std::string& pattern, std::string& regex);
-  std::string get_namespace(size_t& pos);
-  std::string get_code(size_t& pos);
-  std::string escape_bs(const std::string& s);
-  std::string upper_name(const std::string& s);
-  std::string param_args(const std::string& s);
-  bool        get_starts(size_t& pos, Starts& starts);
-  void        abort(const char *message, const char *arg = NULL);
-  void        error(const char *message, const char *arg = NULL, size_t at_lineno = 0);
-  void        warning(const char *message, const char *arg = NULL, size_t at_lineno = 0);
-  const char *SGR(const char *code) { return color_term ? code : ""; }
+    self.write_input(&lexer, &module);
+    self.output(&format!(
+      "pub struct {lexer}<'i> {{\n    \
+        matcher: {module}::Matcher<'i>,\n    \
+        suspended: Vec<{lexer}Input<'i>>,\n    \
+        patterns: Vec<{module}::Pattern>,\n    \
+        condition: usize,\n    \
+        default_rule: fn(&mut Self) -> Option<{token_type}>,\n    \
+        start: usize,\n    \
+        end: usize,\n    \
+        line: usize,\n    \
+        column: usize,\n    \
+        next_line: usize,\n    \
+        next_column: usize,\n    \
+        ended: bool,\n",
+      lexer = lexer,
+      module = module,
+      token_type = token_type
+    ));
+    if self.options.stack {
+      self.output("    condition_stack: Vec<usize>,\n");
+    }
+    if self.options.debug {
+      self.output("    debug: bool,\n");
+    }
+    if self.options.perf_report {
+      self.output(
+        "    match_counts: Vec<usize>,\n    match_bytes: Vec<usize>,\n    started: std::time::Instant,\n"
+      );
+    }
+    self.write_section_class();
+
+    let fields: String =
+        self.section_struct
+            .iter()
+            .flat_map(|code| field_names(code.fragment()))
+            .map(|name| format!("            {}: Default::default(),\n", name))
+            .collect();
+    let condition_stack = if self.options.stack { "            condition_stack: Vec::new(),\n" } else { "" };
+    let debug = if self.options.debug { "            debug: true,\n" } else { "" };
+    let perf_report =
+        if self.options.perf_report {
+          format!(
+            "            match_counts: vec![0; {prefix}RULE_LINES.len()],\n            \
+              match_bytes: vec![0; {prefix}RULE_LINES.len()],\n            \
+              started: std::time::Instant::now(),\n",
+            prefix = prefix
+          )
+        } else {
+          String::new()
+        };
+    let default_rule =
+        if self.options.exception.is_some() {
+          "Self::raise_exception"
+        } else if self.options.nodefault || self.options.no_std {
+          "Self::no_default_rule"
+        } else {
+          "Self::echo_unmatched"
+        };
+    let patterns = self.with_engine(|engine| engine.patterns(&prefix, &module));
+    // Only the `%init` code changes the new lexer.
+    let binding  = if self.section_init.is_empty() { "lexer" } else { "mut lexer" };
+
+    self.output(&format!(
+      "}}\n\n\
+      impl<'i> {lexer}<'i> {{\n    \
+        pub fn new(input: &'i str) -> Self {{\n        \
+          let {binding} = {lexer} {{\n            \
+            matcher: {module}::Matcher::new(input),\n            \
+            suspended: Vec::new(),\n            \
+            patterns: {patterns},\n            \
+            condition: {prefix}INITIAL,\n            \
+            default_rule: {default_rule},\n            \
+            start: 0,\n            \
+            end: 0,\n            \
+            line: 1,\n            \
+            column: 1,\n            \
+            next_line: 1,\n            \
+            next_column: 1,\n            \
+            ended: false,\n\
+            {condition_stack}\
+            {debug}\
+            {perf_report}\
+            {fields}        \
+          }};\n",
+      lexer = lexer,
+      binding = binding,
+      module = module,
+      patterns = patterns,
+      default_rule = default_rule,
+      prefix = prefix,
+      condition_stack = condition_stack,
+      debug = debug,
+      perf_report = perf_report,
+      fields = fields
+    ));
+    if !self.section_init.is_empty() {
+      let unprefixed_names = self.unprefixed_names();
+      self.output(&unprefixed_names);
+    }
+    self.write_section_init();
+    self.output("        lexer\n    }\n\n");
+    if self.options.debug {
+      self.write_debug();
+    }
+    if self.options.perf_report {
+      self.write_perf_report();
+    }
+
+    let trace_begin =
+        if self.options.debug {
+          format!(
+            "        if self.debug {{\n            \
+              eprintln!(\"--begin start condition {{}}\", {}CONDITION_NAMES[condition]);\n        \
+            }}\n",
+            prefix
+          )
+        } else {
+          String::new()
+        };
+    self.output(&format!(
+      "    /// Switches to the start condition `condition`.\n    \
+      pub fn begin(&mut self, condition: usize) {{\n\
+        {}        \
+        self.condition = condition;\n    \
+      }}\n\n",
+      trace_begin
+    ));
+    self.output(&format!(
+      "    /// The current start condition.\n    \
+      pub fn start(&self) -> usize {{\n        \
+        self.condition\n    \
+      }}\n\n    \
+      /// The text of the current match.\n    \
+      pub fn text(&self) -> &str {{\n        \
+        self.matcher.text()\n    \
+      }}\n\n    \
+      /// The byte offsets of the current match in the input.\n    \
+      pub fn span(&self) -> {}::ops::Range<usize> {{\n        \
+        self.start..self.end\n    \
+      }}\n\n",
+      self.core_crate()
+    ));
+    // A `no_std` scanner has no standard output to echo to.
+    if !self.options.no_std {
+      self.output(
+        "    pub fn echo(&self) {\n        \
+          print!(\"{}\", self.text());\n    \
+        }\n\n"
+      );
+    }
+    self.output(
+      "    pub fn at_end(&self) -> bool {\n        \
+        self.matcher.at_end()\n    \
+      }\n\n"
+    );
+    self.write_location();
+    self.write_default_rule(&token_type);
+    if self.options.stack {
+      self.write_state_stack();
+    }
+    self.write_input_stack(&module);
+    self.write_less_and_unput();
+
+    // `--token-type` names a type that must implement `Default`, which marks the end of the input
+    // as `Token::EndOfInput` does.
+    self.output(&format!(
+      "    /// Scans to the next token an action returns, returning `Default::default()` at\n    \
+      /// the end of the input.\n    \
+      pub fn {}(&mut self) -> {} {{\n",
+      lex,
+      token_type
+    ));
+    let unprefixed_names = self.unprefixed_names();
+    self.output(&unprefixed_names);
+    let scanner_top =
+        self.section_2.get(&0).map(|code| self.source_code(code)).unwrap_or_default();
+    self.output(&scanner_top);
+
+    let user_action = self.user_action.map_or(String::new(), |code| {
+      format!("{}\n", code.fragment().trim())
+    });
+    let actions: String =
+        self.modes
+            .actions
+            .iter()
+            .enumerate()
+            .map(|(action_id, code)| {
+              let marker = match self.source_marker(code) {
+                marker if marker.is_empty() => marker,
+                marker => format!("                {}", marker),
+              };
+              format!("                {} => {{\n{}{}{}\n                }}\n",
+                action_id, marker, user_action, code.fragment().trim_end())
+            })
+            .collect();
+
+    // What is done at the end of the input, on unmatched text, and on a match of a rule.
+    let mut at_end     = String::new();
+    let mut on_default = String::new();
+    let mut on_rule    = String::new();
+    if self.options.debug {
+      at_end.push_str(&format!(
+        "                    if self.debug {{\n                        \
+          eprintln!(\"--EOF (start condition {{}})\", {}CONDITION_NAMES[self.condition]);\n                    \
+        }}\n",
+        prefix
+      ));
+      on_default.push_str(
+        "                if self.debug {\n                    \
+          eprintln!(\"--accepting default rule ({:?})\", self.text());\n                \
+        }\n"
+      );
+      on_rule.push_str(&format!(
+        "            if self.debug {{\n                \
+          eprintln!(\"--accepting rule at line {{}} ({{:?}})\", {}RULE_LINES[rule], self.text());\n            \
+        }}\n",
+        prefix
+      ));
+    }
+    if self.options.perf_report {
+      at_end.push_str(
+        "                    if !self.ended {\n                        \
+          self.report_performance();\n                    \
+        }\n"
+      );
+      on_rule.push_str(
+        "            self.match_counts[rule] += 1;\n            \
+        self.match_bytes[rule] += self.text().len();\n"
+      );
+    }
+
+    self.output(&format!(
+      "        loop {{\n            \
+          let accept = match self.matcher.scan(&self.patterns[self.condition]) {{\n                \
+            Some(accept) => accept,\n                \
+            None => {{\n                    \
+              if self.pop_input() {{\n                        \
+                continue;\n                    \
+              }}\n\
+              {at_end}                    \
+              self.ended = true;\n                    \
+              return Default::default();\n                \
+            }}\n            \
+          }};\n            \
+          self.start = self.end;\n            \
+          self.end += self.text().len();\n            \
+          self.advance_location();\n            \
+          if accept == 0 {{\n\
+            {on_default}                \
+            match (self.default_rule)(self) {{\n                    \
+              Some(token) => return token,\n                    \
+              None => continue,\n                \
+            }}\n            \
+          }}\n\n            \
+          let rule = {prefix}ACCEPTS[self.condition][accept - 1];\n\
+          {on_rule}            \
+          match {prefix}RULE_ACTIONS[rule] {{\n\
+          {actions}                \
+            _ => {{ /* No action */ }}\n            \
+          }}\n        \
+        }}\n    \
+      }}\n\
+      }}\n\n",
+      prefix = prefix,
+      at_end = at_end,
+      on_default = on_default,
+      on_rule = on_rule,
+      actions = actions
+    ));
+    self.write_iterator(&lexer, &lex, &token_type);
+  }
+
+  /**
+  The line and column of the current match, counted as the lexer scans, for the diagnostics of a
+  parser downstream. Columns count characters from 1, and a tab advances the column to the next
+  tab stop, every `%option tabs` columns.
+  */
+  fn write_location(&mut self) {
+    let tabs = self.options.tabs;
+    self.output(&format!(
+      "    /// The line and column, counting from 1, at which the current match starts.\n    \
+      pub fn location(&self) -> (usize, usize) {{\n        \
+        (self.line, self.column)\n    \
+      }}\n\n    \
+      fn advance_location(&mut self) {{\n        \
+        self.line = self.next_line;\n        \
+        self.column = self.next_column;\n        \
+        for c in self.matcher.text().chars() {{\n            \
+          match c {{\n                \
+            '\\n' => {{\n                    \
+              self.next_line += 1;\n                    \
+              self.next_column = 1;\n                \
+            }}\n                \
+            '\\t' => self.next_column += {tabs} - (self.next_column - 1) % {tabs},\n                \
+            _ => self.next_column += 1,\n            \
+          }}\n        \
+        }}\n    \
+      }}\n\n",
+      tabs = tabs
+    ));
+  }
+
+  /**
+  The lexer is an iterator over the tokens its actions return, each with its span, for parsers
+  that take one. Where `lex` returns the default token at the end of the input, `next` returns
+  `None`.
+
+  With `%option lalrpop` its items are instead those LALRPOP's external lexers yield, the token
+  between its start and end offsets, which never fails; see `lalrpop_extern`.
+  */
+  fn write_iterator(&mut self, lexer: &str, lex: &str, token_type: &str) {
+    let (item, located) =
+        if self.options.lalrpop {
+          (
+            format!("Result<(usize, {}, usize), core::convert::Infallible>", token_type),
+            "Ok((self.start, token, self.end))",
+          )
+        } else {
+          (
+            format!("({}, {}::ops::Range<usize>)", token_type, self.core_crate()),
+            "(token, self.span())",
+          )
+        };
+
+    self.output(&format!(
+      "impl<'i> Iterator for {lexer}<'i> {{\n    \
+        type Item = {item};\n\n    \
+        fn next(&mut self) -> Option<Self::Item> {{\n        \
+          let token = self.{lex}();\n        \
+          if self.ended {{\n            \
+            None\n        \
+          }} else {{\n            \
+            Some({located})\n        \
+          }}\n    \
+        }}\n\
+      }}\n\n",
+      lexer = lexer,
+      lex = lex,
+      item = item,
+      located = located
+    ));
+  }
+
+  /**
+  The default rule, which `lex` calls with the one character no rule matches. A `Some` token is
+  returned from `lex`; on `None` scanning continues. The scanner starts with `echo_unmatched`,
+  flex's default rule, or, with `%option nodefault`, with `no_default_rule`, which returns
+  `Token::Unmatched` when the specification declares its tokens and panics with flex's "scanner
+  jammed" otherwise. Either can be replaced at runtime with `set_default_rule`. A `no_std` scanner,
+  which has nowhere to echo to, has only `no_default_rule`.
+
+  With `%option exception=EXPR` the scanner starts with `raise_exception` instead, which returns
+  `EXPR`, a value of the token type such as `Token::Error`, from `lex`, as RE-flex's scanner throws
+  it. The expression is pasted verbatim, as an action is.
+  */
+  fn write_default_rule(&mut self, token_type: &str) {
+    let no_default =
+        if self.options.token_type.is_none() && !self.tokens.is_empty() {
+          format!("Some({}::Unmatched)", self.type_name("Token"))
+        } else {
+          "panic!(\"scanner jammed at {:?}\", self.text())".to_string()
+        };
+
+    self.output(&format!(
+      "    /// Sets what is done with text no rule matches: a `Some` token is returned from `lex`,\n    \
+      /// and on `None` scanning continues.\n    \
+      pub fn set_default_rule(&mut self, default_rule: fn(&mut Self) -> Option<{token_type}>) {{\n        \
+        self.default_rule = default_rule;\n    \
+      }}\n\n",
+      token_type = token_type
+    ));
+    if !self.options.no_std {
+      self.output(&format!(
+        "    /// The default rule: echoes unmatched text to standard output.\n    \
+        pub fn echo_unmatched(&mut self) -> Option<{}> {{\n        \
+          self.echo();\n        \
+          None\n    \
+        }}\n\n",
+        token_type
+      ));
+    }
+    self.output(&format!(
+      "    /// `%option nodefault`: unmatched text is an error.\n    \
+      pub fn no_default_rule(&mut self) -> Option<{token_type}> {{\n        \
+        {no_default}\n    \
+      }}\n\n",
+      token_type = token_type,
+      no_default = no_default
+    ));
+
+    if let Some(exception) = &self.options.exception {
+      let unprefixed_names = self.unprefixed_names();
+      self.output(&format!(
+        "    /// `%option exception`: `lex` returns the exception on unmatched text.\n    \
+        pub fn raise_exception(&mut self) -> Option<{}> {{\n\
+          {}        \
+          Some({})\n    \
+        }}\n\n",
+        token_type,
+        unprefixed_names,
+        exception.trim()
+      ));
+    }
+  }
+
+  /**
+  `%option debug`: the lexer reports to standard error, as a flex scanner does, each rule that
+  matches with its line in the specification, each change of start condition, and the end of the
+  input, for as long as its `debug` flag is set. The flag starts out set. The transitions within a
+  match are the regex engine's, and are not reported.
+  */
+  fn write_debug(&mut self) {
+    self.output(
+      "    /// Turns the reports of `%option debug` on or off.\n    \
+      pub fn set_debug(&mut self, debug: bool) {\n        \
+        self.debug = debug;\n    \
+      }\n\n    \
+      /// Whether the lexer reports the rules that match to standard error.\n    \
+      pub fn debug(&self) -> bool {\n        \
+        self.debug\n    \
+      }\n\n"
+    );
+  }
+
+  /**
+  `%option perf-report`: the lexer counts the matches of each rule and the bytes they match, and
+  at the end of the input prints them to standard error, labeled with the line of the rule in the
+  specification, with the time taken since the lexer was created.
+  */
+  fn write_perf_report(&mut self) {
+    self.output(&format!(
+      "    /// Prints the matches of each rule so far and the time they took to standard error.\n    \
+      pub fn report_performance(&self) {{\n        \
+        eprintln!(\"lesk performance report:\");\n        \
+        for (rule, line) in {}RULE_LINES.iter().enumerate() {{\n            \
+          eprintln!(\n                \
+            \"  rule at line {{:>5}}: {{:>9}} matches {{:>11}} bytes\",\n                \
+            line, self.match_counts[rule], self.match_bytes[rule]\n            \
+          );\n        \
+        }}\n        \
+        eprintln!(\"  {{}} bytes scanned in {{:?}}\", self.end, self.started.elapsed());\n    \
+      }}\n\n",
+      self.item_prefix()
+    ));
+  }
 
+  /**
+  With `%option prefix`, the start conditions and the `Token` enum are defined after the prefix,
+  and the actions and the `%init{ … }` code, which are written as for any scanner, see them under
+  their own names through these aliases at the top of `lex` and `new`. Empty without a prefix.
   */
+  fn unprefixed_names(&self) -> String {
+    let prefix      = self.item_prefix();
+    let mut aliases = String::new();
+    if prefix.is_empty() {
+      return aliases;
+    }
+
+    for mode in &self.modes.modes {
+      aliases.push_str(&format!(
+        "        #[allow(dead_code)]\n        const {name}: usize = {prefix}{name};\n",
+        name = mode.name(),
+        prefix = prefix
+      ));
+    }
+    if !self.tokens.is_empty() {
+      aliases.push_str(&format!(
+        "        #[allow(dead_code)]\n        type Token = {};\n",
+        self.type_name("Token")
+      ));
+    }
+    aliases
+  }
+
+  /**
+  With `%option prefix="calc"`, the module `calc`, which re-exports the start conditions and the
+  `Token` enum under their own names. The code of section one and of `%class{ … }` is written at
+  the level of the module the scanner shares with any other, where an alias of its own would
+  collide with theirs, so it names them through this module, as `calc::Token`. Empty without a
+  prefix.
+  */
+  fn unprefixed_module(&self) -> String {
+    let prefix = match &self.options.prefix {
+      Some(prefix) => prefix.to_lowercase(),
+      None         => return String::new(),
+    };
+
+    let item_prefix = self.item_prefix();
+    let mut names: Vec<String> =
+        self.modes
+            .modes
+            .iter()
+            .map(|mode| format!("{}{} as {}", item_prefix, mode.name(), mode.name()))
+            .collect();
+    if !self.tokens.is_empty() {
+      names.push(format!("{} as Token", self.type_name("Token")));
+    }
+
+    format!(
+      "/// The names of this scanner without `%option prefix`.\n\
+      #[allow(unused_imports)]\n\
+      pub mod {} {{\n    \
+        pub use super::{{{}}};\n\
+      }}\n\n",
+      prefix,
+      names.join(", ")
+    )
+  }
+
+  /**
+  The name `write_prelude` gives the module of the regex engine's runtime, through which the lexer
+  names `Matcher` and `Pattern`: `matcher`, or `calc_matcher` with `%option prefix="calc"`, so
+  that two scanners sharing a module each have their own.
+  */
+  fn matcher_module(&self) -> String {
+    match &self.options.prefix {
+      Some(prefix) => format!("{}_matcher", prefix.to_lowercase()),
+      None         => "matcher".to_string(),
+    }
+  }
+
+  /// The crate the scanner names the items of `core` through: `core` in a `no_std` scanner.
+  fn core_crate(&self) -> &'static str {
+    if self.options.no_std { "core" } else { "std" }
+  }
+
+  /// The crate the scanner names the items of `alloc` through: `alloc` in a `no_std` scanner.
+  fn alloc_crate(&self) -> &'static str {
+    if self.options.no_std { "alloc" } else { "std" }
+  }
+
+  /**
+  `%option stack`: the start condition stack and flex's `yy_push_state`, `yy_pop_state` and
+  `yy_top_state`, as `push_state`, `pop_state` and `top_state`. Popping or reading an empty stack
+  is flex's "start-condition stack underflow" fatal error, and panics.
+  */
+  fn write_state_stack(&mut self) {
+    self.output(
+      "    /// Saves the current start condition on the stack and switches to `condition`.\n    \
+      pub fn push_state(&mut self, condition: usize) {\n        \
+        self.condition_stack.push(self.condition);\n        \
+        self.begin(condition);\n    \
+      }\n\n    \
+      /// Switches back to the start condition on top of the stack, removing it.\n    \
+      pub fn pop_state(&mut self) {\n        \
+        let condition = self.condition_stack.pop().expect(\"start-condition stack underflow\");\n        \
+        self.begin(condition);\n    \
+      }\n\n    \
+      /// The start condition on top of the stack, leaving the stack unchanged.\n    \
+      pub fn top_state(&self) -> usize {\n        \
+        *self.condition_stack.last().expect(\"start-condition stack underflow\")\n    \
+      }\n\n"
+    );
+  }
+
+  /**
+  The state of an input the lexer has set aside to scan another, which `write_input_stack` saves
+  and restores: its matcher, where in it the lexer was, and the location of the current match.
+  */
+  fn write_input(&mut self, lexer: &str, module: &str) {
+    self.output(&format!(
+      "/// An input the lexer has set aside to scan another, and where it was in it.\n\
+      struct {lexer}Input<'i> {{\n    \
+        matcher: {module}::Matcher<'i>,\n    \
+        start: usize,\n    \
+        end: usize,\n    \
+        line: usize,\n    \
+        column: usize,\n    \
+        next_line: usize,\n    \
+        next_column: usize,\n\
+      }}\n\n",
+      lexer = lexer,
+      module = module
+    ));
+  }
+
+  /**
+  flex's `yypush_buffer_state` and `yypop_buffer_state`, with which an action scans another input,
+  as an included file, as `push_input` and `pop_input`. The lexer scans a pushed input from its
+  start, at line 1 and column 1, with the start condition unchanged, and at its end goes back to
+  where it was in the input it set aside, as `lex` pops the input at its end before ending. The
+  input is a `&str` or the `String` of a file the action read.
+  */
+  fn write_input_stack(&mut self, module: &str) {
+    self.output(&format!(
+      "    /// Sets the current input aside to scan `input` from its start, until its end or\n    \
+      /// `pop_input`.\n    \
+      pub fn push_input(&mut self, input: impl Into<{alloc}::borrow::Cow<'i, str>>) {{\n        \
+        let matcher = {module}::Matcher::new(input);\n        \
+        let matcher = {core}::mem::replace(&mut self.matcher, matcher);\n        \
+        self.suspended.push({lexer}Input {{\n            \
+          matcher,\n            \
+          start: self.start,\n            \
+          end: self.end,\n            \
+          line: self.line,\n            \
+          column: self.column,\n            \
+          next_line: self.next_line,\n            \
+          next_column: self.next_column,\n        \
+        }});\n        \
+        self.start = 0;\n        \
+        self.end = 0;\n        \
+        self.line = 1;\n        \
+        self.column = 1;\n        \
+        self.next_line = 1;\n        \
+        self.next_column = 1;\n        \
+        self.ended = false;\n    \
+      }}\n\n    \
+      /// Goes back to the input `push_input` last set aside, where the lexer was in it,\n    \
+      /// returning whether there was one.\n    \
+      pub fn pop_input(&mut self) -> bool {{\n        \
+        let input = match self.suspended.pop() {{\n            \
+          Some(input) => input,\n            \
+          None => return false,\n        \
+        }};\n        \
+        self.matcher = input.matcher;\n        \
+        self.start = input.start;\n        \
+        self.end = input.end;\n        \
+        self.line = input.line;\n        \
+        self.column = input.column;\n        \
+        self.next_line = input.next_line;\n        \
+        self.next_column = input.next_column;\n        \
+        true\n    \
+      }}\n\n",
+      lexer = self.lexer_name(),
+      module = module,
+      alloc = self.alloc_crate(),
+      core = self.core_crate()
+    ));
+  }
+
+  /**
+  flex's `yyless(n)`, which keeps the first `n` bytes of the match and gives the rest back to be
+  scanned again, and `unput`, which puts text back in the input to be scanned next. The location
+  of the match is counted again over the text it keeps, and the span of a later match is of the
+  input with the text put back. Putting text back in an input the lexer borrows copies it.
+  */
+  fn write_less_and_unput(&mut self) {
+    self.output(
+      "    /// Keeps the first `n` bytes of the current match, giving the rest back to be\n    \
+      /// scanned again.\n    \
+      pub fn less(&mut self, n: usize) {\n        \
+        self.matcher.less(n);\n        \
+        self.end = self.start + n;\n        \
+        self.next_line = self.line;\n        \
+        self.next_column = self.column;\n        \
+        self.advance_location();\n    \
+      }\n\n    \
+      /// Puts `text` back in the input, to be scanned after the current match.\n    \
+      pub fn unput(&mut self, text: &str) {\n        \
+        self.matcher.unput(text);\n    \
+      }\n\n"
+    );
+  }
+
+  fn write_section_3(&mut self) {
+    let code = self.source_code(&self.section_3);
+    self.output(&code);
+  }
+
+  /**
+  The name of the generated lexer: the name `%option lexer` gives, or else `Lexer` after the
+  prefix `%option prefix` gives, so that `%option prefix="calc"` gives `CalcLexer`.
+  */
+  fn lexer_name(&self) -> String {
+    match &self.options.lexer {
+      Some(lexer) => lexer.clone(),
+      None        => self.type_name("Lexer"),
+    }
+  }
+
+  /// The type `name` after the prefix `%option prefix` gives: `CalcToken` for `Token`.
+  fn type_name(&self, name: &str) -> String {
+    let mut prefix = self.options.prefix.clone().unwrap_or_default();
+    if let Some(first) = prefix.get(..1) {
+      prefix = first.to_uppercase() + &prefix[1..];
+    }
+    format!("{}{}", prefix, name)
+  }
+
+  /**
+  What the names of the constants and tables of the scanner start with: `CALC_` with
+  `%option prefix="calc"`, so that two scanners can share a module, and nothing without a prefix.
+  */
+  fn item_prefix(&self) -> String {
+    self.options
+        .prefix
+        .as_ref()
+        .map_or(String::new(), |prefix| format!("{}_", prefix.to_uppercase()))
+  }
+
+  /// `%option main`: scans standard input to its end.
+  fn write_main(&mut self) {
+    let lexer = self.lexer_name();
+    let lex   = self.options.lex.clone().unwrap_or_else(|| "lex".to_string());
+
+    self.output(&format!(
+      "\nfn main() {{\n    \
+        let mut input = String::new();\n    \
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)\n        \
+          .expect(\"Unable to read standard input\");\n\n    \
+        let mut lexer = {}::new(&input);\n    \
+        while !lexer.at_end() {{\n        \
+          lexer.{}();\n    \
+        }}\n\
+      }}\n",
+      lexer,
+      lex
+    ));
+  }
+
+  fn write_section_top(&mut self) {
+    let code = self.source_code(&self.section_top);
+    self.output(&code);
+  }
+
+  fn write_section_1(&mut self) {
+    let code = self.source_code(&self.section_1);
+    self.output(&code);
+  }
+
+  fn write_section_class(&mut self) {
+    let code = self.source_code(&self.section_struct);
+    self.output(&code);
+  }
+
+  fn write_section_init(&mut self) {
+    let code = self.source_code(&self.section_init);
+    self.output(&code);
+  }
+
+  /// Adds the files `%include`d during parsing to the source database, so that diagnostics
+  /// within them can be rendered.
+  fn add_included_sources(&mut self) {
+    for (name, text) in self.included_sources.iter() {
+      if !self.included_source_ids.contains_key(name) {
+        let source_id = self.source_files.add(name.to_string(), text);
+        self.included_source_ids.insert(name.to_string(), source_id);
+      }
+    }
+  }
+
+  /**
+  Renders `errors` to `writer`, showing the source lines they refer to in the colors
+  `color_choice` selects, or with `--message-format=json`, writes each as a JSON object on a line
+  of its own:
+
+  ```json
+  {"code": null, "severity": "error", "message": "…", "file": "calc.l",
+   "range": {"start": {"line": 3, "column": 1}, "end": {"line": 3, "column": 7}},
+   "labels": [{"file": "calc.l", "range": {…}, "message": "…", "primary": true}],
+   "notes": ["…"],
+   "suggestions": [{"message": "insert `%}`", "range": {…}, "replacement": "%}\n"}]}
+  ```
+
+  Lines and columns count from 1, and columns count characters. `range` is that of the primary
+  label, and `null` for a diagnostic without one. `suggestions` are the edits `--fix` applies.
+  */
+  pub fn emit_diagnostics(&self, errors: &Errors, writer: &mut dyn WriteColor) -> io::Result<()> {
+    let diagnostics = errors.to_diagnostics_with(|name| self.error_source_id(name));
+
+    match self.options.message_format {
+      MessageFormat::Human => {
+        let config = codespan_reporting::term::Config::default();
+
+        for d in diagnostics.iter().chain(omitted_note(errors).as_ref()) {
+          emit(writer, &config, &self.source_files, d)?;
+        }
+      }
+
+      MessageFormat::Json => {
+        for (e, d) in errors.iter().zip(diagnostics) {
+          let suggestions = e.suggestions();
+          let file_id     = self.error_source_id(e.source_name());
+          writeln!(writer, "{}", self.diagnostic_json(&d, file_id, &suggestions))?;
+        }
+        if let Some(note) = omitted_note(errors) {
+          writeln!(writer, "{}", self.diagnostic_json(&note, self.source_id, &[]))?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Whether diagnostics written to standard error are colored, as `--color` chooses.
+  pub fn color_choice(&self) -> ColorChoice {
+    match self.options.color {
+      ColorWhen::Always => ColorChoice::Always,
+      ColorWhen::Never  => ColorChoice::Never,
+      ColorWhen::Auto   => {
+        if std::env::var_os("NO_COLOR").is_some() || !atty::is(atty::Stream::Stderr) {
+          ColorChoice::Never
+        } else {
+          ColorChoice::Auto
+        }
+      }
+    }
+  }
+
+  fn diagnostic_json(&self, diagnostic: &Diagnostic<SourceID>, file_id: SourceID,
+                     suggestions: &[Suggestion]) -> serde_json::Value
+  {
+    let file_name = |file_id: SourceID| {
+      self.source_files.get(file_id).map_or(String::new(), |file| file.name().to_string())
+    };
+    let position = |file_id: SourceID, offset: usize| {
+      let text       = self.source_files.get(file_id).map_or("", |file| *file.source());
+      let before     = &text[..offset.min(text.len())];
+      let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+      json!({
+        "line": before.matches('\n').count() + 1,
+        "column": before[line_start..].chars().count() + 1,
+      })
+    };
+    let range = |label: &Label<SourceID>| {
+      json!({
+        "start": position(label.file_id, label.range.start),
+        "end": position(label.file_id, label.range.end),
+      })
+    };
+
+    let primary = diagnostic.labels.iter().find(|label| label.style == LabelStyle::Primary);
+    let labels: Vec<serde_json::Value> =
+        diagnostic.labels
+                  .iter()
+                  .map(|label| json!({
+                    "file": file_name(label.file_id),
+                    "range": range(label),
+                    "message": label.message,
+                    "primary": label.style == LabelStyle::Primary,
+                  }))
+                  .collect();
+    let suggestions: Vec<serde_json::Value> =
+        suggestions.iter()
+                   .map(|suggestion| json!({
+                     "message": suggestion.message,
+                     "range": {
+                       "start": position(file_id, usize::from(suggestion.span.start())),
+                       "end": position(file_id, usize::from(suggestion.span.end())),
+                     },
+                     "replacement": suggestion.replacement,
+                   }))
+                   .collect();
+
+    json!({
+      "code": diagnostic.code,
+      "severity": format!("{:?}", diagnostic.severity).to_lowercase(),
+      "message": diagnostic.message,
+      "file": file_name(primary.map_or(self.source_id, |label| label.file_id)),
+      "range": primary.map(range),
+      "labels": labels,
+      "notes": diagnostic.notes,
+      "suggestions": suggestions,
+    })
+  }
+
+  /// The source ID of the file an error occurred in, given its name as `Error::source_name` does.
+  fn error_source_id(&self, name: Option<&str>) -> SourceID {
+    name.and_then(|name| self.included_source_ids.get(name))
+        .copied()
+        .unwrap_or(self.source_id)
+  }
+
+  /**
+  Applies the suggestions of `errors`, and of the warnings, to the text of the specification file,
+  returning the fixed text and how many were applied, or `None` if none could be. Suggestions in
+  `%include`d files are left alone.
+  */
+  pub fn fixed_source(&self, errors: &Errors) -> Option<(String, usize)> {
+    let suggestions: Vec<Suggestion> =
+        errors.iter()
+              .chain(self.warnings.iter())
+              .filter(|e| e.source_name().is_none())
+              .flat_map(|e| e.suggestions())
+              .collect();
+    if suggestions.is_empty() {
+      return None;
+    }
+
+    let source = self.source_files.get(self.source_id)?.source();
+    Some(apply_suggestions(source, &suggestions)).filter(|(_, applied)| *applied > 0)
+  }
+
+
+
+  /*
+  void        parse_section_2();
+  void        parse_section_3();
+  void        include(const std::string& filename);
+  void        write_perf_report();
+  void        write_namespace_open();
+  void        write_namespace_close();
+  void        write_namespace_scope();
+
+  void        undot_namespace(std::string& s);
+  bool        get_line();
+  bool        skip_comment(size_t& pos);
+  bool        is(const char *s);
+  bool        br(size_t pos, const char *s = NULL);
+  bool        as(size_t& pos, const char *s);
+  bool        ws(size_t& pos);
+  bool        eq(size_t& pos);
+  bool        nl(size_t& pos);
+  bool        is_code();
+  bool        is_topcode();
+  bool        is_classcode();
+  bool        is_initcode();
+  std::string get_name(size_t& pos);
+  std::string get_option(size_t& pos);
+  std::string get_start(size_t& pos);
+  std::string get_string(size_t& pos);
+  bool        get_pattern(size_t& pos, std::string& pattern, std::string& regex);
+  std::string get_namespace(size_t& pos);
+  std::string get_code(size_t& pos);
+  std::string escape_bs(const std::string& s);
+  std::string upper_name(const std::string& s);
+  std::string param_args(const std::string& s);
+  bool        get_starts(size_t& pos, Starts& starts);
+  void        abort(const char *message, const char *arg = NULL);
+  void        error(const char *message, const char *arg = NULL, size_t at_lineno = 0);
+  void        warning(const char *message, const char *arg = NULL, size_t at_lineno = 0);
+
+  */
+}
+
+
+/**
+Builds a `Specification` for programmatic use:
+
+```ignore
+let mut specification = Specification::builder("lexer.l", source)
+    .options(Options::builder().case_insensitive(true).build()?)
+    .sink(Box::new(StdoutSink::new()))
+    .build()?;
+specification.parse();
+```
+*/
+pub struct SpecificationBuilder {
+  name: String,
+  source: String,
+  options: Options,
+  sink: Option<Box<dyn OutputSink>>,
+  engine: Option<Box<dyn RegexEngine>>,
+}
+
+impl SpecificationBuilder {
+  pub fn new<N, T>(name: N, source: T) -> Self
+    where N: Into<String>,
+          T: Into<String>
+  {
+    SpecificationBuilder {
+      name: name.into(),
+      source: source.into(),
+      options: Options::default(),
+      sink: None,
+      engine: None,
+    }
+  }
+
+  pub fn options(mut self, options: Options) -> Self {
+    self.options = options;
+    self
+  }
+
+  /// Where the generated output is written. Without a sink, output is discarded.
+  pub fn sink(mut self, sink: Box<dyn OutputSink>) -> Self {
+    self.sink = Some(sink);
+    self
+  }
+
+  /// The regex engine the scanner is generated for, in place of the one the options select.
+  pub fn engine(mut self, engine: Box<dyn RegexEngine>) -> Self {
+    self.engine = Some(engine);
+    self
+  }
+
+  /// Checks the options for conflicts and values that are not valid, and creates the
+  /// specification.
+  pub fn build<'s>(self) -> Result<Specification<'s>, LeskError> {
+    let mut errors = self.options.conflicts(&[]);
+    errors.merge(self.options.invalid_values(&[]));
+    if errors.has_errors() {
+      return Err(errors.into());
+    }
+
+    let mut specification = Specification::from_source(self.name, self.source, self.options);
+    if let Some(sink) = self.sink {
+      specification.set_sink(sink);
+    }
+    if let Some(engine) = self.engine {
+      specification.set_engine(engine);
+    }
+
+    Ok(specification)
+  }
+}
+
+/**
+The errors of a failed parse. The parsers are complete rather than streaming, so `Incomplete`
+should not occur, but it is reported at the end of `input` rather than trusted not to.
+*/
+fn parse_errors(error: NomErr<Errors>, input: InputType) -> Errors {
+  match error {
+    | NomErr::Error(errors)
+    | NomErr::Failure(errors) => errors,
+
+    NomErr::Incomplete(_) => {
+      let end = input.slice(input.fragment().len()..);
+      Errors::from(Error::Message(end.to_span(), "unexpected end of input".into()))
+    }
+  }
+}
+
+/// A note of how many errors `errors` dropped for exceeding the error limit, if it dropped any.
+fn omitted_note(errors: &Errors) -> Option<Diagnostic<SourceID>> {
+  match errors.omitted {
+    0 => None,
+    n => Some(Diagnostic::note().with_message(format!(
+      "{} more error{} not shown; `--error-limit` sets how many are",
+      n,
+      if n == 1 { " was" } else { "s were" }
+    ))),
+  }
+}
+
+/**
+The names of the fields declared in `%class{ … }` code, `count: usize, pub names: Vec<String>`.
+Commas inside `<…>`, `(…)`, `[…]`, and `//` comments do not separate fields.
+*/
+fn field_names(code: &str) -> Vec<String> {
+  let mut names   = vec![];
+  let mut depth   = 0;
+  let mut start   = 0;
+  let mut comment = false;
+
+  for (idx, c) in code.char_indices() {
+    match c {
+      '\n' if comment => comment = false,
+      _ if comment     => { /* pass */ }
+      '/' if code[idx..].starts_with("//") => comment = true,
+      '<' | '(' | '[' => depth += 1,
+      '>' | ')' | ']' => depth -= 1,
+      ',' if depth == 0 => {
+        names.extend(field_name(&code[start..idx]));
+        start = idx + 1;
+      }
+      _ => { /* pass */ }
+    }
+  }
+  names.extend(field_name(&code[start..]));
+
+  names
+}
+
+/// `pub(crate) count: usize` → `count`
+fn field_name(field: &str) -> Option<String> {
+  let field: Vec<&str> = field.lines().map(|line| line.split("//").next().unwrap_or("")).collect();
+  let field = field.join(" ");
+
+  let head = field.split(':').next()?.trim();
+  let name = head.rsplit(|c: char| c.is_whitespace() || c == ')').next()?;
+
+  if field.contains(':') && !name.is_empty() {
+    Some(name.to_string())
+  } else {
+    None
+  }
+}
+
+/**
+The body of a `#define YY_USER_ACTION …` line in flex user code. Flex expands the macro before
+every action, and Lesk copies its body to the start of every action instead.
+*/
+fn user_action<'s>(code: &Span<'s>) -> Option<Span<'s>> {
+  const DEFINE: &str = "#define YY_USER_ACTION";
+
+  let mut offset = 0;
+  for line in code.fragment().split('\n') {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with(DEFINE) {
+      let start = offset + (line.len() - trimmed.len()) + DEFINE.len();
+      let end   = offset + line.trim_end().len();
+      return Some(code.slice(start..end.max(start)));
+    }
+    // Add one for the newline.
+    offset += line.len() + 1;
+  }
+
+  None
+}
+
+/**
+Uses of flex features that Lesk's matcher cannot support. `REJECT` makes the scanner fall back
+to the next best match, which needs a backtracking matcher rather than a DFA; a scanner generated
+without it would silently do the wrong thing. An `<<EOF>>` rule would otherwise be read as a
+regex matching the text `<<EOF>>`.
+*/
+fn unsupported_features(items: &[Item]) -> Errors {
+  const REJECT_HELP: &str =
+    "Lesk scanners always take the longest match and cannot fall back to another rule. Write a \
+    rule for each match `REJECT` would fall back to, or match the text once and examine it in \
+    the action.";
+  const EOF_HELP: &str =
+    "At the end of the input `lex` returns the default value of the token type, \
+    `Token::EndOfInput` when the specification declares its tokens. Handle the end of the input \
+    where `lex` is called, or test `at_end()` in the actions.";
+
+  let mut errors = Errors::new();
+
+  for item in items {
+    if let Item::Rule { pattern, .. } = item {
+      if *pattern.fragment() == "<<EOF>>" {
+        errors.push(UnsupportedError::new("<<EOF>>", *pattern, EOF_HELP));
+      }
+    }
+    if let Item::Rule { action: Action::Code(code), .. } = item {
+      for offset in identifier_offsets(code.fragment(), "REJECT") {
+        errors.push(UnsupportedError::new(
+          "REJECT",
+          code.slice(offset..offset + "REJECT".len()),
+          REJECT_HELP
+        ));
+      }
+    }
+  }
+
+  errors
+}
+
+/// The offsets of `name` as a whole identifier in `code`, outside of comments and literals.
+pub(crate) fn identifier_offsets(code: &str, name: &str) -> Vec<usize> {
+  let bytes       = code.as_bytes();
+  let mut offsets = vec![];
+  let mut idx     = 0;
+
+  while idx < bytes.len() {
+    match bytes[idx] {
+      b'/' if bytes.get(idx + 1) == Some(&b'/') => {
+        while idx < bytes.len() && bytes[idx] != b'\n' {
+          idx += 1;
+        }
+      }
+      b'/' if bytes.get(idx + 1) == Some(&b'*') => {
+        idx = code[idx + 2..].find("*/").map_or(bytes.len(), |end| idx + 2 + end + 2);
+      }
+      quote @ b'"' | quote @ b'\'' => {
+        idx += 1;
+        while idx < bytes.len() && bytes[idx] != quote {
+          idx += if bytes[idx] == b'\\' { 2 } else { 1 };
+        }
+        idx += 1;
+      }
+      c if c == b'_' || c.is_ascii_alphanumeric() => {
+        let start = idx;
+        while idx < bytes.len() && (bytes[idx] == b'_' || bytes[idx].is_ascii_alphanumeric()) {
+          idx += 1;
+        }
+        if &code[start..idx] == name {
+          offsets.push(start);
+        }
+      }
+      _ => idx += 1,
+    }
+  }
+
+  offsets
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use clap::Parser;
+  use codespan_reporting::term::termcolor;
+  use syn::visit::{self, Visit};
+
+  const CALCULATOR: &str = "\
+%token Number Plus
+%%
+[0-9]+  return Token::Number;
+\"+\"     return Token::Plus;
+";
+
+  /// The scanner generated from the specification `text` with the command line flags `flags`.
+  fn generate(text: &str, flags: &[&str]) -> String {
+    let options = Options::parse_from(std::iter::once("lesk").chain(flags.iter().copied()));
+    let buffer  = BufferSink::new();
+    let mut specification = Specification::from_source("calc.l", text, options);
+    specification.set_sink(Box::new(buffer.clone()));
+    specification.parse().unwrap();
+    buffer.contents()
+  }
+
+  #[test]
+  fn lexer_iterates_over_tokens_and_spans() {
+    let scanner = generate(CALCULATOR, &[]);
+    assert!(scanner.contains(
+      "impl<'i> Iterator for Lexer<'i> {\n    type Item = (Token, std::ops::Range<usize>);\n"
+    ));
+    assert!(scanner.contains("let token = self.lex();"));
+  }
+
+  #[test]
+  fn formatting_round_trips() {
+    let text = "\
+%token Number Plus
+DIGIT   [0-9]
+%x comment
+%s other
+%%
+<comment>\"*/\"  BEGIN(INITIAL);
+{DIGIT}+  return Token::Number;
+\"+\"     return Token::Plus;
+";
+    let formatted = generate(text, &["--fmt"]);
+    assert!(formatted.contains("\nDIGIT  [0-9]\n\n%xstate comment\n%state other\n\n%%\n"));
+    assert!(formatted.contains("\n{DIGIT}+       return Token::Number;\n"));
+    assert_eq!(generate(&formatted, &["--fmt"]), formatted);
+    assert_eq!(generate(&formatted, &["--noline"]), generate(text, &["--noline"]));
+  }
+
+  #[test]
+  fn ast_owns_the_items_and_their_locations() {
+    use crate::ast::{Action as AstAction, Item as AstItem};
+
+    let text = "DIGIT [0-9]\n%x comment\n%%\n<comment>{DIGIT}+/x   f();\n%%\nfn g() {}\n";
+    let mut specification = Specification::from_source("ast.l", text, Options::default());
+    specification.set_sink(Box::new(BufferSink::new()));
+    specification.parse().unwrap();
+    let ast = specification.ast().unwrap().clone();
+    drop(specification);
+
+    match ast.section_one.as_slice() {
+      [AstItem::Definition { name, params, regex }, AstItem::State { is_exclusive: true, .. }] => {
+        assert_eq!((name.text.as_str(), regex.text.as_str()), ("DIGIT", "[0-9]"));
+        assert!(params.is_empty());
+        assert_eq!((name.location.start, name.location.end, name.location.line), (0, 5, 1));
+      }
+      items => panic!("unexpected section one: {:?}", items),
+    }
+    match ast.section_two.as_slice() {
+      [AstItem::Rule { conditions, pattern, context: Some(context), action, .. }] => {
+        assert_eq!(conditions[0].text, "comment");
+        assert_eq!((pattern.text.as_str(), context.text.as_str()), ("{DIGIT}+", "x"));
+        assert_eq!((pattern.location.line, pattern.location.column), (4, 10));
+        assert!(matches!(action, AstAction::Code { code } if code.text.trim() == "f();"));
+      }
+      items => panic!("unexpected section two: {:?}", items),
+    }
+    assert_eq!(ast.user_code, "fn g() {}\n");
+
+    let json = serde_json::to_value(&ast).unwrap();
+    assert_eq!(json["section_two"][0]["kind"], "rule");
+    assert_eq!(json["section_two"][0]["action"]["kind"], "code");
+    assert_eq!(json["section_one"][1]["name"]["text"], "comment");
+  }
+
+  #[test]
+  fn flex_export_lowers_what_flex_lacks() {
+    let text = "\
+%token Number
+DELIM(l,r)  {l}[^{r}]*{r}
+DIGIT  [0-9]
+%x comment
+%%
+<-comment>{DELIM(<,>)}   f();
+<comment>{
+  %option case-insensitive
+  end  BEGIN(INITIAL);
+}
+{DIGIT}+   return Token::Number;
+%%
+fn main() {}
+";
+    let path = std::env::temp_dir().join(format!("lesk-export-{}.l", std::process::id()));
+    let path = path.display().to_string();
+    assert_eq!(generate(text, &["--export-flex", &path]), "");
+
+    let flex = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(flex, "\
+/* lesk: %token Number has no flex equivalent */
+DIGIT  [0-9]
+%x comment
+%%
+<INITIAL>(<[^>]*>)  f();
+<comment>(?i:end)  BEGIN(INITIAL);
+{DIGIT}+  return Token::Number;
+%%
+fn main() {}
+");
+  }
+
+  #[test]
+  fn builder_configures_the_specification() {
+    let options = Options::builder()
+        .define("PLUS")
+        .option(OptionField::Line(false))
+        .build()
+        .unwrap();
+    let buffer  = BufferSink::new();
+    let text    = "%token Number Plus\n%%\n[0-9]+   return Token::Number;\n\
+                   %if PLUS\n\"+\"   return Token::Plus;\n%endif\n";
+    let mut specification = Specification::builder("calc.l", text)
+        .options(options)
+        .sink(Box::new(buffer.clone()))
+        .build()
+        .unwrap();
+    specification.parse().unwrap();
+
+    let scanner = buffer.contents();
+    assert!(scanner.contains("return Token::Plus;"));
+    assert!(!scanner.contains("// lesk:"));
+
+    let options = Options::parse_from(["lesk", "--full", "--fast"]);
+    assert!(Specification::builder("calc.l", text).options(options).build().is_err());
+  }
+
+  #[test]
+  fn actions_name_the_lines_they_come_from() {
+    assert!(generate(CALCULATOR, &[]).contains("                // lesk:calc.l:3\n"));
+    assert!(!generate(CALCULATOR, &["--noline"]).contains("// lesk:"));
+    assert!(!generate(&format!("%option noline\n{}", CALCULATOR), &[]).contains("// lesk:"));
+  }
+
+  #[test]
+  fn debug_reports_the_lines_of_rules() {
+    let scanner = generate(&format!("%option debug\n{}", CALCULATOR), &[]);
+    assert!(scanner.contains("static RULE_LINES: [u32; 2] = [4, 5];"));
+    assert!(scanner.contains("--accepting rule at line {} ({:?})"));
+    assert!(scanner.contains("pub fn set_debug(&mut self, debug: bool)"));
+    assert!(!generate(CALCULATOR, &[]).contains("RULE_LINES"));
+  }
+
+  #[test]
+  fn perf_report_counts_the_matches_of_rules() {
+    let scanner = generate(CALCULATOR, &["--perf-report"]);
+    assert!(scanner.contains("static RULE_LINES: [u32; 2] = [3, 4];"));
+    assert!(scanner.contains("self.match_counts[rule] += 1;"));
+    assert!(scanner.contains("self.report_performance();"));
+  }
+
+  #[test]
+  fn prefix_applies_to_every_item_of_the_scanner() {
+    let scanner = generate(&format!("%option prefix=\"calc\"\n{}", CALCULATOR), &[]);
+    assert!(scanner.contains("pub const CALC_INITIAL: usize = 0;"));
+    assert!(scanner.contains("pub enum CalcToken {"));
+    assert!(scanner.contains("pub struct CalcLexer<'i>"));
+    assert!(scanner.contains("static CALC_RULE_ACTIONS: [usize; 2]"));
+    assert!(scanner.contains("static CALC_RULES_INITIAL: [(&str, Option<&str>); 2]"));
+    assert!(scanner.contains("type Token = CalcToken;"));
+    assert!(scanner.contains(
+      "pub mod calc {\n    pub use super::{CALC_INITIAL as INITIAL, CalcToken as Token};"
+    ));
+    assert!(scanner.contains("mod calc_matcher {"));
+    assert!(scanner.contains("matcher: calc_matcher::Matcher<'i>,"));
+    assert!(!scanner.contains("static RULE_ACTIONS"));
+  }
+
+  /// The names of the items at the top level of `file`, including those `use` brings in.
+  fn item_names(file: &syn::File) -> Vec<String> {
+    fn use_names(tree: &syn::UseTree, names: &mut Vec<String>) {
+      match tree {
+        syn::UseTree::Path(path)     => use_names(&path.tree, names),
+        syn::UseTree::Name(name)     => names.push(name.ident.to_string()),
+        syn::UseTree::Rename(rename) => names.push(rename.rename.to_string()),
+        syn::UseTree::Group(group)   => group.items.iter().for_each(|tree| use_names(tree, names)),
+        syn::UseTree::Glob(_)        => { /* pass */ }
+      }
+    }
+
+    let mut names = vec![];
+    for item in &file.items {
+      match item {
+        syn::Item::Const(item)  => names.push(item.ident.to_string()),
+        syn::Item::Static(item) => names.push(item.ident.to_string()),
+        syn::Item::Struct(item) => names.push(item.ident.to_string()),
+        syn::Item::Enum(item)   => names.push(item.ident.to_string()),
+        syn::Item::Fn(item)     => names.push(item.sig.ident.to_string()),
+        syn::Item::Mod(item)    => names.push(item.ident.to_string()),
+        syn::Item::Type(item)   => names.push(item.ident.to_string()),
+        syn::Item::Use(item)    => use_names(&item.tree, &mut names),
+        _                       => { /* pass */ }
+      }
+    }
+    names
+  }
+
+  #[test]
+  fn scanners_with_different_prefixes_share_a_module() {
+    let init = "%init{\n  lexer.begin(INITIAL);\n}\n";
+    let calc = generate(&format!("%option prefix=\"calc\"\n{}{}", init, CALCULATOR), &[]);
+    let expr = generate(&format!("%option prefix=\"expr\"\n{}{}", init, CALCULATOR), &[]);
+    let mut names = item_names(&syn::parse_file(&calc).unwrap());
+    names.extend(item_names(&syn::parse_file(&expr).unwrap()));
+
+    let mut unique = names.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), names.len(), "{:?}", names);
+    // `%init` code sees the start conditions under their own names.
+    let alias = calc.find("const INITIAL: usize = CALC_INITIAL;").unwrap();
+    assert!(calc[alias..].contains("lexer.begin(INITIAL);"));
+  }
+
+  /// The variants of the `Token` enum a scanner declares, and those its code names.
+  #[derive(Default)]
+  struct TokenVariants {
+    declared: Vec<String>,
+    named: Vec<String>,
+  }
+
+  impl<'ast> Visit<'ast> for TokenVariants {
+    fn visit_item_enum(&mut self, item: &'ast syn::ItemEnum) {
+      if item.ident == "Token" {
+        self.declared.extend(item.variants.iter().map(|variant| variant.ident.to_string()));
+      }
+      visit::visit_item_enum(self, item);
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+      let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+      if let [token, variant] = segments.as_slice() {
+        if token == "Token" {
+          self.named.push(variant.clone());
+        }
+      }
+      visit::visit_path(self, path);
+    }
+  }
+
+  #[test]
+  fn default_rules_return_declared_tokens() {
+    for flags in [&[][..], &["--nodefault"]] {
+      let scanner = syn::parse_file(&generate(CALCULATOR, flags)).unwrap();
+      let mut tokens = TokenVariants::default();
+      tokens.visit_file(&scanner);
+      assert!(tokens.named.contains(&"Unmatched".to_string()));
+      for variant in &tokens.named {
+        assert!(tokens.declared.contains(variant), "`Token::{}` is not declared", variant);
+      }
+    }
+  }
+
+  #[test]
+  fn token_type_is_returned_by_lex() {
+    let text    = "%%\n[0-9]+  return MyToken::Number;\n";
+    let scanner = generate(text, &["--token-type", "MyToken"]);
+    assert!(scanner.contains(
+      "returning `Default::default()` at\n    /// the end of the input.\n    \
+      pub fn lex(&mut self) -> MyToken {"
+    ));
+    assert!(scanner.contains("default_rule: fn(&mut Self) -> Option<MyToken>,"));
+    assert!(scanner.contains("type Item = (MyToken, std::ops::Range<usize>);"));
+    assert!(!scanner.contains("enum Token"));
+  }
+
+  #[test]
+  fn lalrpop_lexer_yields_located_tokens() {
+    let scanner = generate(CALCULATOR, &["--lalrpop"]);
+    assert!(scanner.contains(
+      "type Item = Result<(usize, Token, usize), core::convert::Infallible>;"
+    ));
+    assert!(scanner.contains("Some(Ok((self.start, token, self.end)))"));
+    assert!(scanner.contains("#[derive(Clone, Debug)]\npub enum Token {"));
+    syn::parse_file(&scanner).unwrap();
+  }
+
+  #[test]
+  fn lalrpop_extern_block_declares_the_tokens() {
+    let text = "%option prefix=calc\n%token Number(i64) Plus\n%%\n[0-9]+  f();\n";
+    let options = Options::parse_from(["lesk", "--lalrpop"]);
+    let mut specification = Specification::from_source("calc.l", text, options);
+    specification.set_sink(Box::new(BufferSink::new()));
+    specification.parse().unwrap();
+
+    assert_eq!(
+      specification.lalrpop_extern().unwrap(),
+      "// The tokens of the scanner lesk generated from calc.l, for a LALRPOP grammar.\n\
+      extern {\n    \
+        type Location = usize;\n    \
+        type Error = core::convert::Infallible;\n\n    \
+        enum CalcToken {\n        \
+          \"Number\" => CalcToken::Number(<i64>),\n        \
+          \"Plus\" => CalcToken::Plus,\n    \
+        }\n\
+      }\n"
+    );
+  }
+
+  #[test]
+  fn lalrpop_extern_block_needs_the_tokens() {
+    let options = Options::parse_from(["lesk", "--lalrpop"]);
+    let mut specification = Specification::from_source("calc.l", "%%\n[0-9]+  f();\n", options);
+    specification.set_sink(Box::new(BufferSink::new()));
+    specification.parse().unwrap();
+    assert!(matches!(specification.lalrpop_extern(), Err(LeskError::Config { .. })));
+  }
+
+  #[test]
+  fn nostd_scanner_names_core_and_alloc() {
+    let scanner = generate(CALCULATOR, &["--no-std"]);
+    assert!(scanner.contains("extern crate alloc;\n\nuse alloc::vec::Vec;\n"));
+    assert!(scanner.contains("pub fn span(&self) -> core::ops::Range<usize> {"));
+    assert!(scanner.contains("type Item = (Token, core::ops::Range<usize>);"));
+    assert!(scanner.contains("input: impl Into<alloc::borrow::Cow<'i, str>>"));
+    assert!(scanner.contains("default_rule: Self::no_default_rule,"));
+    assert!(!scanner.contains("std::"));
+    assert!(!scanner.contains("print!"));
+    syn::parse_file(&scanner).unwrap();
+  }
+
+  #[test]
+  fn lexer_gives_back_and_puts_back_text() {
+    let scanner = generate(CALCULATOR, &[]);
+    assert!(scanner.contains(
+      "pub fn less(&mut self, n: usize) {\n        \
+        self.matcher.less(n);\n        \
+        self.end = self.start + n;\n"
+    ));
+    assert!(scanner.contains(
+      "pub fn unput(&mut self, text: &str) {\n        self.matcher.unput(text);\n    }"
+    ));
+    assert!(scanner.contains("self.input.to_mut().insert_str(self.position, text);"));
+    syn::parse_file(&scanner).unwrap();
+  }
+
+  #[test]
+  fn lexer_scans_pushed_inputs_before_popping_them() {
+    let scanner = generate(CALCULATOR, &[]);
+    assert!(scanner.contains("struct LexerInput<'i> {\n    matcher: matcher::Matcher<'i>,"));
+    assert!(scanner.contains("suspended: Vec<LexerInput<'i>>,"));
+    assert!(scanner.contains(
+      "pub fn push_input(&mut self, input: impl Into<std::borrow::Cow<'i, str>>) {"
+    ));
+    assert!(scanner.contains("pub fn pop_input(&mut self) -> bool {"));
+    assert!(scanner.contains(
+      "None => {\n                    if self.pop_input() {\n                        continue;"
+    ));
+    syn::parse_file(&scanner).unwrap();
+  }
+
+  #[test]
+  fn exception_is_returned_by_the_default_rule() {
+    let text    = format!("%option exception=\"Token::Plus\"\n{}", CALCULATOR);
+    let scanner = generate(&text, &[]);
+    assert!(scanner.contains("default_rule: Self::raise_exception,"));
+    assert!(scanner.contains(
+      "pub fn raise_exception(&mut self) -> Option<Token> {\n        Some(Token::Plus)\n"
+    ));
+  }
+
+  #[test]
+  fn lexer_tracks_lines_and_columns() {
+    let scanner = generate(CALCULATOR, &["--tabs", "4"]);
+    assert!(scanner.contains("pub fn location(&self) -> (usize, usize) {"));
+    assert!(scanner.contains("self.next_column += 4 - (self.next_column - 1) % 4,"));
+    assert!(scanner.contains("self.advance_location();"));
+  }
+
+  #[test]
+  fn end_of_file_rules_are_unsupported() {
+    let text   = "%x STR\n%%\n<<EOF>>   { }\n<STR><<EOF>>   { }\n";
+    let result = Specification::from_source("eof.l", text, Options::default()).parse();
+    match result {
+      Err(LeskError::Diagnostics(errors)) => {
+        assert_eq!(errors.iter().map(Error::code).collect::<Vec<_>>(), vec!["L0013", "L0013"]);
+      }
+      _ => panic!("`<<EOF>>` rules are accepted"),
+    }
+  }
+
+  #[test]
+  fn variable_length_trailing_context_is_a_warning() {
+    let text = "%%\n[a-z]+/[0-9]*   { }\n[a-z]+/[0-9]    { }\n";
+    let mut specification = Specification::from_source("context.l", text, Options::default());
+    specification.set_sink(Box::new(BufferSink::new()));
+    specification.parse().unwrap();
+    let codes: Vec<&str> = specification.warnings().iter().map(Error::code).collect();
+    assert_eq!(codes, vec!["L0107"]);
+  }
+
+  /// The codes of the warnings that parsing the specification `text` reports.
+  fn warning_codes(text: &str) -> Vec<&'static str> {
+    let mut specification = Specification::from_source("test.l", text, Options::default());
+    specification.set_sink(Box::new(BufferSink::new()));
+    specification.parse().unwrap();
+    specification.warnings().iter().map(Error::code).collect()
+  }
+
+  /// The codes of the warnings, then of the errors, that parsing `text` with `flags` reports.
+  fn diagnostic_codes(text: &str, flags: &[&str]) -> (Vec<&'static str>, Vec<&'static str>) {
+    let options = Options::parse_from(std::iter::once("lesk").chain(flags.iter().copied()));
+    let mut specification = Specification::from_source("test.l", text, options);
+    specification.set_sink(Box::new(BufferSink::new()));
+    let errors = match specification.parse() {
+      Err(LeskError::Diagnostics(errors)) => errors.iter().map(Error::code).collect(),
+      _ => vec![],
+    };
+    (specification.warnings().iter().map(Error::code).collect(), errors)
+  }
+
+  #[test]
+  fn check_compiles_the_rules_with_the_selected_engine() {
+    // A backreference, which `regex-automata` lacks and the approximate check of `relesk` passes
+    const BACKREFERENCE: &str = "%%\n(a)\\1   f();\n";
+    let none = Vec::<&str>::new();
+
+    assert_eq!(diagnostic_codes(BACKREFERENCE, &["--check"]), (none.clone(), vec!["L0014"]));
+    assert_eq!(
+      diagnostic_codes(BACKREFERENCE, &["--check", "--matcher", "relesk"]),
+      (none.clone(), none.clone())
+    );
+    assert_eq!(diagnostic_codes("%%\n[[:alpha:]]+   f();\n", &["--check"]), (none.clone(), none));
+  }
+
+  #[test]
+  fn allow_and_deny_set_the_level_of_warnings() {
+    const UNUSED: &str = "DIGIT [0-9]\n%%\n[a-z]*   f();\n";
+    let none = Vec::<&str>::new();
+
+    assert_eq!(diagnostic_codes(UNUSED, &[]), (vec!["L0101", "L0103"], none.clone()));
+    assert_eq!(
+      diagnostic_codes(UNUSED, &["--allow", "unused-definition"]),
+      (vec!["L0103"], none.clone())
+    );
+    assert_eq!(diagnostic_codes(UNUSED, &["--allow", "warnings"]), (none.clone(), none.clone()));
+    assert_eq!(
+      diagnostic_codes(UNUSED, &["--allow", "warnings", "--warn", "L0103"]),
+      (vec!["L0103"], none.clone())
+    );
+    assert_eq!(diagnostic_codes(UNUSED, &["--deny", "L0101"]), (vec!["L0103"], vec!["L0101"]));
+    assert_eq!(
+      diagnostic_codes(UNUSED, &["--allow", "L0101", "--deny", "L0101"]),
+      (vec!["L0103"], vec!["L0101"])
+    );
+  }
+
+  #[test]
+  fn the_command_line_overrides_the_level_options() {
+    let text = "%option deny=\"unused-definition\"\nDIGIT [0-9]\n%%\n[a-z]+   f();\n";
+    let none = Vec::<&str>::new();
+
+    assert_eq!(diagnostic_codes(text, &[]), (none.clone(), vec!["L0101"]));
+    assert_eq!(diagnostic_codes(text, &["--allow", "unused-definition"]), (none.clone(), none));
+    assert_eq!(
+      diagnostic_codes("%option allow=\"no-such-warning\"\n%%\na   f();\n", &[]).1,
+      vec!["L0014"]
+    );
+  }
+
+  #[test]
+  fn unused_definitions_are_warnings() {
+    assert_eq!(warning_codes("DIGIT [0-9]\n%%\n[a-z]+   f();\n"), vec!["L0101"]);
+    assert_eq!(warning_codes("DIGIT [0-9]\n%%\n{DIGIT}+   f();\n"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn exclusive_conditions_without_rules_are_warnings() {
+    assert_eq!(warning_codes("%x comment\n%%\na   f();\n"), vec!["L0102"]);
+    assert_eq!(warning_codes("%x comment\n%%\n<comment>a   f();\n"), Vec::<&str>::new());
+    assert_eq!(warning_codes("%s comment\n%%\na   f();\n"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn patterns_matching_the_empty_string_are_warnings() {
+    assert_eq!(warning_codes("%%\n[a-z]*   f();\n"), vec!["L0103"]);
+    assert_eq!(warning_codes("%%\nab|c?   f();\n"), vec!["L0103"]);
+    assert_eq!(warning_codes("%%\n[a-z]+   f();\n"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn overridden_options_are_warnings() {
+    let text = "%option prefix=\"a\"\n%option prefix=\"b\"\n%%\na   f();\n";
+    assert_eq!(warning_codes(text), vec!["L0104"]);
+    let text = "%option prefix=\"a\"\n%option prefix=\"a\"\n%%\na   f();\n";
+    assert_eq!(warning_codes(text), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn ignored_options_are_warnings() {
+    assert_eq!(warning_codes("%option 8bit\n%%\na   f();\n"), vec!["L0105"]);
+    assert_eq!(warning_codes("%option noline\n%%\na   f();\n"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn code_between_rules_is_a_warning() {
+    assert_eq!(warning_codes("%%\na   f();\n    count += 1;\nb   g();\n"), vec!["L0106"]);
+    assert_eq!(warning_codes("%%\n    count += 1;\na   f();\nb   g();\n"), Vec::<&str>::new());
+  }
+
+  /// The codes of the errors that parsing the specification `text` reports.
+  fn error_codes(text: &str) -> Vec<&'static str> {
+    let mut specification = Specification::from_source("test.l", text, Options::default());
+    specification.set_sink(Box::new(BufferSink::new()));
+    match specification.parse() {
+      Err(LeskError::Diagnostics(errors)) => errors.iter().map(Error::code).collect(),
+      _ => vec![],
+    }
+  }
+
+  #[test]
+  fn diagnostics_are_written_to_the_given_writer() {
+    let text    = "%%\n{DIGIT}+   f();\n";
+    let options = Options::parse_from(["lesk", "--message-format", "json"]);
+    let mut specification = Specification::from_source("test.l", text, options);
+    specification.set_sink(Box::new(BufferSink::new()));
+    let errors = match specification.parse() {
+      Err(LeskError::Diagnostics(errors)) => errors,
+      _ => panic!("an undefined name is accepted"),
+    };
+
+    let mut writer = termcolor::NoColor::new(vec![]);
+    specification.emit_diagnostics(&errors, &mut writer).unwrap();
+    let written = String::from_utf8(writer.into_inner()).unwrap();
+    let lines: Vec<serde_json::Value> =
+        written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["code"], "L0008");
+    assert_eq!(lines[0]["range"]["start"], json!({"line": 2, "column": 1}));
+  }
+
+  #[test]
+  fn verbose_keeps_the_statistics_rather_than_printing_them() {
+    let statistics = |text: &str| {
+      let mut specification = Specification::from_source("calc.l", text, Options::default());
+      specification.set_sink(Box::new(BufferSink::new()));
+      specification.parse().unwrap();
+      specification.statistics().map(|statistics| statistics.to_string())
+    };
+    assert_eq!(statistics(CALCULATOR), None);
+    assert!(statistics(&format!("%option verbose\n{}", CALCULATOR)).is_some());
+  }
+
+  #[test]
+  fn inclusive_start_condition() {
+    // The example of inclusive start conditions in the flex manual.
+    let text    = "%s example\n%%\n<example>foo   do_something();\nbar   something_else();\n";
+    let scanner = generate(text, &[]);
+    assert!(scanner.contains("static ACCEPT_INITIAL: [usize; 1] = [1];"));
+    assert!(scanner.contains("static ACCEPT_example: [usize; 2] = [0, 1];"));
+  }
+
+  #[test]
+  fn exclusive_start_condition() {
+    // The same scanner with an exclusive start condition, as the flex manual writes it.
+    let text    = "\
+%x example
+%%
+<example>foo   do_something();
+<INITIAL,example>bar   something_else();
+";
+    let scanner = generate(text, &[]);
+    assert!(scanner.contains("static ACCEPT_INITIAL: [usize; 1] = [1];"));
+    assert!(scanner.contains("static ACCEPT_example: [usize; 2] = [0, 1];"));
+
+    let scanner = generate("%x example\n%%\n<example>foo   f();\nbar   g();\n", &[]);
+    assert!(scanner.contains("static ACCEPT_example: [usize; 1] = [0];"));
+  }
+
+  #[test]
+  fn wildcard_and_excluded_start_conditions() {
+    let scanner = generate("%s a\n%x b\n%%\n<*>x   f();\n<-a>y   g();\n", &[]);
+    assert!(scanner.contains("static ACCEPT_INITIAL: [usize; 2] = [0, 1];"));
+    assert!(scanner.contains("static ACCEPT_a: [usize; 1] = [0];"));
+    assert!(scanner.contains("static ACCEPT_b: [usize; 2] = [0, 1];"));
+  }
+
+  #[test]
+  fn fall_through_rules_share_the_next_action() {
+    let scanner = generate("%%\na   |\nb   |\nc   f();\nd\n", &[]);
+    assert!(scanner.contains("static RULE_ACTIONS: [usize; 4] = [0, 0, 0, NO_ACTION];"));
+  }
+
+  #[test]
+  fn definitions_are_expanded() {
+    let scanner = generate("DIGIT   [0-9]\n%%\n{DIGIT}+   f();\n", &[]);
+    let regex   = scanner.lines().find(|line| line.starts_with("static RULES_INITIAL")).unwrap();
+    assert!(regex.contains("[0-9]"));
+    assert!(!regex.contains("{DIGIT}"));
+
+    assert!(error_codes("%%\n{DIGIT}+   f();\n").contains(&"L0008"));
+    assert!(error_codes("A   {B}\nB   x{A}\n%%\n{A}   f();\n").contains(&"L0009"));
+  }
+
+  #[test]
+  fn parameterized_definitions_are_expanded() {
+    let scanner = generate("DELIM(l,r)   {l}[^{r}]*{r}\n%%\n{DELIM(<,>)}   f();\n", &[]);
+    let regex   = scanner.lines().find(|line| line.starts_with("static RULES_INITIAL")).unwrap();
+    assert!(regex.contains("(<[^>]*>)"), "{}", regex);
+  }
+
+  #[test]
+  fn trailing_context_follows_the_whole_pattern() {
+    let scanner = generate("%%\nab|cd/x   f();\n", &["--matcher", "relesk"]);
+    let regex   = scanner.lines().find(|line| line.starts_with("static REGEX_INITIAL")).unwrap();
+    assert!(regex.contains("(?:ab|cd)(?=x)"), "{}", regex);
+
+    // The default engine matches the context apart from the text, lacking lookahead.
+    let scanner = generate("%%\nab|cd/x   f();\n", &[]);
+    assert!(scanner.contains(
+      "static RULES_INITIAL: [(&str, Option<&str>); 1] = [(\"ab|cd\", Some(\"x\"))];"
+    ));
+  }
+
+  /// Writes `files` into a new directory named for `test` under the temporary directory.
+  fn include_dir(test: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("lesk-{}-{}", test, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, text) in files {
+      std::fs::write(dir.join(name), text).unwrap();
+    }
+    dir
+  }
+
+  #[test]
+  fn a_file_including_itself_is_a_cycle() {
+    let dir  = include_dir("direct-cycle", &[("self.l", "%include \"self.l\"\n")]);
+    let text = format!("%include \"{}\"\n%%\na   f();\n", dir.join("self.l").display());
+    assert_eq!(error_codes(&text), vec!["L0012"]);
+  }
+
+  #[test]
+  fn files_including_each_other_are_a_cycle() {
+    let dir  = include_dir(
+      "indirect-cycle",
+      &[("a.l", "%include \"b.l\"\n"), ("b.l", "%include \"a.l\"\n")]
+    );
+    let text = format!("%include \"{}\"\n%%\na   f();\n", dir.join("a.l").display());
+    assert_eq!(error_codes(&text), vec!["L0012"]);
+  }
+
+  #[test]
+  fn start_conditions_fit_in_a_mode_id() {
+    let conditions = |count: usize| -> String {
+      (1..=count).map(|n| format!("%x s{}\n", n)).collect::<String>() + "%%\na   f();\n"
+    };
+    assert!(!error_codes(&conditions(255)).contains(&"L0014"));
+    assert!(error_codes(&conditions(256)).contains(&"L0014"));
+  }
+
+  #[test]
+  fn regexes_the_engine_rejects_are_errors() {
+    assert!(error_codes("%%\na{3,2}   f();\nc   h();\n").contains(&"L0014"));
+    let scanner = generate(CALCULATOR, &[]);
+    assert!(scanner.contains(
+      "static RULES: [(&str, &[(&str, Option<&str>)]); 1] = [(\"INITIAL\", &RULES_INITIAL)];"
+    ));
+    assert!(scanner.contains("panic!(\"the rules of {} do not compile: {}\""));
+
+    let scanner = generate(CALCULATOR, &["--matcher", "relesk"]);
+    assert!(scanner.contains(
+      "static REGEXES: [(&str, &str); 1] = [(\"INITIAL\", REGEX_INITIAL)];"
+    ));
+    assert!(scanner.contains("panic!(\"the regex of start condition {} does not compile: {:?}\""));
+  }
 }
@@ -0,0 +1,84 @@
+/*!
+
+The summary of a generated scanner that `%option verbose` prints, in the manner of flex's `-v`.
+
+Flex also reports the sizes of its DFAs and the equivalence classes it found. Lesk scanners
+compile their patterns when the lexer is created, so those are not known when the scanner is
+generated, and the summary reports the tables instead.
+
+*/
+
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+
+use crate::mode::ConditionPattern;
+
+
+/// The statistics of one start condition.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConditionStatistics {
+  pub name: String,
+  pub exclusive: bool,
+  /// The number of rules active in the start condition
+  pub rules: usize,
+  /// The length of the start condition's regex
+  pub regex_bytes: usize,
+}
+
+/// The statistics of a generated scanner.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Statistics {
+  pub conditions: Vec<ConditionStatistics>,
+  pub rules: usize,
+  pub actions: usize,
+  pub definitions: usize,
+  /// The size of `RULE_ACTIONS`, the `REGEX_<NAME>` strings, and the `ACCEPT_<NAME>` arrays
+  pub table_bytes: usize,
+}
+
+impl Statistics {
+  pub fn new(patterns: &[ConditionPattern], rules: usize, actions: usize, definitions: usize)
+    -> Self
+  {
+    let conditions: Vec<ConditionStatistics> =
+        patterns.iter()
+                .map(|pattern| ConditionStatistics {
+                  name: pattern.condition.clone(),
+                  exclusive: pattern.exclusive,
+                  rules: pattern.rule_ids.len(),
+                  regex_bytes: pattern.regex.len(),
+                })
+                .collect();
+
+    let table_bytes =
+        rules * size_of::<usize>()
+        + conditions.iter()
+                    .map(|condition| condition.regex_bytes + condition.rules * size_of::<usize>())
+                    .sum::<usize>();
+
+    Statistics { conditions, rules, actions, definitions, table_bytes }
+  }
+}
+
+impl Display for Statistics {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "lesk scanner statistics:")?;
+    writeln!(
+      f,
+      "  {} rules, {} actions, {} definitions",
+      self.rules, self.actions, self.definitions
+    )?;
+    writeln!(f, "  {} start conditions", self.conditions.len())?;
+    for condition in &self.conditions {
+      writeln!(
+        f,
+        "    {} ({}): {} rules, {} regex bytes",
+        condition.name,
+        if condition.exclusive { "exclusive" } else { "inclusive" },
+        condition.rules,
+        condition.regex_bytes
+      )?;
+    }
+    writeln!(f, "  {} bytes of tables", self.table_bytes)
+  }
+}
@@ -0,0 +1,92 @@
+/*!
+
+How long each phase of generating a scanner took, which `--timings` reports to standard error as a
+table, or with `--timings=json` as a line of JSON:
+
+```text
+phase        time (ms)
+parse            1.204
+lint             0.031
+lower            0.117
+write            0.862
+total            2.214
+```
+
+The phases are those a specification goes through in `Specification::parse`, together with any
+the caller records itself, such as the binary compiling the rules' regexes for `--check`.
+
+*/
+
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+
+/// The elapsed time of each phase, in the order they ran.
+#[derive(Clone, Debug, Default)]
+pub struct Timings {
+  phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Runs `f`, recording its elapsed time as `phase`.
+  pub fn time<T, F: FnOnce() -> T>(&mut self, phase: &'static str, f: F) -> T {
+    let start  = Instant::now();
+    let result = f();
+    self.record(phase, start.elapsed());
+    result
+  }
+
+  /// Adds `elapsed` to `phase`, which is added after the others if it has not been recorded.
+  pub fn record(&mut self, phase: &'static str, elapsed: Duration) {
+    match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+      Some((_, duration)) => *duration += elapsed,
+      None => self.phases.push((phase, elapsed)),
+    }
+  }
+
+  pub fn phases(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+    self.phases.iter().copied()
+  }
+
+  pub fn total(&self) -> Duration {
+    self.phases.iter().map(|(_, duration)| *duration).sum()
+  }
+
+  /**
+  The timings of the specification `file` as one line of JSON, with the time of each phase and
+  the total in milliseconds:
+  `{"file":"calc.l","phases":{"parse":1.204,"lint":0.031},"total":1.235}`.
+  */
+  pub fn to_json(&self, file: &str) -> String {
+    let mut phases = serde_json::Map::new();
+    for (phase, duration) in self.phases() {
+      phases.insert(phase.to_string(), json!(milliseconds(duration)));
+    }
+
+    json!({
+      "file": file,
+      "phases": phases,
+      "total": milliseconds(self.total()),
+    }).to_string()
+  }
+}
+
+impl Display for Timings {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "{:<12} {:>9}", "phase", "time (ms)")?;
+    for (phase, duration) in self.phases() {
+      writeln!(f, "{:<12} {:>9.3}", phase, milliseconds(duration))?;
+    }
+    writeln!(f, "{:<12} {:>9.3}", "total", milliseconds(self.total()))
+  }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1000.0
+}
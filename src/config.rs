@@ -0,0 +1,101 @@
+/*!
+
+The project configuration file, `Lesk.toml`, which holds default command line options so that a
+project's specifications can be built without long command lines or wrapper scripts:
+
+```toml
+[options]
+case-insensitive = true
+include-path     = ["grammar/common"]
+out-file         = "src/lexer.rs"
+full             = true
+```
+
+The file is looked for in the current directory and then in each of its ancestors. Each key is
+the long name of a command line option. Its options go on the command line ahead of those given
+there, which therefore take precedence: a value given on the command line replaces the file's,
+and a list given on the command line, such as `--include-path`, replaces the file's list. A flag
+set in the file cannot be turned off on the command line. Both take precedence over the
+specification's `%option`s, and `--explain-option NAME` shows which of them set an option.
+
+Relative paths in the file are relative to the directory the file is in.
+
+`lesk --print-config` prints the options in effect, those of a specification's `%option`s
+included, as such a file, which can be saved to reproduce a build or attached to a bug report.
+
+*/
+
+use std::path::{Path, PathBuf};
+
+use toml::Value;
+
+
+pub const FILE_NAME: &str = "Lesk.toml";
+
+/// The options whose values are paths.
+const PATH_OPTIONS: [&str; 6] =
+  ["include-path", "out-file", "graphs-file", "regexp-file", "tables-file", "export-flex"];
+
+/// The flags whose value is the number of times they are given.
+const COUNTED_OPTIONS: [&str; 1] = ["verbose"];
+
+
+/// The nearest `Lesk.toml`, in the current directory or one of its ancestors.
+pub fn find() -> Option<PathBuf> {
+  let current = std::env::current_dir().ok()?;
+
+  current.ancestors()
+         .map(|directory| directory.join(FILE_NAME))
+         .find(|path| path.is_file())
+}
+
+/// The command line arguments equivalent to the options of the configuration file at `path`.
+pub fn arguments(path: &Path) -> Result<Vec<String>, String> {
+  let text   = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+  let config = text.parse::<Value>().map_err(|e| format!("{}: {}", path.display(), e))?;
+  let base   = path.parent().unwrap_or_else(|| Path::new("."));
+
+  let options =
+      match config.get("options") {
+        Some(Value::Table(options)) => options,
+        Some(_) => return Err(format!("{}: `options` must be a table", path.display())),
+        None    => return Ok(vec![]),
+      };
+
+  let mut arguments = vec![];
+  for (name, value) in options {
+    let values =
+        match value {
+          Value::Array(values) => values.iter().collect(),
+          value                => vec![value],
+        };
+
+    for value in values {
+      let value =
+          match value {
+            Value::Boolean(true)  => {
+              arguments.push(format!("--{}", name));
+              continue;
+            }
+            Value::Boolean(false) => continue,
+            // A count, such as `verbose = 2`, for a flag that may be repeated.
+            Value::Integer(n) if COUNTED_OPTIONS.contains(&name.as_str()) => {
+              arguments.extend((0..*n).map(|_| format!("--{}", name)));
+              continue;
+            }
+            Value::Integer(n)     => n.to_string(),
+            Value::String(s) if PATH_OPTIONS.contains(&name.as_str()) && s.as_str() != "-" => {
+              base.join(s).display().to_string()
+            }
+            Value::String(s)      => s.clone(),
+            _ => {
+              return Err(format!("{}: unsupported value for `{}`", path.display(), name));
+            }
+          };
+      arguments.push(format!("--{}", name));
+      arguments.push(value);
+    }
+  }
+
+  Ok(arguments)
+}
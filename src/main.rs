@@ -1,16 +1,810 @@
-#![feature(entry_insert)]
 
-use lesk_specification::Specification;
-use saucepan::Span;
+mod config;
+mod tokenize;
+
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use clap::{AppSettings, CommandFactory, Parser};
+use clap_complete::Shell;
+use tracing_subscriber::EnvFilter;
+
+use lesk_specification::{
+  explain, Emit, Errors, LeskError, Options, Specification, TimingsFormat,
+  DEFAULT_ENGINE, ENGINE_NAMES
+};
+use lesk_specification::sink::{FileSink, NullSink, OutputSink, StdoutSink, TeeSink};
+use lesk_specification::termcolor::StandardStream;
+
+
+static DEFAULT_OUTPUT_PATH: &str = "lex.yy.rs";
+
+/// The names of the subcommands, and of the arguments that are answered without one.
+static SUBCOMMANDS: [&str; 13] = [
+  "build", "check", "graph", "fmt", "tokenize", "explain", "completions", "man", "help", "-h",
+  "--help", "-V", "--version"
+];
+
+/// The subcommands that take no options, to which the options of `Lesk.toml` are not given.
+static WITHOUT_OPTIONS: [&str; 4] = ["help", "explain", "completions", "man"];
+
+
+#[derive(Parser)]
+#[clap(
+  name = "lesk",
+  version,
+  about = "A lexer generator.",
+  long_version = version_report(),
+  after_help = "EXIT STATUS:
+    0    success
+    1    a specification has errors, which have been reported
+    2    usage error: the command line or Lesk.toml is wrong, or a file cannot be read or written
+    3    internal error: lesk itself failed",
+  // So that options on the command line replace those from `Lesk.toml`.
+  global_setting = AppSettings::AllArgsOverrideSelf
+)]
+enum Command {
+  /// Generates a scanner, the default when no subcommand is given
+  #[clap(after_help = option_table())]
+  Build {
+    #[clap(flatten)]
+    options: Options,
+
+    #[clap(name = "MORE")]
+    /// further specifications, each generating a scanner of its own
+    more: Vec<String>,
+
+    #[clap(long)]
+    /// regenerate the scanner whenever the specification or a file it includes changes
+    watch: bool,
+  },
+  /// Checks a specification for errors and warnings without writing anything, as `--check` does
+  #[clap(after_help = option_table())]
+  Check {
+    #[clap(flatten)]
+    options: Options,
+
+    #[clap(name = "MORE")]
+    /// further specifications to check
+    more: Vec<String>,
+  },
+  /// Writes the start condition graph of a specification in Graphviz DOT format
+  Graph(Options),
+  /// Prints a specification in canonical form
+  Fmt(Options),
+  /// Scans sample input with the rules of a specification, printing each token
+  Tokenize {
+    #[clap(flatten)]
+    options: Options,
+
+    #[clap(long)]
+    /// the file of sample input to scan, or - for standard input
+    sample: String,
+
+    #[clap(long, default_value = "INITIAL")]
+    /// the start condition whose rules scan the sample
+    condition: String,
+  },
+  /// Explains a diagnostic code, such as L0008, with examples
+  Explain {
+    /// the code of the diagnostic, such as L0008, in either case
+    code: String,
+  },
+  /// Writes a completion script for a shell to standard output
+  Completions {
+    #[clap(value_enum, ignore_case = true)]
+    shell: Shell,
+  },
+  /// Writes the manual page of lesk, or of one of its subcommands, in roff format to standard
+  /// output
+  Man {
+    /// The subcommand whose page, such as lesk-build(1), to write
+    subcommand: Option<String>,
+  },
+}
+
+
+/**
+Why `lesk` failed, which is its exit status, so that a Makefile or CI job can tell a specification
+with errors from a bad command line or a failure of lesk itself. The failure is reported to
+standard error where it happens, as a diagnostic or by `Failure::report`, so that building several
+specifications can go on after one of them fails and exit with the worst status.
+*/
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Failure {
+  /// A specification has errors.
+  Diagnostics = 1,
+  /// The command line or `Lesk.toml` is wrong, or an input cannot be read or an output written.
+  Usage = 2,
+  /// Lesk itself failed.
+  Internal = 3,
+}
+
+impl Failure {
+  /// Reports `error`, returning the failure it is.
+  fn report<E: Display>(self, error: E) -> Self {
+    eprintln!("lesk: {}", error);
+    self
+  }
+
+  /// The failure a `LeskError` from a specification that has been read is.
+  fn of(error: &LeskError) -> Self {
+    match error {
+      LeskError::Diagnostics(_)          => Failure::Diagnostics,
+      LeskError::Io { .. }               => Failure::Usage,
+      LeskError::Config { .. }           => Failure::Usage,
+      LeskError::Nondeterministic { .. } => Failure::Internal,
+    }
+  }
+
+  fn status(self) -> i32 {
+    self as i32
+  }
+}
 
 
 fn main() {
-  //let _parser = Parser::new("(?imsqx)abc*|ghj", "bimopf=one.h, one.cpp, two.cpp, stdout;qrswx");
-  //let _parser = Parser::new("abc*?|g{1,5}hj", "");
+  // The language server speaks over stdin and stdout and takes no other arguments. It reads
+  // specifications with the options of `Lesk.toml`.
+  #[cfg(feature = "lsp")]
+  {
+    if std::env::args().nth(1).as_deref() == Some("lsp") {
+      let arguments =
+          match config::find() {
+            Some(path) => config::arguments(&path).unwrap_or_else(|e| fail(Failure::Usage, e)),
+            None       => vec![],
+          };
+      let options =
+          Options::try_parse_from(std::iter::once("lesk".to_string()).chain(arguments))
+              .unwrap_or_else(|e| fail(Failure::Usage, format!("Lesk.toml: {}", e)));
+      if let Err(e) = lesk_specification::lsp::run(options) {
+        fail(Failure::Internal, format!("lsp: {}", e));
+      }
+      return;
+    }
+  }
+
+  // The panic has been reported by the default hook by the time `catch_unwind` returns.
+  let status =
+      match std::panic::catch_unwind(run) {
+        Ok(Ok(()))       => 0,
+        Ok(Err(failure)) => failure.status(),
+        Err(_)           => Failure::Internal.report("internal error, please report it").status(),
+      };
+  std::process::exit(status);
+}
+
+fn run() -> Result<(), Failure> {
+  let (arguments, config) = arguments();
+  let mut command = Command::try_parse_from(&arguments).unwrap_or_else(|e| {
+    if e.use_stderr() {
+      eprintln!("{}", e);
+      std::process::exit(Failure::Usage.status());
+    }
+    // `--help` and `--version`, which exit successfully.
+    e.exit()
+  });
+  record_sources(&mut command, &arguments, config.as_ref());
+  init_logging(command.verbose());
+
+  match command {
+    Command::Explain { code } => {
+      let explanation = explain(&code).ok_or_else(|| {
+        Failure::Usage.report(format!("no diagnostic has the code `{}`", code))
+      })?;
+      print(format!("{}: {}\n\n{}\n", explanation.code, explanation.summary, explanation.text))?;
+    }
+
+    Command::Completions { shell } => {
+      let mut script = vec![];
+      clap_complete::generate(shell, &mut Command::command(), "lesk", &mut script);
+      print(String::from_utf8_lossy(&script))?;
+    }
+
+    Command::Man { subcommand } => manual(subcommand.as_deref())?,
+
+    Command::Build { options, more, watch: true } => watch(options, &more),
+
+    Command::Build { options, more, watch: false } => build_all(options, &more).0?,
+
+    Command::Check { mut options, more } => {
+      options.check = true;
+      build_all(options, &more).0?
+    }
+
+    Command::Graph(options) => {
+      let out_file      = options.out_file.clone();
+      let specification = parse(options, Box::new(NullSink))?;
+      let graph         = specification.condition_graph();
+
+      match out_file {
+        Some(path) if path != "-" => {
+          std::fs::write(&path, graph)
+              .map_err(|e| Failure::Usage.report(LeskError::io(path.as_str(), e)))?;
+        }
+        _ => print(graph)?,
+      }
+    }
+
+    Command::Fmt(mut options) => {
+      options.fmt = true;
+      let sink = output_sink(&options);
+      parse(options, sink)?;
+    }
+
+    Command::Tokenize { options, sample, condition } => {
+      let sample =
+          if sample == "-" {
+            if options.reads_stdin() {
+              return Err(Failure::Usage.report(
+                "the specification and the sample cannot both be read from standard input"
+              ));
+            }
+            read_stdin().map_err(|e| Failure::Usage.report(e))?
+          } else {
+            std::fs::read_to_string(&sample)
+                .map_err(|e| Failure::Usage.report(LeskError::io(sample.as_str(), e)))?
+          };
+      let specification = parse(options, Box::new(NullSink))?;
+      let patterns = specification.patterns().map_err(|errors| {
+        report(&specification, &errors);
+        Failure::Diagnostics
+      })?;
+
+      let pattern = patterns.iter().find(|pattern| pattern.condition == condition).ok_or_else(|| {
+        Failure::Usage.report(format!("no start condition `{}`", condition))
+      })?;
+      tokenize::tokenize(pattern, &sample).map_err(|e| Failure::Diagnostics.report(e))?;
+    }
+  }
+
+  Ok(())
+}
+
+impl Command {
+  /// How many times `-v` was given.
+  fn verbose(&self) -> u8 {
+    match self {
+      Command::Build { options, .. }
+      | Command::Check { options, .. }
+      | Command::Graph(options)
+      | Command::Fmt(options)
+      | Command::Tokenize { options, .. } => options.verbose,
+      Command::Explain { .. } | Command::Completions { .. } | Command::Man { .. } => 0,
+    }
+  }
+
+  /// The options of the subcommand, if it takes any.
+  fn options_mut(&mut self) -> Option<&mut Options> {
+    match self {
+      Command::Build { options, .. }
+      | Command::Check { options, .. }
+      | Command::Graph(options)
+      | Command::Fmt(options)
+      | Command::Tokenize { options, .. } => Some(options),
+      Command::Explain { .. } | Command::Completions { .. } | Command::Man { .. } => None,
+    }
+  }
+}
+
+/**
+What `lesk --version` prints: the version, and the configuration it was built with, for bug
+reports. `-V` prints the version alone.
+*/
+fn version_report() -> &'static str {
+  let mut features = vec![];
+  if cfg!(feature = "DEBUG") {
+    features.push("DEBUG");
+  }
+  if cfg!(feature = "lsp") {
+    features.push("lsp");
+  }
+  let artifacts: Vec<&str> = Emit::ALL.iter().map(|artifact| artifact.name()).collect();
+
+  let report = format!(
+    "{}\n\
+     target:          {}-{}\n\
+     generates:       Rust scanners\n\
+     artifacts:       {}\n\
+     matchers:        {} (--matcher, default {}), compiled when the lexer is created\n\
+     edge compaction: none, as no DFA tables are generated\n\
+     unicode:         --unicode, passed to the matcher as the `u` modifier\n\
+     check compiler:  regex-automata's parser (regex), an approximate syntax check (relesk)\n\
+     features:        {}",
+    env!("CARGO_PKG_VERSION"),
+    std::env::consts::ARCH,
+    std::env::consts::OS,
+    artifacts.join(", "),
+    ENGINE_NAMES.join(", "),
+    DEFAULT_ENGINE,
+    if features.is_empty() { "none".to_string() } else { features.join(", ") }
+  );
+
+  Box::leak(report.into_boxed_str())
+}
+
+/**
+The table of the `%option` equivalents of the flags, for `--help`. Clap keeps the help text for
+the life of the program, so the table, which is built when the arguments are parsed, is leaked.
+*/
+fn option_table() -> &'static str {
+  Box::leak(Options::option_table().into_boxed_str())
+}
+
+/**
+Writes the manual page of `lesk`, which lists its subcommands, or, given `subcommand`, the page of
+that subcommand, `lesk-build(1)` for `build`, which describes its options.
+*/
+fn manual(subcommand: Option<&str>) -> Result<(), Failure> {
+  let command = Command::command();
+  let page =
+      match subcommand {
+        None       => clap_mangen::Man::new(command),
+        Some(name) => {
+          let subcommand = command
+              .find_subcommand(name)
+              .filter(|_| name != "help")
+              .ok_or_else(|| Failure::Usage.report(format!("`{}` is not a subcommand", name)))?;
+          clap_mangen::Man::new(subcommand.clone().name(format!("lesk-{}", name)))
+        }
+      };
+
+  let mut roff = vec![];
+  page.source(format!("lesk {}", env!("CARGO_PKG_VERSION")))
+      .render(&mut roff)
+      .map_err(|e| Failure::Internal.report(e))?;
+  print(String::from_utf8_lossy(&roff))
+}
+
+/**
+Sends the library's `tracing` events to standard error. `RUST_LOG` selects them if it is set, for
+instance `RUST_LOG=lesk_specification::parser=trace`. Otherwise warnings are always shown, `-vv`
+adds debugging output, and `-vvv` traces every item parsed.
+*/
+fn init_logging(verbose: u8) {
+  let level =
+      match verbose {
+        0 | 1 => "warn",
+        2     => "warn,lesk=debug,lesk_specification=debug",
+        _     => "warn,lesk=trace,lesk_specification=trace",
+      };
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+
+  tracing_subscriber::fmt()
+      .with_env_filter(filter)
+      .with_writer(std::io::stderr)
+      .without_time()
+      .init();
+}
+
+/// The project's `Lesk.toml`, and the arguments it supplies.
+struct Config {
+  path     : PathBuf,
+  arguments: Vec<String>,
+}
+
+/**
+The command line arguments, with `build` supplied when no subcommand is given, so that
+`lesk spec.l` goes on meaning what it always has, and the options of the project's `Lesk.toml`
+inserted ahead of those given, with the file they came from.
+*/
+fn arguments() -> (Vec<String>, Option<Config>) {
+  let mut arguments: Vec<String> = std::env::args().collect();
+
+  match arguments.get(1) {
+    Some(first) if SUBCOMMANDS.contains(&first.as_str()) => { /* pass */ }
+    _ => arguments.insert(1, "build".to_string()),
+  }
+
+  let subcommand = arguments[1].as_str();
+  let is_help    = WITHOUT_OPTIONS.contains(&subcommand) || subcommand.starts_with('-');
+  let mut found  = None;
+  if !is_help {
+    if let Some(path) = config::find() {
+      let options = config::arguments(&path).unwrap_or_else(|e| fail(Failure::Usage, e));
+      arguments.splice(2..2, options.iter().cloned());
+      found = Some(Config { path, arguments: options });
+    }
+  }
+
+  (arguments, found)
+}
+
+/**
+Records where each option of `command` was set: in `Lesk.toml`, whose arguments `config` put
+ahead of those given, or on the command line. Clap keeps only the last occurrence of an option,
+so the arguments given are parsed again on their own to tell them from the file's. On their own
+they may lack what the file supplies, such as a flag another requires, in which case the command
+line is not recorded.
+*/
+fn record_sources(command: &mut Command, arguments: &[String], config: Option<&Config>) {
+  let options =
+      match command.options_mut() {
+        Some(options) => options,
+        None          => return,
+      };
+
+  let mut given = arguments.to_vec();
+  if let Some(config) = config {
+    options.record_config(&config.path.display().to_string(), &config.arguments);
+    given.drain(2..2 + config.arguments.len());
+  }
+
+  if let Ok(matches) = Command::command().try_get_matches_from(&given) {
+    if let Some((_, matches)) = matches.subcommand() {
+      options.record_command_line(matches);
+    }
+  }
+}
+
+/**
+Generates a scanner, returning whether it succeeded and the files the specification was read
+from. With `--check`, nothing is written, and instead every rule is compiled by the regex engine
+the options select, so that a specification that checks cleanly is one a scanner can be generated
+from.
+*/
+fn build(options: Options) -> (Result<(), Failure>, Vec<PathBuf>) {
+  if let Some(name) = options.explain_option.clone() {
+    let in_file = PathBuf::from(&options.in_file);
+    return (explain_option(options, &name), vec![in_file]);
+  }
+  if options.print_config {
+    let in_file = PathBuf::from(&options.in_file);
+    return (print_config(options), vec![in_file]);
+  }
+
+  if options.fix {
+    if let Err(failure) = fix(&options) {
+      return (Err(failure), vec![PathBuf::from(&options.in_file)]);
+    }
+  }
+
+  let sink                   = output_sink(&options);
+  let (specification, files) = parse_reporting(options, sink);
+  let specification =
+      match specification {
+        Ok(specification) => specification,
+        Err(failure)      => return (Err(failure), files),
+      };
+
+  let in_file = &specification.options.in_file;
+  let timings = specification.timings();
+  if let Some(statistics) = specification.statistics() {
+    eprint!("{}", statistics);
+  }
+  match specification.options.timings_format() {
+    Some(TimingsFormat::Table) => eprint!("{}:\n{}", in_file, timings),
+    Some(TimingsFormat::Json)  => eprintln!("{}", timings.to_json(in_file)),
+    None                       => { /* pass */ }
+  }
+
+  (Ok(()), files)
+}
+
+/**
+Generates a scanner from each of the specifications `options.in_file` and `more` in turn, with the
+options given once for all of them, returning the worst failure, if any, and the files they were
+read from. With more than one specification, each scanner is named after its specification by
+`--prefix`, unless a prefix is given, and written to the output directory, `-o DIR`, or else
+alongside its specification, with the extension `.rs`: `calc.l` gives `CalcLexer` in `calc.rs`.
+With `--fail-fast`, the specifications after the first that fails are not built. Two
+specifications that would be written to the same file, as `a/calc.l` and `b/calc.l` would be with
+`-o DIR`, are a usage error, and none is built.
+*/
+fn build_all(options: Options, more: &[String]) -> (Result<(), Failure>, Vec<PathBuf>) {
+  if more.is_empty() {
+    return build(options);
+  }
+
+  let in_files: Vec<&String> = std::iter::once(&options.in_file).chain(more).collect();
+  let mut builds: Vec<Options> = vec![];
+
+  for &in_file in &in_files {
+    let mut spec_options = options.clone();
+    let path             = Path::new(in_file);
+    let stem             = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into());
+
+    spec_options.in_file = in_file.clone();
+    if spec_options.prefix.is_none() {
+      spec_options.prefix = Some(identifier(&stem));
+    }
+    spec_options.out_file =
+        match &options.out_file {
+          Some(out) if out == "-" => Some(out.clone()),
+          Some(directory)         => {
+            Some(Path::new(directory).join(format!("{}.rs", stem)).display().to_string())
+          }
+          None                    => Some(path.with_extension("rs").display().to_string()),
+        };
+
+    // Standard output takes every scanner in turn, but a file would only keep the last.
+    let out_file = spec_options.out_file.clone().unwrap_or_default();
+    if out_file != "-" {
+      let out  = Path::new(&out_file);
+      let same = |other: &&Options| other.out_file.as_deref().map(Path::new) == Some(out);
+      if let Some(other) = builds.iter().find(same) {
+        let failure = Failure::Usage.report(format!(
+          "{} and {} would both be written to {}",
+          other.in_file, in_file, out_file
+        ));
+        return (Err(failure), in_files.iter().map(PathBuf::from).collect());
+      }
+    }
+    builds.push(spec_options);
+  }
+
+  let mut result = Ok(());
+  let mut files  = vec![];
+
+  for spec_options in builds {
+    let (spec_result, spec_files) = build(spec_options);
+    files.extend(spec_files);
+    if let Err(failure) = spec_result {
+      result = Err(result.err().map_or(failure, |worst: Failure| worst.max(failure)));
+      // The remaining specifications are not built.
+      if options.fail_fast {
+        break;
+      }
+    }
+  }
+
+  (result, files)
+}
+
+/// The stem of a specification's file name as an identifier, for the prefix of its scanner: each
+/// character that cannot be in an identifier becomes `_`, and a leading digit gets a `_` before it.
+fn identifier(stem: &str) -> String {
+  let mut name: String =
+      stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+  if name.chars().next().is_none_or(|c| c.is_ascii_digit()) {
+    name.insert(0, '_');
+  }
+  name
+}
+
+/**
+`lesk build --watch`: generates the scanners, then again whenever a specification or one of the
+files it includes changes, until interrupted. The directories of the files are watched rather
+than the files themselves, as editors often save by replacing a file.
+*/
+fn watch(options: Options, more: &[String]) -> ! {
+  if options.reads_stdin() {
+    fail(Failure::Usage, "--watch needs a specification file to watch");
+  }
+
+  let (sender, receiver) = channel();
+  let mut watcher =
+      notify::watcher(sender, Duration::from_millis(200))
+          .unwrap_or_else(|e| fail(Failure::Internal, e));
+  let mut watched: Vec<PathBuf> = vec![];
+
+  loop {
+    let (_, files) = build_all(options.clone(), more);
+    let files: Vec<PathBuf> =
+        files.iter().map(|file| file.canonicalize().unwrap_or_else(|_| file.clone())).collect();
+
+    for directory in watched.drain(..) {
+      // The directory may be gone.
+      let _ = watcher.unwatch(&directory);
+    }
+    for file in &files {
+      let directory = file.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+      if !watched.contains(&directory) {
+        watcher.watch(&directory, RecursiveMode::NonRecursive)
+               .unwrap_or_else(|e| fail(Failure::Internal, e));
+        watched.push(directory);
+      }
+    }
+    eprintln!("lesk: watching {} files for changes", files.len());
+
+    loop {
+      let changed =
+          match receiver.recv().unwrap_or_else(|e| fail(Failure::Internal, e)) {
+            | DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path)
+            | DebouncedEvent::Rename(_, path) => path,
+            _ => continue,
+          };
+      if files.contains(&changed) {
+        eprintln!("lesk: {} changed, regenerating", changed.display());
+        break;
+      }
+    }
+  }
+}
+
+/// Reports `error` and exits with the status of `failure`, where there is no returning it.
+fn fail<E: Display>(failure: Failure, error: E) -> ! {
+  std::process::exit(failure.report(error).status());
+}
 
-  let mut specification = Specification::default();
-  specification.parse();
-  // println!("Options: {:?}", specification.options);
-  println!("Done!")
+/// Writes the diagnostics `errors` of `specification` to standard error.
+fn report(specification: &Specification, errors: &Errors) {
+  let mut stderr = StandardStream::stderr(specification.color_choice());
+  // Nothing more can be reported if standard error cannot be written.
+  let _ = specification.emit_diagnostics(errors, &mut stderr);
 }
 
+/// As `parse_reporting`, without the files the specification was read from.
+fn parse<'s>(options: Options, sink: Box<dyn OutputSink>) -> Result<Specification<'s>, Failure> {
+  parse_reporting(options, sink).0
+}
+
+/**
+Reads and parses the specification, writing what it generates to `sink`, and reports its
+diagnostics. Returns the specification if it has no errors, together with the files it was read
+from.
+*/
+fn parse_reporting<'s>(options: Options, sink: Box<dyn OutputSink>)
+  -> (Result<Specification<'s>, Failure>, Vec<PathBuf>)
+{
+  let mut files = vec![PathBuf::from(&options.in_file)];
+  let mut specification =
+      match read_specification(options) {
+        Ok(specification) => specification,
+        Err(e)            => return (Err(Failure::Usage.report(e)), files),
+      };
+  specification.set_sink(sink);
+
+  let result = specification.parse();
+  files.extend(specification.included_files());
+  report(&specification, specification.warnings());
+
+  match result {
+    Ok(()) => (Ok(specification), files),
+    Err(LeskError::Diagnostics(errors)) => {
+      report(&specification, &errors);
+      (Err(Failure::Diagnostics), files)
+    }
+    Err(e) => (Err(Failure::of(&e).report(e)), files),
+  }
+}
+
+/**
+Applies the fixes the diagnostics of the specification suggest, such as inserting a missing `%}`
+or correcting the name of an option, rewriting the specification file. The diagnostics themselves
+are left for the build that follows, which reports those that remain.
+*/
+fn fix(options: &Options) -> Result<(), Failure> {
+  if options.reads_stdin() {
+    return Err(Failure::Usage.report("--fix needs a specification file, not standard input"));
+  }
+
+  let mut options = options.clone();
+  options.check = true;
+  let in_file = options.in_file.clone();
+  let mut specification = read_specification(options).map_err(|e| Failure::Usage.report(e))?;
+  specification.set_sink(Box::new(NullSink));
+
+  let errors =
+      match specification.parse() {
+        Ok(())                              => Errors::new(),
+        Err(LeskError::Diagnostics(errors)) => errors,
+        Err(e)                              => return Err(Failure::of(&e).report(e)),
+      };
+
+  if let Some((text, applied)) = specification.fixed_source(&errors) {
+    std::fs::write(&in_file, text)
+        .map_err(|e| Failure::Usage.report(LeskError::io(in_file.as_str(), e)))?;
+    let plural = if applied == 1 { "" } else { "es" };
+    eprintln!("lesk: applied {} fix{} to {}", applied, plural, in_file);
+  }
+
+  Ok(())
+}
+
+/**
+`--explain-option NAME`: prints the value of the option and where it was set. The specification is
+parsed first, so that its `%option`s are taken into account. Its diagnostics are left for a build.
+*/
+fn explain_option(options: Options, name: &str) -> Result<(), Failure> {
+  let options     = effective_options(options)?;
+  let explanation = options.explain(name).ok_or_else(|| {
+    Failure::Usage.report(format!("no option is named `{}`", name))
+  })?;
+  print(explanation)
+}
+
+/// `--print-config`: prints the options in effect as a `Lesk.toml`, which reproduces them.
+fn print_config(options: Options) -> Result<(), Failure> {
+  print(effective_options(options)?.to_toml())
+}
+
+/**
+Writes `text` to standard output. A reader that stops reading early, as `lesk explain L0008 | head`
+does, is not a failure, as it is not for the other tools of a pipeline, but any other error writing
+it is.
+*/
+fn print<T: Display>(text: T) -> Result<(), Failure> {
+  let mut stdout = std::io::stdout().lock();
+  match write!(stdout, "{}", text).and_then(|()| stdout.flush()) {
+    Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+      Err(Failure::Usage.report(format!("standard output: {}", e)))
+    }
+    _ => Ok(()),
+  }
+}
+
+/**
+The options with the specification's `%option`s applied beneath those of `Lesk.toml` and the
+command line. The specification is parsed without writing anything, and its errors are ignored.
+*/
+fn effective_options(mut options: Options) -> Result<Options, Failure> {
+  options.check = true;
+  let mut specification = read_specification(options).map_err(|e| Failure::Usage.report(e))?;
+  specification.set_sink(Box::new(NullSink));
+
+  match specification.parse() {
+    Ok(()) | Err(LeskError::Diagnostics(_)) => { /* pass */ }
+    Err(e) => return Err(Failure::of(&e).report(e)),
+  }
+
+  Ok(specification.options)
+}
+
+/// Reads the specification named on the command line, or standard input for `-`.
+fn read_specification<'s>(options: Options) -> Result<Specification<'s>, LeskError> {
+  if options.reads_stdin() {
+    let source = read_stdin()?;
+    Ok(Specification::from_source("<stdin>", source, options))
+  } else {
+    let in_file = options.in_file.clone();
+    Specification::from_file(&in_file, options)
+  }
+}
+
+fn read_stdin() -> Result<String, LeskError> {
+  let mut text = String::default();
+  std::io::stdin()
+      .read_to_string(&mut text)
+      .map_err(|e| LeskError::io("<stdin>", e))?;
+  Ok(text)
+}
+
+/**
+Establishes the output stream: the output file, standard output, or both. Only the generated
+code goes to standard output; diagnostics and progress always go to standard error. The output
+file is not created until the output is written, which a specification with errors never is.
+*/
+fn output_sink(options: &Options) -> Box<dyn OutputSink> {
+  let sink: Box<dyn OutputSink> =
+      // Nothing is written when checking, and only the other artifacts when `--emit` leaves out
+      // the code.
+      if options.check || !(options.fmt || options.emits(Emit::Code)) {
+        Box::new(NullSink)
+      }
+      else if options.out_file.as_deref() == Some("-") {
+        Box::new(StdoutSink::new())
+      }
+      else if let Some(path) = &options.out_file {
+        let file = FileSink::deferred(path);
+
+        // Write to both file and stdout.
+        if options.stdout {
+          Box::new(TeeSink::new(vec![Box::new(file), Box::new(StdoutSink::new())]))
+        }
+        // Only write to file
+        else {
+          Box::new(file)
+        }
+      }
+      // No filename supplied
+      else {
+        // Only write to STDOUT. A formatted specification is never written to the default
+        // output file.
+        if options.stdout || options.fmt {
+          Box::new(StdoutSink::new())
+        }
+        // Only write to default output file `lex.yy.rs`
+        else {
+          Box::new(FileSink::deferred(DEFAULT_OUTPUT_PATH))
+        }
+      };
+
+  sink
+}
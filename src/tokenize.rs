@@ -0,0 +1,63 @@
+/*!
+
+`lesk tokenize`: scans sample input with the rules of one start condition of a specification and
+prints each token, without generating a scanner. Actions are not run, so the start condition never
+changes.
+
+The rules are matched the way the generated scanner matches them: the longest match wins, and of
+matches of the same length, the earliest rule. Text no rule matches is reported one character at
+a time.
+
+*/
+
+use fancy_regex::Regex;
+
+use lesk_specification::ConditionPattern;
+
+
+/// Scans `sample` with the rules of `pattern`, printing a line for each token.
+pub fn tokenize(pattern: &ConditionPattern, sample: &str) -> Result<(), String> {
+  let mut rules = vec![];
+  for (regex, span) in pattern.alternatives.iter().zip(&pattern.rules) {
+    let compiled = Regex::new(&format!("^(?:{})", regex))
+        .map_err(|e| format!("rule `{}`: {}", span.fragment(), e))?;
+    rules.push((compiled, *span.fragment()));
+  }
+
+  let mut offset = 0;
+  let mut line   = 1;
+  let mut column = 1;
+
+  while offset < sample.len() {
+    let rest = &sample[offset..];
+    let mut best: Option<(usize, &str)> = None;
+
+    for (regex, source) in &rules {
+      let found = regex.find(rest).map_err(|e| format!("rule `{}`: {}", source, e))?;
+      if let Some(found) = found {
+        if found.end() > best.map_or(0, |(length, _)| length) {
+          best = Some((found.end(), *source));
+        }
+      }
+    }
+
+    let (length, source) = match best {
+      Some(best) => best,
+      None       => (rest.chars().next().map_or(1, char::len_utf8), "<unmatched>"),
+    };
+    let text = &rest[..length];
+    println!("{}:{}\t{}\t{:?}", line, column, source, text);
+
+    for c in text.chars() {
+      if c == '\n' {
+        line  += 1;
+        column = 1;
+      } else {
+        column += 1;
+      }
+    }
+    offset += length;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{lesk, scratch, stderr, CALCULATOR, UNDEFINED};
+
+
+#[test]
+fn check_writes_no_artifacts() {
+  let dir = scratch("check-writes-no-artifacts", &[("calc.l", CALCULATOR)]);
+  lesk(&dir)
+      .args(["--check", "calc.l", "-o", "calc.rs", "--emit", "code,graph,tables"])
+      .assert()
+      .success()
+      .stdout("");
+  assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn check_fails_on_errors() {
+  let dir    = scratch("check-fails-on-errors", &[("bad.l", UNDEFINED)]);
+  let output = lesk(&dir).args(["--check", "bad.l", "-o", "bad.rs"]).assert().code(1);
+  assert!(stderr(output.get_output()).contains("error[L0008]"));
+  assert!(!dir.join("bad.rs").exists());
+}
+
+#[test]
+fn check_compiles_every_rule() {
+  // A backreference, which the default regex engine rejects
+  let dir    = scratch("check-compiles-every-rule", &[("backreference.l", "%%\n(a)\\1   f();\n")]);
+  let output = lesk(&dir).args(["--check", "backreference.l"]).assert().code(1);
+  assert!(stderr(output.get_output()).contains("backreferences are not supported"));
+}
+
+#[test]
+fn check_fails_on_denied_warnings() {
+  let unused = "DIGIT [0-9]\n%%\n[a-z]+   f();\n";
+  let dir    = scratch("check-fails-on-denied-warnings", &[("unused.l", unused)]);
+  lesk(&dir).args(["--check", "unused.l"]).assert().success();
+  lesk(&dir).args(["--check", "unused.l", "--deny", "L0101"]).assert().code(1);
+}
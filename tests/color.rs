@@ -0,0 +1,54 @@
+mod common;
+
+use common::{lesk, scratch, stderr, UNDEFINED};
+
+
+const ESCAPE: char = '\u{1b}';
+
+/// The diagnostics of the undefined definition, with `arguments` and the environment variable
+/// `NO_COLOR` set if `no_color`.
+fn diagnostics(test: &str, arguments: &[&str], no_color: bool) -> String {
+  let dir         = scratch(test, &[("bad.l", UNDEFINED)]);
+  let mut command = lesk(&dir);
+  if no_color {
+    command.env("NO_COLOR", "1");
+  }
+  let output = command.arg("bad.l").args(arguments).assert().code(1);
+  stderr(output.get_output())
+}
+
+#[test]
+fn always_colors_diagnostics() {
+  let diagnostics = diagnostics("always-colors-diagnostics", &["--color", "always"], false);
+  assert!(diagnostics.contains(ESCAPE), "{}", diagnostics);
+  assert!(diagnostics.contains("L0008"));
+}
+
+#[test]
+fn never_leaves_diagnostics_plain() {
+  let diagnostics = diagnostics("never-leaves-diagnostics-plain", &["--color", "never"], false);
+  assert!(!diagnostics.contains(ESCAPE), "{}", diagnostics);
+  assert!(diagnostics.starts_with("error[L0008]"));
+}
+
+#[test]
+fn auto_leaves_diagnostics_to_a_pipe_plain() {
+  // The tests' standard error is a pipe, not a terminal.
+  let diagnostics = diagnostics("auto-leaves-diagnostics-to-a-pipe-plain", &[], false);
+  assert!(!diagnostics.contains(ESCAPE), "{}", diagnostics);
+}
+
+#[test]
+fn no_color_is_honoured_unless_color_is_asked_for() {
+  let plain = diagnostics("no-color-is-honoured", &["--color", "auto"], true);
+  assert!(!plain.contains(ESCAPE), "{}", plain);
+
+  let colored = diagnostics("no-color-is-overridden", &["--color", "always"], true);
+  assert!(colored.contains(ESCAPE), "{}", colored);
+}
+
+#[test]
+fn unknown_color_choice_is_a_usage_error() {
+  let dir = scratch("unknown-color-choice-is-a-usage-error", &[("bad.l", UNDEFINED)]);
+  lesk(&dir).args(["bad.l", "--color", "sometimes"]).assert().code(2);
+}
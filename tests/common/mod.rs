@@ -0,0 +1,63 @@
+/*!
+
+What the command line tests share: the `lesk` binary, run in a scratch directory of its own, and
+the specifications it is run on.
+
+*/
+
+// Each test crate uses some of these.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use assert_cmd::Command;
+
+
+pub const CALCULATOR: &str = "\
+%x COMMENT
+%token Number Plus
+%%
+[0-9]+  return Token::Number;
+\"+\"     return Token::Plus;
+\"/*\"    self.begin(COMMENT);
+<COMMENT>\"*/\" self.begin(INITIAL);
+";
+
+/// A specification with an error, L0008, a reference to a definition there is not.
+pub const UNDEFINED: &str = "%%\n{DIGIT}+   f();\n";
+
+/// A new directory for the test `test` holding `files`, which `lesk` is run in. Each test has
+/// its own, as the tests run in parallel.
+pub fn scratch(test: &str, files: &[(&str, &str)]) -> PathBuf {
+  let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(test);
+  // Left over from the last run
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+
+  for (name, text) in files {
+    std::fs::write(dir.join(name), text).unwrap();
+  }
+  dir
+}
+
+/// `lesk`, to be run in `dir`, without the environment variables that change what it prints.
+pub fn lesk(dir: &Path) -> Command {
+  let mut command = Command::cargo_bin("lesk").unwrap();
+  command.current_dir(dir).env_remove("RUST_LOG").env_remove("NO_COLOR");
+  command
+}
+
+pub fn stdout(output: &Output) -> String {
+  String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+  String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// The text of the file `name` in `dir`.
+pub fn read(dir: &Path, name: &str) -> String {
+  std::fs::read_to_string(dir.join(name))
+      .unwrap_or_else(|e| panic!("{}: {}", dir.join(name).display(), e))
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::{lesk, read, scratch, stderr, stdout, CALCULATOR};
+
+
+#[test]
+fn options_of_the_config_file_are_applied() {
+  let dir = scratch(
+    "options-of-the-config-file-are-applied",
+    &[("Lesk.toml", "[options]\nout-file = \"lexer.rs\"\n"), ("calc.l", CALCULATOR)],
+  );
+  lesk(&dir).arg("calc.l").assert().success();
+  assert!(read(&dir, "lexer.rs").starts_with("// Generated by lesk"));
+  assert!(!dir.join("lex.yy.rs").exists());
+}
+
+#[test]
+fn config_file_is_found_in_an_ancestor() {
+  let dir = scratch(
+    "config-file-is-found-in-an-ancestor",
+    &[("Lesk.toml", "[options]\nout-file = \"lexer.rs\"\n")],
+  );
+  // Its relative paths are relative to the directory the file is in.
+  let sub = dir.join("grammar");
+  std::fs::create_dir(&sub).unwrap();
+  std::fs::write(sub.join("calc.l"), CALCULATOR).unwrap();
+
+  lesk(&sub).arg("calc.l").assert().success();
+  assert!(dir.join("lexer.rs").exists());
+  assert!(!sub.join("lexer.rs").exists());
+}
+
+#[test]
+fn command_line_overrides_the_config_file() {
+  let dir = scratch(
+    "command-line-overrides-the-config-file",
+    &[("Lesk.toml", "[options]\nout-file = \"lexer.rs\"\n"), ("calc.l", CALCULATOR)],
+  );
+  lesk(&dir).args(["calc.l", "-o", "other.rs"]).assert().success();
+  assert!(dir.join("other.rs").exists());
+  assert!(!dir.join("lexer.rs").exists());
+
+  let output = lesk(&dir).args(["calc.l", "--explain-option", "out-file"]).assert().success();
+  assert!(stdout(output.get_output()).contains("Lesk.toml"));
+}
+
+#[test]
+fn malformed_config_file_is_a_usage_error() {
+  let dir = scratch(
+    "malformed-config-file-is-a-usage-error",
+    &[("Lesk.toml", "[options\n"), ("calc.l", CALCULATOR)],
+  );
+  let output = lesk(&dir).arg("calc.l").assert().code(2);
+  assert!(stderr(output.get_output()).contains("Lesk.toml"));
+  assert!(!dir.join("lex.yy.rs").exists());
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{lesk, read, scratch, CALCULATOR};
+
+
+const ARTIFACTS: [&str; 3] = ["calc.rs", "calc.gv", "calc.tables.rs"];
+
+/// Generates every artifact of the calculator, read from `in_file` in `dir`.
+fn generate(dir: &std::path::Path, in_file: &str, arguments: &[&str]) -> Vec<String> {
+  lesk(dir).args([in_file, "-o", "calc.rs", "--emit", "code,graph,tables"])
+           .args(arguments)
+           .assert()
+           .success();
+  ARTIFACTS.iter().map(|name| read(dir, name)).collect()
+}
+
+#[test]
+fn output_is_the_same_wherever_the_specification_is() {
+  let first  = scratch("output-is-the-same-first", &[("calc.l", CALCULATOR)]);
+  let second = scratch("output-is-the-same-second", &[]);
+  std::fs::create_dir(second.join("grammar")).unwrap();
+  std::fs::write(second.join("grammar/calc.l"), CALCULATOR).unwrap();
+
+  let from_first  = generate(&first, "calc.l", &["--deterministic"]);
+  let absolute    = second.join("grammar/calc.l").display().to_string();
+  let from_second = generate(&second, &absolute, &["--deterministic"]);
+  assert_eq!(from_first, from_second);
+  assert!(from_first[0].lines().next().unwrap().ends_with(" from calc.l. Do not edit."));
+}
+
+#[test]
+fn banner_names_the_path_given_otherwise() {
+  let dir = scratch("banner-names-the-path-given-otherwise", &[]);
+  std::fs::create_dir(dir.join("grammar")).unwrap();
+  std::fs::write(dir.join("grammar/calc.l"), CALCULATOR).unwrap();
+
+  let artifacts = generate(&dir, "grammar/calc.l", &[]);
+  assert!(artifacts[0].contains("from grammar/calc.l."), "{}", artifacts[0]);
+}
+
+#[test]
+fn output_is_the_same_from_run_to_run() {
+  let dir   = scratch("output-is-the-same-from-run-to-run", &[("calc.l", CALCULATOR)]);
+  let first = generate(&dir, "calc.l", &["--deterministic"]);
+  assert_eq!(generate(&dir, "calc.l", &["--deterministic"]), first);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{lesk, scratch, stderr, stdout};
+
+
+/// What `lesk` prints to standard output with `arguments`, which succeed.
+fn printed(test: &str, arguments: &[&str]) -> String {
+  let dir    = scratch(test, &[]);
+  let output = lesk(&dir).args(arguments).assert().success();
+  stdout(output.get_output())
+}
+
+#[test]
+fn completions_are_written_for_each_shell() {
+  for shell in ["bash", "elvish", "fish", "powershell", "zsh"] {
+    let script = printed(&format!("completions-for-{}", shell), &["completions", shell]);
+    // The subcommands and the options of `build` are completed.
+    assert!(script.contains("explain"), "{}: {}", shell, script);
+    assert!(script.contains("out-file"), "{}: {}", shell, script);
+  }
+}
+
+#[test]
+fn unknown_shell_is_a_usage_error() {
+  let dir = scratch("unknown-shell-is-a-usage-error", &[]);
+  lesk(&dir).args(["completions", "tcsh"]).assert().code(2).stdout("");
+}
+
+#[test]
+fn manual_page_lists_the_subcommands() {
+  let page = printed("manual-page-lists-the-subcommands", &["man"]);
+  assert!(page.contains(".TH lesk 1"), "{}", page);
+  assert!(page.contains("lesk\\-build(1)"), "{}", page);
+}
+
+#[test]
+fn manual_page_of_a_subcommand_describes_its_options() {
+  let page = printed("manual-page-of-a-subcommand", &["man", "build"]);
+  assert!(page.contains(".TH lesk-build 1"), "{}", page);
+  assert!(page.contains("\\-\\-out\\-file"), "{}", page);
+}
+
+#[test]
+fn manual_page_of_an_unknown_subcommand_is_a_usage_error() {
+  let dir    = scratch("manual-page-of-an-unknown-subcommand", &[]);
+  let output = lesk(&dir).args(["man", "nope"]).assert().code(2).stdout("");
+  assert!(stderr(output.get_output()).contains("`nope` is not a subcommand"));
+}
+
+#[test]
+fn help_lists_the_option_of_each_flag() {
+  let help = printed("help-lists-the-option-of-each-flag", &["build", "--help"]);
+  assert!(help.contains("%option outfile=VALUE"), "{}", help);
+}
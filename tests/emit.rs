@@ -0,0 +1,41 @@
+mod common;
+
+use common::{lesk, read, scratch, CALCULATOR};
+
+
+#[test]
+fn emit_writes_the_artifacts_it_names() {
+  let dir = scratch("emit-writes-the-artifacts", &[("calc.l", CALCULATOR)]);
+  lesk(&dir)
+      .args(["calc.l", "-o", "calc.rs", "--emit", "graph,tables,json,regexp,lalrpop"])
+      .assert()
+      .success();
+
+  // The code is left out, as `--emit` does not name it.
+  assert!(!dir.join("calc.rs").exists());
+  assert!(read(&dir, "calc.gv").starts_with("digraph conditions {"));
+  assert!(read(&dir, "calc.tables.rs").contains("static RULE_ACTIONS: [usize; 4]"));
+  assert!(read(&dir, "calc.json").starts_with("{\n  \"section_one\": ["));
+  assert_eq!(read(&dir, "calc.txt"), "([0-9]+)|(\\+)|(\\/\\*)\n(\\*\\/)\n");
+  assert!(read(&dir, "calc.extern").contains("\"Number\" => Token::Number,"));
+}
+
+#[test]
+fn artifacts_go_to_the_files_given_for_them() {
+  let dir = scratch("artifacts-go-to-their-files", &[("calc.l", CALCULATOR)]);
+  lesk(&dir)
+      .args(["calc.l", "-o", "calc.rs", "--emit", "code,graph", "--graphs-file", "calc.dot"])
+      .assert()
+      .success();
+
+  assert!(read(&dir, "calc.rs").contains("pub struct Lexer<'i>"));
+  assert!(read(&dir, "calc.dot").starts_with("digraph conditions {"));
+  assert!(!dir.join("calc.gv").exists());
+}
+
+#[test]
+fn unknown_artifacts_are_usage_errors() {
+  let dir = scratch("unknown-artifacts", &[("calc.l", CALCULATOR)]);
+  lesk(&dir).args(["calc.l", "--emit", "code,bogus"]).assert().code(2);
+  assert!(!dir.join("lex.yy.rs").exists());
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{lesk, scratch, stderr, stdout, UNDEFINED};
+
+
+#[test]
+fn explain_prints_the_extended_description() {
+  let dir    = scratch("explain-prints-the-extended-description", &[]);
+  let output = lesk(&dir).args(["explain", "L0008"]).assert().success();
+  let text   = stdout(output.get_output());
+  assert!(text.starts_with("L0008: undefined name\n\n"), "{}", text);
+  // With an example
+  assert!(text.contains("{DIGIT}+"), "{}", text);
+}
+
+#[test]
+fn explain_ignores_the_case_of_the_code() {
+  let dir   = scratch("explain-ignores-the-case-of-the-code", &[]);
+  let upper = lesk(&dir).args(["explain", "L0008"]).output().unwrap();
+  let lower = lesk(&dir).args(["explain", "l0008"]).output().unwrap();
+  assert_eq!(upper.stdout, lower.stdout);
+}
+
+#[test]
+fn unknown_code_is_a_usage_error() {
+  let dir    = scratch("unknown-code-is-a-usage-error", &[]);
+  let output = lesk(&dir).args(["explain", "L9999"]).assert().code(2).stdout("");
+  assert!(stderr(output.get_output()).contains("no diagnostic has the code `L9999`"));
+}
+
+#[test]
+fn each_code_reported_can_be_explained() {
+  let dir    = scratch("each-code-reported-can-be-explained", &[("bad.l", UNDEFINED)]);
+  let output = lesk(&dir).arg("bad.l").assert().code(1);
+  let text   = stderr(output.get_output());
+  let code   = &text[text.find("[L").unwrap() + 1..][..5];
+  lesk(&dir).args(["explain", code]).assert().success();
+}
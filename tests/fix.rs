@@ -0,0 +1,42 @@
+mod common;
+
+use common::{lesk, read, scratch, stderr, UNDEFINED};
+
+
+/// A misspelt negated option, whose fix is `%option noline`.
+const MISSPELT: &str = "%option nolines\n%%\n[a-z]+   f();\n";
+
+#[test]
+fn suggestion_is_shown_without_fix() {
+  let dir    = scratch("suggestion-is-shown-without-fix", &[("misspelt.l", MISSPELT)]);
+  let output = lesk(&dir).arg("misspelt.l").assert().code(1);
+  assert!(stderr(output.get_output()).contains("help: did you mean `noline`?"));
+  assert_eq!(read(&dir, "misspelt.l"), MISSPELT);
+  assert!(!dir.join("lex.yy.rs").exists());
+}
+
+#[test]
+fn fix_applies_the_suggestion_and_builds() {
+  let dir    = scratch("fix-applies-the-suggestion-and-builds", &[("misspelt.l", MISSPELT)]);
+  let output = lesk(&dir).args(["misspelt.l", "--fix"]).assert().success();
+  assert_eq!(stderr(output.get_output()), "lesk: applied 1 fix to misspelt.l\n");
+  assert_eq!(read(&dir, "misspelt.l"), MISSPELT.replace("nolines", "noline"));
+  assert!(dir.join("lex.yy.rs").exists());
+
+  // There is nothing left to fix.
+  lesk(&dir).args(["misspelt.l", "--fix"]).assert().success().stderr("");
+}
+
+#[test]
+fn errors_without_a_suggestion_are_left() {
+  let dir = scratch("errors-without-a-suggestion-are-left", &[("bad.l", UNDEFINED)]);
+  lesk(&dir).args(["bad.l", "--fix"]).assert().code(1);
+  assert_eq!(read(&dir, "bad.l"), UNDEFINED);
+}
+
+#[test]
+fn fix_needs_a_specification_file() {
+  let dir    = scratch("fix-needs-a-specification-file", &[]);
+  let output = lesk(&dir).args(["-", "--fix"]).write_stdin(MISSPELT).assert().code(2);
+  assert!(stderr(output.get_output()).contains("--fix needs a specification file"));
+}
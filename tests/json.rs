@@ -0,0 +1,52 @@
+mod common;
+
+use serde_json::Value;
+
+use common::{lesk, scratch, stderr, CALCULATOR, UNDEFINED};
+
+
+/// The diagnostics of `output`, one JSON object to a line of standard error.
+fn diagnostics(output: &std::process::Output) -> Vec<Value> {
+  stderr(output)
+      .lines()
+      .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+      .collect()
+}
+
+#[test]
+fn each_diagnostic_is_a_json_object() {
+  let dir    = scratch("each-diagnostic-is-a-json-object", &[("bad.l", UNDEFINED)]);
+  let output = lesk(&dir).args(["bad.l", "--message-format", "json"]).assert().code(1);
+  let output = output.get_output();
+  assert_eq!(output.stdout, b"");
+
+  let diagnostics = diagnostics(output);
+  assert_eq!(diagnostics.len(), 1);
+  let diagnostic = &diagnostics[0];
+  assert_eq!(diagnostic["code"], "L0008");
+  assert_eq!(diagnostic["severity"], "error");
+  assert_eq!(diagnostic["file"], "bad.l");
+  assert_eq!(diagnostic["message"], "undefined definition `DIGIT`");
+  assert_eq!(diagnostic["range"]["start"]["line"], 2);
+  assert_eq!(diagnostic["range"]["start"]["column"], 1);
+  assert!(diagnostic["notes"].is_array());
+}
+
+#[test]
+fn warnings_are_json_objects_too() {
+  let unused = "DIGIT [0-9]\n%%\n[a-z]+   f();\n";
+  let dir    = scratch("warnings-are-json-objects-too", &[("unused.l", unused)]);
+  let output = lesk(&dir).args(["unused.l", "--message-format=json"]).assert().success();
+
+  let diagnostics = diagnostics(output.get_output());
+  assert_eq!(diagnostics.len(), 1);
+  assert_eq!(diagnostics[0]["code"], "L0101");
+  assert_eq!(diagnostics[0]["severity"], "warning");
+}
+
+#[test]
+fn clean_specification_prints_nothing() {
+  let dir    = scratch("clean-specification-prints-nothing", &[("calc.l", CALCULATOR)]);
+  let output = lesk(&dir).args(["calc.l", "--message-format", "json"]).assert().success();
+  assert!(diagnostics(output.get_output()).is_empty());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{lesk, scratch, stderr};
+
+
+/// Four errors, one to a rule.
+const MANY: &str = "%%\n{A}+   f();\n{B}+   f();\n{C}+   f();\n{D}+   f();\n";
+
+/// The lines of `report` that begin a diagnostic or a note.
+fn headlines(report: &str) -> Vec<&str> {
+  report.lines().filter(|line| line.starts_with("error") || line.starts_with("note")).collect()
+}
+
+/// The diagnostics of `MANY` with `arguments`, which fail.
+fn diagnostics(test: &str, arguments: &[&str]) -> String {
+  let dir    = scratch(test, &[("many.l", MANY)]);
+  let output = lesk(&dir).arg("many.l").args(arguments).assert().code(1);
+  stderr(output.get_output())
+}
+
+#[test]
+fn every_error_is_reported_by_default() {
+  let report = diagnostics("every-error-is-reported-by-default", &[]);
+  assert_eq!(headlines(&report).len(), 4, "{}", report);
+}
+
+#[test]
+fn error_limit_stops_collecting_errors() {
+  let report = diagnostics("error-limit-stops-collecting-errors", &["--error-limit", "2"]);
+  assert_eq!(
+    headlines(&report),
+    [
+      "error[L0008]: undefined definition `A`",
+      "error[L0008]: undefined definition `B`",
+      "note: 2 more errors were not shown; `--error-limit` sets how many are",
+    ]
+  );
+}
+
+#[test]
+fn error_limit_of_zero_is_no_limit() {
+  let report = diagnostics("error-limit-of-zero-is-no-limit", &["--error-limit", "0"]);
+  assert_eq!(headlines(&report).len(), 4, "{}", report);
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_error() {
+  let report = diagnostics("fail-fast-stops-at-the-first-error", &["--fail-fast"]);
+  assert_eq!(headlines(&report).len(), 2, "{}", report);
+  assert!(report.contains("3 more errors were not shown"), "{}", report);
+}
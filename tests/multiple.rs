@@ -0,0 +1,51 @@
+mod common;
+
+use common::{lesk, read, scratch, stderr, CALCULATOR, UNDEFINED};
+
+
+#[test]
+fn each_specification_gets_a_scanner_of_its_own() {
+  let dir = scratch(
+    "each-specification-gets-a-scanner",
+    &[("calc.l", CALCULATOR), ("words.l", CALCULATOR)],
+  );
+  lesk(&dir).args(["calc.l", "words.l"]).assert().success();
+  assert!(read(&dir, "calc.rs").contains("pub struct CalcLexer"));
+  assert!(read(&dir, "words.rs").contains("pub struct WordsLexer"));
+}
+
+#[test]
+fn scanners_are_written_to_the_output_directory() {
+  let dir = scratch(
+    "scanners-are-written-to-the-output-directory",
+    &[("calc.l", CALCULATOR), ("words.l", CALCULATOR)],
+  );
+  std::fs::create_dir(dir.join("generated")).unwrap();
+  lesk(&dir).args(["calc.l", "words.l", "-o", "generated"]).assert().success();
+  assert!(dir.join("generated/calc.rs").exists());
+  assert!(dir.join("generated/words.rs").exists());
+}
+
+#[test]
+fn specifications_sharing_an_output_file_are_refused() {
+  let dir = scratch("specifications-sharing-an-output-file", &[]);
+  for sub in ["a", "b"] {
+    std::fs::create_dir(dir.join(sub)).unwrap();
+    std::fs::write(dir.join(sub).join("calc.l"), CALCULATOR).unwrap();
+  }
+  std::fs::create_dir(dir.join("generated")).unwrap();
+  let output = lesk(&dir).args(["a/calc.l", "b/calc.l", "-o", "generated"]).assert().code(2);
+  assert!(stderr(output.get_output()).contains("would both be written to"));
+  assert_eq!(std::fs::read_dir(dir.join("generated")).unwrap().count(), 0);
+}
+
+#[test]
+fn a_failing_specification_does_not_stop_the_others() {
+  let dir = scratch("a-failing-specification", &[("bad.l", UNDEFINED), ("calc.l", CALCULATOR)]);
+  lesk(&dir).args(["bad.l", "calc.l"]).assert().code(1);
+  assert!(read(&dir, "calc.rs").starts_with("// Generated by lesk"));
+
+  std::fs::remove_file(dir.join("calc.rs")).unwrap();
+  lesk(&dir).args(["bad.l", "calc.l", "--fail-fast"]).assert().code(1);
+  assert!(!dir.join("calc.rs").exists());
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use std::io::Read;
+use std::process::Stdio;
+
+use common::{lesk, scratch, stderr, CALCULATOR, UNDEFINED};
+
+
+#[test]
+fn success_is_zero() {
+  let dir = scratch("success-is-zero", &[("calc.l", CALCULATOR)]);
+  lesk(&dir).arg("calc.l").assert().code(0);
+}
+
+#[test]
+fn errors_in_a_specification_are_one() {
+  let dir = scratch("errors-in-a-specification-are-one", &[("bad.l", UNDEFINED)]);
+  lesk(&dir).arg("bad.l").assert().code(1);
+}
+
+#[test]
+fn usage_errors_are_two() {
+  let dir = scratch("usage-errors-are-two", &[("calc.l", CALCULATOR)]);
+  // An unknown option
+  lesk(&dir).args(["calc.l", "--no-such-option"]).assert().code(2);
+  // A specification there is not
+  let output = lesk(&dir).arg("missing.l").assert().code(2);
+  assert!(stderr(output.get_output()).contains("missing.l"));
+  // An output that cannot be written
+  lesk(&dir).args(["calc.l", "-o", "no/such/directory/calc.rs"]).assert().code(2);
+}
+
+#[test]
+fn worst_status_of_several_specifications_is_the_status() {
+  let dir = scratch("worst-status-of-several", &[("calc.l", CALCULATOR), ("bad.l", UNDEFINED)]);
+  lesk(&dir).args(["calc.l", "bad.l"]).assert().code(1);
+  lesk(&dir).args(["calc.l", "bad.l", "missing.l"]).assert().code(2);
+}
+
+#[test]
+fn reader_that_stops_early_is_not_a_failure() {
+  let dir       = scratch("reader-that-stops-early", &[]);
+  let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("lesk"))
+      .args(["explain", "L0008"])
+      .current_dir(&dir)
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .unwrap();
+  // Closes standard output before lesk writes to it.
+  drop(child.stdout.take());
+
+  let status    = child.wait().unwrap();
+  let mut error = String::new();
+  child.stderr.take().unwrap().read_to_string(&mut error).unwrap();
+  assert_eq!(status.code(), Some(0), "{}", error);
+  assert_eq!(error, "");
+}
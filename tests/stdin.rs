@@ -0,0 +1,35 @@
+mod common;
+
+use common::{lesk, read, scratch, stderr, stdout, CALCULATOR, UNDEFINED};
+
+
+#[test]
+fn scanner_of_standard_input_is_written_to_standard_output() {
+  let dir    = scratch("scanner-of-standard-input", &[]);
+  let output = lesk(&dir).args(["-", "-o", "-"]).write_stdin(CALCULATOR).assert().success();
+  let code   = stdout(output.get_output());
+  assert!(code.starts_with("// Generated by lesk"), "{}", code);
+  assert!(code.contains("<stdin>"));
+  assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn errors_leave_standard_output_empty() {
+  let dir    = scratch("errors-leave-standard-output-empty", &[]);
+  let output = lesk(&dir).args(["-", "-o", "-"]).write_stdin(UNDEFINED).assert().code(1);
+  assert_eq!(stdout(output.get_output()), "");
+  let diagnostics = stderr(output.get_output());
+  assert!(diagnostics.contains("error[L0008]"), "{}", diagnostics);
+  assert!(diagnostics.contains("<stdin>:2:1"), "{}", diagnostics);
+}
+
+#[test]
+fn errors_leave_the_output_file_as_it_was() {
+  let dir = scratch("errors-leave-the-output-file-as-it-was", &[("bad.l", UNDEFINED)]);
+  lesk(&dir).arg("bad.l").assert().code(1);
+  assert!(!dir.join("lex.yy.rs").exists());
+
+  std::fs::write(dir.join("bad.rs"), "// The last scanner\n").unwrap();
+  lesk(&dir).args(["bad.l", "-o", "bad.rs"]).assert().code(1);
+  assert_eq!(read(&dir, "bad.rs"), "// The last scanner\n");
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{lesk, read, scratch, stdout, CALCULATOR};
+
+
+#[test]
+fn build_is_the_default_subcommand() {
+  let dir = scratch("build-is-the-default", &[("calc.l", CALCULATOR)]);
+  lesk(&dir).arg("calc.l").assert().success();
+  assert!(read(&dir, "lex.yy.rs").contains("pub struct Lexer<'i>"));
+
+  lesk(&dir).args(["build", "calc.l", "-o", "calc.rs"]).assert().success();
+  assert_eq!(read(&dir, "calc.rs"), read(&dir, "lex.yy.rs"));
+}
+
+#[test]
+fn check_writes_nothing() {
+  let dir = scratch("check-writes-nothing", &[("calc.l", CALCULATOR)]);
+  lesk(&dir).args(["check", "calc.l"]).assert().success().stdout("");
+  assert!(!dir.join("lex.yy.rs").exists());
+}
+
+#[test]
+fn graph_prints_the_start_condition_graph() {
+  let dir    = scratch("graph-prints", &[("calc.l", CALCULATOR)]);
+  let output = lesk(&dir).args(["graph", "calc.l"]).assert().success();
+  let graph  = stdout(output.get_output());
+  assert!(graph.starts_with("digraph conditions {"), "{}", graph);
+  assert!(graph.contains("\"INITIAL\" -> \"COMMENT\""), "{}", graph);
+}
+
+#[test]
+fn fmt_prints_the_specification_in_canonical_form() {
+  let dir       = scratch("fmt-prints", &[("calc.l", CALCULATOR)]);
+  let output    = lesk(&dir).args(["fmt", "calc.l"]).assert().success();
+  let formatted = stdout(output.get_output());
+  assert!(formatted.contains("%xstate COMMENT\n"), "{}", formatted);
+  assert!(formatted.contains("\n[0-9]+         return Token::Number;\n"), "{}", formatted);
+  assert!(!dir.join("lex.yy.rs").exists());
+}
+
+#[test]
+fn tokenize_scans_a_sample() {
+  let dir = scratch("tokenize-scans", &[("calc.l", CALCULATOR), ("sample.txt", "12+3")]);
+  lesk(&dir)
+      .args(["tokenize", "calc.l", "--sample", "sample.txt"])
+      .assert()
+      .success()
+      .stdout("1:1\t[0-9]+\t\"12\"\n1:3\t\"+\"\t\"+\"\n1:4\t[0-9]+\t\"3\"\n");
+}
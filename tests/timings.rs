@@ -0,0 +1,40 @@
+mod common;
+
+use common::{lesk, scratch, stderr, stdout, CALCULATOR};
+
+
+/// The phases of the timings report in `report`, in order.
+fn phases(report: &str) -> Vec<&str> {
+  report.lines()
+        .skip_while(|line| !line.starts_with("phase"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .collect()
+}
+
+#[test]
+fn timings_are_reported_on_standard_error() {
+  let dir    = scratch("timings-are-reported-on-standard-error", &[("calc.l", CALCULATOR)]);
+  let output = lesk(&dir).args(["calc.l", "-o", "-", "--timings"]).assert().success();
+  let output = output.get_output();
+
+  let report = stderr(output);
+  assert!(report.starts_with("calc.l:\n"), "{}", report);
+  assert_eq!(phases(&report), ["parse", "lint", "lower", "write", "total"]);
+  // The scanner on standard output is the same as without them.
+  assert!(stdout(output).starts_with("// Generated by lesk"));
+  assert!(!stdout(output).contains("phase"));
+}
+
+#[test]
+fn check_times_the_compilation_instead_of_the_writing() {
+  let dir    = scratch("check-times-the-compilation", &[("calc.l", CALCULATOR)]);
+  let output = lesk(&dir).args(["calc.l", "--check", "--timings"]).assert().success();
+  assert_eq!(phases(&stderr(output.get_output())), ["parse", "lint", "lower", "compile", "total"]);
+}
+
+#[test]
+fn no_timings_are_reported_unless_asked_for() {
+  let dir = scratch("no-timings-are-reported-unless-asked-for", &[("calc.l", CALCULATOR)]);
+  lesk(&dir).arg("calc.l").assert().success().stderr("");
+}
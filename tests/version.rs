@@ -0,0 +1,30 @@
+mod common;
+
+use common::{lesk, scratch, stdout};
+
+
+#[test]
+fn short_version_is_the_version_alone() {
+  let dir    = scratch("short-version-is-the-version-alone", &[]);
+  let output = lesk(&dir).arg("-V").assert().success();
+  assert_eq!(stdout(output.get_output()), format!("lesk {}\n", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn long_version_reports_the_build_configuration() {
+  let dir    = scratch("long-version-reports-the-build-configuration", &[]);
+  let output = lesk(&dir).arg("--version").assert().success();
+  let report = stdout(output.get_output());
+
+  assert!(report.starts_with(&format!("lesk {}\n", env!("CARGO_PKG_VERSION"))), "{}", report);
+  for field in ["target:", "artifacts:", "matchers:", "check compiler:", "features:"] {
+    assert!(report.lines().any(|line| line.starts_with(field)), "{}: {}", field, report);
+  }
+  assert!(report.contains("regex, relesk"), "{}", report);
+}
+
+#[test]
+fn version_ignores_the_config_file() {
+  let dir = scratch("version-ignores-the-config-file", &[("Lesk.toml", "[options\n")]);
+  lesk(&dir).arg("--version").assert().success();
+}
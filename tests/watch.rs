@@ -0,0 +1,62 @@
+mod common;
+
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{read, scratch, CALCULATOR};
+
+
+/// Kills the watching `lesk` when the test ends, whether or not it passes.
+struct Watching(Child);
+
+impl Drop for Watching {
+  fn drop(&mut self) {
+    let _ = self.0.kill();
+    let _ = self.0.wait();
+  }
+}
+
+/// Waits up to ten seconds for the file `name` in `dir` to hold `text`.
+fn wait_for(dir: &Path, name: &str, text: &str) -> bool {
+  let start = Instant::now();
+  while start.elapsed() < Duration::from_secs(10) {
+    if std::fs::read_to_string(dir.join(name)).is_ok_and(|contents| contents.contains(text)) {
+      return true;
+    }
+    std::thread::sleep(Duration::from_millis(50));
+  }
+  false
+}
+
+#[test]
+fn watch_regenerates_when_an_included_file_changes() {
+  let main = "%include \"tokens.l\"\n%%\n[0-9]+  return Token::Number;\n";
+  let dir  = scratch("watch-regenerates", &[("calc.l", main), ("tokens.l", "%token Number\n")]);
+  let _watching = Watching(
+    std::process::Command::new(assert_cmd::cargo::cargo_bin("lesk"))
+        .args(["build", "--watch", "calc.l", "-o", "calc.rs"])
+        .current_dir(&dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap()
+  );
+
+  assert!(wait_for(&dir, "calc.rs", "    Number,\n"), "the scanner was not generated");
+  // Give the watcher time to start watching before the change it is to see.
+  std::thread::sleep(Duration::from_millis(500));
+
+  std::fs::write(dir.join("tokens.l"), "%token Number Plus\n").unwrap();
+  assert!(wait_for(&dir, "calc.rs", "    Plus,\n"), "{}", read(&dir, "calc.rs"));
+}
+
+#[test]
+fn watch_needs_a_file() {
+  let dir = scratch("watch-needs-a-file", &[]);
+  common::lesk(&dir)
+      .args(["build", "--watch", "-"])
+      .write_stdin(CALCULATOR)
+      .assert()
+      .code(2);
+}